@@ -0,0 +1,289 @@
+// QuectoClaw — Slash commands shared by the REPL and every chat channel
+
+use super::AgentLoop;
+
+/// A built-in slash command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Reset,
+    Model(Option<String>),
+    Fork(Option<String>),
+    Sessions,
+    Cost,
+    Metrics,
+    Stop,
+    Tools,
+    Help,
+}
+
+struct Spec {
+    usage: &'static str,
+    about: &'static str,
+    admin: bool,
+}
+
+/// Shown by `/help`, in this order.
+const SPECS: &[Spec] = &[
+    Spec {
+        usage: "/reset",
+        about: "Start a new conversation (the old one is backed up)",
+        admin: false,
+    },
+    Spec {
+        usage: "/stop",
+        about: "Cancel the reply being worked on",
+        admin: false,
+    },
+    Spec {
+        usage: "/fork [name]",
+        about: "Copy this conversation to a new session",
+        admin: false,
+    },
+    Spec {
+        usage: "/cost",
+        about: "Token usage and cost of this conversation",
+        admin: false,
+    },
+    Spec {
+        usage: "/tools",
+        about: "Tools available here",
+        admin: false,
+    },
+    Spec {
+        usage: "/model [name|default]",
+        about: "Show or switch this conversation's model",
+        admin: true,
+    },
+    Spec {
+        usage: "/sessions",
+        about: "List stored sessions",
+        admin: true,
+    },
+    Spec {
+        usage: "/metrics",
+        about: "Performance metrics",
+        admin: true,
+    },
+    Spec {
+        usage: "/help",
+        about: "Show this help",
+        admin: false,
+    },
+];
+
+impl Command {
+    /// Parse a built-in command. `None` for anything else, including MCP
+    /// prompt commands.
+    pub fn parse(input: &str) -> Option<Self> {
+        let (name, arg) = split_command(input)?;
+        let arg = arg.map(str::to_string);
+        Some(match name.to_ascii_lowercase().as_str() {
+            "reset" | "clear" | "new" => Command::Reset,
+            "model" => Command::Model(arg),
+            "fork" => Command::Fork(arg),
+            "sessions" => Command::Sessions,
+            "cost" => Command::Cost,
+            "metrics" => Command::Metrics,
+            "stop" => Command::Stop,
+            "tools" => Command::Tools,
+            // Telegram sends /start when a user opens the bot
+            "help" | "start" => Command::Help,
+            _ => return None,
+        })
+    }
+
+    /// Whether only admins (`gateway.admins`, and the local REPL) may run it.
+    pub fn admin_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Model(_) | Command::Sessions | Command::Metrics
+        )
+    }
+}
+
+/// Split `/name args` into the name and optional argument text. Text that
+/// merely starts with a slash, like a path, is not a command.
+fn split_command(input: &str) -> Option<(&str, Option<&str>)> {
+    let rest = input.trim().strip_prefix('/')?;
+    let (name, arg) = match rest.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, Some(arg.trim()).filter(|a| !a.is_empty())),
+        None => (rest, None),
+    };
+    // Telegram appends the bot's username in groups: /reset@my_bot
+    let name = name.split('@').next().unwrap_or_default();
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.'));
+    valid.then_some((name, arg))
+}
+
+impl AgentLoop {
+    /// Run a slash command in `session_key`. Returns the reply, or `None`
+    /// when the input is for the model: plain text and MCP prompt commands
+    /// (see `expand_input`). `admin` unlocks admin-only commands.
+    pub async fn run_command(&self, input: &str, session_key: &str, admin: bool) -> Option<String> {
+        let (name, _) = split_command(input)?;
+        let Some(command) = Command::parse(input) else {
+            let prompt = match self.tools.get_mcp().await {
+                Some(mcp) => mcp.find_prompt(name).await,
+                None => None,
+            };
+            return match prompt {
+                Some(_) => None,
+                None => Some(format!("Unknown command: /{} (try /help)", name)),
+            };
+        };
+        if command.admin_only() && !admin {
+            return Some(format!("⛔ /{} is for admins only.", name));
+        }
+        // A running turn would write its history back over the change
+        if matches!(command, Command::Reset | Command::Fork(_)) && self.has_turns(session_key) {
+            return Some(format!(
+                "⏳ A reply is still in progress. Wait for it or /stop it before /{}.",
+                name
+            ));
+        }
+
+        let reply = match command {
+            Command::Reset => {
+                let backup = format!("{}-backup-{}", session_key, chrono::Utc::now().timestamp());
+                let saved = self.sessions.fork_session(session_key, &backup).await;
+                self.sessions.clear(session_key).await;
+                if saved {
+                    format!("🗑️ Conversation cleared. (Backup saved as {})", backup)
+                } else {
+                    "🗑️ Conversation cleared.".to_string()
+                }
+            }
+            Command::Stop => match self.stop_turns(session_key).await {
+                0 => "Nothing to stop.".to_string(),
+                _ => "⏹️ Stopped.".to_string(),
+            },
+            Command::Fork(name) => {
+                // Only admins may name any session; others fork next to
+                // their own, so they cannot overwrite someone else's
+                let target = match name {
+                    Some(name) if admin => name,
+                    Some(name) => format!("{}-fork-{}", session_key, name),
+                    None => format!("{}-fork-{}", session_key, chrono::Utc::now().timestamp()),
+                };
+                if self.sessions.fork_session(session_key, &target).await {
+                    format!("✂️ Forked session to: {}", target)
+                } else {
+                    "⚠️ Nothing to fork — session is empty.".to_string()
+                }
+            }
+            Command::Cost => {
+                let usage = self.metrics.session_usage(session_key).await;
+                let line = format!(
+                    "This conversation (incl. subagents): {} requests, {} tokens, ${:.4}",
+                    usage.llm_requests,
+                    usage.prompt_tokens + usage.completion_tokens,
+                    usage.cost
+                );
+                if admin {
+                    format!("{}\n{}", self.metrics.format_cost_report().await, line)
+                } else {
+                    line
+                }
+            }
+            Command::Tools => {
+                let tools =
+                    self.tools_for_profile(self.config.tools.profile_for_session(session_key));
+                let mut names = tools.list().await;
+                names.sort();
+                format!("🛠️ Tools ({}): {}", names.len(), names.join(", "))
+            }
+            Command::Model(None) => {
+                let model = self.session_model(session_key).await;
+                match model {
+                    Some(m) => format!("Model for this conversation: {}", m),
+                    None => format!(
+                        "Model for this conversation: {} (default)",
+                        self.config.agents.defaults.model
+                    ),
+                }
+            }
+            Command::Model(Some(model)) => {
+                let mut models = self.session_models.write().await;
+                if model == "default" {
+                    models.remove(session_key);
+                    format!(
+                        "Model reset to the default: {}",
+                        self.config.agents.defaults.model
+                    )
+                } else {
+                    models.insert(session_key.to_string(), model.clone());
+                    format!("Model for this conversation: {}", model)
+                }
+            }
+            Command::Sessions => {
+                let mut sessions = self.sessions.list_sessions().await;
+                sessions.sort();
+                if sessions.is_empty() {
+                    "No stored sessions.".to_string()
+                } else {
+                    format!("Sessions ({}):\n{}", sessions.len(), sessions.join("\n"))
+                }
+            }
+            Command::Metrics => self.metrics.format_report().await,
+            Command::Help => self.help(session_key, admin).await,
+        };
+        Some(reply)
+    }
+
+    async fn help(&self, session_key: &str, admin: bool) -> String {
+        let mut lines = vec!["Commands:".to_string()];
+        for spec in SPECS.iter().filter(|s| admin || !s.admin) {
+            lines.push(format!("  {} — {}", spec.usage, spec.about));
+        }
+        let prompts = self.mcp_prompts(session_key).await;
+        if !prompts.is_empty() {
+            lines.push("MCP prompts:".to_string());
+            for p in prompts {
+                lines.push(format!(
+                    "  {} — {}",
+                    p.usage(),
+                    p.description.as_deref().unwrap_or("")
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("/reset"), Some(Command::Reset));
+        assert_eq!(Command::parse("  /clear "), Some(Command::Reset));
+        assert_eq!(
+            Command::parse("/model gpt-4o-mini"),
+            Some(Command::Model(Some("gpt-4o-mini".into())))
+        );
+        assert_eq!(Command::parse("/model"), Some(Command::Model(None)));
+        assert_eq!(Command::parse("/stop@quecto_bot"), Some(Command::Stop));
+        assert_eq!(Command::parse("/HELP"), Some(Command::Help));
+        assert_eq!(Command::parse("/github:review 42"), None);
+        assert_eq!(Command::parse("hello /reset"), None);
+    }
+
+    #[test]
+    fn test_paths_are_not_commands() {
+        assert_eq!(split_command("/etc/hosts is broken"), None);
+        assert_eq!(split_command("/ nothing"), None);
+        assert_eq!(split_command("/foo bar"), Some(("foo", Some("bar"))));
+    }
+
+    #[test]
+    fn test_admin_only() {
+        assert!(Command::Model(None).admin_only());
+        assert!(Command::Sessions.admin_only());
+        assert!(!Command::Stop.admin_only());
+        assert!(!Command::Cost.admin_only());
+    }
+}
@@ -1,21 +1,38 @@
 // QuectoClaw — Gateway service (runs all channels and agent loop)
 
+use crate::agent::heartbeat::HeartbeatService;
 use crate::agent::AgentLoop;
 use crate::bus::{MessageBus, OutboundMessage};
+use crate::channel::dingtalk::DingTalkChannel;
 use crate::channel::discord::DiscordChannel;
+use crate::channel::email::EmailChannel;
+use crate::channel::feishu::FeishuChannel;
+use crate::channel::health::HealthBoard;
+use crate::channel::line::LineChannel;
+use crate::channel::maixcam::MaixCamChannel;
+use crate::channel::onebot::OneBotChannel;
+use crate::channel::outbox::{Delivery, Outbox};
+use crate::channel::relay::OutboundRelay;
 use crate::channel::slack::SlackChannel;
+use crate::channel::supervisor::Supervisor;
 use crate::channel::telegram::TelegramChannel;
+use crate::channel::webhook::WebhookChannel;
+use crate::channel::whatsapp::WhatsAppChannel;
 use crate::channel::Channel;
 use crate::config::Config;
+use crate::scheduler::{JobStore, Scheduler};
+use crate::tool::hot_reload::PluginWatcher;
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Duration;
 
 pub struct Gateway {
     config: Config,
     agent: Arc<AgentLoop>,
     bus: Arc<MessageBus>,
     channels: Vec<Arc<dyn Channel>>,
+    health: HealthBoard,
+    shutdown: watch::Sender<bool>,
 }
 
 impl Gateway {
@@ -53,46 +70,106 @@ impl Gateway {
             channels.push(Arc::new(ch));
         }
 
+        // Initialize WhatsApp (bridge) if enabled
+        if config.channels.whatsapp.enabled {
+            let ch = WhatsAppChannel::new(&config.channels.whatsapp, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize LINE if enabled
+        if config.channels.line.enabled {
+            let ch = LineChannel::new(&config.channels.line, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize Feishu/Lark if enabled
+        if config.channels.feishu.enabled {
+            let ch = FeishuChannel::new(&config.channels.feishu, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize DingTalk if enabled
+        if config.channels.dingtalk.enabled {
+            let ch = DingTalkChannel::new(&config.channels.dingtalk, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize OneBot (QQ) if enabled
+        if config.channels.onebot.enabled {
+            let ch = OneBotChannel::new(&config.channels.onebot, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize MaixCam devices if enabled
+        if config.channels.maixcam.enabled {
+            let media_dir = config
+                .workspace_path()
+                .map(|p| p.join("media").join("maixcam"))
+                .unwrap_or_else(|_| std::env::temp_dir().join("quectoclaw-maixcam"));
+            let ch = MaixCamChannel::new(&config.channels.maixcam, media_dir, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize email if enabled
+        if config.channels.email.enabled {
+            let workspace = config
+                .workspace_path()
+                .unwrap_or_else(|_| std::env::temp_dir().join("quectoclaw-email"));
+            let ch = EmailChannel::new(&config.channels.email, workspace, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
+        // Initialize generic webhook if enabled
+        if config.channels.webhook.enabled {
+            let ch = WebhookChannel::new(&config.channels.webhook, bus.clone());
+            channels.push(Arc::new(ch));
+        }
+
         Self {
             config,
             agent,
             bus,
             channels,
+            health: HealthBoard::new(),
+            shutdown: watch::channel(false).0,
         }
     }
 
+    /// Ask `run` to stop the channels and return, as Ctrl-C does.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
     pub async fn run(&self) -> anyhow::Result<()> {
         let mut set = tokio::task::JoinSet::new();
 
         tracing::info!("Gateway starting with {} channels", self.channels.len());
 
-        // 1. Start all channels
-        for channel in &self.channels {
-            let ch = channel.clone();
-            let name = ch.name().to_string();
+        // Replies that fail to send wait here for another try
+        let outbox = Arc::new(Outbox::new(std::path::Path::new(self.agent.workspace())));
 
-            // Register a handler in the bus for this channel
-            let (tx, mut rx) = mpsc::channel::<OutboundMessage>(100);
-            self.bus.register_handler(&name, tx).await;
-
-            // Spawn the channel's background task
-            if let Err(e) = ch.start().await {
-                tracing::error!(channel = %name, "Failed to start channel: {}", e);
-                continue;
-            }
-
-            // Spawn a task to bridge bus outbound -> channel send
-            let ch_inner = ch.clone();
-            set.spawn(async move {
-                while let Some(msg) = rx.recv().await {
-                    if let Err(e) = ch_inner.send(msg).await {
-                        tracing::error!(channel = %name, "Failed to send message: {}", e);
-                    }
-                }
-                tracing::info!(channel = %name, "Outbound bridge stopped");
-            });
+        // 1. Bridge bus outbound -> channel send, for every channel; the
+        // outbox holds replies while one is down
+        for channel in &self.channels {
+            let (tx, rx) = mpsc::channel::<OutboundMessage>(100);
+            self.bus.register_handler(channel.name(), tx).await;
+
+            let delivery = Delivery::new(
+                OutboundRelay::new(channel.clone()),
+                outbox.clone(),
+                &self.config.channels.outbox,
+                self.agent.metrics().clone(),
+            )
+            .with_health(self.health.clone());
+            set.spawn(delivery.run(rx));
         }
 
+        // Start the channels and restart any that fail or drop out
+        let supervisor =
+            Supervisor::new(self.channels.clone(), self.bus.clone(), self.health.clone())
+                .with_snapshot(std::path::PathBuf::from(self.agent.workspace()));
+        let supervised = tokio::spawn(supervisor.run(self.shutdown.subscribe()));
+
         // 2. Start the Agent Loop
         let agent = self.agent.clone();
         set.spawn(async move {
@@ -101,54 +178,74 @@ impl Gateway {
             }
         });
 
-        // 3. Start Heartbeat Service
+        // 3. Watch plugin directories for changes
+        if self.config.plugins.hot_reload {
+            let workspace = std::path::Path::new(self.agent.workspace());
+            #[allow(unused_mut)]
+            let mut watcher =
+                PluginWatcher::new(self.agent.tools().clone(), workspace.join("plugins"));
+            #[cfg(feature = "wasm")]
+            if self.config.wasm.enabled {
+                watcher = watcher.with_wasm_dir(workspace.join("wasm_plugins"));
+            }
+            let interval = Duration::from_secs(self.config.plugins.reload_interval.max(1));
+            set.spawn(watcher.run(interval));
+        }
+
+        // 4. Run scheduled tasks
+        if self.config.scheduler.enabled {
+            let jobs = JobStore::shared(std::path::Path::new(self.agent.workspace()));
+            let scheduler = Scheduler::new(jobs, self.agent.clone(), self.bus.clone());
+            let tick = Duration::from_secs(self.config.scheduler.tick_interval.max(1));
+            set.spawn(scheduler.run(tick));
+        }
+
+        // 5. Start Heartbeat Service
         if self.config.heartbeat.enabled {
-            let agent = self.agent.clone();
-            let bus = self.bus.clone();
-            let interval_secs = self.config.heartbeat.interval;
-            set.spawn(async move {
-                heartbeat_service(agent, bus, interval_secs).await;
-            });
-        }
-
-        // Wait for all tasks (though they should run indefinitely)
-        while let Some(res) = set.join_next().await {
-            match res {
-                Ok(_) => tracing::info!("Task completed"),
-                Err(e) => tracing::error!("Task failed: {}", e),
+            let heartbeat = HeartbeatService::new(
+                self.agent.clone(),
+                self.bus.clone(),
+                self.config.heartbeat.clone(),
+            );
+            set.spawn(heartbeat.run());
+        }
+
+        // Run until Ctrl-C, SIGTERM or `shutdown`
+        let mut stop = self.shutdown.subscribe();
+        loop {
+            tokio::select! {
+                _ = shutdown_signal() => break,
+                _ = stop.wait_for(|stop| *stop) => break,
+                Some(res) = set.join_next() => match res {
+                    Ok(_) => tracing::info!("Task completed"),
+                    Err(e) => tracing::error!("Task failed: {}", e),
+                },
             }
         }
 
+        tracing::info!("Gateway shutting down");
+        self.shutdown();
+        if let Err(e) = supervised.await {
+            tracing::error!("Channel supervisor failed: {}", e);
+        }
+        set.shutdown().await;
+
         Ok(())
     }
 }
 
-/// Heartbeat service: periodically checks HEARTBEAT.md and runs tasks.
-async fn heartbeat_service(agent: Arc<AgentLoop>, _bus: Arc<MessageBus>, interval_mins: u64) {
-    let mut ticker = interval(Duration::from_secs(interval_mins * 60));
-    tracing::info!(interval_mins = interval_mins, "Heartbeat service started");
-
-    loop {
-        ticker.tick().await;
-        tracing::debug!("Heartbeat tick");
-
-        let workspace = agent.workspace();
-        let path = std::path::Path::new(workspace).join("HEARTBEAT.md");
-
-        if path.exists() {
-            if let Ok(content) = tokio::fs::read_to_string(&path).await {
-                let trimmed = content.trim();
-                if !trimmed.is_empty() {
-                    tracing::info!("Heartbeat: found tasks in HEARTBEAT.md");
-                    // System-level one-shot processing (no context usually)
-                    let session_key = "system:heartbeat";
-                    let prompt = format!("Periodic check of HEARTBEAT.md. Current tasks:\n{}\n\nPerform any pending actions.", trimmed);
-
-                    if let Err(e) = agent.process_direct(&prompt, session_key).await {
-                        tracing::error!("Heartbeat processing failed: {}", e);
-                    }
-                }
+/// Ctrl-C, or SIGTERM from systemd or Docker.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
             }
+            return;
         }
     }
+    let _ = tokio::signal::ctrl_c().await;
 }
@@ -0,0 +1,146 @@
+// QuectoClaw — Group-chat rules: trigger, session scope and sender names
+
+use crate::bus::InboundMessage;
+use crate::config::{GroupsConfig, SessionScope};
+
+/// Apply the trigger rules and session scope to an inbound message.
+/// Returns `None` for a group message not meant for the bot. A matched
+/// prefix is stripped; direct messages pass unchanged.
+pub fn route(config: &GroupsConfig, mut msg: InboundMessage) -> Option<InboundMessage> {
+    let Some(group) = &msg.group else {
+        return Some(msg);
+    };
+
+    if !config.all {
+        let text = msg.content.trim_start();
+        let prefix = config
+            .prefixes
+            .iter()
+            .find(|p| !p.is_empty() && text.starts_with(p.as_str()));
+        if let Some(prefix) = prefix {
+            msg.content = text[prefix.len()..].trim_start().to_string();
+        } else if !(config.mention && group.mentioned || config.reply && group.reply_to_bot) {
+            return None;
+        }
+    }
+
+    match config.session_scope {
+        SessionScope::Chat => {}
+        SessionScope::Thread => {
+            if let Some(thread) = &group.thread_id {
+                msg.session_key = format!("{}#{}", msg.session_key, thread);
+            }
+        }
+        SessionScope::User => {
+            msg.session_key = format!("{}@{}", msg.session_key, sender_id(&msg.sender_id));
+        }
+    }
+    Some(msg)
+}
+
+/// The text the model sees: in groups, prefixed with the sender's name
+/// (falling back to their id) when `sender_names` is on.
+pub fn speaker_text(config: &GroupsConfig, msg: &InboundMessage, content: &str) -> String {
+    match &msg.group {
+        Some(group) if config.sender_names => {
+            let name = group
+                .sender_name
+                .as_deref()
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or_else(|| sender_id(&msg.sender_id));
+            format!("[{}] {}", name, content)
+        }
+        _ => content.to_string(),
+    }
+}
+
+/// The stable part of a compound `id|username` sender.
+fn sender_id(sender: &str) -> &str {
+    sender.split('|').next().unwrap_or(sender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::GroupContext;
+    use std::collections::HashMap;
+
+    fn message(content: &str, group: Option<GroupContext>) -> InboundMessage {
+        InboundMessage {
+            channel: "telegram".into(),
+            sender_id: "7|alice".into(),
+            chat_id: "-100".into(),
+            content: content.into(),
+            media: vec![],
+            session_key: "telegram:-100".into(),
+            metadata: HashMap::new(),
+            group,
+        }
+    }
+
+    fn group(mentioned: bool, reply_to_bot: bool) -> Option<GroupContext> {
+        Some(GroupContext {
+            mentioned,
+            reply_to_bot,
+            thread_id: Some("55".into()),
+            sender_name: Some("Alice".into()),
+        })
+    }
+
+    #[test]
+    fn test_triggers() {
+        let config = GroupsConfig {
+            prefixes: vec!["!ai".into()],
+            ..Default::default()
+        };
+        assert!(route(&config, message("hi all", group(false, false))).is_none());
+        assert!(route(&config, message("hi bot", group(true, false))).is_some());
+        assert!(route(&config, message("and?", group(false, true))).is_some());
+        let prefixed = route(&config, message("!ai  weather?", group(false, false))).unwrap();
+        assert_eq!(prefixed.content, "weather?");
+        // Direct chats need no trigger
+        assert!(route(&config, message("hi", None)).is_some());
+
+        let everything = GroupsConfig {
+            all: true,
+            ..Default::default()
+        };
+        assert!(route(&everything, message("hi all", group(false, false))).is_some());
+        let mention_only = GroupsConfig {
+            reply: false,
+            ..Default::default()
+        };
+        assert!(route(&mention_only, message("and?", group(false, true))).is_none());
+    }
+
+    #[test]
+    fn test_session_scope() {
+        let scoped = |scope| {
+            let config = GroupsConfig {
+                session_scope: scope,
+                ..Default::default()
+            };
+            route(&config, message("hi", group(true, false)))
+                .unwrap()
+                .session_key
+        };
+        assert_eq!(scoped(SessionScope::Chat), "telegram:-100");
+        assert_eq!(scoped(SessionScope::Thread), "telegram:-100#55");
+        assert_eq!(scoped(SessionScope::User), "telegram:-100@7");
+    }
+
+    #[test]
+    fn test_speaker_text() {
+        let config = GroupsConfig::default();
+        let msg = message("hi", group(true, false));
+        assert_eq!(speaker_text(&config, &msg, "hi"), "[Alice] hi");
+        let unnamed = message("hi", Some(GroupContext::default()));
+        assert_eq!(speaker_text(&config, &unnamed, "hi"), "[7] hi");
+        assert_eq!(speaker_text(&config, &message("hi", None), "hi"), "hi");
+        let off = GroupsConfig {
+            sender_names: false,
+            ..Default::default()
+        };
+        assert_eq!(speaker_text(&off, &msg, "hi"), "hi");
+    }
+}
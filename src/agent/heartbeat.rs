@@ -0,0 +1,297 @@
+// QuectoClaw — Heartbeat service (periodic HEARTBEAT.md checks with per-item state)
+
+use crate::agent::AgentLoop;
+use crate::bus::{MessageBus, OutboundKind, OutboundMessage};
+use crate::config::HeartbeatConfig;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Reply the model gives when nothing needs the user's attention; never delivered.
+pub const HEARTBEAT_OK: &str = "HEARTBEAT_OK";
+
+/// A checklist item in HEARTBEAT.md: `- [ ] text [@YYYY-MM-DD[ HH:MM]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatItem {
+    pub text: String,
+    pub done: bool,
+    /// Not due before this wall-clock time (in the heartbeat timezone).
+    pub due: Option<NaiveDateTime>,
+}
+
+impl HeartbeatItem {
+    pub fn is_due(&self, now: NaiveDateTime) -> bool {
+        !self.done && self.due.is_none_or(|d| d <= now)
+    }
+}
+
+/// Split a line into (checked, item text) if it is a checklist item.
+fn checkbox(line: &str) -> Option<(bool, &str)> {
+    let rest = line
+        .trim_start()
+        .strip_prefix("- ")
+        .or_else(|| line.trim_start().strip_prefix("* "))?;
+    if let Some(text) = rest.strip_prefix("[ ] ") {
+        Some((false, text.trim()))
+    } else {
+        rest.strip_prefix("[x] ")
+            .or_else(|| rest.strip_prefix("[X] "))
+            .map(|text| (true, text.trim()))
+    }
+}
+
+fn parse_due(text: &str) -> Option<NaiveDateTime> {
+    let (_, when) = text.rsplit_once(" @")?;
+    let when = when.trim();
+    NaiveDateTime::parse_from_str(when, "%Y-%m-%d %H:%M")
+        .ok()
+        .or_else(|| {
+            chrono::NaiveDate::parse_from_str(when, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Lines of the file outside HTML comments.
+fn visible_lines(content: &str) -> impl Iterator<Item = (bool, &str)> {
+    let mut in_comment = false;
+    content.lines().map(move |line| {
+        let t = line.trim();
+        if in_comment || t.starts_with("<!--") {
+            in_comment = !t.contains("-->");
+            return (false, line);
+        }
+        (true, line)
+    })
+}
+
+/// Parse the checklist items of HEARTBEAT.md.
+pub fn parse_items(content: &str) -> Vec<HeartbeatItem> {
+    visible_lines(content)
+        .filter(|(visible, _)| *visible)
+        .filter_map(|(_, line)| checkbox(line))
+        .map(|(done, text)| HeartbeatItem {
+            text: text.to_string(),
+            done,
+            due: parse_due(text),
+        })
+        .collect()
+}
+
+/// Tick the checkboxes of the given (unchecked) items.
+pub fn mark_done(content: &str, texts: &HashSet<String>) -> String {
+    let mut out: Vec<String> = visible_lines(content)
+        .map(|(visible, line)| match checkbox(line) {
+            Some((false, text)) if visible && texts.contains(text) => {
+                line.replacen("[ ]", "[x]", 1)
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+    if content.ends_with('\n') {
+        out.push(String::new());
+    }
+    out.join("\n")
+}
+
+/// Whether the file has anything besides headings and HTML comments.
+fn has_content(content: &str) -> bool {
+    visible_lines(content).any(|(visible, line)| {
+        let t = line.trim();
+        visible && !t.is_empty() && !t.starts_with('#')
+    })
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut h);
+    h.finish()
+}
+
+/// Periodically checks HEARTBEAT.md and runs the items that are due.
+///
+/// The LLM is only called when the file changed since the last run or an
+/// unchecked item is due; run items are ticked off afterwards, unless the
+/// reply was `HEARTBEAT_OK` (nothing was done, so they stay due).
+pub struct HeartbeatService {
+    agent: Arc<AgentLoop>,
+    bus: Arc<MessageBus>,
+    config: HeartbeatConfig,
+    path: PathBuf,
+    /// Hash of the file content after the last run.
+    last_seen: Option<u64>,
+}
+
+impl HeartbeatService {
+    pub fn new(agent: Arc<AgentLoop>, bus: Arc<MessageBus>, config: HeartbeatConfig) -> Self {
+        let path = std::path::Path::new(agent.workspace()).join("HEARTBEAT.md");
+        Self {
+            agent,
+            bus,
+            config,
+            path,
+            last_seen: None,
+        }
+    }
+
+    /// Wall-clock time in the configured timezone.
+    fn local_now(&self, now: DateTime<Utc>) -> NaiveDateTime {
+        match self.config.tz() {
+            Ok(Some(tz)) => now.with_timezone(&tz).naive_local(),
+            _ => now.with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+
+    /// Run one heartbeat check. Returns the agent's reply if the LLM was called.
+    pub async fn tick(&mut self, now: DateTime<Utc>) -> Option<String> {
+        let local = self.local_now(now);
+        if self.config.is_quiet_at(local.time()) {
+            tracing::debug!("Heartbeat: quiet hours, skipping");
+            return None;
+        }
+
+        let content = tokio::fs::read_to_string(&self.path).await.ok()?;
+        if !has_content(&content) {
+            return None;
+        }
+        let trimmed = content.trim();
+
+        let hash = content_hash(&content);
+        let items = parse_items(&content);
+        let due: Vec<&HeartbeatItem> = items.iter().filter(|i| i.is_due(local)).collect();
+        if self.last_seen == Some(hash) && due.is_empty() {
+            tracing::debug!("Heartbeat: nothing due, skipping LLM call");
+            return None;
+        }
+
+        tracing::info!(due = due.len(), "Heartbeat: running HEARTBEAT.md");
+        let mut prompt = format!(
+            "Periodic check of HEARTBEAT.md (current time {}).\n\n{}\n\n",
+            local.format("%Y-%m-%d %H:%M"),
+            trimmed
+        );
+        if !due.is_empty() {
+            prompt.push_str("Items due now:\n");
+            for item in &due {
+                prompt.push_str(&format!("- {}\n", item.text));
+            }
+            prompt.push('\n');
+        }
+        prompt.push_str(&format!(
+            "Perform any pending actions. If nothing needs the user's attention, reply with exactly {}.",
+            HEARTBEAT_OK
+        ));
+
+        let response = match self.agent.process_direct(&prompt, "system:heartbeat").await {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::error!("Heartbeat processing failed: {}", e);
+                return None;
+            }
+        };
+
+        // Tick off what ran. Re-read: the agent may have edited the file itself.
+        let done: HashSet<String> = if response.trim() == HEARTBEAT_OK {
+            HashSet::new()
+        } else {
+            due.iter().map(|i| i.text.clone()).collect()
+        };
+        let mut latest = tokio::fs::read_to_string(&self.path)
+            .await
+            .unwrap_or(content);
+        if !done.is_empty() {
+            latest = mark_done(&latest, &done);
+            if let Err(e) = tokio::fs::write(&self.path, &latest).await {
+                tracing::warn!("Heartbeat: failed to update HEARTBEAT.md: {}", e);
+            }
+        }
+        self.last_seen = Some(content_hash(&latest));
+
+        self.deliver(&response).await;
+        Some(response)
+    }
+
+    async fn deliver(&self, response: &str) {
+        let reply = response.trim();
+        if reply.is_empty() || reply == HEARTBEAT_OK {
+            return;
+        }
+        if self.config.channel.is_empty() || self.config.chat_id.is_empty() {
+            tracing::info!(response = %reply, "Heartbeat result (no channel configured)");
+            return;
+        }
+        let mut metadata = HashMap::new();
+        metadata.insert("source".to_string(), "heartbeat".to_string());
+        self.bus
+            .publish_outbound(OutboundMessage {
+                channel: self.config.channel.clone(),
+                chat_id: self.config.chat_id.clone(),
+                content: reply.to_string(),
+                metadata,
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await;
+    }
+
+    /// Check every `interval` minutes forever.
+    pub async fn run(mut self) {
+        let mins = self.config.interval.max(1);
+        tracing::info!(
+            interval_mins = mins,
+            channel = %self.config.channel,
+            "Heartbeat service started"
+        );
+        let mut ticker = tokio::time::interval(Duration::from_secs(mins * 60));
+        loop {
+            ticker.tick().await;
+            tracing::debug!("Heartbeat tick");
+            self.tick(Utc::now()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse_items() {
+        let md = "# Tasks\nSome notes\n- [ ] Water plants\n- [x] Pay rent\n* [ ] Call mum @2030-05-01 18:00\n- [ ] Renew passport @2030-06-01\n- plain bullet\n";
+        let items = parse_items(md);
+        assert_eq!(items.len(), 4);
+        assert!(!items[0].done && items[0].due.is_none());
+        assert!(items[1].done);
+        assert_eq!(items[2].due, Some(at("2030-05-01 18:00")));
+        assert_eq!(items[3].due, Some(at("2030-06-01 00:00")));
+
+        let now = at("2030-05-01 12:00");
+        let due: Vec<_> = items.iter().filter(|i| i.is_due(now)).collect();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].text, "Water plants");
+        assert!(items[2].is_due(at("2030-05-01 18:00")));
+    }
+
+    #[test]
+    fn test_has_content() {
+        assert!(!has_content("# Heartbeat\n\n<!-- add\nitems -->\n"));
+        assert!(has_content("# Heartbeat\nCheck the server\n"));
+        assert!(has_content("<!-- note -->\n- [ ] a\n"));
+        assert!(parse_items("<!--\n- [ ] example\n-->\n").is_empty());
+    }
+
+    #[test]
+    fn test_mark_done() {
+        let md = "- [ ] a\n- [ ] b\n  - [x] c\n";
+        let done: HashSet<String> = ["b".to_string()].into();
+        assert_eq!(mark_done(md, &done), "- [ ] a\n- [x] b\n  - [x] c\n");
+        assert_eq!(mark_done("- [ ] b", &done), "- [x] b");
+    }
+}
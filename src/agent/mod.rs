@@ -1,21 +1,47 @@
 // QuectoClaw — Agent loop (core orchestrator)
 
+pub mod commands;
 pub mod context;
 pub mod gateway;
+pub mod groups;
+pub mod heartbeat;
 pub mod memory;
+pub mod reply;
 
-use crate::bus::{InboundMessage, MessageBus, OutboundMessage};
-use crate::config::Config;
+use crate::agent::reply::ReplyStream;
+use crate::bus::{ChatTarget, InboundMessage, MessageBus, OutboundKind, OutboundMessage};
+use crate::config::{Config, ModelPricing, ToolProfile};
+use crate::mcp::sampling::{SamplingHandler, SamplingRequest, SamplingResult};
 use crate::metrics::Metrics;
 use crate::provider::router::ModelRouter;
 use crate::provider::{LLMProvider, Message, ToolCall};
 use crate::session::SessionManager;
-use crate::tool::ToolRegistry;
+use crate::tool::{ToolContext, ToolProgress, ToolRegistry};
 use crate::tui::app::{TuiEvent, TuiState};
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 
+/// Per-run overrides for the agent loop. The defaults reproduce a normal
+/// top-level turn: tools from the session's profile, routed model.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Tool view to expose; `None` resolves the profile from the session key.
+    pub tools: Option<ToolRegistry>,
+    /// Model override (skips multi-model routing).
+    pub model: Option<String>,
+    /// Extra instructions appended to the system prompt.
+    pub instructions: Option<String>,
+    /// Overrides `agents.defaults.max_tool_iterations`.
+    pub max_iterations: Option<usize>,
+    /// Subagent nesting depth of this run.
+    pub depth: u32,
+    /// Session that token usage and cost are attributed to (defaults to the run's own).
+    pub bill_to: Option<String>,
+    /// Chat the run answers, for tools that send to it (channel turns).
+    pub chat: Option<ChatTarget>,
+}
+
 struct InternalToolResult {
     tool_call_id: String,
     tool_name: String,
@@ -36,6 +62,23 @@ pub struct AgentLoop {
     rate_limiter: Arc<RateLimiter>,
     router: ModelRouter,
     audit_logger: Arc<crate::audit::AuditLogger>,
+    /// Models chosen with `/model`, by session.
+    session_models: tokio::sync::RwLock<HashMap<String, String>>,
+    /// Channel turns by session (gateway mode).
+    turns: std::sync::Mutex<HashMap<String, SessionTurns>>,
+}
+
+/// A session's turns: they run one at a time, and `/stop` aborts them.
+#[derive(Default)]
+struct SessionTurns {
+    lock: Arc<tokio::sync::Mutex<()>>,
+    running: Vec<Turn>,
+}
+
+struct Turn {
+    handle: tokio::task::AbortHandle,
+    /// Published if the turn is stopped, for a request waiting on its reply.
+    on_stop: Option<OutboundMessage>,
 }
 
 struct RateLimiter {
@@ -119,6 +162,8 @@ impl AgentLoop {
             rate_limiter: Arc::new(RateLimiter::new(rate_limit_requests, rate_limit_seconds)),
             router,
             audit_logger,
+            session_models: tokio::sync::RwLock::new(HashMap::new()),
+            turns: std::sync::Mutex::new(HashMap::new()),
         }
     }
 
@@ -143,11 +188,54 @@ impl AgentLoop {
             .await
     }
 
-    /// Process an inbound message from a channel.
+    /// Process an inbound message from a channel. Typing indicators and,
+    /// if configured, partial text and tool activity are published while
+    /// the turn runs (see `channels.streaming`).
     pub async fn process_message(&self, msg: InboundMessage) -> anyhow::Result<String> {
-        let response = self
-            .run_agent_loop(&msg.content, &msg.session_key, true, None)
-            .await?;
+        let progress = ReplyStream::start(
+            self.bus.clone(),
+            &msg.channel,
+            &msg.chat_id,
+            &self.config.channels.streaming,
+        );
+        let opts = RunOptions {
+            chat: Some(ChatTarget {
+                channel: msg.channel.clone(),
+                chat_id: msg.chat_id.clone(),
+            }),
+            ..Default::default()
+        };
+        let result = match self.expand_input(&msg.content, &msg.session_key).await {
+            Ok(content) => {
+                let content = groups::speaker_text(&self.config.channels.groups, &msg, &content);
+                self.run_agent_loop_with(&content, &msg.session_key, true, progress.events(), opts)
+                    .await
+            }
+            Err(e) => Ok(format!("⚠️ {}", e)),
+        };
+        let kind = progress.finish().await;
+
+        let metadata = msg.reply_metadata();
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                // Don't leave partial text standing as if it were the answer,
+                // nor a waiting request without one
+                if kind != OutboundKind::Message || !metadata.is_empty() {
+                    self.bus
+                        .publish_outbound(OutboundMessage {
+                            channel: msg.channel,
+                            chat_id: msg.chat_id,
+                            content: format!("⚠️ {}", e),
+                            metadata,
+                            kind,
+                            attachments: Vec::new(),
+                        })
+                        .await;
+                }
+                return Err(e);
+            }
+        };
 
         // Send response back via bus
         self.bus
@@ -155,14 +243,71 @@ impl AgentLoop {
                 channel: msg.channel,
                 chat_id: msg.chat_id,
                 content: response.clone(),
-                metadata: HashMap::new(),
+                metadata,
+                kind,
+                attachments: Vec::new(),
             })
             .await;
 
         Ok(response)
     }
 
+    /// Process a direct message with explicit run options (used by subagents).
+    pub async fn process_direct_with(
+        &self,
+        content: &str,
+        session_key: &str,
+        opts: RunOptions,
+    ) -> anyhow::Result<String> {
+        self.run_agent_loop_with(content, session_key, true, None, opts)
+            .await
+    }
+
+    /// Expand user input from MCP servers: a `/server:prompt args` command
+    /// becomes the prompt's text, and `@server:uri` mentions are inlined.
+    /// Errors (unknown arguments, failed `prompts/get`) are meant for the user.
+    pub async fn expand_input(&self, content: &str, session_key: &str) -> anyhow::Result<String> {
+        let Some(mcp) = self.tools.get_mcp().await else {
+            return Ok(content.to_string());
+        };
+        let tools = self.tools_for_profile(self.config.tools.profile_for_session(session_key));
+        let content = match mcp.expand_command(content, &tools).await {
+            Some(expanded) => expanded?,
+            None => content.to_string(),
+        };
+        Ok(mcp.inline_mentions(&content, &tools).await)
+    }
+
+    /// Prompts offered by MCP servers that `session_key`'s tool profile
+    /// permits, usable as slash commands.
+    pub async fn mcp_prompts(&self, session_key: &str) -> Vec<crate::mcp::MCPPrompt> {
+        let Some(mcp) = self.tools.get_mcp().await else {
+            return Vec::new();
+        };
+        let tools = self.tools_for_profile(self.config.tools.profile_for_session(session_key));
+        mcp.prompts()
+            .await
+            .into_iter()
+            .filter(|p| tools.is_permitted(&p.permission()))
+            .collect()
+    }
+
+    /// Registry view for a tool profile. Unknown profile names deny every tool.
+    pub fn tools_for_profile(&self, profile: Option<&str>) -> ToolRegistry {
+        let Some(name) = profile else {
+            return self.tools.clone();
+        };
+        match self.config.tools.profiles.get(name) {
+            Some(p) => self.tools.with_profile(name, p.clone()),
+            None => {
+                tracing::warn!(profile = %name, "Unknown tool profile, denying all tools");
+                self.tools.with_profile(name, ToolProfile::deny_all())
+            }
+        }
+    }
+
     /// Core agent loop: build context → call LLM → execute tools → repeat.
+    /// The tool profile is resolved from the session key (see `tools.session_profiles`).
     pub async fn run_agent_loop(
         &self,
         user_message: &str,
@@ -170,9 +315,42 @@ impl AgentLoop {
         use_history: bool,
         stream_tx: Option<tokio::sync::mpsc::Sender<crate::provider::StreamEvent>>,
     ) -> anyhow::Result<String> {
-        let max_iterations = self.config.agents.defaults.max_tool_iterations;
-        // Use router to select model based on message content
-        let routed_model = self.router.resolve_model(user_message).to_string();
+        self.run_agent_loop_with(
+            user_message,
+            session_key,
+            use_history,
+            stream_tx,
+            RunOptions::default(),
+        )
+        .await
+    }
+
+    /// Core agent loop with explicit run options.
+    pub async fn run_agent_loop_with(
+        &self,
+        user_message: &str,
+        session_key: &str,
+        use_history: bool,
+        stream_tx: Option<tokio::sync::mpsc::Sender<crate::provider::StreamEvent>>,
+        opts: RunOptions,
+    ) -> anyhow::Result<String> {
+        let tools = match opts.tools {
+            Some(tools) => tools,
+            None => self.tools_for_profile(self.config.tools.profile_for_session(session_key)),
+        };
+        let bill_to = opts.bill_to.as_deref().unwrap_or(session_key);
+        let max_iterations = opts
+            .max_iterations
+            .unwrap_or(self.config.agents.defaults.max_tool_iterations);
+        // An explicit model wins, then the session's `/model`; otherwise
+        // route based on message content
+        let routed_model = match opts.model {
+            Some(m) => m,
+            None => match self.session_model(session_key).await {
+                Some(m) => m,
+                None => self.router.resolve_model(user_message).to_string(),
+            },
+        };
         let model = &routed_model;
         if self.router.has_routes() && *model != self.config.agents.defaults.model {
             tracing::info!(routed_model = %model, "Multi-model routing selected alternate model");
@@ -206,8 +384,12 @@ impl AgentLoop {
             }
         }
 
-        let system_prompt =
-            context::build_system_prompt(&self.workspace, &self.tools, &retrieved_context).await;
+        let mut system_prompt =
+            context::build_system_prompt(&self.workspace, &tools, &retrieved_context).await;
+        if let Some(instructions) = opts.instructions.as_deref().filter(|i| !i.is_empty()) {
+            system_prompt.push_str("\n\n## Specialist Instructions\n");
+            system_prompt.push_str(instructions);
+        }
 
         // 2. Build message history
         let mut messages = Vec::new();
@@ -244,7 +426,7 @@ impl AgentLoop {
         self.sessions.add_message(session_key, user_msg).await;
 
         // 3. Get tool definitions
-        let tool_defs = self.tools.get_definitions().await;
+        let tool_defs = tools.get_definitions().await;
 
         // 4. LLM iteration loop
         let mut options = HashMap::new();
@@ -265,6 +447,28 @@ impl AgentLoop {
             serde_json::Value::from(self.config.agents.defaults.retry_delay_ms),
         );
 
+        // Tool progress goes to the stream and the TUI while this turn runs
+        let progress_tx = if stream_tx.is_some() || self.tui_state.is_some() {
+            let (tx, mut rx) = tokio::sync::mpsc::channel::<ToolProgress>(32);
+            let stream = stream_tx.clone();
+            let tui = self.tui_state.clone();
+            tokio::spawn(async move {
+                while let Some(p) = rx.recv().await {
+                    if let Some(tui) = &tui {
+                        tui.handle_event(TuiEvent::ToolProgress(p.clone())).await;
+                    }
+                    if let Some(stream) = &stream {
+                        let _ = stream
+                            .send(crate::provider::StreamEvent::ToolProgress(p))
+                            .await;
+                    }
+                }
+            });
+            Some(tx)
+        } else {
+            None
+        };
+
         let mut final_content = String::new();
 
         for iteration in 0..max_iterations {
@@ -287,42 +491,33 @@ impl AgentLoop {
 
             // Call LLM (streaming on last iteration or when no tool calls expected)
             let response = if let Some(ref tx) = stream_tx {
-                // Use streaming — tokens will be sent via tx
-                let (done_tx, mut done_rx) = tokio::sync::mpsc::channel(256);
-                let tx_clone = tx.clone();
-
-                // Forward events and capture the Done response
-                let fwd_tx = done_tx.clone();
-                let provider = self.provider.clone();
-                let msgs = messages.clone();
-                let defs = tool_defs.clone();
-                let mdl = model.to_string();
-                let opts = options.clone();
-
-                tokio::spawn(async move {
-                    let (event_tx, mut event_rx) = tokio::sync::mpsc::channel(256);
-                    let _ = provider
-                        .chat_stream(&msgs, &defs, &mdl, &opts, event_tx)
-                        .await;
-
+                // Use streaming — tokens will be sent via tx. The call runs
+                // in this turn, so aborting the turn cancels it.
+                let (event_tx, mut event_rx) = tokio::sync::mpsc::channel(256);
+
+                // Forward events while the provider is still producing them
+                // (it blocks once the event queue is full), and capture the
+                // Done response or the provider's error
+                let forward = async {
+                    let mut done = None;
                     while let Some(event) = event_rx.recv().await {
-                        match &event {
-                            crate::provider::StreamEvent::Done(resp) => {
-                                let _ = fwd_tx.send(resp.clone()).await;
-                                let _ = tx_clone.send(event).await;
-                                break;
-                            }
-                            _ => {
-                                let _ = tx_clone.send(event).await;
-                            }
+                        if let crate::provider::StreamEvent::Done(resp) = &event {
+                            done = Some(resp.clone());
                         }
+                        let _ = tx.send(event).await;
                     }
-                });
-
-                done_rx
-                    .recv()
-                    .await
-                    .ok_or_else(|| anyhow::anyhow!("Stream ended without response"))?
+                    done
+                };
+                let (result, done) = tokio::join!(
+                    self.provider
+                        .chat_stream(&messages, &tool_defs, model, &options, event_tx),
+                    forward
+                );
+                match (result, done) {
+                    (_, Some(resp)) => resp,
+                    (Err(e), None) => return Err(e),
+                    (Ok(()), None) => anyhow::bail!("Stream ended without response"),
+                }
             } else {
                 self.provider
                     .chat(&messages, &tool_defs, model, &options)
@@ -339,64 +534,8 @@ impl AgentLoop {
                 .await;
             }
 
-            // Log usage and record metrics
-            if let Some(ref usage) = response.usage {
-                tracing::info!(
-                    prompt_tokens = usage.prompt_tokens,
-                    completion_tokens = usage.completion_tokens,
-                    total_tokens = usage.total_tokens,
-                    "Token usage"
-                );
-                self.metrics
-                    .record_llm_call(
-                        model,
-                        usage.prompt_tokens,
-                        usage.completion_tokens,
-                        llm_duration,
-                    )
-                    .await;
-
-                // Record cost if pricing is available
-                if self.config.cost.enabled {
-                    // Try exact match first, then prefix match
-                    let pricing = self.config.cost.pricing.get(model).or_else(|| {
-                        self.config
-                            .cost
-                            .pricing
-                            .iter()
-                            .find(|(k, _)| model.starts_with(k.as_str()))
-                            .map(|(_, v)| v)
-                    });
-                    if let Some(p) = pricing {
-                        self.metrics
-                            .record_cost(model, usage.prompt_tokens, usage.completion_tokens, p)
-                            .await;
-                        // Check budget alert
-                        if let Some(alert) = self
-                            .metrics
-                            .check_budget(
-                                self.config.cost.budget_limit,
-                                self.config.cost.alert_threshold,
-                            )
-                            .await
-                        {
-                            tracing::warn!(
-                                total_cost = alert.total_cost,
-                                budget_limit = alert.budget_limit,
-                                percentage = format!("{:.1}%", alert.percentage_used),
-                                "⚠️ Budget alert: {:.1}% of ${:.2} budget used (${:.4} spent)",
-                                alert.percentage_used,
-                                alert.budget_limit,
-                                alert.total_cost
-                            );
-                        }
-                    }
-                }
-            } else {
-                self.metrics
-                    .record_llm_call(model, 0, 0, llm_duration)
-                    .await;
-            }
+            self.record_usage(model, response.usage.as_ref(), llm_duration, bill_to)
+                .await;
 
             // If no tool calls, we're done
             if !response.has_tool_calls() {
@@ -446,7 +585,15 @@ impl AgentLoop {
             self.sessions.add_message(session_key, assistant_msg).await;
 
             // Execute tools (parallel when multiple)
-            let tool_results = self.execute_tools(&tool_calls).await;
+            let ctx = ToolContext {
+                session_key: session_key.to_string(),
+                tools: Some(tools.clone()),
+                depth: opts.depth,
+                bill_to: bill_to.to_string(),
+                progress: progress_tx.clone(),
+                chat: opts.chat.clone(),
+            };
+            let tool_results = self.execute_tools(&tools, &tool_calls, &ctx).await;
 
             // Add tool results as messages
             for (tc, result) in tool_calls.iter().zip(&tool_results) {
@@ -492,11 +639,15 @@ impl AgentLoop {
     }
 
     /// Execute tool calls in parallel using tokio::JoinSet.
-    async fn execute_tools(&self, tool_calls: &[ToolCall]) -> Vec<InternalToolResult> {
+    async fn execute_tools(
+        &self,
+        registry: &ToolRegistry,
+        tool_calls: &[ToolCall],
+        ctx: &ToolContext,
+    ) -> Vec<InternalToolResult> {
         use tokio::task::JoinSet;
 
         let mut set = JoinSet::new();
-        let registry = self.tools.clone();
         let metrics = self.metrics.clone();
 
         for tc in tool_calls {
@@ -505,11 +656,12 @@ impl AgentLoop {
             let reg = registry.clone();
             let m = metrics.clone();
             let id = tc.id.clone();
+            let ctx = ctx.clone();
 
             set.spawn(async move {
                 let start = std::time::Instant::now();
                 let args_converted: HashMap<String, serde_json::Value> = args;
-                let result = reg.execute(&name, args_converted).await;
+                let result = reg.execute_with_context(&name, args_converted, &ctx).await;
                 let duration = start.elapsed();
                 m.record_tool_call(&name, !result.is_error, duration).await;
 
@@ -605,10 +757,18 @@ impl AgentLoop {
         tracing::info!(workflow = %workflow.name, "Starting workflow execution");
 
         for step in workflow.steps {
-            tracing::info!(step = %step.name, "Executing workflow step");
+            tracing::info!(step = %step.name, profile = ?step.profile, "Executing workflow step");
             // Each step is processed as a direct message in the same session.
+            let profile = step
+                .profile
+                .as_deref()
+                .or_else(|| self.config.tools.profile_for_session(session_key));
+            let opts = RunOptions {
+                tools: Some(self.tools_for_profile(profile)),
+                ..Default::default()
+            };
             let _ = self
-                .run_agent_loop(&step.prompt, session_key, true, None)
+                .run_agent_loop_with(&step.prompt, session_key, true, None, opts)
                 .await?;
         }
 
@@ -617,12 +777,18 @@ impl AgentLoop {
     }
 
     /// Run the agent loop listening for inbound messages (gateway mode).
-    pub async fn run(&self) -> anyhow::Result<()> {
+    /// Slash commands are answered at once; other messages start a turn,
+    /// and each session's turns run one at a time in arrival order.
+    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
         tracing::info!("Agent loop started, waiting for messages...");
 
         loop {
             match self.bus.consume_inbound().await {
                 Some(msg) => {
+                    // Group messages not meant for the bot are ignored
+                    let Some(msg) = groups::route(&self.config.channels.groups, msg) else {
+                        continue;
+                    };
                     tracing::info!(
                         channel = %msg.channel,
                         sender = %msg.sender_id,
@@ -632,26 +798,41 @@ impl AgentLoop {
                     // Rate limiting
                     if !self.rate_limiter.check_rate_limit(&msg.sender_id).await {
                         tracing::warn!(sender = %msg.sender_id, "Rate limit exceeded");
+                        let metadata = msg.reply_metadata();
                         self.bus
                             .publish_outbound(OutboundMessage {
                                 channel: msg.channel,
                                 chat_id: msg.chat_id,
                                 content: "⚠️ Rate limit exceeded. Please wait a moment."
                                     .to_string(),
-                                metadata: HashMap::new(),
+                                metadata,
+                                kind: OutboundKind::Message,
+                                attachments: Vec::new(),
                             })
                             .await;
                         continue;
                     }
 
-                    match self.process_message(msg).await {
-                        Ok(response) => {
-                            tracing::debug!(response_len = response.len(), "Message processed");
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to process message: {}", e);
-                        }
+                    let admin = self.config.gateway.is_admin(&msg.channel, &msg.sender_id);
+                    if let Some(reply) = self
+                        .run_command(&msg.content, &msg.session_key, admin)
+                        .await
+                    {
+                        let metadata = msg.reply_metadata();
+                        self.bus
+                            .publish_outbound(OutboundMessage {
+                                channel: msg.channel,
+                                chat_id: msg.chat_id,
+                                content: reply,
+                                metadata,
+                                kind: OutboundKind::Message,
+                                attachments: Vec::new(),
+                            })
+                            .await;
+                        continue;
                     }
+
+                    self.spawn_turn(msg);
                 }
                 None => {
                     tracing::info!("Message bus closed, shutting down agent loop");
@@ -663,10 +844,212 @@ impl AgentLoop {
         Ok(())
     }
 
+    /// Process a channel message in the background, after the session's
+    /// earlier turns.
+    fn spawn_turn(self: &Arc<Self>, msg: InboundMessage) {
+        let this = self.clone();
+        let session_key = msg.session_key.clone();
+        let metadata = msg.reply_metadata();
+        let on_stop = (!metadata.is_empty()).then(|| OutboundMessage {
+            channel: msg.channel.clone(),
+            chat_id: msg.chat_id.clone(),
+            content: "⏹️ Stopped.".to_string(),
+            metadata,
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        });
+        self.queue_turn(&session_key, on_stop, async move {
+            match this.process_message(msg).await {
+                Ok(response) => {
+                    tracing::debug!(response_len = response.len(), "Message processed");
+                }
+                Err(e) => {
+                    tracing::error!("Failed to process message: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Run a prompt as a turn of `session_key`, after the session's channel
+    /// turns (scheduled tasks). `/stop` aborts it like any other turn.
+    pub async fn process_turn(
+        self: &Arc<Self>,
+        content: &str,
+        session_key: &str,
+    ) -> anyhow::Result<String> {
+        let this = self.clone();
+        let content = content.to_string();
+        let key = session_key.to_string();
+        self.queue_turn(session_key, None, async move {
+            this.process_direct(&content, &key).await
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("turn did not finish: {}", e))?
+    }
+
+    /// Spawn `work` as a turn of `session_key`; `on_stop` is published if
+    /// `/stop` aborts it.
+    fn queue_turn<T: Send + 'static>(
+        &self,
+        session_key: &str,
+        on_stop: Option<OutboundMessage>,
+        work: impl std::future::Future<Output = T> + Send + 'static,
+    ) -> tokio::task::JoinHandle<T> {
+        let mut turns = self.turns.lock().unwrap();
+        // Forget sessions whose turns have all finished
+        turns.retain(|_, session| {
+            session.running.retain(|turn| !turn.handle.is_finished());
+            !session.running.is_empty()
+        });
+        let session = turns.entry(session_key.to_string()).or_default();
+        let lock = session.lock.clone();
+        let handle = tokio::spawn(async move {
+            // The mutex is fair, so waiting turns keep their order
+            let _turn = lock.lock().await;
+            work.await
+        });
+        session.running.push(Turn {
+            handle: handle.abort_handle(),
+            on_stop,
+        });
+        handle
+    }
+
+    /// Whether a session has a turn running or waiting.
+    pub fn has_turns(&self, session_key: &str) -> bool {
+        self.turns
+            .lock()
+            .unwrap()
+            .get(session_key)
+            .is_some_and(|session| {
+                session
+                    .running
+                    .iter()
+                    .any(|turn| !turn.handle.is_finished())
+            })
+    }
+
+    /// Abort a session's running and waiting turns; returns how many.
+    pub async fn stop_turns(&self, session_key: &str) -> usize {
+        let stopped: Vec<Turn> = {
+            let mut turns = self.turns.lock().unwrap();
+            let Some(session) = turns.get_mut(session_key) else {
+                return 0;
+            };
+            session
+                .running
+                .drain(..)
+                .filter(|turn| !turn.handle.is_finished())
+                .collect()
+        };
+        for turn in &stopped {
+            turn.handle.abort();
+        }
+        // Requests waiting on a stopped turn still get an answer
+        for reply in stopped.iter().filter_map(|turn| turn.on_stop.clone()) {
+            self.bus.publish_outbound(reply).await;
+        }
+        stopped.len()
+    }
+
+    /// The model chosen for a session with `/model`, if any.
+    pub async fn session_model(&self, session_key: &str) -> Option<String> {
+        self.session_models.read().await.get(session_key).cloned()
+    }
+
     pub fn workspace(&self) -> &str {
         &self.workspace
     }
 
+    /// The shared (unrestricted) tool registry.
+    pub fn tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+
+    /// Get the loaded configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Log an LLM call's usage and record it in the metrics: tokens, cost
+    /// (when pricing is known) and the session it is billed to.
+    async fn record_usage(
+        &self,
+        model: &str,
+        usage: Option<&crate::provider::UsageInfo>,
+        duration: std::time::Duration,
+        bill_to: &str,
+    ) {
+        let Some(usage) = usage else {
+            self.metrics.record_llm_call(model, 0, 0, duration).await;
+            return;
+        };
+        tracing::info!(
+            prompt_tokens = usage.prompt_tokens,
+            completion_tokens = usage.completion_tokens,
+            total_tokens = usage.total_tokens,
+            "Token usage"
+        );
+        self.metrics
+            .record_llm_call(
+                model,
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                duration,
+            )
+            .await;
+
+        // Record cost if pricing is available
+        let mut call_cost = 0.0;
+        if self.config.cost.enabled {
+            if let Some(p) = self.pricing_for(model) {
+                call_cost = self
+                    .metrics
+                    .record_cost(model, usage.prompt_tokens, usage.completion_tokens, p)
+                    .await;
+                // Check budget alert
+                if let Some(alert) = self
+                    .metrics
+                    .check_budget(
+                        self.config.cost.budget_limit,
+                        self.config.cost.alert_threshold,
+                    )
+                    .await
+                {
+                    tracing::warn!(
+                        total_cost = alert.total_cost,
+                        budget_limit = alert.budget_limit,
+                        percentage = format!("{:.1}%", alert.percentage_used),
+                        "⚠️ Budget alert: {:.1}% of ${:.2} budget used (${:.4} spent)",
+                        alert.percentage_used,
+                        alert.budget_limit,
+                        alert.total_cost
+                    );
+                }
+            }
+        }
+        self.metrics
+            .record_session_usage(
+                bill_to,
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                call_cost,
+            )
+            .await;
+    }
+
+    /// Pricing for a model: exact match first, then prefix match.
+    fn pricing_for(&self, model: &str) -> Option<&ModelPricing> {
+        self.config.cost.pricing.get(model).or_else(|| {
+            self.config
+                .cost
+                .pricing
+                .iter()
+                .find(|(k, _)| model.starts_with(k.as_str()))
+                .map(|(_, v)| v)
+        })
+    }
+
     /// Fork a session into a new session key.
     pub async fn fork_session(&self, source_key: &str, target_key: &str) -> bool {
         self.sessions.fork_session(source_key, target_key).await
@@ -676,4 +1059,80 @@ impl AgentLoop {
     pub fn metrics(&self) -> &Metrics {
         &self.metrics
     }
+
+    /// Let the configured MCP servers sample through this agent's provider
+    /// (subject to each server's `sampling` policy).
+    pub async fn attach_mcp_sampling(self: &Arc<Self>) {
+        if let Some(mcp) = self.tools.get_mcp().await {
+            mcp.sampling().set_handler(self.clone()).await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SamplingHandler for AgentLoop {
+    /// Run a server's completion request on the default model, without
+    /// tools or history. Usage is billed to session `sampling:<server>`.
+    async fn create_message(
+        &self,
+        server: &str,
+        request: SamplingRequest,
+    ) -> anyhow::Result<SamplingResult> {
+        let defaults = &self.config.agents.defaults;
+        let model = defaults.model.clone();
+
+        let mut messages = Vec::with_capacity(request.messages.len() + 1);
+        if let Some(system) = request.system_prompt {
+            messages.push(Message::system(system));
+        }
+        messages.extend(request.messages);
+
+        let mut options = HashMap::new();
+        options.insert(
+            "temperature".to_string(),
+            serde_json::Value::from(request.temperature.unwrap_or(defaults.temperature)),
+        );
+        options.insert(
+            "max_tokens".to_string(),
+            serde_json::Value::from(request.max_tokens.min(defaults.max_tokens)),
+        );
+        options.insert(
+            "max_retries".to_string(),
+            serde_json::Value::from(defaults.max_retries),
+        );
+        options.insert(
+            "retry_delay_ms".to_string(),
+            serde_json::Value::from(defaults.retry_delay_ms),
+        );
+        if !request.stop_sequences.is_empty() {
+            options.insert(
+                "stop".to_string(),
+                serde_json::Value::from(request.stop_sequences.clone()),
+            );
+        }
+
+        let start = std::time::Instant::now();
+        let response = self.provider.chat(&messages, &[], &model, &options).await?;
+        let bill_to = format!("sampling:{}", server);
+        self.record_usage(&model, response.usage.as_ref(), start.elapsed(), &bill_to)
+            .await;
+
+        let stop_reason = match response.finish_reason.as_str() {
+            "length" => "maxTokens",
+            "stop"
+                if request
+                    .stop_sequences
+                    .iter()
+                    .any(|s| response.content.ends_with(s.as_str())) =>
+            {
+                "stopSequence"
+            }
+            _ => "endTurn",
+        };
+        Ok(SamplingResult {
+            text: response.content,
+            model,
+            stop_reason: stop_reason.to_string(),
+        })
+    }
 }
@@ -0,0 +1,313 @@
+// QuectoClaw — Reply progress for chat channels (typing, partial text, tool activity)
+
+use crate::bus::{MessageBus, OutboundKind, OutboundMessage};
+use crate::config::StreamingConfig;
+use crate::provider::StreamEvent;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// Typing indicators expire after about five seconds on most platforms.
+const TYPING_REFRESH: Duration = Duration::from_secs(4);
+
+/// Publishes a turn's progress to the chat it answers: typing indicators,
+/// and `Update`s of a placeholder with the text and tool activity so far.
+/// A stream dropped without `finish` (its turn was aborted) closes the
+/// placeholder with `Cancelled`.
+pub struct ReplyStream {
+    stream_id: String,
+    events: mpsc::Sender<StreamEvent>,
+    streams_events: bool,
+    finished: Arc<AtomicBool>,
+    task: JoinHandle<bool>,
+}
+
+impl ReplyStream {
+    pub fn start(
+        bus: Arc<MessageBus>,
+        channel: &str,
+        chat_id: &str,
+        config: &StreamingConfig,
+    ) -> Self {
+        let stream_id = uuid::Uuid::new_v4().to_string();
+        let (events, rx) = mpsc::channel(256);
+        let finished = Arc::new(AtomicBool::new(false));
+        let relay = Relay {
+            bus,
+            channel: channel.to_string(),
+            chat_id: chat_id.to_string(),
+            stream_id: stream_id.clone(),
+            config: config.clone(),
+            finished: finished.clone(),
+        };
+        Self {
+            stream_id,
+            events,
+            streams_events: config.partial_replies || config.tool_progress,
+            finished,
+            task: tokio::spawn(relay.run(rx)),
+        }
+    }
+
+    /// Sender for the agent loop's stream events, when partial text or tool
+    /// progress is shown (otherwise the turn runs without streaming).
+    pub fn events(&self) -> Option<mpsc::Sender<StreamEvent>> {
+        self.streams_events.then(|| self.events.clone())
+    }
+
+    /// Stop publishing progress. Returns how to publish the complete reply:
+    /// `Final` if a placeholder was posted, else a plain `Message`.
+    pub async fn finish(self) -> OutboundKind {
+        self.finished.store(true, Ordering::SeqCst);
+        drop(self.events);
+        if self.task.await.unwrap_or(false) {
+            OutboundKind::Final {
+                stream_id: self.stream_id,
+            }
+        } else {
+            OutboundKind::Message
+        }
+    }
+}
+
+struct Relay {
+    bus: Arc<MessageBus>,
+    channel: String,
+    chat_id: String,
+    stream_id: String,
+    config: StreamingConfig,
+    /// Set by `ReplyStream::finish`; otherwise the turn was aborted.
+    finished: Arc<AtomicBool>,
+}
+
+impl Relay {
+    async fn publish(&self, kind: OutboundKind, content: String) {
+        self.bus
+            .publish_outbound(OutboundMessage {
+                channel: self.channel.clone(),
+                chat_id: self.chat_id.clone(),
+                content,
+                metadata: HashMap::new(),
+                kind,
+                attachments: Vec::new(),
+            })
+            .await;
+    }
+
+    /// Runs until the event sender is dropped; returns whether an update
+    /// was published. A placeholder left by an aborted turn is closed.
+    async fn run(self, mut rx: mpsc::Receiver<StreamEvent>) -> bool {
+        let mut typing = tokio::time::interval(TYPING_REFRESH);
+        let mut flush = tokio::time::interval(Duration::from_millis(
+            self.config.update_interval_ms.max(10),
+        ));
+        // After a quiet spell, wait a full interval rather than catching up
+        typing.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut text = String::new();
+        let mut activity: Option<String> = None;
+        let mut dirty = false;
+        let mut updated = false;
+
+        loop {
+            tokio::select! {
+                biased;
+                _ = typing.tick(), if self.config.typing => {
+                    self.publish(OutboundKind::Typing, String::new()).await;
+                }
+                _ = flush.tick(), if dirty => {
+                    dirty = false;
+                    let content = match &activity {
+                        Some(a) if text.is_empty() => a.clone(),
+                        Some(a) => format!("{}\n\n{}", text, a),
+                        None => text.clone(),
+                    };
+                    if !content.trim().is_empty() {
+                        let kind = OutboundKind::Update { stream_id: self.stream_id.clone() };
+                        self.publish(kind, content).await;
+                        updated = true;
+                    }
+                }
+                event = rx.recv() => match event {
+                    None => break,
+                    Some(StreamEvent::Token(token)) if self.config.partial_replies => {
+                        text.push_str(&token);
+                        activity = None;
+                        dirty = true;
+                    }
+                    Some(StreamEvent::ToolProgress(p)) if self.config.tool_progress => {
+                        activity = Some(format!("🔧 {}: {}", p.tool, p.describe()));
+                        dirty = true;
+                    }
+                    // Text before tool calls is not the answer; show the
+                    // tools instead
+                    Some(StreamEvent::Done(response)) if response.has_tool_calls() => {
+                        text.clear();
+                        if self.config.tool_progress {
+                            let names: Vec<&str> = response
+                                .tool_calls
+                                .iter()
+                                .flatten()
+                                .map(|tc| tc.function_name())
+                                .collect();
+                            activity = Some(format!("🔧 running {}…", names.join(", ")));
+                        }
+                        dirty = true;
+                    }
+                    Some(_) => {}
+                },
+            }
+        }
+        if updated && !self.finished.load(Ordering::SeqCst) {
+            let content = if text.trim().is_empty() {
+                "⏹️ Stopped.".to_string()
+            } else {
+                format!("{}\n\n⏹️ Stopped.", text)
+            };
+            let kind = OutboundKind::Cancelled {
+                stream_id: self.stream_id.clone(),
+            };
+            self.publish(kind, content).await;
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{FunctionCall, LLMResponse, ToolCall};
+    use crate::tool::ToolProgress;
+
+    fn config(partial_replies: bool, tool_progress: bool) -> StreamingConfig {
+        StreamingConfig {
+            typing: true,
+            partial_replies,
+            tool_progress,
+            update_interval_ms: 20,
+        }
+    }
+
+    fn tool_call(name: &str) -> LLMResponse {
+        LLMResponse {
+            content: "Let me check.".into(),
+            tool_calls: Some(vec![ToolCall {
+                id: "call_1".into(),
+                call_type: Some("function".into()),
+                function: Some(FunctionCall {
+                    name: name.into(),
+                    arguments: "{}".into(),
+                }),
+                name: None,
+                arguments: None,
+            }]),
+            finish_reason: "tool_calls".into(),
+            usage: None,
+        }
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<OutboundMessage>) -> Vec<String> {
+        let mut seen = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            seen.push(match msg.kind {
+                OutboundKind::Typing => "typing".to_string(),
+                OutboundKind::Update { .. } => format!("update {}", msg.content),
+                OutboundKind::Cancelled { .. } => format!("cancelled {}", msg.content),
+                other => format!("{:?} {}", other, msg.content),
+            });
+        }
+        seen
+    }
+
+    #[tokio::test]
+    async fn test_streams_text_and_tool_activity() {
+        let bus = Arc::new(MessageBus::new());
+        let (tx, mut rx) = mpsc::channel(64);
+        bus.register_handler("telegram", tx).await;
+
+        let stream = ReplyStream::start(bus.clone(), "telegram", "42", &config(true, true));
+        let events = stream.events().unwrap();
+        let pause = || tokio::time::sleep(Duration::from_millis(60));
+
+        events
+            .send(StreamEvent::Token("Let me ".into()))
+            .await
+            .unwrap();
+        pause().await;
+        events
+            .send(StreamEvent::Done(tool_call("web_search")))
+            .await
+            .unwrap();
+        pause().await;
+        events
+            .send(StreamEvent::ToolProgress(ToolProgress {
+                tool: "web_search".into(),
+                progress: 1.0,
+                total: Some(2.0),
+                message: Some("fetching".into()),
+            }))
+            .await
+            .unwrap();
+        pause().await;
+        events
+            .send(StreamEvent::Token("Sunny".into()))
+            .await
+            .unwrap();
+        pause().await;
+        drop(events);
+
+        assert!(matches!(stream.finish().await, OutboundKind::Final { .. }));
+        assert_eq!(
+            drain(&mut rx),
+            vec![
+                "typing",
+                "update Let me ",
+                "update 🔧 running web_search…",
+                "update 🔧 web_search: 1/2 fetching",
+                "update Sunny",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aborted_turn_closes_its_placeholder() {
+        let bus = Arc::new(MessageBus::new());
+        let (tx, mut rx) = mpsc::channel(64);
+        bus.register_handler("telegram", tx).await;
+
+        let stream = ReplyStream::start(bus.clone(), "telegram", "42", &config(true, false));
+        let events = stream.events().unwrap();
+        events
+            .send(StreamEvent::Token("Half an ans".into()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        // What an aborted turn leaves behind
+        drop(events);
+        drop(stream);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let seen = drain(&mut rx);
+        assert_eq!(
+            seen[seen.len() - 2..],
+            ["update Half an ans", "cancelled Half an ans\n\n⏹️ Stopped."]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_typing_only_sends_reply_whole() {
+        let bus = Arc::new(MessageBus::new());
+        let (tx, mut rx) = mpsc::channel(64);
+        bus.register_handler("telegram", tx).await;
+
+        let stream = ReplyStream::start(bus.clone(), "telegram", "42", &config(false, false));
+        assert!(stream.events().is_none());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(stream.finish().await, OutboundKind::Message);
+        assert_eq!(drain(&mut rx), vec!["typing"]);
+    }
+}
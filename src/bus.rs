@@ -1,7 +1,9 @@
 // QuectoClaw — Message bus (async channels for inter-component communication)
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::sync::RwLock;
@@ -21,6 +23,45 @@ pub struct InboundMessage {
     pub session_key: String,
     #[serde(default)]
     pub metadata: HashMap<String, String>,
+    /// Set for messages in group chats; `None` in direct chats.
+    #[serde(default)]
+    pub group: Option<GroupContext>,
+}
+
+impl InboundMessage {
+    /// Metadata a reply must carry back to the channel: the `request_id` a
+    /// webhook request waits on.
+    pub fn reply_metadata(&self) -> HashMap<String, String> {
+        self.metadata
+            .get("request_id")
+            .map(|id| HashMap::from([("request_id".to_string(), id.clone())]))
+            .unwrap_or_default()
+    }
+}
+
+/// What a channel knows about a group message, for the trigger rules and
+/// session scope of `channels.groups`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupContext {
+    /// The bot was @-mentioned (the mention is stripped from the text).
+    #[serde(default)]
+    pub mentioned: bool,
+    /// The message replies to one of the bot's messages.
+    #[serde(default)]
+    pub reply_to_bot: bool,
+    /// Thread or topic within the chat.
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// The sender's display name.
+    #[serde(default)]
+    pub sender_name: Option<String>,
+}
+
+/// A chat that replies can be sent to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatTarget {
+    pub channel: String,
+    pub chat_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +71,120 @@ pub struct OutboundMessage {
     pub content: String,
     #[serde(default)]
     pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub kind: OutboundKind,
+    /// Files sent after the text (which may be empty).
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// A file to send to a chat: an image, chart, report or other document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub filename: String,
+    pub mime: String,
+    pub data: AttachmentData,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentData {
+    /// A file on disk, read when the message is sent.
+    Path(PathBuf),
+    /// Stored as base64, so a queued photo stays a compact outbox entry.
+    Bytes(#[serde(with = "base64_bytes")] Vec<u8>),
+}
+
+mod base64_bytes {
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        // Entries written before base64 hold a plain array of bytes
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Base64(String),
+            Array(Vec<u8>),
+        }
+        match Stored::deserialize(deserializer)? {
+            Stored::Base64(s) => base64::engine::general_purpose::STANDARD
+                .decode(s)
+                .map_err(serde::de::Error::custom),
+            Stored::Array(data) => Ok(data),
+        }
+    }
+}
+
+impl Attachment {
+    /// Attach a file on disk; the MIME type is guessed from its extension.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_string());
+        Self {
+            mime: guess_mime(&filename),
+            filename,
+            data: AttachmentData::Path(path),
+        }
+    }
+
+    pub fn from_bytes(filename: &str, data: Vec<u8>) -> Self {
+        Self {
+            filename: filename.to_string(),
+            mime: guess_mime(filename),
+            data: AttachmentData::Bytes(data),
+        }
+    }
+
+    /// The file's contents.
+    pub async fn read(&self) -> std::io::Result<Vec<u8>> {
+        match &self.data {
+            AttachmentData::Path(path) => tokio::fs::read(path).await,
+            AttachmentData::Bytes(data) => Ok(data.clone()),
+        }
+    }
+
+    /// Whether chat apps can show this inline as a picture.
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self.mime.as_str(),
+            "image/jpeg" | "image/png" | "image/gif" | "image/webp"
+        )
+    }
+}
+
+fn guess_mime(filename: &str) -> String {
+    mime_guess::from_path(filename)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string()
+}
+
+/// What an outbound message does to the conversation. A streamed reply is
+/// a series of `Update`s (the whole text so far, not a delta) closed by a
+/// `Final` with the same `stream_id`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutboundKind {
+    /// A complete message.
+    #[default]
+    Message,
+    /// The agent is working on a reply; show a typing indicator.
+    Typing,
+    /// Partial text of a streamed reply: post or edit its placeholder.
+    Update { stream_id: String },
+    /// The complete text of a streamed reply.
+    Final { stream_id: String },
+    /// A streamed reply that will not be completed (its turn was stopped):
+    /// close the placeholder with this text.
+    Cancelled { stream_id: String },
 }
 
 // ---------------------------------------------------------------------------
@@ -42,6 +197,8 @@ pub struct MessageBus {
     outbound_tx: mpsc::Sender<OutboundMessage>,
     outbound_rx: Arc<tokio::sync::Mutex<mpsc::Receiver<OutboundMessage>>>,
     handlers: Arc<RwLock<HashMap<String, mpsc::Sender<OutboundMessage>>>>,
+    /// When each channel last delivered an inbound message.
+    last_inbound: Arc<std::sync::RwLock<HashMap<String, DateTime<Utc>>>>,
 }
 
 impl MessageBus {
@@ -55,10 +212,14 @@ impl MessageBus {
             outbound_tx,
             outbound_rx: Arc::new(tokio::sync::Mutex::new(outbound_rx)),
             handlers: Arc::new(RwLock::new(HashMap::new())),
+            last_inbound: Arc::new(std::sync::RwLock::new(HashMap::new())),
         }
     }
 
     pub async fn publish_inbound(&self, msg: InboundMessage) {
+        if let Ok(mut last) = self.last_inbound.write() {
+            last.insert(msg.channel.clone(), Utc::now());
+        }
         if let Err(e) = self.inbound_tx.send(msg).await {
             tracing::error!("Failed to publish inbound message: {}", e);
         }
@@ -77,9 +238,10 @@ impl MessageBus {
             }
         }
 
-        // Also publish to global outbound
-        if let Err(e) = self.outbound_tx.send(msg).await {
-            tracing::error!("Failed to publish outbound message: {}", e);
+        // Also publish to global outbound. Nothing may be subscribed, so
+        // never wait for room: a full queue would stall every reply.
+        if let Err(e) = self.outbound_tx.try_send(msg) {
+            tracing::debug!("Global outbound queue not drained: {}", e);
         }
     }
 
@@ -87,6 +249,11 @@ impl MessageBus {
         self.outbound_rx.lock().await.recv().await
     }
 
+    /// When `channel` last received a message, if it has since startup.
+    pub fn last_inbound(&self, channel: &str) -> Option<DateTime<Utc>> {
+        self.last_inbound.read().ok()?.get(channel).copied()
+    }
+
     /// Register a channel-specific handler for outbound messages.
     pub async fn register_handler(&self, channel: &str, sender: mpsc::Sender<OutboundMessage>) {
         self.handlers
@@ -95,6 +262,11 @@ impl MessageBus {
             .insert(channel.to_string(), sender);
     }
 
+    /// Whether a channel has registered to receive outbound messages.
+    pub async fn has_handler(&self, channel: &str) -> bool {
+        self.handlers.read().await.contains_key(channel)
+    }
+
     /// Get the inbound sender (for channels to publish messages).
     pub fn inbound_sender(&self) -> mpsc::Sender<InboundMessage> {
         self.inbound_tx.clone()
@@ -0,0 +1,596 @@
+// QuectoClaw — DingTalk channel implementation (Stream mode for receiving, robot API for sending)
+
+use crate::bus::{GroupContext, MessageBus, OutboundMessage};
+use crate::channel::{looks_like_markdown, rate_limited, BaseChannel, CachedToken, Channel};
+use crate::config::DingTalkConfig;
+use crate::util::Backoff;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+const DINGTALK_API: &str = "https://api.dingtalk.com";
+
+/// The Stream topic robot messages arrive on.
+const BOT_MESSAGE_TOPIC: &str = "/v1.0/im/bot/messages/get";
+
+/// Stop using a session webhook this long before it expires.
+const WEBHOOK_MARGIN_MS: i64 = 60_000;
+
+/// Each chat's session webhook and when it expires (epoch milliseconds).
+type SessionWebhooks = Arc<Mutex<HashMap<String, (String, i64)>>>;
+
+pub struct DingTalkChannel {
+    base: Arc<BaseChannel>,
+    client_id: String,
+    client_secret: String,
+    api_base: String,
+    client: Client,
+    token: CachedToken,
+    webhooks: SessionWebhooks,
+    running: Arc<AtomicBool>,
+    /// Whether the Stream socket is open.
+    connected: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DingTalkChannel {
+    pub fn new(config: &DingTalkConfig, bus: Arc<MessageBus>) -> Self {
+        Self {
+            base: Arc::new(BaseChannel::new("dingtalk", config.allow_from.clone(), bus)),
+            client_id: config.client_id.clone(),
+            client_secret: config.client_secret.clone(),
+            api_base: DINGTALK_API.to_string(),
+            client: Client::new(),
+            token: CachedToken::default(),
+            webhooks: Arc::new(Mutex::new(HashMap::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            connected: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Use a different Open API base URL (for tests).
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    async fn access_token(&self) -> anyhow::Result<String> {
+        self.token
+            .get(|| async {
+                let resp: Value = self
+                    .client
+                    .post(format!("{}/v1.0/oauth2/accessToken", self.api_base))
+                    .json(&json!({ "appKey": self.client_id, "appSecret": self.client_secret }))
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                let token = resp["accessToken"].as_str().ok_or_else(|| {
+                    anyhow!(
+                        "DingTalk token error: {}",
+                        resp["message"].as_str().unwrap_or("unknown error")
+                    )
+                })?;
+                let expire = resp["expireIn"].as_u64().unwrap_or(7200);
+                Ok((token.to_string(), Duration::from_secs(expire)))
+            })
+            .await
+    }
+
+    /// Answer through the conversation's session webhook.
+    async fn send_webhook(&self, webhook: &str, content: &str) -> anyhow::Result<()> {
+        let body = if looks_like_markdown(content) {
+            json!({ "msgtype": "markdown", "markdown": { "title": title(content), "text": content } })
+        } else {
+            json!({ "msgtype": "text", "text": { "content": content } })
+        };
+        let resp: Value = self
+            .client
+            .post(webhook)
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if resp["errcode"].as_i64().unwrap_or(0) != 0 {
+            anyhow::bail!(
+                "DingTalk webhook error: {}",
+                resp["errmsg"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(())
+    }
+
+    /// Send through the robot Open API, which needs no session webhook.
+    async fn send_api(&self, chat_id: &str, content: &str) -> anyhow::Result<()> {
+        let (msg_key, msg_param) = if looks_like_markdown(content) {
+            (
+                "sampleMarkdown",
+                json!({ "title": title(content), "text": content }),
+            )
+        } else {
+            ("sampleText", json!({ "content": content }))
+        };
+        let (endpoint, mut body) = match chat_id.split_once(':') {
+            Some(("group", id)) => (
+                "v1.0/robot/groupMessages/send",
+                json!({ "openConversationId": id }),
+            ),
+            Some(("user", id)) => (
+                "v1.0/robot/oToMessages/batchSend",
+                json!({ "userIds": [id] }),
+            ),
+            _ => anyhow::bail!("invalid DingTalk chat id '{}'", chat_id),
+        };
+        body["robotCode"] = json!(self.client_id);
+        body["msgKey"] = json!(msg_key);
+        body["msgParam"] = json!(msg_param.to_string());
+
+        let token = self.access_token().await?;
+        let resp = self
+            .client
+            .post(format!("{}/{}", self.api_base, endpoint))
+            .header("x-acs-dingtalk-access-token", token)
+            .json(&body)
+            .send()
+            .await?;
+        if let Some(limited) = rate_limited(&resp) {
+            return Err(limited.into());
+        }
+        let status = resp.status();
+        if !status.is_success() {
+            if status == reqwest::StatusCode::UNAUTHORIZED {
+                self.token.invalidate().await;
+            }
+            let body: Value = resp.json().await.unwrap_or_default();
+            anyhow::bail!(
+                "DingTalk API error ({}): {}",
+                status,
+                body["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A short title for Markdown messages (shown in notifications).
+fn title(content: &str) -> String {
+    let first = content
+        .lines()
+        .map(|l| l.trim_start_matches(['#', ' ', '*', '-', '>']).trim())
+        .find(|l| !l.is_empty())
+        .unwrap_or("Reply");
+    first.chars().take(20).collect()
+}
+
+/// The Stream connection loop, run in the background by `start`.
+struct Stream {
+    base: Arc<BaseChannel>,
+    client: Client,
+    api_base: String,
+    client_id: String,
+    client_secret: String,
+    webhooks: SessionWebhooks,
+    running: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+}
+
+impl Stream {
+    async fn run(self) {
+        let mut backoff = Backoff::default();
+        while self.running.load(Ordering::SeqCst) {
+            backoff.attempt();
+            let end = self.session().await;
+            self.connected.store(false, Ordering::SeqCst);
+            match end {
+                Ok(()) => {
+                    tracing::info!(
+                        channel = "dingtalk",
+                        "Stream connection closed, reconnecting"
+                    );
+                    backoff.reset();
+                }
+                Err(e) => {
+                    tracing::warn!(channel = "dingtalk", error = %e, retry_in_secs = backoff.delay().as_secs(), "Stream connection failed");
+                }
+            }
+            if !self.running.load(Ordering::SeqCst) {
+                break;
+            }
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    /// Register for a connection ticket, then process frames until the
+    /// socket ends or DingTalk asks us to reconnect.
+    async fn session(&self) -> anyhow::Result<()> {
+        let resp = self
+            .client
+            .post(format!("{}/v1.0/gateway/connections/open", self.api_base))
+            .json(&json!({
+                "clientId": self.client_id,
+                "clientSecret": self.client_secret,
+                "subscriptions": [{ "type": "CALLBACK", "topic": BOT_MESSAGE_TOPIC }],
+                "ua": format!("quectoclaw/{}", crate::VERSION),
+            }))
+            .send()
+            .await?;
+        let status = resp.status();
+        let open: Value = resp.json().await?;
+        let (Some(endpoint), Some(ticket)) = (open["endpoint"].as_str(), open["ticket"].as_str())
+        else {
+            anyhow::bail!(
+                "connections/open failed ({}): {}",
+                status,
+                open["message"].as_str().unwrap_or("no endpoint")
+            );
+        };
+        let mut url = url::Url::parse(endpoint)?;
+        url.query_pairs_mut().append_pair("ticket", ticket);
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+        self.connected.store(true, Ordering::SeqCst);
+        tracing::info!(channel = "dingtalk", "Stream connected");
+
+        while let Some(frame) = ws.next().await {
+            let text = match frame? {
+                WsMessage::Text(text) => text,
+                WsMessage::Close(_) => break,
+                _ => continue,
+            };
+            let Ok(frame) = serde_json::from_str::<Value>(&text) else {
+                continue;
+            };
+            let headers = &frame["headers"];
+            let topic = headers["topic"].as_str().unwrap_or("");
+
+            match (frame["type"].as_str().unwrap_or(""), topic) {
+                ("SYSTEM", "ping") => {
+                    let pong = json!({
+                        "code": 200,
+                        "headers": headers,
+                        "message": "OK",
+                        "data": frame["data"],
+                    });
+                    ws.send(WsMessage::text(pong.to_string())).await?;
+                }
+                ("SYSTEM", "disconnect") => return Ok(()),
+                ("CALLBACK", BOT_MESSAGE_TOPIC) => {
+                    // Acknowledge first, or DingTalk retries the callback
+                    ws.send(WsMessage::text(ack(headers, json!({ "response": null }))))
+                        .await?;
+                    if let Some(data) = frame["data"]
+                        .as_str()
+                        .and_then(|d| serde_json::from_str::<Value>(d).ok())
+                    {
+                        self.on_message(&data).await;
+                    }
+                }
+                ("EVENT", _) => {
+                    let data = json!({ "status": "SUCCESS", "message": "success" });
+                    ws.send(WsMessage::text(ack(headers, data))).await?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_message(&self, data: &Value) {
+        if data["msgtype"] != "text" {
+            return;
+        }
+        let text = data["text"]["content"].as_str().unwrap_or("").trim();
+        if text.is_empty() {
+            return;
+        }
+        let staff_id = data["senderStaffId"]
+            .as_str()
+            .or_else(|| data["senderId"].as_str())
+            .unwrap_or("");
+        let chat_id = if data["conversationType"] == "2" {
+            format!("group:{}", data["conversationId"].as_str().unwrap_or(""))
+        } else {
+            format!("user:{}", staff_id)
+        };
+        // Nicks are not unique or stable; the staff id is the identity
+        let nick = data["senderNick"].as_str().unwrap_or("");
+        let sender_id = staff_id.to_string();
+
+        if let Some(webhook) = data["sessionWebhook"].as_str() {
+            let expires = data["sessionWebhookExpiredTime"].as_i64().unwrap_or(0);
+            self.webhooks
+                .lock()
+                .await
+                .insert(chat_id.clone(), (webhook.to_string(), expires));
+        }
+
+        let mut metadata = HashMap::new();
+        for key in ["msgId", "conversationTitle"] {
+            if let Some(value) = data[key].as_str() {
+                metadata.insert(key.to_string(), value.to_string());
+            }
+        }
+        if !nick.is_empty() {
+            metadata.insert("sender_name".to_string(), nick.to_string());
+        }
+
+        if data["conversationType"] == "2" {
+            // Group robots are only sent messages that @ them
+            let group = GroupContext {
+                mentioned: data["isInAtList"].as_bool().unwrap_or(true),
+                sender_name: Some(nick.to_string()).filter(|n| !n.is_empty()),
+                ..Default::default()
+            };
+            self.base
+                .handle_group_message(&sender_id, &chat_id, text, Vec::new(), metadata, group)
+                .await;
+        } else {
+            self.base
+                .handle_message(&sender_id, &chat_id, text, Vec::new(), metadata)
+                .await;
+        }
+    }
+}
+
+/// The reply to a Stream frame, echoing its message id.
+fn ack(headers: &Value, data: Value) -> String {
+    json!({
+        "code": 200,
+        "headers": { "contentType": "application/json", "messageId": headers["messageId"] },
+        "message": "OK",
+        "data": data.to_string(),
+    })
+    .to_string()
+}
+
+#[async_trait]
+impl Channel for DingTalkChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.client_id.is_empty() || self.client_secret.is_empty() {
+            anyhow::bail!("DingTalk client_id and client_secret are not configured");
+        }
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        tracing::info!(channel = %self.name(), "Starting DingTalk channel (Stream mode)");
+
+        let stream = Stream {
+            base: self.base.clone(),
+            client: self.client.clone(),
+            api_base: self.api_base.clone(),
+            client_id: self.client_id.clone(),
+            client_secret: self.client_secret.clone(),
+            webhooks: self.webhooks.clone(),
+            running: self.running.clone(),
+            connected: self.connected.clone(),
+        };
+        *self.task.lock().await = Some(tokio::spawn(stream.run()));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        self.connected.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let webhook = self.webhooks.lock().await.get(&msg.chat_id).cloned();
+        if let Some((url, expires)) = webhook {
+            if chrono::Utc::now().timestamp_millis() + WEBHOOK_MARGIN_MS < expires {
+                match self.send_webhook(&url, &msg.content).await {
+                    Ok(()) => return Ok(()),
+                    Err(e) => {
+                        tracing::debug!(channel = "dingtalk", error = %e, "Session webhook failed, using the API")
+                    }
+                }
+            }
+        }
+        self.send_api(&msg.chat_id, &msg.content).await
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::WebSocketStream;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    type Socket = WebSocketStream<tokio::net::TcpStream>;
+
+    async fn accept(listener: &TcpListener) -> Socket {
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        tokio_tungstenite::accept_async(stream).await.unwrap()
+    }
+
+    async fn frame(ws: &mut Socket, kind: &str, topic: &str, id: &str, data: Value) {
+        let frame = json!({
+            "specVersion": "1.0",
+            "type": kind,
+            "headers": { "topic": topic, "messageId": id, "contentType": "application/json" },
+            "data": data.to_string(),
+        });
+        ws.send(WsMessage::text(frame.to_string())).await.unwrap();
+    }
+
+    async fn reply(ws: &mut Socket) -> Value {
+        loop {
+            if let Some(Ok(WsMessage::Text(text))) = ws.next().await {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn bot_message(kind: &str, text: &str, webhook: &str, expires: i64) -> Value {
+        json!({
+            "msgtype": "text", "text": { "content": format!(" {}", text) },
+            "conversationType": kind, "conversationId": "cid1",
+            "senderStaffId": "staff1", "senderNick": "Ann", "msgId": "m1",
+            "sessionWebhook": webhook, "sessionWebhookExpiredTime": expires,
+        })
+    }
+
+    fn outbound(chat_id: &str, content: &str) -> OutboundMessage {
+        OutboundMessage {
+            channel: "dingtalk".into(),
+            chat_id: chat_id.into(),
+            content: content.into(),
+            metadata: HashMap::new(),
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_title() {
+        assert_eq!(title("## Weather today\nSunny"), "Weather today");
+        assert_eq!(title("\n\n"), "Reply");
+    }
+
+    #[tokio::test]
+    async fn test_stream_messages_and_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1.0/gateway/connections/open"))
+            .and(body_partial_json(json!({
+                "clientId": "ding1",
+                "subscriptions": [{ "type": "CALLBACK", "topic": BOT_MESSAGE_TOPIC }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "endpoint": format!("ws://{}/connect", listener.local_addr().unwrap()),
+                "ticket": "tk",
+            })))
+            .expect(2)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/session/send"))
+            .and(query_param("session", "s1"))
+            .and(body_partial_json(
+                json!({ "msgtype": "markdown", "markdown": { "title": "Hello there" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "errcode": 0 })))
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1.0/oauth2/accessToken"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "accessToken": "at", "expireIn": 7200 })),
+            )
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1.0/robot/groupMessages/send"))
+            .and(header("x-acs-dingtalk-access-token", "at"))
+            .and(body_partial_json(json!({
+                "robotCode": "ding1", "openConversationId": "cid1", "msgKey": "sampleText"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&api)
+            .await;
+
+        let config = DingTalkConfig {
+            enabled: true,
+            client_id: "ding1".into(),
+            client_secret: "secret".into(),
+            allow_from: vec!["staff1".into()],
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = DingTalkChannel::new(&config, bus.clone()).with_api_base(&api.uri());
+        channel.start().await.unwrap();
+        let mut ws = accept(&listener).await;
+
+        // Pings are echoed; callbacks are acked and published
+        frame(&mut ws, "SYSTEM", "ping", "p1", json!({ "opaque": "x" })).await;
+        let pong = reply(&mut ws).await;
+        assert_eq!(pong["headers"]["messageId"], "p1");
+        assert_eq!(pong["data"], json!({ "opaque": "x" }).to_string());
+
+        let later = chrono::Utc::now().timestamp_millis() + 3_600_000;
+        let webhook = format!("{}/session/send?session=s1", api.uri());
+        frame(
+            &mut ws,
+            "CALLBACK",
+            BOT_MESSAGE_TOPIC,
+            "c1",
+            bot_message("1", "hi", &webhook, later),
+        )
+        .await;
+        assert_eq!(reply(&mut ws).await["headers"]["messageId"], "c1");
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "user:staff1");
+        assert_eq!(msg.sender_id, "staff1");
+        assert_eq!(msg.metadata["sender_name"], "Ann");
+        assert_eq!(msg.content, "hi");
+
+        // Live session webhook: Markdown reply through it
+        channel
+            .send(outbound("user:staff1", "## Hello there\n- one"))
+            .await
+            .unwrap();
+
+        // Expired webhook in a group: the robot API instead
+        let expired = chrono::Utc::now().timestamp_millis() - 1;
+        frame(
+            &mut ws,
+            "CALLBACK",
+            BOT_MESSAGE_TOPIC,
+            "c2",
+            bot_message("2", "status?", &webhook, expired),
+        )
+        .await;
+        reply(&mut ws).await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "group:cid1");
+        assert_eq!(msg.group.unwrap().sender_name.as_deref(), Some("Ann"));
+        channel
+            .send(outbound("group:cid1", "all good"))
+            .await
+            .unwrap();
+
+        // DingTalk asks for a reconnect; a new ticket is fetched
+        frame(&mut ws, "SYSTEM", "disconnect", "d1", json!({})).await;
+        let _ws = accept(&listener).await;
+
+        channel.stop().await.unwrap();
+        assert!(!channel.is_running());
+    }
+}
@@ -2,17 +2,32 @@
 
 #[cfg(feature = "discord")]
 mod implementation {
-    use crate::bus::{MessageBus, OutboundMessage};
+    use crate::bus::{Attachment, GroupContext, MessageBus, OutboundMessage};
+    use crate::channel::format::{split_message, to_discord, DISCORD_MAX_CHARS};
     use crate::channel::{BaseChannel, Channel};
     use async_trait::async_trait;
-    use serenity::all::{GatewayIntents, Message, Ready};
+    use serenity::all::{CreateAttachment, GatewayIntents, Message, Ready, ShardManager, UserId};
     use serenity::prelude::*;
     use std::collections::HashMap;
-    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
     use tokio::sync::Mutex;
 
     struct Handler {
         base: Arc<BaseChannel>,
+        /// The bot's user id, known once connected.
+        me: OnceLock<UserId>,
+    }
+
+    /// Remove `<@id>` / `<@!id>` mentions of the bot; returns the text and
+    /// whether there was one.
+    fn strip_mention(text: &str, me: UserId) -> (String, bool) {
+        let mut rest = text.to_string();
+        for mention in [format!("<@{}>", me), format!("<@!{}>", me)] {
+            rest = rest.replace(&mention, "");
+        }
+        let mentioned = rest.len() != text.len();
+        (rest.trim().to_string(), mentioned)
     }
 
     #[async_trait]
@@ -25,21 +40,58 @@ mod implementation {
             let sender_id = format!("{}|{}", msg.author.id, msg.author.name);
             let chat_id = msg.channel_id.to_string();
 
-            self.base
-                .handle_message(&sender_id, &chat_id, &msg.content, vec![], HashMap::new())
-                .await;
+            // Server channels (and their threads, which have their own
+            // channel ids) are group chats
+            match (msg.guild_id, self.me.get()) {
+                (Some(_), Some(&me)) => {
+                    let (text, mentioned) = strip_mention(&msg.content, me);
+                    let reply_to_bot = msg
+                        .referenced_message
+                        .as_ref()
+                        .is_some_and(|m| m.author.id == me);
+                    let sender_name = msg
+                        .member
+                        .as_ref()
+                        .and_then(|m| m.nick.clone())
+                        .or_else(|| msg.author.global_name.clone())
+                        .unwrap_or_else(|| msg.author.name.clone());
+                    let group = GroupContext {
+                        mentioned,
+                        reply_to_bot,
+                        thread_id: None,
+                        sender_name: Some(sender_name),
+                    };
+                    self.base
+                        .handle_group_message(
+                            &sender_id,
+                            &chat_id,
+                            &text,
+                            vec![],
+                            HashMap::new(),
+                            group,
+                        )
+                        .await;
+                }
+                _ => {
+                    self.base
+                        .handle_message(&sender_id, &chat_id, &msg.content, vec![], HashMap::new())
+                        .await;
+                }
+            }
         }
 
         async fn ready(&self, _: Context, ready: Ready) {
             tracing::info!("Discord bot {} is connected!", ready.user.name);
+            let _ = self.me.set(ready.user.id);
         }
     }
 
     pub struct DiscordChannel {
         base: BaseChannel,
         token: String,
-        running: Arc<Mutex<bool>>,
-        client: Arc<Mutex<Option<Client>>>,
+        running: Arc<AtomicBool>,
+        /// Shuts down the client started by `start`.
+        shards: Mutex<Option<Arc<ShardManager>>>,
     }
 
     impl DiscordChannel {
@@ -47,12 +99,23 @@ mod implementation {
             Self {
                 base: BaseChannel::new("discord", allow_list, bus),
                 token: token.to_string(),
-                running: Arc::new(Mutex::new(false)),
-                client: Arc::new(Mutex::new(None)),
+                running: Arc::new(AtomicBool::new(false)),
+                shards: Mutex::new(None),
             }
         }
     }
 
+    /// Discord Markdown for a chunk, unless conversion pushed it over the
+    /// limit.
+    fn discord_text(chunk: String) -> String {
+        let formatted = to_discord(&chunk);
+        if formatted.chars().count() <= DISCORD_MAX_CHARS {
+            formatted
+        } else {
+            chunk
+        }
+    }
+
     #[async_trait]
     impl Channel for DiscordChannel {
         fn name(&self) -> &str {
@@ -60,12 +123,11 @@ mod implementation {
         }
 
         async fn start(&self) -> anyhow::Result<()> {
-            let mut running = self.running.lock().await;
-            if *running {
+            let mut shards = self.shards.lock().await;
+            if self.running.load(Ordering::SeqCst) {
                 return Ok(());
             }
 
-            *running = true;
             let token = self.token.clone();
             let base = Arc::new(BaseChannel::new(
                 self.base.name(),
@@ -79,50 +141,110 @@ mod implementation {
                 | GatewayIntents::DIRECT_MESSAGES
                 | GatewayIntents::MESSAGE_CONTENT;
 
-            let handler = Handler { base };
+            let handler = Handler {
+                base,
+                me: OnceLock::new(),
+            };
             let mut client = Client::builder(&token, intents)
                 .event_handler(handler)
                 .await?;
 
-            let running_clone = self.running.clone();
-            let _client_ptr = self.client.clone();
+            *shards = Some(client.shard_manager.clone());
+            self.running.store(true, Ordering::SeqCst);
 
-            // We can't easily "stop" serenity client once it starts with .start()
-            // but we can spawn it.
+            let running = self.running.clone();
             tokio::spawn(async move {
                 if let Err(why) = client.start().await {
                     tracing::error!("Discord client error: {:?}", why);
                 }
-                let mut r = running_clone.lock().await;
-                *r = false;
+                running.store(false, Ordering::SeqCst);
+                tracing::info!("Discord client stopped");
             });
 
             Ok(())
         }
 
         async fn stop(&self) -> anyhow::Result<()> {
-            let mut running = self.running.lock().await;
-            *running = false;
-            // Serenity client doesn't have a simple .stop(), usually you drop it or use a shard manager shutdown.
+            if let Some(shards) = self.shards.lock().await.take() {
+                shards.shutdown_all().await;
+            }
+            self.running.store(false, Ordering::SeqCst);
             Ok(())
         }
 
         async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
-            let token = self.token.clone();
-            let http = serenity::http::Http::new(&token);
+            let http = serenity::http::Http::new(&self.token);
             let channel_id: u64 = msg.chat_id.parse()?;
 
+            for chunk in split_message(&msg.content, DISCORD_MAX_CHARS) {
+                let map = serde_json::json!({
+                    "content": discord_text(chunk),
+                });
+                http.send_message(channel_id.into(), vec![], &map).await?;
+            }
+
+            Ok(())
+        }
+
+        async fn send_typing(&self, chat_id: &str) -> anyhow::Result<()> {
+            let http = serenity::http::Http::new(&self.token);
+            let channel_id: u64 = chat_id.parse()?;
+            http.broadcast_typing(channel_id.into()).await?;
+            Ok(())
+        }
+
+        async fn send_draft(&self, chat_id: &str, content: &str) -> anyhow::Result<Option<String>> {
+            let http = serenity::http::Http::new(&self.token);
+            let channel_id: u64 = chat_id.parse()?;
+            let first = split_message(content, DISCORD_MAX_CHARS).remove(0);
+            let map = serde_json::json!({
+                "content": discord_text(first),
+            });
+            let sent = http.send_message(channel_id.into(), vec![], &map).await?;
+            Ok(Some(sent.id.to_string()))
+        }
+
+        async fn edit_draft(
+            &self,
+            chat_id: &str,
+            draft_id: &str,
+            content: &str,
+            done: bool,
+        ) -> anyhow::Result<()> {
+            let http = serenity::http::Http::new(&self.token);
+            let channel_id: u64 = chat_id.parse()?;
+            let message_id: u64 = draft_id.parse()?;
+            let mut chunks = split_message(content, DISCORD_MAX_CHARS).into_iter();
             let map = serde_json::json!({
-                "content": msg.content,
+                "content": discord_text(chunks.next().unwrap_or_default()),
             });
+            http.edit_message(channel_id.into(), message_id.into(), &map, vec![])
+                .await?;
 
-            http.send_message(channel_id.into(), vec![], &map).await?;
+            // Whatever does not fit the placeholder follows it once complete
+            if done {
+                for chunk in chunks {
+                    let map = serde_json::json!({
+                        "content": discord_text(chunk),
+                    });
+                    http.send_message(channel_id.into(), vec![], &map).await?;
+                }
+            }
+            Ok(())
+        }
 
+        async fn send_file(&self, chat_id: &str, attachment: &Attachment) -> anyhow::Result<()> {
+            let http = serenity::http::Http::new(&self.token);
+            let channel_id: u64 = chat_id.parse()?;
+            let file =
+                CreateAttachment::bytes(attachment.read().await?, attachment.filename.clone());
+            http.send_message(channel_id.into(), vec![file], &serde_json::json!({}))
+                .await?;
             Ok(())
         }
 
         fn is_running(&self) -> bool {
-            true
+            self.running.load(Ordering::SeqCst)
         }
     }
 }
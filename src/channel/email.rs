@@ -0,0 +1,1013 @@
+// QuectoClaw — Email channel (IMAP polling, SMTP replies)
+//
+// Each poll logs in to the IMAP mailbox, fetches UNSEEN messages, publishes
+// them and flags them \Seen. A conversation is one sender's email thread:
+// the chat id is `<sender>/<root Message-ID>` (root: first of References,
+// else In-Reply-To, else the message's own id), so naming another thread in
+// References never joins it. Replies go out over SMTP with In-Reply-To and
+// References set so mail clients keep them in the thread. Thread state is
+// kept in `<workspace>/email_threads.json` so replies survive a restart.
+
+use crate::bus::{MessageBus, OutboundMessage};
+use crate::channel::{BaseChannel, Channel};
+use crate::config::EmailConfig;
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use mail_parser::{MessageParser, MimeHeaders};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls;
+
+/// Upper bound for one poll (connect, fetch, flag, logout).
+const POLL_TIMEOUT: Duration = Duration::from_secs(120);
+/// Threads kept on disk; the least recently active are dropped first.
+const MAX_THREADS: usize = 1000;
+
+/// What a reply to a thread needs: who to answer and how to thread it.
+/// Message ids are stored without angle brackets.
+#[derive(Clone, Serialize, Deserialize)]
+struct Thread {
+    to: String,
+    to_name: Option<String>,
+    subject: String,
+    last_id: String,
+    references: Vec<String>,
+    /// Unix time of the last message in either direction.
+    #[serde(default)]
+    updated_at: i64,
+}
+
+pub struct EmailChannel {
+    base: Arc<BaseChannel>,
+    config: EmailConfig,
+    inbox: Arc<Inbox>,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl EmailChannel {
+    /// Attachments go to `<workspace>/media/email`, thread state to
+    /// `<workspace>/email_threads.json`.
+    pub fn new(config: &EmailConfig, workspace: PathBuf, bus: Arc<MessageBus>) -> Self {
+        // Addresses compare case-insensitively
+        let allow_list = config
+            .allow_from
+            .iter()
+            .map(|a| a.trim().to_lowercase())
+            .collect();
+        let base = Arc::new(BaseChannel::new("email", allow_list, bus));
+        let threads_path = workspace.join("email_threads.json");
+        Self {
+            base: base.clone(),
+            config: config.clone(),
+            inbox: Arc::new(Inbox {
+                base,
+                config: config.clone(),
+                media_dir: workspace.join("media").join("email"),
+                threads: Mutex::new(load_threads(&threads_path)),
+                threads_path,
+            }),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    fn transport(&self) -> anyhow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let host = self.config.smtp_host.as_str();
+        let builder = match self.config.smtp_security.as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            other => anyhow::bail!("unknown smtp_security '{}'", other),
+        };
+        let mut builder = builder.port(self.config.smtp_port);
+        if !self.config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                self.config.username.clone(),
+                self.config.password.clone(),
+            ));
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Shared state of the polling task.
+struct Inbox {
+    base: Arc<BaseChannel>,
+    config: EmailConfig,
+    media_dir: PathBuf,
+    threads: Mutex<HashMap<String, Thread>>,
+    threads_path: PathBuf,
+}
+
+impl Inbox {
+    /// Write the threads out, dropping the oldest beyond `MAX_THREADS`.
+    async fn save_threads(&self, threads: &mut HashMap<String, Thread>) {
+        if threads.len() > MAX_THREADS {
+            let mut by_age: Vec<(i64, String)> = threads
+                .iter()
+                .map(|(id, t)| (t.updated_at, id.clone()))
+                .collect();
+            by_age.sort();
+            for (_, id) in by_age.into_iter().take(threads.len() - MAX_THREADS) {
+                threads.remove(&id);
+            }
+        }
+        if let Err(e) = save_threads(&self.threads_path, threads).await {
+            tracing::warn!(channel = "email", error = %e, "Failed to save email threads");
+        }
+    }
+
+    async fn poll(&self) -> anyhow::Result<()> {
+        let mut imap = Imap::connect(&self.config).await?;
+        imap.command(&format!(
+            "LOGIN {} {}",
+            quote(&self.config.username),
+            quote(&self.config.password)
+        ))
+        .await?;
+        imap.command(&format!("SELECT {}", quote(&self.config.mailbox)))
+            .await?;
+
+        let uids: Vec<u32> = imap
+            .command("UID SEARCH UNSEEN")
+            .await?
+            .iter()
+            .filter_map(|r| r.text.strip_prefix("* SEARCH"))
+            .flat_map(|rest| rest.split_whitespace().filter_map(|u| u.parse().ok()))
+            .collect();
+
+        for uid in uids {
+            let responses = imap
+                .command(&format!("UID FETCH {} BODY.PEEK[]", uid))
+                .await?;
+            if let Some(raw) = responses
+                .into_iter()
+                .find_map(|r| r.literals.into_iter().next())
+            {
+                self.handle(&raw).await;
+            }
+            // Flag even ignored mail, so it is not fetched again
+            imap.command(&format!("UID STORE {} +FLAGS (\\Seen)", uid))
+                .await?;
+        }
+
+        let _ = imap.command("LOGOUT").await;
+        Ok(())
+    }
+
+    async fn handle(&self, raw: &[u8]) {
+        let Some(message) = MessageParser::default().parse(raw) else {
+            tracing::warn!(channel = "email", "Could not parse email");
+            return;
+        };
+        let Some(sender) = message.from().and_then(|a| a.first()) else {
+            return;
+        };
+        let Some(address) = sender.address().map(|a| a.to_lowercase()) else {
+            return;
+        };
+        if address == from_address(&self.config).to_lowercase()
+            || is_auto_submitted(message.header_raw("Auto-Submitted"))
+        {
+            return;
+        }
+        if !is_sender_allowed(self.base.allow_list(), &address) {
+            tracing::warn!(channel = "email", sender = %address, "Email blocked: sender not in allow list");
+            return;
+        }
+        if self.config.require_auth {
+            let results: Vec<&str> = message
+                .headers_raw()
+                .filter(|(name, _)| name.eq_ignore_ascii_case("Authentication-Results"))
+                .map(|(_, value)| value)
+                .collect();
+            if !is_authenticated(&results, &address, &self.config.auth_serv_id) {
+                tracing::warn!(channel = "email", sender = %address, "Email blocked: sender not authenticated (no DMARC or aligned DKIM pass)");
+                return;
+            }
+        }
+
+        let message_id = message
+            .message_id()
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let in_reply_to = message.in_reply_to().as_text().map(str::to_string);
+        let mut references: Vec<String> = message
+            .references()
+            .as_text_list()
+            .map(|ids| ids.iter().map(|id| id.to_string()).collect())
+            .unwrap_or_default();
+        if references.is_empty() {
+            references.extend(in_reply_to.clone());
+        }
+        let root = references
+            .first()
+            .cloned()
+            .unwrap_or_else(|| message_id.clone());
+        references.push(message_id.clone());
+
+        let subject = message.subject().unwrap_or_default().to_string();
+        let body = message
+            .body_text(0)
+            .map(|text| strip_quoted(&text))
+            .unwrap_or_default();
+
+        let mut media = Vec::new();
+        for part in message.attachments() {
+            let name = part.attachment_name().unwrap_or("attachment");
+            match save_attachment(&self.media_dir, name, part.contents()).await {
+                Ok(path) => media.push(path),
+                Err(e) => {
+                    tracing::warn!(channel = "email", file = %name, error = %e, "Failed to save attachment")
+                }
+            }
+        }
+        if body.is_empty() && media.is_empty() {
+            return;
+        }
+
+        // A new thread's subject is often the question itself
+        let content = if in_reply_to.is_none() && !subject.is_empty() {
+            format!("Subject: {}\n\n{}", subject, body)
+        } else {
+            body
+        };
+
+        let sender_name = sender.name().map(str::to_string);
+        let mut metadata = HashMap::new();
+        metadata.insert("message_id".to_string(), message_id.clone());
+        metadata.insert("subject".to_string(), subject.clone());
+        if let Some(name) = &sender_name {
+            metadata.insert("sender_name".to_string(), name.clone());
+        }
+
+        // Keyed by sender too, so References cannot reach another sender's thread
+        let chat_id = format!("{}/{}", address, root);
+        {
+            let mut threads = self.threads.lock().await;
+            threads.insert(
+                chat_id.clone(),
+                Thread {
+                    to: address.clone(),
+                    to_name: sender_name,
+                    subject,
+                    last_id: message_id,
+                    references,
+                    updated_at: chrono::Utc::now().timestamp(),
+                },
+            );
+            self.save_threads(&mut threads).await;
+        }
+        self.base
+            .handle_message(&address, &chat_id, &content, media, metadata)
+            .await;
+    }
+}
+
+fn load_threads(path: &Path) -> HashMap<String, Thread> {
+    let Ok(data) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    serde_json::from_str(&data).unwrap_or_else(|e| {
+        tracing::warn!(channel = "email", path = %path.display(), error = %e, "Ignoring unreadable email threads");
+        HashMap::new()
+    })
+}
+
+async fn save_threads(path: &Path, threads: &HashMap<String, Thread>) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_string_pretty(threads)?).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// Address replies are sent from.
+fn from_address(config: &EmailConfig) -> &str {
+    if config.from_address.is_empty() {
+        &config.username
+    } else {
+        &config.from_address
+    }
+}
+
+/// Whether an address is on the (lowercased) allow list. Matched whole,
+/// since `BaseChannel::is_allowed` would let `x|boss@corp.com` pass as
+/// `boss@corp.com`. Empty denies everyone, `*` allows everyone.
+fn is_sender_allowed(allow_list: &[String], address: &str) -> bool {
+    allow_list.iter().any(|a| a == "*" || a == address)
+}
+
+/// Whether the receiving server vouched for the sender's address: its
+/// `Authentication-Results` show `dmarc=pass`, or `dkim=pass` for a signing
+/// domain aligned with the From domain. Only headers stamped with
+/// `auth_serv_id` count, or the topmost header when it is empty, since
+/// anything below it may have come with the message.
+fn is_authenticated(results: &[&str], address: &str, auth_serv_id: &str) -> bool {
+    let Some((_, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+    // Relaxed alignment: the signing domain may be a parent of the From domain
+    let aligned = |d: &str| {
+        let d = d.rsplit('@').next().unwrap_or_default().to_lowercase();
+        domain == d || domain.ends_with(&format!(".{}", d))
+    };
+    let trusted: Vec<&str> = if auth_serv_id.is_empty() {
+        results.iter().take(1).copied().collect()
+    } else {
+        results
+            .iter()
+            .copied()
+            .filter(|r| {
+                r.split(';')
+                    .next()
+                    .and_then(|id| id.split_whitespace().next())
+                    .is_some_and(|id| id.eq_ignore_ascii_case(auth_serv_id))
+            })
+            .collect()
+    };
+    trusted.iter().any(|header| {
+        header.split(';').skip(1).any(|result| {
+            let result = strip_comments(result);
+            let mut tokens = result.split_whitespace();
+            let verdict = tokens.next().unwrap_or_default().to_lowercase();
+            let props: Vec<(String, &str)> = tokens
+                .filter_map(|t| t.split_once('='))
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect();
+            let prop = |name: &str| props.iter().find(|(k, _)| k == name).map(|(_, v)| *v);
+            match verdict.as_str() {
+                "dmarc=pass" => prop("header.from").is_some_and(aligned),
+                "dkim=pass" => prop("header.d").or(prop("header.i")).is_some_and(aligned),
+                _ => false,
+            }
+        })
+    })
+}
+
+/// Drop `(comments)` from a header value.
+fn strip_comments(value: &str) -> String {
+    let mut depth = 0usize;
+    value
+        .chars()
+        .filter(|c| match c {
+            '(' => {
+                depth += 1;
+                false
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            _ => depth == 0,
+        })
+        .collect()
+}
+
+/// `Auto-Submitted` other than `no` marks auto-replies, bounces and the
+/// like; answering those risks mail loops.
+fn is_auto_submitted(header: Option<&str>) -> bool {
+    header.is_some_and(|v| !v.trim().eq_ignore_ascii_case("no"))
+}
+
+/// Drop the quoted history mail clients append to replies.
+fn strip_quoted(text: &str) -> String {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("-----Original Message-----")
+            || (trimmed.starts_with("On ") && trimmed.ends_with("wrote:"))
+        {
+            break;
+        }
+        if !trimmed.starts_with('>') {
+            lines.push(line.trim_end());
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+fn reply_subject(subject: &str) -> String {
+    if subject.is_empty() {
+        "Re: your message".to_string()
+    } else if subject.to_lowercase().starts_with("re:") {
+        subject.to_string()
+    } else {
+        format!("Re: {}", subject)
+    }
+}
+
+async fn save_attachment(dir: &Path, name: &str, bytes: &[u8]) -> anyhow::Result<String> {
+    let safe_name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let safe_name = safe_name.trim_start_matches('.');
+    let file = format!(
+        "{}-{}",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f"),
+        safe_name
+    );
+
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(file);
+    tokio::fs::write(&path, bytes).await?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// IMAP quoted string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// ---------------------------------------------------------------------------
+// Minimal IMAP4rev1 client: tagged commands, untagged responses, literals
+// ---------------------------------------------------------------------------
+
+trait ImapStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ImapStream for T {}
+
+/// One untagged (or tagged) response, with the literals it carried.
+struct ImapResponse {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+struct Imap {
+    stream: BufReader<Box<dyn ImapStream>>,
+    tag: u32,
+}
+
+impl Imap {
+    async fn connect(config: &EmailConfig) -> anyhow::Result<Self> {
+        let tcp = TcpStream::connect((config.imap_host.as_str(), config.imap_port)).await?;
+        let stream: Box<dyn ImapStream> = match config.imap_security.as_str() {
+            "tls" => Box::new(tls_connect(&config.imap_host, tcp).await?),
+            "none" => Box::new(tcp),
+            other => anyhow::bail!("unknown imap_security '{}'", other),
+        };
+        let mut imap = Self {
+            stream: BufReader::new(stream),
+            tag: 0,
+        };
+        let greeting = imap.read_response().await?;
+        if !greeting.text.starts_with("* OK") && !greeting.text.starts_with("* PREAUTH") {
+            anyhow::bail!("unexpected IMAP greeting: {}", greeting.text);
+        }
+        Ok(imap)
+    }
+
+    async fn read_response(&mut self) -> anyhow::Result<ImapResponse> {
+        let mut response = ImapResponse {
+            text: String::new(),
+            literals: Vec::new(),
+        };
+        loop {
+            let mut line = Vec::new();
+            if self.stream.read_until(b'\n', &mut line).await? == 0 {
+                anyhow::bail!("IMAP connection closed");
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            response.text.push_str(line);
+            let Some(len) = literal_len(line) else {
+                return Ok(response);
+            };
+            let mut literal = vec![0; len];
+            self.stream.read_exact(&mut literal).await?;
+            response.literals.push(literal);
+        }
+    }
+
+    /// Run a command, returning its untagged responses, or the server's
+    /// reason if it answers NO or BAD.
+    async fn command(&mut self, command: &str) -> anyhow::Result<Vec<ImapResponse>> {
+        self.tag += 1;
+        let tag = format!("A{}", self.tag);
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await?;
+        stream.flush().await?;
+
+        let prefix = format!("{} ", tag);
+        let mut responses = Vec::new();
+        loop {
+            let response = self.read_response().await?;
+            let Some(status) = response.text.strip_prefix(&prefix) else {
+                responses.push(response);
+                continue;
+            };
+            if status.starts_with("OK") {
+                return Ok(responses);
+            }
+            // Never echo the command itself: LOGIN carries the password
+            let verb = command.split_whitespace().next().unwrap_or_default();
+            anyhow::bail!("IMAP {} failed: {}", verb, status);
+        }
+    }
+}
+
+/// Length of the literal announced at the end of a line (`… {123}`).
+fn literal_len(line: &str) -> Option<usize> {
+    let inner = line.strip_suffix('}')?;
+    let start = inner.rfind('{')?;
+    inner[start + 1..].parse().ok()
+}
+
+async fn tls_connect(
+    host: &str,
+    tcp: TcpStream,
+) -> anyhow::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let name = rustls::pki_types::ServerName::try_from(host.to_string())?;
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    Ok(connector.connect(name, tcp).await?)
+}
+
+#[async_trait]
+impl Channel for EmailChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.config.imap_host.is_empty() || self.config.smtp_host.is_empty() {
+            anyhow::bail!("email channel needs imap_host and smtp_host");
+        }
+        if self.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        tracing::info!(
+            channel = %self.name(),
+            mailbox = %self.config.mailbox,
+            host = %self.config.imap_host,
+            "Starting email polling"
+        );
+
+        let inbox = self.inbox.clone();
+        let running = self.running.clone();
+        let interval = Duration::from_secs(self.config.poll_interval_secs.max(1));
+        running.store(true, Ordering::SeqCst);
+        *self.task.lock().await = Some(tokio::spawn(async move {
+            while running.load(Ordering::SeqCst) {
+                match tokio::time::timeout(POLL_TIMEOUT, inbox.poll()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => {
+                        tracing::warn!(channel = "email", error = %e, "Mailbox poll failed")
+                    }
+                    Err(_) => tracing::warn!(channel = "email", "Mailbox poll timed out"),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        }));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let Some(thread) = self.inbox.threads.lock().await.get(&msg.chat_id).cloned() else {
+            anyhow::bail!("no email thread '{}'", msg.chat_id);
+        };
+
+        let from: Mailbox = from_address(&self.config).parse()?;
+        let id = format!("{}@{}", uuid::Uuid::new_v4(), from.email.domain());
+        let references = thread
+            .references
+            .iter()
+            .map(|r| format!("<{}>", r))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let email = Message::builder()
+            .from(from)
+            .to(Mailbox::new(thread.to_name.clone(), thread.to.parse()?))
+            .subject(reply_subject(&thread.subject))
+            .message_id(Some(format!("<{}>", id)))
+            .in_reply_to(format!("<{}>", thread.last_id))
+            .references(references)
+            .header(ContentType::TEXT_PLAIN)
+            .body(msg.content)?;
+        self.transport()?.send(email).await?;
+
+        // Later replies in the thread answer this message
+        let mut threads = self.inbox.threads.lock().await;
+        if let Some(thread) = threads.get_mut(&msg.chat_id) {
+            thread.last_id = id.clone();
+            thread.references.push(id);
+            thread.updated_at = chrono::Utc::now().timestamp();
+            self.inbox.save_threads(&mut threads).await;
+        }
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use std::collections::HashSet;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    const REPLY: &str = "Authentication-Results: mx.example.com;\r\n\
+\tdkim=pass header.d=example.com; dmarc=pass (p=none) header.from=example.com\r\n\
+From: Alice <Alice@Example.com>\r\n\
+To: bot@example.com\r\n\
+Subject: Re: Quarterly report\r\n\
+Message-ID: <msg2@example.com>\r\n\
+In-Reply-To: <root@example.com>\r\n\
+References: <root@example.com>\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+\r\n\
+--b1\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+Numbers attached.\r\n\
+\r\n\
+On Mon, Bob wrote:\r\n\
+> what are the numbers?\r\n\
+--b1\r\n\
+Content-Type: text/csv; name=\"report.csv\"\r\n\
+Content-Disposition: attachment; filename=\"report.csv\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+YSxiCjEsMgo=\r\n\
+--b1--\r\n";
+
+    const STRANGER: &str = "From: mallory@example.net\r\n\
+To: bot@example.com\r\n\
+Subject: hi\r\n\
+Message-ID: <spam@example.net>\r\n\
+\r\n\
+Let me in\r\n";
+
+    /// IMAP stand-in serving two messages and recording which got \Seen.
+    async fn imap_server(seen: Arc<std::sync::Mutex<HashSet<u32>>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    writer.write_all(b"* OK ready\r\n").await.unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let (tag, command) = line.split_once(' ').unwrap();
+                        let mut out = String::new();
+                        if command.starts_with("LOGIN") {
+                            assert_eq!(command, "LOGIN \"bot@example.com\" \"pw\\\"1\"");
+                        } else if command.starts_with("SELECT") {
+                            out.push_str("* 2 EXISTS\r\n");
+                        } else if command == "UID SEARCH UNSEEN" {
+                            let seen = seen.lock().unwrap();
+                            let unseen: Vec<String> = [1, 2]
+                                .into_iter()
+                                .filter(|u| !seen.contains(u))
+                                .map(|u| u.to_string())
+                                .collect();
+                            out.push_str(&format!("* SEARCH {}\r\n", unseen.join(" ")));
+                        } else if let Some(rest) = command.strip_prefix("UID FETCH ") {
+                            let uid: u32 = rest.split(' ').next().unwrap().parse().unwrap();
+                            let raw = if uid == 1 { REPLY } else { STRANGER };
+                            out.push_str(&format!(
+                                "* {0} FETCH (UID {0} BODY[] {{{1}}}\r\n{2})\r\n",
+                                uid,
+                                raw.len(),
+                                raw
+                            ));
+                        } else if let Some(rest) = command.strip_prefix("UID STORE ") {
+                            let uid = rest.split(' ').next().unwrap().parse().unwrap();
+                            seen.lock().unwrap().insert(uid);
+                        } else if command == "LOGOUT" {
+                            out.push_str("* BYE\r\n");
+                        }
+                        out.push_str(&format!("{} OK done\r\n", tag));
+                        writer.write_all(out.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    /// SMTP stand-in that hands over the first message's DATA.
+    async fn smtp_server() -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost\r\n").await.unwrap();
+            let mut tx = Some(tx);
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    writer.write_all(b"354 go\r\n").await.unwrap();
+                    let mut data = String::new();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(data);
+                    }
+                    b"250 queued\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+        (port, rx)
+    }
+
+    #[test]
+    fn test_strip_quoted_and_subjects() {
+        assert_eq!(
+            strip_quoted("Sounds good.\n\nOn Tue, 1 Oct 2024, Bot wrote:\n> earlier\n> text"),
+            "Sounds good."
+        );
+        assert_eq!(strip_quoted("> quoted\nanswer\n"), "answer");
+        assert_eq!(
+            strip_quoted("Yes\n-----Original Message-----\nFrom: bot"),
+            "Yes"
+        );
+        assert_eq!(reply_subject("Quarterly report"), "Re: Quarterly report");
+        assert_eq!(
+            reply_subject("RE: Quarterly report"),
+            "RE: Quarterly report"
+        );
+        assert_eq!(reply_subject(""), "Re: your message");
+        assert!(is_auto_submitted(Some("auto-replied")));
+        assert!(!is_auto_submitted(Some("no")));
+        assert!(!is_auto_submitted(None));
+        assert_eq!(literal_len("* 1 FETCH (UID 1 BODY[] {42}"), Some(42));
+        assert_eq!(literal_len("* 1 FETCH (FLAGS (\\Seen))"), None);
+    }
+
+    #[test]
+    fn test_sender_allow_list() {
+        let allow = vec!["boss@corp.com".to_string()];
+        assert!(is_sender_allowed(&allow, "boss@corp.com"));
+        assert!(!is_sender_allowed(&allow, "x|boss@corp.com"));
+        assert!(!is_sender_allowed(&allow, "other@corp.com"));
+        assert!(!is_sender_allowed(&[], "boss@corp.com"));
+        assert!(is_sender_allowed(&["*".to_string()], "anyone@example.com"));
+    }
+
+    #[test]
+    fn test_sender_authentication() {
+        let results = ["mx.example.com; spf=pass; dkim=pass header.d=mail.example.com"];
+        assert!(!is_authenticated(&results, "alice@example.com", ""));
+        assert!(is_authenticated(&results, "alice@mail.example.com", ""));
+        assert!(is_authenticated(
+            &["mx.example.com; dmarc=pass header.from=example.com"],
+            "alice@example.com",
+            ""
+        ));
+        // A pass for another domain, or only an SPF pass, proves nothing
+        assert!(!is_authenticated(
+            &["mx.example.com; dkim=pass header.d=evil.test; spf=pass"],
+            "alice@example.com",
+            ""
+        ));
+        assert!(!is_authenticated(&[], "alice@example.com", ""));
+        // A DMARC pass must say which From domain it was for
+        assert!(!is_authenticated(
+            &["mx.example.com; dmarc=pass"],
+            "alice@example.com",
+            ""
+        ));
+        assert!(!is_authenticated(
+            &["mx.example.com; dmarc=pass header.from=evil.test"],
+            "alice@example.com",
+            ""
+        ));
+
+        // A header the sender added below the server's own is not trusted
+        let forged = [
+            "mx.example.com; dmarc=fail header.from=example.com",
+            "mx.example.com; dmarc=pass header.from=example.com",
+        ];
+        assert!(!is_authenticated(&forged, "alice@example.com", ""));
+        let stamped = [
+            "relay.test; dmarc=pass (forged) header.from=example.com",
+            "mx.example.com; dmarc=pass header.from=example.com",
+        ];
+        assert!(!is_authenticated(
+            &stamped[..1],
+            "alice@example.com",
+            "mx.example.com"
+        ));
+        assert!(is_authenticated(
+            &stamped,
+            "alice@example.com",
+            "mx.example.com"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_poll_and_threaded_reply() {
+        let seen = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let imap_port = imap_server(seen.clone()).await;
+        let (smtp_port, sent) = smtp_server().await;
+        let workspace = tempfile::tempdir().unwrap();
+
+        let mut config = EmailConfig {
+            enabled: true,
+            imap_host: "127.0.0.1".into(),
+            imap_port,
+            imap_security: "none".into(),
+            smtp_host: "127.0.0.1".into(),
+            smtp_port,
+            smtp_security: "none".into(),
+            username: "bot@example.com".into(),
+            password: "pw\"1".into(),
+            poll_interval_secs: 1,
+            allow_from: vec!["alice@example.com".into()],
+            ..Default::default()
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = EmailChannel::new(&config, workspace.path().to_path_buf(), bus.clone());
+        channel.start().await.unwrap();
+
+        let msg = tokio::time::timeout(Duration::from_secs(10), bus.consume_inbound())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.sender_id, "alice@example.com");
+        assert_eq!(msg.chat_id, "alice@example.com/root@example.com");
+        assert_eq!(msg.session_key, "email:alice@example.com/root@example.com");
+        assert_eq!(msg.content, "Numbers attached.");
+        assert_eq!(msg.metadata["sender_name"], "Alice");
+        assert_eq!(msg.media.len(), 1);
+        assert!(msg.media[0].ends_with("report.csv"));
+        assert!(msg.media[0].starts_with(
+            &workspace
+                .path()
+                .join("media")
+                .join("email")
+                .display()
+                .to_string()
+        ));
+        assert_eq!(
+            std::fs::read_to_string(&msg.media[0]).unwrap(),
+            "a,b\n1,2\n"
+        );
+
+        // The stranger's mail is flagged but never reaches the agent
+        for _ in 0..50 {
+            if seen.lock().unwrap().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(seen.lock().unwrap().len(), 2);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), bus.consume_inbound())
+                .await
+                .is_err()
+        );
+
+        channel
+            .send(OutboundMessage {
+                channel: "email".into(),
+                chat_id: msg.chat_id.clone(),
+                content: "Revenue is up 12%.".into(),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await
+            .unwrap();
+        let data = sent.await.unwrap();
+        assert!(data.contains("To: Alice <alice@example.com>"));
+        assert!(data.contains("Subject: Re: Quarterly report"));
+        assert!(data.contains("In-Reply-To: <msg2@example.com>"));
+        assert!(data.contains("References: <root@example.com> <msg2@example.com>"));
+        assert!(data.contains("Revenue is up 12%."));
+
+        assert!(channel
+            .send(OutboundMessage {
+                channel: "email".into(),
+                chat_id: "unknown@example.com".into(),
+                content: "?".into(),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await
+            .is_err());
+        channel.stop().await.unwrap();
+
+        // After a restart the thread is still known and answered in place
+        let (smtp_port, sent) = smtp_server().await;
+        config.smtp_port = smtp_port;
+        let channel = EmailChannel::new(&config, workspace.path().to_path_buf(), bus.clone());
+        channel
+            .send(OutboundMessage {
+                channel: "email".into(),
+                chat_id: msg.chat_id.clone(),
+                content: "Costs are flat.".into(),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await
+            .unwrap();
+        let data = sent.await.unwrap();
+        assert!(data.contains("To: Alice <alice@example.com>"));
+        // It answers the first reply, which the new channel never sent
+        assert!(data.contains("References: <root@example.com> <msg2@example.com>"));
+        assert!(data.contains("In-Reply-To: <"));
+        assert!(!data.contains("In-Reply-To: <msg2@example.com>"));
+    }
+
+    #[tokio::test]
+    async fn test_references_do_not_cross_senders() {
+        let workspace = tempfile::tempdir().unwrap();
+        let config = EmailConfig {
+            allow_from: vec!["alice@example.com".into(), "bob@example.com".into()],
+            ..Default::default()
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = EmailChannel::new(&config, workspace.path().to_path_buf(), bus.clone());
+
+        channel.inbox.handle(REPLY.as_bytes()).await;
+        let alice = bus.consume_inbound().await.unwrap();
+        let hijack = REPLY
+            .replace("Alice <Alice@Example.com>", "bob@example.com")
+            .replace("msg2@example.com", "msg3@example.com");
+        channel.inbox.handle(hijack.as_bytes()).await;
+        let bob = bus.consume_inbound().await.unwrap();
+
+        // A forged From: without the receiving server's vouching is dropped
+        let forged = REPLY
+            .replace("dkim=pass", "dkim=fail")
+            .replace("dmarc=pass", "dmarc=fail");
+        channel.inbox.handle(forged.as_bytes()).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), bus.consume_inbound())
+                .await
+                .is_err()
+        );
+
+        assert_eq!(bob.chat_id, "bob@example.com/root@example.com");
+        assert_ne!(bob.session_key, alice.session_key);
+        let threads = channel.inbox.threads.lock().await;
+        assert_eq!(threads[&alice.chat_id].to, "alice@example.com");
+        assert_eq!(threads[&alice.chat_id].last_id, "msg2@example.com");
+    }
+}
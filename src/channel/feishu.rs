@@ -0,0 +1,616 @@
+// QuectoClaw — Feishu/Lark channel implementation (event subscription webhook + IM API)
+
+use crate::bus::{GroupContext, MessageBus, OutboundMessage};
+use crate::channel::{looks_like_markdown, BaseChannel, CachedToken, Channel};
+use crate::config::FeishuConfig;
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use axum::Router;
+use base64::Engine;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+const FEISHU_API: &str = "https://open.feishu.cn";
+const LARK_API: &str = "https://open.larksuite.com";
+const DEFAULT_WEBHOOK_PATH: &str = "/webhook/feishu";
+
+/// How many recent event ids to remember for dropping Feishu's retries.
+const SEEN_EVENTS: usize = 256;
+
+/// Error codes for an invalid or expired tenant access token.
+const TOKEN_ERRORS: [i64; 2] = [99991661, 99991663];
+
+pub struct FeishuChannel {
+    base: Arc<BaseChannel>,
+    config: FeishuConfig,
+    api_base: String,
+    client: Client,
+    token: CachedToken,
+    local_addr: Mutex<Option<SocketAddr>>,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl FeishuChannel {
+    pub fn new(config: &FeishuConfig, bus: Arc<MessageBus>) -> Self {
+        let api_base = if config.domain.eq_ignore_ascii_case("lark") {
+            LARK_API
+        } else {
+            FEISHU_API
+        };
+        Self {
+            base: Arc::new(BaseChannel::new("feishu", config.allow_from.clone(), bus)),
+            config: config.clone(),
+            api_base: api_base.to_string(),
+            client: Client::new(),
+            token: CachedToken::default(),
+            local_addr: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Use a different Open API base URL (for tests).
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The address the event server is bound to, once started.
+    pub async fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.lock().await
+    }
+
+    async fn tenant_token(&self) -> anyhow::Result<String> {
+        self.token
+            .get(|| async {
+                let resp: Value = self
+                    .client
+                    .post(format!(
+                        "{}/open-apis/auth/v3/tenant_access_token/internal",
+                        self.api_base
+                    ))
+                    .json(&json!({
+                        "app_id": self.config.app_id,
+                        "app_secret": self.config.app_secret,
+                    }))
+                    .send()
+                    .await?
+                    .json()
+                    .await?;
+                if resp["code"].as_i64() != Some(0) {
+                    anyhow::bail!(
+                        "Feishu token error: {}",
+                        resp["msg"].as_str().unwrap_or("unknown error")
+                    );
+                }
+                let token = resp["tenant_access_token"]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Feishu token response has no token"))?;
+                let expire = resp["expire"].as_u64().unwrap_or(7200);
+                Ok((token.to_string(), Duration::from_secs(expire)))
+            })
+            .await
+    }
+
+    async fn send_message(&self, body: &Value) -> anyhow::Result<i64> {
+        let token = self.tenant_token().await?;
+        let resp: Value = self
+            .client
+            .post(format!(
+                "{}/open-apis/im/v1/messages?receive_id_type=chat_id",
+                self.api_base
+            ))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        match resp["code"].as_i64() {
+            Some(0) => Ok(0),
+            Some(code) if TOKEN_ERRORS.contains(&code) => Ok(code),
+            _ => Err(anyhow!(
+                "Feishu API error: {}",
+                resp["msg"].as_str().unwrap_or("unknown error")
+            )),
+        }
+    }
+}
+
+/// Decrypt an `encrypt` payload: AES-256-CBC keyed with SHA-256 of the
+/// encrypt key, the IV in the first block.
+fn decrypt(encrypt_key: &str, encrypted: &str) -> anyhow::Result<String> {
+    let data = base64::engine::general_purpose::STANDARD.decode(encrypted)?;
+    if data.len() < 32 || data.len() % 16 != 0 {
+        anyhow::bail!("encrypted payload has an invalid length");
+    }
+    let key = Sha256::digest(encrypt_key.as_bytes());
+    let (iv, ciphertext) = data.split_at(16);
+    let mut buf = ciphertext.to_vec();
+    let plain = cbc::Decryptor::<aes::Aes256>::new_from_slices(&key, iv)
+        .map_err(|_| anyhow!("invalid key or IV"))?
+        .decrypt_padded_mut::<Pkcs7>(&mut buf)
+        .map_err(|_| anyhow!("wrong encrypt key"))?;
+    Ok(String::from_utf8(plain.to_vec())?)
+}
+
+/// `X-Lark-Signature`: hex SHA-256 of timestamp, nonce, encrypt key and body.
+fn signature(timestamp: &str, nonce: &str, encrypt_key: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(timestamp.as_bytes());
+    hasher.update(nonce.as_bytes());
+    hasher.update(encrypt_key.as_bytes());
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// The text of a `text` or `post` message, with `@_user_N` mention
+/// placeholders removed.
+fn message_text(message: &Value) -> String {
+    let content: Value = message["content"]
+        .as_str()
+        .and_then(|c| serde_json::from_str(c).ok())
+        .unwrap_or_default();
+    let mut text = match message["message_type"].as_str() {
+        Some("text") => content["text"].as_str().unwrap_or("").to_string(),
+        Some("post") => {
+            // Rich text: a title and paragraphs of inline elements
+            let mut lines: Vec<String> = content["title"]
+                .as_str()
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .into_iter()
+                .collect();
+            for paragraph in content["content"].as_array().into_iter().flatten() {
+                let line: String = paragraph
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|el| match el["tag"].as_str() {
+                        Some("text") | Some("a") => el["text"].as_str(),
+                        _ => None,
+                    })
+                    .collect();
+                lines.push(line);
+            }
+            lines.join("\n")
+        }
+        _ => String::new(),
+    };
+    for mention in message["mentions"].as_array().into_iter().flatten() {
+        if let Some(key) = mention["key"].as_str() {
+            text = text.replace(key, "");
+        }
+    }
+    text.trim().to_string()
+}
+
+/// The IM API body for a reply: Markdown goes out as a card, since plain
+/// text messages do not render it.
+fn message_body(chat_id: &str, content: &str) -> Value {
+    if looks_like_markdown(content) {
+        let card = json!({
+            "config": { "wide_screen_mode": true },
+            "elements": [{ "tag": "markdown", "content": content }],
+        });
+        json!({ "receive_id": chat_id, "msg_type": "interactive", "content": card.to_string() })
+    } else {
+        let text = json!({ "text": content });
+        json!({ "receive_id": chat_id, "msg_type": "text", "content": text.to_string() })
+    }
+}
+
+/// Shared state of the event server.
+struct Events {
+    base: Arc<BaseChannel>,
+    encrypt_key: String,
+    verification_token: String,
+    seen: Mutex<VecDeque<String>>,
+}
+
+impl Events {
+    async fn on_message(&self, event: &Value) {
+        if event["sender"]["sender_type"] != "user" {
+            return;
+        }
+        let message = &event["message"];
+        let Some(chat_id) = message["chat_id"].as_str() else {
+            return;
+        };
+        let text = message_text(message);
+        if text.is_empty() {
+            return;
+        }
+
+        let ids = &event["sender"]["sender_id"];
+        let open_id = ids["open_id"].as_str().unwrap_or("");
+        let sender_id = match ids["user_id"].as_str() {
+            Some(user_id) if !user_id.is_empty() => format!("{}|{}", open_id, user_id),
+            _ => open_id.to_string(),
+        };
+
+        let mut metadata = HashMap::new();
+        for key in ["message_id", "chat_type", "root_id"] {
+            if let Some(value) = message[key].as_str() {
+                metadata.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        if message["chat_type"] == "group" {
+            // Bots have no user_id, so a mention without one is (most
+            // likely) of us
+            let mentioned = message["mentions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|m| m["id"]["user_id"].as_str().unwrap_or("").is_empty());
+            let group = GroupContext {
+                mentioned,
+                thread_id: message["root_id"].as_str().map(String::from),
+                ..Default::default()
+            };
+            self.base
+                .handle_group_message(&sender_id, chat_id, &text, Vec::new(), metadata, group)
+                .await;
+        } else {
+            self.base
+                .handle_message(&sender_id, chat_id, &text, Vec::new(), metadata)
+                .await;
+        }
+    }
+}
+
+async fn events(State(events): State<Arc<Events>>, headers: HeaderMap, body: Bytes) -> Response {
+    let Ok(mut payload) = serde_json::from_slice::<Value>(&body) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    // With an encrypt key set, everything arrives encrypted and signed
+    if !events.encrypt_key.is_empty() {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+                .to_string()
+        };
+        let sig = header("x-lark-signature");
+        if !sig.is_empty()
+            && sig
+                != signature(
+                    &header("x-lark-request-timestamp"),
+                    &header("x-lark-request-nonce"),
+                    &events.encrypt_key,
+                    &body,
+                )
+        {
+            tracing::warn!(channel = "feishu", "Rejected event with invalid signature");
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        let Some(encrypted) = payload["encrypt"].as_str() else {
+            tracing::warn!(channel = "feishu", "Rejected unencrypted event");
+            return StatusCode::UNAUTHORIZED.into_response();
+        };
+        payload = match decrypt(&events.encrypt_key, encrypted)
+            .and_then(|plain| Ok(serde_json::from_str(&plain)?))
+        {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::warn!(channel = "feishu", error = %e, "Failed to decrypt event");
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+        };
+    }
+
+    // v1 callbacks (and the URL check) carry the token at the top level
+    let token = payload["token"]
+        .as_str()
+        .or_else(|| payload["header"]["token"].as_str())
+        .unwrap_or("");
+    if !events.verification_token.is_empty() && token != events.verification_token {
+        tracing::warn!(
+            channel = "feishu",
+            "Rejected event with wrong verification token"
+        );
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    if payload["type"] == "url_verification" {
+        return Json(json!({ "challenge": payload["challenge"] })).into_response();
+    }
+
+    let header = &payload["header"];
+    if let Some(event_id) = header["event_id"].as_str() {
+        let mut seen = events.seen.lock().await;
+        if seen.iter().any(|id| id == event_id) {
+            return Json(json!({})).into_response();
+        }
+        if seen.len() == SEEN_EVENTS {
+            seen.pop_front();
+        }
+        seen.push_back(event_id.to_string());
+    }
+    if header["event_type"] == "im.message.receive_v1" {
+        events.on_message(&payload["event"]).await;
+    }
+    Json(json!({})).into_response()
+}
+
+#[async_trait]
+impl Channel for FeishuChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.config.app_id.is_empty() || self.config.app_secret.is_empty() {
+            anyhow::bail!("Feishu app_id and app_secret are not configured");
+        }
+        if self.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if self.config.encrypt_key.is_empty() && self.config.verification_token.is_empty() {
+            tracing::warn!(
+                channel = "feishu",
+                "Neither encrypt_key nor verification_token is set; events are not authenticated"
+            );
+        }
+
+        let host = if self.config.webhook_host.is_empty() {
+            "127.0.0.1"
+        } else {
+            &self.config.webhook_host
+        };
+        let path = if self.config.webhook_path.is_empty() {
+            DEFAULT_WEBHOOK_PATH
+        } else {
+            &self.config.webhook_path
+        };
+
+        let state = Arc::new(Events {
+            base: self.base.clone(),
+            encrypt_key: self.config.encrypt_key.clone(),
+            verification_token: self.config.verification_token.clone(),
+            seen: Mutex::new(VecDeque::new()),
+        });
+        let app = Router::new()
+            .route(path, axum::routing::post(events))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind((host, self.config.webhook_port)).await?;
+        let addr = listener.local_addr()?;
+        *self.local_addr.lock().await = Some(addr);
+        tracing::info!(channel = %self.name(), addr = %addr, path = %path, "Starting Feishu event server");
+
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+        *self.task.lock().await = Some(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!(channel = "feishu", error = %e, "Event server failed");
+            }
+            running.store(false, Ordering::SeqCst);
+        }));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let body = message_body(&msg.chat_id, &msg.content);
+        if self.send_message(&body).await? != 0 {
+            // The cached token was revoked or expired early; fetch a new one
+            self.token.invalidate().await;
+            if self.send_message(&body).await? != 0 {
+                anyhow::bail!("Feishu rejected the tenant access token");
+            }
+        }
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use aes::cipher::BlockEncryptMut;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ENCRYPT_KEY: &str = "encrypt-key";
+
+    fn encrypt(plain: &str) -> String {
+        let key = Sha256::digest(ENCRYPT_KEY.as_bytes());
+        let iv = [7u8; 16];
+        let mut buf = plain.as_bytes().to_vec();
+        let len = buf.len();
+        buf.resize(len + 16, 0);
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new_from_slices(&key, &iv)
+            .unwrap()
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, len)
+            .unwrap()
+            .to_vec();
+        base64::engine::general_purpose::STANDARD.encode([iv.to_vec(), ciphertext].concat())
+    }
+
+    fn message_event(event_id: &str, text: &str) -> Value {
+        json!({
+            "schema": "2.0",
+            "header": { "event_id": event_id, "event_type": "im.message.receive_v1", "token": "verify" },
+            "event": {
+                "sender": { "sender_id": { "open_id": "ou_1", "user_id": "alice" }, "sender_type": "user" },
+                "message": {
+                    "message_id": "om_1", "chat_id": "oc_1", "chat_type": "group", "message_type": "text",
+                    "content": json!({ "text": format!("@_user_1 {}", text) }).to_string(),
+                    "mentions": [{ "key": "@_user_1", "name": "bot" }]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_decrypt_and_signature() {
+        let plain = r#"{"challenge":"abc"}"#;
+        assert_eq!(decrypt(ENCRYPT_KEY, &encrypt(plain)).unwrap(), plain);
+        assert!(decrypt("other-key", &encrypt(plain)).is_err());
+        assert!(decrypt(ENCRYPT_KEY, "c2hvcnQ=").is_err());
+
+        let sig = signature("1700000000", "nonce", ENCRYPT_KEY, b"{}");
+        assert_eq!(sig.len(), 64);
+        assert_ne!(sig, signature("1700000001", "nonce", ENCRYPT_KEY, b"{}"));
+    }
+
+    #[test]
+    fn test_message_text_and_body() {
+        let post = json!({
+            "message_type": "post",
+            "content": json!({
+                "title": "Plan",
+                "content": [[{ "tag": "at", "user_id": "x" }, { "tag": "text", "text": "step one" }]]
+            }).to_string()
+        });
+        assert_eq!(message_text(&post), "Plan\nstep one");
+
+        let body = message_body("oc_1", "plain words");
+        assert_eq!(body["msg_type"], "text");
+        let body = message_body("oc_1", "## Title\n- item");
+        assert_eq!(body["msg_type"], "interactive");
+        assert!(body["content"]
+            .as_str()
+            .unwrap()
+            .contains("\"tag\":\"markdown\""));
+    }
+
+    #[tokio::test]
+    async fn test_events_and_replies() {
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/auth/v3/tenant_access_token/internal"))
+            .and(body_partial_json(
+                json!({ "app_id": "cli_1", "app_secret": "secret" }),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(
+                    json!({ "code": 0, "tenant_access_token": "t-1", "expire": 7200 }),
+                ),
+            )
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/open-apis/im/v1/messages"))
+            .and(query_param("receive_id_type", "chat_id"))
+            .and(header("authorization", "Bearer t-1"))
+            .and(body_partial_json(json!({ "receive_id": "oc_1" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": 0 })))
+            .expect(2)
+            .mount(&api)
+            .await;
+
+        let config = FeishuConfig {
+            enabled: true,
+            app_id: "cli_1".into(),
+            app_secret: "secret".into(),
+            encrypt_key: ENCRYPT_KEY.into(),
+            verification_token: "verify".into(),
+            webhook_host: "127.0.0.1".into(),
+            webhook_port: 0,
+            allow_from: vec!["alice".into()],
+            ..Default::default()
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = FeishuChannel::new(&config, bus.clone()).with_api_base(&api.uri());
+        channel.start().await.unwrap();
+        let url = format!(
+            "http://{}/webhook/feishu",
+            channel.local_addr().await.unwrap()
+        );
+        let http = Client::new();
+        let post = |payload: Value| {
+            let body = json!({ "encrypt": encrypt(&payload.to_string()) }).to_string();
+            http.post(&url)
+                .header("x-lark-request-timestamp", "1700000000")
+                .header("x-lark-request-nonce", "n")
+                .header(
+                    "x-lark-signature",
+                    signature("1700000000", "n", ENCRYPT_KEY, body.as_bytes()),
+                )
+                .body(body)
+                .send()
+        };
+
+        // URL verification echoes the challenge
+        let resp =
+            post(json!({ "type": "url_verification", "challenge": "c-1", "token": "verify" }))
+                .await
+                .unwrap();
+        assert_eq!(resp.json::<Value>().await.unwrap()["challenge"], "c-1");
+
+        // Plain, mis-signed and wrong-token requests are refused
+        let plain = http
+            .post(&url)
+            .body(message_event("e0", "hi").to_string())
+            .send();
+        assert_eq!(plain.await.unwrap().status(), 401);
+        let forged = http
+            .post(&url)
+            .header("x-lark-signature", "00")
+            .body(json!({ "encrypt": encrypt("{}") }).to_string())
+            .send();
+        assert_eq!(forged.await.unwrap().status(), 401);
+        let mut wrong = message_event("e0", "hi");
+        wrong["header"]["token"] = json!("nope");
+        assert_eq!(post(wrong).await.unwrap().status(), 401);
+
+        // A retried event is handled once
+        post(message_event("e1", "what's up")).await.unwrap();
+        post(message_event("e1", "what's up")).await.unwrap();
+        post(message_event("e2", "second")).await.unwrap();
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "oc_1");
+        assert_eq!(msg.sender_id, "ou_1|alice");
+        assert_eq!(msg.content, "what's up");
+        assert_eq!(msg.metadata["chat_type"], "group");
+        assert!(msg.group.unwrap().mentioned);
+        assert_eq!(bus.consume_inbound().await.unwrap().content, "second");
+
+        // Both replies share one tenant token
+        for content in ["plain reply", "**bold** reply"] {
+            channel
+                .send(OutboundMessage {
+                    channel: "feishu".into(),
+                    chat_id: "oc_1".into(),
+                    content: content.into(),
+                    metadata: HashMap::new(),
+                    kind: OutboundKind::Message,
+                    attachments: Vec::new(),
+                })
+                .await
+                .unwrap();
+        }
+        channel.stop().await.unwrap();
+    }
+}
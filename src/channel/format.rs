@@ -0,0 +1,572 @@
+// QuectoClaw — Reply formatting: Markdown to platform dialects, long-message splitting
+//
+// The agent writes CommonMark-ish Markdown. Telegram gets HTML (its
+// MarkdownV2 needs escaping of almost every symbol), Slack gets mrkdwn and
+// Discord renders Markdown itself. Replies over a platform's length limit
+// are split on paragraph boundaries; a code block that has to be cut is
+// closed and reopened so every part still renders.
+
+/// Telegram rejects messages over 4096 characters.
+pub const TELEGRAM_MAX_CHARS: usize = 4096;
+/// Discord rejects messages over 2000 characters.
+pub const DISCORD_MAX_CHARS: usize = 2000;
+/// Slack truncates long texts; it recommends staying under 4000.
+pub const SLACK_MAX_CHARS: usize = 4000;
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// The fence marker (run of ``` or ~~~) a line opens or closes, if any.
+fn fence_marker(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let c = trimmed.chars().next()?;
+    if c != '`' && c != '~' {
+        return None;
+    }
+    let len = trimmed.chars().take_while(|&x| x == c).count();
+    (len >= 3).then(|| &trimmed[..len])
+}
+
+/// A paragraph, or a whole fenced code block (fences included).
+struct Segment<'a> {
+    lines: Vec<&'a str>,
+    fenced: bool,
+    /// Blank line before it in the source (else a single newline).
+    blank_before: bool,
+}
+
+fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut blank_before = false;
+    let mut fence: Option<&str> = None;
+
+    for line in text.lines() {
+        if let Some(open) = fence {
+            current.push(line);
+            if fence_marker(line).is_some_and(|m| m.starts_with(open))
+                && line
+                    .trim()
+                    .chars()
+                    .all(|c| c == open.chars().next().unwrap_or('`'))
+            {
+                segments.push(Segment {
+                    lines: std::mem::take(&mut current),
+                    fenced: true,
+                    blank_before,
+                });
+                blank_before = false;
+                fence = None;
+            }
+        } else if let Some(marker) = fence_marker(line) {
+            if !current.is_empty() {
+                segments.push(Segment {
+                    lines: std::mem::take(&mut current),
+                    fenced: false,
+                    blank_before,
+                });
+                blank_before = false;
+            }
+            current.push(line);
+            fence = Some(marker);
+        } else if line.trim().is_empty() {
+            if !current.is_empty() {
+                segments.push(Segment {
+                    lines: std::mem::take(&mut current),
+                    fenced: false,
+                    blank_before,
+                });
+            }
+            blank_before = true;
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        segments.push(Segment {
+            lines: current,
+            fenced: fence.is_some(),
+            blank_before,
+        });
+    }
+    segments
+}
+
+/// Cut a single over-long line, preferring the last whitespace in range.
+fn hard_split(line: &str, max: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest: Vec<char> = line.chars().collect();
+    while rest.len() > max {
+        let cut = rest[..max]
+            .iter()
+            .rposition(|c| c.is_whitespace())
+            .filter(|&i| i > 0)
+            .unwrap_or(max);
+        parts.push(
+            rest[..cut]
+                .iter()
+                .collect::<String>()
+                .trim_end()
+                .to_string(),
+        );
+        rest = rest[cut..].to_vec();
+        while rest.first().is_some_and(|c| c.is_whitespace()) {
+            rest.remove(0);
+        }
+    }
+    if !rest.is_empty() {
+        parts.push(rest.into_iter().collect());
+    }
+    parts
+}
+
+/// Join lines greedily into parts of at most `max` characters.
+fn group_lines(lines: &[&str], max: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        let pieces = if char_len(line) > max {
+            hard_split(line, max)
+        } else {
+            vec![line.to_string()]
+        };
+        for piece in pieces {
+            if current.is_empty() {
+                current = piece;
+            } else if char_len(&current) + 1 + char_len(&piece) <= max {
+                current.push('\n');
+                current.push_str(&piece);
+            } else {
+                parts.push(std::mem::replace(&mut current, piece));
+            }
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// Split a segment that does not fit into parts that do.
+fn fit(segment: &Segment, max: usize) -> Vec<String> {
+    let text = segment.lines.join("\n");
+    if char_len(&text) <= max {
+        return vec![text];
+    }
+    if !segment.fenced {
+        return group_lines(&segment.lines, max);
+    }
+
+    let open = segment.lines[0];
+    let marker = fence_marker(open).unwrap_or("```");
+    let mut inner = &segment.lines[1..];
+    if inner.last().is_some_and(|l| fence_marker(l).is_some()) {
+        inner = &inner[..inner.len() - 1];
+    }
+    let budget = max
+        .saturating_sub(char_len(open) + char_len(marker) + 2)
+        .max(1);
+    group_lines(inner, budget)
+        .into_iter()
+        .map(|body| format!("{}\n{}\n{}", open, body, marker))
+        .collect()
+}
+
+/// Split a reply into parts of at most `max_chars` characters, breaking
+/// between paragraphs where possible and never inside a code block without
+/// closing and reopening it.
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    if char_len(text) <= max_chars {
+        return vec![text.to_string()];
+    }
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for segment in segments(text) {
+        let separator = if segment.blank_before { "\n\n" } else { "\n" };
+        for part in fit(&segment, max_chars) {
+            if current.is_empty() {
+                current = part;
+            } else if char_len(&current) + char_len(separator) + char_len(&part) <= max_chars {
+                current.push_str(separator);
+                current.push_str(&part);
+            } else {
+                chunks.push(std::mem::replace(&mut current, part));
+            }
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// ---------------------------------------------------------------------------
+// Dialects
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dialect {
+    TelegramHtml,
+    SlackMrkdwn,
+}
+
+/// Both dialects escape the same three characters.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn find(chars: &[char], from: usize, pattern: &str) -> Option<usize> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    (from..chars.len().saturating_sub(pattern.len() - 1))
+        .find(|&i| chars[i..i + pattern.len()] == pattern[..])
+}
+
+/// Closing delimiter of a `*`/`_` emphasis opened at `start`.
+fn find_emphasis_end(chars: &[char], start: usize, delim: char) -> Option<usize> {
+    if chars.get(start + 1).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    // `_` inside words (snake_case) is not emphasis
+    if delim == '_' && start > 0 && chars[start - 1].is_alphanumeric() {
+        return None;
+    }
+    (start + 2..chars.len()).find(|&j| {
+        chars[j] == delim
+            && !chars[j - 1].is_whitespace()
+            && chars.get(j + 1) != Some(&delim)
+            && !(delim == '_' && chars.get(j + 1).is_some_and(|c| c.is_alphanumeric()))
+    })
+}
+
+fn inline(text: &str, dialect: Dialect) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let slice = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+
+        if c == '`' {
+            if let Some(end) = find(&chars, i + 1, "`") {
+                let code = escape(&slice(i + 1, end));
+                match dialect {
+                    Dialect::TelegramHtml => out.push_str(&format!("<code>{}</code>", code)),
+                    Dialect::SlackMrkdwn => out.push_str(&format!("`{}`", code)),
+                }
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if rest.starts_with(&['*', '*'])
+            || rest.starts_with(&['_', '_'])
+            || rest.starts_with(&['~', '~'])
+        {
+            let delim = slice(i, i + 2);
+            if let Some(end) = find(&chars, i + 2, &delim).filter(|&end| end > i + 2) {
+                let inner = inline(&slice(i + 2, end), dialect);
+                let (open, close) = match (dialect, c) {
+                    (Dialect::TelegramHtml, '~') => ("<s>", "</s>"),
+                    (Dialect::TelegramHtml, _) => ("<b>", "</b>"),
+                    (Dialect::SlackMrkdwn, '~') => ("~", "~"),
+                    (Dialect::SlackMrkdwn, _) => ("*", "*"),
+                };
+                out.push_str(&format!("{}{}{}", open, inner, close));
+                i = end + 2;
+                continue;
+            }
+        }
+
+        if c == '*' || c == '_' {
+            if let Some(end) = find_emphasis_end(&chars, i, c) {
+                let inner = inline(&slice(i + 1, end), dialect);
+                match dialect {
+                    Dialect::TelegramHtml => out.push_str(&format!("<i>{}</i>", inner)),
+                    Dialect::SlackMrkdwn => out.push_str(&format!("_{}_", inner)),
+                }
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if c == '[' {
+            let link = find(&chars, i + 1, "](").and_then(|mid| {
+                let end = find(&chars, mid + 2, ")")?;
+                Some((mid, end))
+            });
+            if let Some((mid, end)) = link {
+                let label = inline(&slice(i + 1, mid), dialect);
+                let url = slice(mid + 2, end);
+                match dialect {
+                    Dialect::TelegramHtml => out.push_str(&format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape(&url).replace('"', "&quot;"),
+                        label
+                    )),
+                    Dialect::SlackMrkdwn => out.push_str(&format!("<{}|{}>", escape(&url), label)),
+                }
+                i = end + 1;
+                continue;
+            }
+        }
+
+        out.push_str(&escape(&c.to_string()));
+        i += 1;
+    }
+    out
+}
+
+/// `- item`, `* item` or `+ item`, as (indent, item).
+fn list_item(line: &str) -> Option<(&str, &str)> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| trimmed.strip_prefix(bullet))
+        .map(|item| (indent, item))
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) {
+        line[hashes..].strip_prefix(' ').map(str::trim)
+    } else {
+        None
+    }
+}
+
+fn code_block(language: &str, lines: &[&str], dialect: Dialect) -> String {
+    let code = escape(&lines.join("\n"));
+    match dialect {
+        Dialect::TelegramHtml if !language.is_empty() => format!(
+            "<pre><code class=\"language-{}\">{}</code></pre>",
+            escape(language),
+            code
+        ),
+        Dialect::TelegramHtml => format!("<pre><code>{}</code></pre>", code),
+        // Slack code blocks take no language
+        Dialect::SlackMrkdwn => format!("```\n{}\n```", code),
+    }
+}
+
+fn convert(md: &str, dialect: Dialect) -> String {
+    let mut out: Vec<String> = Vec::new();
+    // Open code block: (fence marker, language, lines)
+    let mut fence: Option<(&str, &str, Vec<&str>)> = None;
+    let mut quote: Vec<String> = Vec::new();
+
+    let flush_quote = |quote: &mut Vec<String>, out: &mut Vec<String>| {
+        if quote.is_empty() {
+            return;
+        }
+        match dialect {
+            Dialect::TelegramHtml => {
+                out.push(format!("<blockquote>{}</blockquote>", quote.join("\n")))
+            }
+            Dialect::SlackMrkdwn => out.extend(quote.iter().map(|l| format!("> {}", l))),
+        }
+        quote.clear();
+    };
+
+    for line in md.lines() {
+        if let Some((marker, language, lines)) = &mut fence {
+            if fence_marker(line).is_some_and(|m| m.starts_with(*marker))
+                && line.trim().chars().all(|c| c == '`' || c == '~')
+            {
+                out.push(code_block(language, lines, dialect));
+                fence = None;
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        if let Some(rest) = trimmed.strip_prefix('>') {
+            quote.push(inline(rest.trim_start(), dialect));
+            continue;
+        }
+        flush_quote(&mut quote, &mut out);
+
+        if let Some(marker) = fence_marker(line) {
+            fence = Some((marker, trimmed[marker.len()..].trim(), Vec::new()));
+        } else if let Some(title) = heading(trimmed) {
+            out.push(match dialect {
+                Dialect::TelegramHtml => format!("<b>{}</b>", inline(title, dialect)),
+                Dialect::SlackMrkdwn => format!("*{}*", inline(title, dialect)),
+            });
+        } else if let Some((indent, item)) = list_item(line) {
+            out.push(format!("{}• {}", indent, inline(item, dialect)));
+        } else {
+            out.push(inline(line, dialect));
+        }
+    }
+    flush_quote(&mut quote, &mut out);
+
+    // An unclosed block would make Telegram reject the whole message
+    if let Some((_, language, lines)) = &fence {
+        out.push(code_block(language, lines, dialect));
+    }
+    out.join("\n")
+}
+
+/// Markdown to Telegram's HTML parse mode.
+pub fn to_telegram_html(md: &str) -> String {
+    convert(md, Dialect::TelegramHtml)
+}
+
+/// Length of Telegram HTML as Telegram counts it against
+/// `TELEGRAM_MAX_CHARS`: tags do not count and an entity is one character.
+pub fn telegram_visible_len(html: &str) -> usize {
+    let mut len = 0;
+    let mut chars = html.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                chars.by_ref().find(|&c| c == '>');
+            }
+            '&' => {
+                chars.by_ref().find(|&c| c == ';');
+                len += 1;
+            }
+            _ => len += 1,
+        }
+    }
+    len
+}
+
+/// Markdown to Slack mrkdwn.
+pub fn to_slack_mrkdwn(md: &str) -> String {
+    convert(md, Dialect::SlackMrkdwn)
+}
+
+/// Discord renders Markdown natively, except headings below `###`.
+pub fn to_discord(md: &str) -> String {
+    let mut fenced = false;
+    md.lines()
+        .map(|line| {
+            if fence_marker(line).is_some() {
+                fenced = !fenced;
+                return line.to_string();
+            }
+            let hashes = line.chars().take_while(|&c| c == '#').count();
+            match heading(line) {
+                Some(title) if !fenced && hashes > 3 => format!("**{}**", title),
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_messages_are_not_split() {
+        assert_eq!(split_message("hello", 10), vec!["hello"]);
+    }
+
+    #[test]
+    fn test_split_on_paragraphs() {
+        let text = format!(
+            "{}\n\n{}\n\n{}",
+            "a".repeat(30),
+            "b".repeat(30),
+            "c".repeat(30)
+        );
+        let parts = split_message(&text, 70);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            parts[0],
+            format!("{}\n\n{}", "a".repeat(30), "b".repeat(30))
+        );
+        assert_eq!(parts[1], "c".repeat(30));
+    }
+
+    #[test]
+    fn test_split_reopens_code_fences() {
+        let code: Vec<String> = (0..20).map(|i| format!("let x{} = {};", i, i)).collect();
+        let text = format!("Here:\n\n```rust\n{}\n```\n\nDone.", code.join("\n"));
+        let parts = split_message(&text, 120);
+        assert!(parts.len() > 2);
+        for part in &parts {
+            assert!(part.chars().count() <= 120, "{}", part);
+            assert_eq!(part.matches("```").count() % 2, 0, "unbalanced: {}", part);
+        }
+        let code_parts: Vec<_> = parts.iter().filter(|p| p.contains("let x")).collect();
+        assert!(code_parts.iter().all(|p| p.contains("```rust\n")));
+        assert!(parts.last().unwrap().ends_with("Done."));
+    }
+
+    #[test]
+    fn test_split_long_lines() {
+        let text = "word ".repeat(100);
+        let parts = split_message(text.trim(), 50);
+        assert!(parts.iter().all(|p| p.chars().count() <= 50));
+        assert_eq!(parts.join(" "), text.trim());
+        let unbroken = "x".repeat(120);
+        assert_eq!(split_message(&unbroken, 50).concat(), unbroken);
+    }
+
+    #[test]
+    fn test_telegram_html() {
+        assert_eq!(
+            to_telegram_html("## Result\n**Done** in *3s* with `a<b` — see [docs](https://x.io/?a=1&b=2)"),
+            "<b>Result</b>\n<b>Done</b> in <i>3s</i> with <code>a&lt;b</code> — see <a href=\"https://x.io/?a=1&amp;b=2\">docs</a>"
+        );
+        assert_eq!(
+            to_telegram_html("```python\nif a < b:\n    pass\n```"),
+            "<pre><code class=\"language-python\">if a &lt; b:\n    pass</code></pre>"
+        );
+        assert_eq!(
+            to_telegram_html("- one\n- two_three\n> quoted\n> more"),
+            "• one\n• two_three\n<blockquote>quoted\nmore</blockquote>"
+        );
+        assert_eq!(to_telegram_html("3 * 4 = 12, x_y_z"), "3 * 4 = 12, x_y_z");
+        // Unclosed fences are closed
+        assert_eq!(
+            to_telegram_html("```\ncode"),
+            "<pre><code>code</code></pre>"
+        );
+    }
+
+    #[test]
+    fn test_telegram_visible_len() {
+        let html = to_telegram_html("**bold** [link](https://example.com) a < b");
+        assert_eq!(telegram_visible_len(&html), "bold link a < b".len());
+
+        // Markup alone never pushes a full chunk over the limit
+        let md = "**x** ".repeat(TELEGRAM_MAX_CHARS / 6);
+        let html = to_telegram_html(&md);
+        assert!(char_len(&html) > TELEGRAM_MAX_CHARS);
+        assert!(telegram_visible_len(&html) <= TELEGRAM_MAX_CHARS);
+    }
+
+    #[test]
+    fn test_slack_mrkdwn() {
+        assert_eq!(
+            to_slack_mrkdwn("# Title\n**bold**, *it*, ~~gone~~ and [link](https://x.io)"),
+            "*Title*\n*bold*, _it_, ~gone~ and <https://x.io|link>"
+        );
+        assert_eq!(
+            to_slack_mrkdwn("```rust\nlet a = b && c;\n```\n> note"),
+            "```\nlet a = b &amp;&amp; c;\n```\n> note"
+        );
+    }
+
+    #[test]
+    fn test_discord() {
+        assert_eq!(
+            to_discord("## Kept\n#### Deep\n```\n#### not a heading\n```"),
+            "## Kept\n**Deep**\n```\n#### not a heading\n```"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// QuectoClaw — Channel health: connection state, last message and last error
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// A snapshot older than this means the gateway is no longer running.
+const STALE_AFTER_SECS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelState {
+    Starting,
+    Connected,
+    /// Down, waiting to be started again.
+    Reconnecting,
+    Stopped,
+}
+
+impl ChannelState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChannelState::Starting => "starting",
+            ChannelState::Connected => "connected",
+            ChannelState::Reconnecting => "reconnecting",
+            ChannelState::Stopped => "stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelHealth {
+    pub name: String,
+    pub state: ChannelState,
+    /// When the channel entered its current state.
+    pub since: DateTime<Utc>,
+    #[serde(default)]
+    pub last_message: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Restarts after a failed start or an unexpected stop.
+    #[serde(default)]
+    pub restarts: u32,
+}
+
+impl ChannelHealth {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            state: ChannelState::Starting,
+            since: Utc::now(),
+            last_message: None,
+            last_error: None,
+            last_error_at: None,
+            restarts: 0,
+        }
+    }
+
+    pub fn connected(&self) -> bool {
+        self.state == ChannelState::Connected
+    }
+}
+
+/// Health of every channel, shared by the supervisor and the outbound
+/// deliveries.
+#[derive(Clone, Default)]
+pub struct HealthBoard {
+    channels: Arc<RwLock<BTreeMap<String, ChannelHealth>>>,
+}
+
+impl HealthBoard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with<R>(&self, name: &str, f: impl FnOnce(&mut ChannelHealth) -> R) -> Option<R> {
+        let mut channels = self.channels.write().ok()?;
+        let health = channels
+            .entry(name.to_string())
+            .or_insert_with(|| ChannelHealth::new(name));
+        Some(f(health))
+    }
+
+    pub fn set_state(&self, name: &str, state: ChannelState) {
+        self.with(name, |h| {
+            if h.state != state {
+                h.state = state;
+                h.since = Utc::now();
+            }
+        });
+    }
+
+    pub fn record_error(&self, name: &str, error: impl std::fmt::Display) {
+        self.with(name, |h| {
+            h.last_error = Some(error.to_string());
+            h.last_error_at = Some(Utc::now());
+        });
+    }
+
+    pub fn record_restart(&self, name: &str) {
+        self.with(name, |h| h.restarts += 1);
+    }
+
+    pub fn set_last_message(&self, name: &str, at: DateTime<Utc>) {
+        self.with(name, |h| h.last_message = Some(at));
+    }
+
+    pub fn get(&self, name: &str) -> Option<ChannelHealth> {
+        self.channels.read().ok()?.get(name).cloned()
+    }
+
+    /// All channels, by name.
+    pub fn snapshot(&self) -> Vec<ChannelHealth> {
+        self.channels
+            .read()
+            .map(|c| c.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// What the gateway last wrote to `<workspace>/channel_health.json`, for
+/// the web UI and dashboard, which run in other processes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthSnapshot {
+    pub updated_at: DateTime<Utc>,
+    /// False once the gateway has stopped its channels.
+    #[serde(default)]
+    pub running: bool,
+    pub channels: Vec<ChannelHealth>,
+}
+
+impl HealthSnapshot {
+    pub fn path(workspace: &Path) -> PathBuf {
+        workspace.join("channel_health.json")
+    }
+
+    pub async fn save(board: &HealthBoard, workspace: &Path, running: bool) -> anyhow::Result<()> {
+        let snapshot = HealthSnapshot {
+            updated_at: Utc::now(),
+            running,
+            channels: board.snapshot(),
+        };
+        let path = Self::path(workspace);
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_string_pretty(&snapshot)?).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    pub fn load(workspace: &Path) -> Option<Self> {
+        let data = std::fs::read_to_string(Self::path(workspace)).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Whether a gateway is still running and updating the snapshot.
+    pub fn is_live(&self) -> bool {
+        self.running && (Utc::now() - self.updated_at).num_seconds() < STALE_AFTER_SECS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let board = HealthBoard::new();
+        board.set_state("slack", ChannelState::Connected);
+        board.record_error("telegram", "connection reset");
+        board.record_restart("telegram");
+
+        HealthSnapshot::save(&board, dir.path(), true)
+            .await
+            .unwrap();
+        let snapshot = HealthSnapshot::load(dir.path()).unwrap();
+        assert!(snapshot.is_live());
+        assert_eq!(snapshot.channels.len(), 2);
+        assert!(snapshot.channels[0].connected());
+        assert_eq!(snapshot.channels[1].name, "telegram");
+        assert_eq!(
+            snapshot.channels[1].last_error.as_deref(),
+            Some("connection reset")
+        );
+        assert_eq!(snapshot.channels[1].restarts, 1);
+
+        HealthSnapshot::save(&board, dir.path(), false)
+            .await
+            .unwrap();
+        assert!(!HealthSnapshot::load(dir.path()).unwrap().is_live());
+    }
+}
@@ -0,0 +1,410 @@
+// QuectoClaw — LINE channel implementation (Messaging API webhook + reply/push)
+
+use crate::bus::{GroupContext, MessageBus, OutboundMessage};
+use crate::channel::{rate_limited, BaseChannel, Channel};
+use crate::config::LineConfig;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::Router;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+const LINE_API: &str = "https://api.line.me";
+const DEFAULT_WEBHOOK_PATH: &str = "/webhook/line";
+
+/// Reply tokens expire about a minute after the event; older ones go
+/// straight to the push API.
+const REPLY_TOKEN_TTL: Duration = Duration::from_secs(50);
+
+/// The latest unused reply token of each chat.
+type ReplyTokens = Arc<Mutex<HashMap<String, (String, Instant)>>>;
+
+pub struct LineChannel {
+    base: Arc<BaseChannel>,
+    config: LineConfig,
+    api_base: String,
+    client: Client,
+    reply_tokens: ReplyTokens,
+    local_addr: Mutex<Option<SocketAddr>>,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LineChannel {
+    pub fn new(config: &LineConfig, bus: Arc<MessageBus>) -> Self {
+        Self {
+            base: Arc::new(BaseChannel::new("line", config.allow_from.clone(), bus)),
+            config: config.clone(),
+            api_base: LINE_API.to_string(),
+            client: Client::new(),
+            reply_tokens: Arc::new(Mutex::new(HashMap::new())),
+            local_addr: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Use a different Messaging API base URL (for tests).
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The address the webhook server is bound to, once started.
+    pub async fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.lock().await
+    }
+
+    async fn post(&self, endpoint: &str, body: Value) -> anyhow::Result<()> {
+        let resp = self
+            .client
+            .post(format!("{}/v2/bot/message/{}", self.api_base, endpoint))
+            .bearer_auth(&self.config.channel_access_token)
+            .json(&body)
+            .send()
+            .await?;
+        if let Some(limited) = rate_limited(&resp) {
+            return Err(limited.into());
+        }
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body: Value = resp.json().await.unwrap_or_default();
+            anyhow::bail!(
+                "LINE {} error ({}): {}",
+                endpoint,
+                status,
+                body["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Check `X-Line-Signature`: base64 of the HMAC-SHA256 of the raw body,
+/// keyed with the channel secret.
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(expected) = base64::engine::general_purpose::STANDARD.decode(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// The chat a LINE event belongs to: the group, the room, or the user.
+fn chat_id(source: &Value) -> Option<&str> {
+    match source["type"].as_str()? {
+        "group" => source["groupId"].as_str(),
+        "room" => source["roomId"].as_str(),
+        _ => source["userId"].as_str(),
+    }
+}
+
+/// Shared state of the webhook server.
+struct Webhook {
+    base: Arc<BaseChannel>,
+    channel_secret: String,
+    reply_tokens: ReplyTokens,
+}
+
+async fn webhook(State(hook): State<Arc<Webhook>>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let signature = headers
+        .get("x-line-signature")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !verify_signature(&hook.channel_secret, &body, signature) {
+        tracing::warn!(channel = "line", "Rejected webhook with invalid signature");
+        return StatusCode::UNAUTHORIZED;
+    }
+    let Ok(payload) = serde_json::from_slice::<Value>(&body) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    for event in payload["events"].as_array().into_iter().flatten() {
+        if event["type"] != "message" || event["message"]["type"] != "text" {
+            continue;
+        }
+        let Some(chat_id) = chat_id(&event["source"]) else {
+            continue;
+        };
+        let sender_id = event["source"]["userId"].as_str().unwrap_or("");
+        let text = event["message"]["text"].as_str().unwrap_or("").trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        if let Some(token) = event["replyToken"].as_str() {
+            hook.reply_tokens
+                .lock()
+                .await
+                .insert(chat_id.to_string(), (token.to_string(), Instant::now()));
+        }
+
+        let mut metadata = HashMap::new();
+        if let Some(id) = event["message"]["id"].as_str() {
+            metadata.insert("message_id".to_string(), id.to_string());
+        }
+        if let Some(kind) = event["source"]["type"].as_str() {
+            metadata.insert("source_type".to_string(), kind.to_string());
+        }
+
+        if event["source"]["type"] == "user" {
+            hook.base
+                .handle_message(sender_id, chat_id, text, Vec::new(), metadata)
+                .await;
+        } else {
+            let mentioned = event["message"]["mention"]["mentionees"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|m| m["isSelf"].as_bool().unwrap_or(false));
+            let group = GroupContext {
+                mentioned,
+                ..Default::default()
+            };
+            hook.base
+                .handle_group_message(sender_id, chat_id, text, Vec::new(), metadata, group)
+                .await;
+        }
+    }
+    StatusCode::OK
+}
+
+#[async_trait]
+impl Channel for LineChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.config.channel_secret.is_empty() {
+            anyhow::bail!("LINE channel_secret is not configured");
+        }
+        if self.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let host = if self.config.webhook_host.is_empty() {
+            "127.0.0.1"
+        } else {
+            &self.config.webhook_host
+        };
+        let path = if self.config.webhook_path.is_empty() {
+            DEFAULT_WEBHOOK_PATH
+        } else {
+            &self.config.webhook_path
+        };
+
+        let hook = Arc::new(Webhook {
+            base: self.base.clone(),
+            channel_secret: self.config.channel_secret.clone(),
+            reply_tokens: self.reply_tokens.clone(),
+        });
+        let app = Router::new()
+            .route(path, axum::routing::post(webhook))
+            .with_state(hook);
+
+        let listener = tokio::net::TcpListener::bind((host, self.config.webhook_port)).await?;
+        let addr = listener.local_addr()?;
+        *self.local_addr.lock().await = Some(addr);
+        tracing::info!(channel = %self.name(), addr = %addr, path = %path, "Starting LINE webhook");
+
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+        *self.task.lock().await = Some(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!(channel = "line", error = %e, "Webhook server failed");
+            }
+            running.store(false, Ordering::SeqCst);
+        }));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let messages = json!([{ "type": "text", "text": msg.content }]);
+
+        // A reply token can be used once; take it whether or not it works
+        let token = self.reply_tokens.lock().await.remove(&msg.chat_id);
+        if let Some((token, received)) = token {
+            if received.elapsed() < REPLY_TOKEN_TTL {
+                let body = json!({ "replyToken": token, "messages": messages });
+                match self.post("reply", body).await {
+                    Ok(()) => return Ok(()),
+                    Err(e) => {
+                        tracing::debug!(channel = "line", error = %e, "Reply failed, pushing instead")
+                    }
+                }
+            }
+        }
+
+        self.post("push", json!({ "to": msg.chat_id, "messages": messages }))
+            .await
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const SECRET: &str = "line-secret";
+
+    fn sign(body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+    }
+
+    fn text_event(user: &str, text: &str, reply_token: &str) -> String {
+        json!({
+            "destination": "Ubot",
+            "events": [{
+                "type": "message",
+                "replyToken": reply_token,
+                "source": { "type": "user", "userId": user },
+                "message": { "id": "m1", "type": "text", "text": text }
+            }]
+        })
+        .to_string()
+    }
+
+    fn reply(chat_id: &str, content: &str) -> OutboundMessage {
+        OutboundMessage {
+            channel: "line".into(),
+            chat_id: chat_id.into(),
+            content: content.into(),
+            metadata: HashMap::new(),
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = text_event("U1", "hi", "t");
+        assert!(verify_signature(SECRET, body.as_bytes(), &sign(&body)));
+        assert!(!verify_signature("other", body.as_bytes(), &sign(&body)));
+        assert!(!verify_signature(SECRET, b"tampered", &sign(&body)));
+        assert!(!verify_signature(SECRET, body.as_bytes(), "not base64!"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_reply_and_push_fallback() {
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/bot/message/reply"))
+            .and(header("authorization", "Bearer access"))
+            .and(body_partial_json(json!({ "replyToken": "fresh" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v2/bot/message/reply"))
+            .and(body_partial_json(json!({ "replyToken": "expired" })))
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .set_body_json(json!({ "message": "Invalid reply token" })),
+            )
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v2/bot/message/push"))
+            .and(body_partial_json(json!({ "to": "U1" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(2)
+            .mount(&api)
+            .await;
+
+        let config = LineConfig {
+            enabled: true,
+            channel_secret: SECRET.into(),
+            channel_access_token: "access".into(),
+            webhook_host: "127.0.0.1".into(),
+            webhook_port: 0,
+            webhook_path: "/hook".into(),
+            allow_from: vec!["U1".into()],
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = LineChannel::new(&config, bus.clone()).with_api_base(&api.uri());
+        channel.start().await.unwrap();
+        assert!(channel.is_running());
+        let url = format!("http://{}/hook", channel.local_addr().await.unwrap());
+        let http = Client::new();
+
+        // Unsigned and mis-signed requests are rejected
+        let body = text_event("U1", "hello", "fresh");
+        let resp = http.post(&url).body(body.clone()).send().await.unwrap();
+        assert_eq!(resp.status(), 401);
+        let resp = http
+            .post(&url)
+            .header("x-line-signature", sign("something else"))
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 401);
+
+        let resp = http
+            .post(&url)
+            .header("x-line-signature", sign(&body))
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "U1");
+        assert_eq!(msg.content, "hello");
+        assert_eq!(msg.session_key, "line:U1");
+        assert!(msg.group.is_none());
+
+        // First answer uses the reply token, the next one has to push
+        channel.send(reply("U1", "hi there")).await.unwrap();
+        channel.send(reply("U1", "follow-up")).await.unwrap();
+
+        // A token LINE refuses falls back to push too
+        let body = text_event("U1", "again", "expired");
+        http.post(&url)
+            .header("x-line-signature", sign(&body))
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        bus.consume_inbound().await.unwrap();
+        channel.send(reply("U1", "late answer")).await.unwrap();
+
+        channel.stop().await.unwrap();
+        assert!(!channel.is_running());
+    }
+}
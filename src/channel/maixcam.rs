@@ -0,0 +1,592 @@
+// QuectoClaw — MaixCam channel implementation (devices connect over TCP, newline-delimited JSON)
+//
+// Each device opens a TCP connection and sends one JSON object per line:
+//
+//   {"type":"hello","device_id":"cam-1","name":"Front door","token":"…"}
+//   {"type":"person_detected","count":1,"confidence":0.93,"image":"<base64 JPEG>"}
+//   {"type":"image_captured","caption":"…","image":"<base64 JPEG>"}
+//   {"type":"message","text":"…"}
+//   {"type":"ping"}
+//
+// and receives, also one object per line:
+//
+//   {"type":"reply","text":"…"}
+//   {"type":"command","command":"capture","args":{…}}
+//   {"type":"pong"}
+//   {"type":"error","error":"…"}
+//
+// `hello` must come first and carry the configured token; anything before it
+// is ignored. A device id that is already connected is refused, so another
+// peer cannot take over a camera's replies and commands. Images are optional
+// and saved under the media directory.
+
+use crate::bus::{MessageBus, OutboundMessage};
+use crate::channel::{BaseChannel, Channel};
+use crate::config::MaixCamConfig;
+use async_trait::async_trait;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// Largest line a device may send (a base64 camera frame fits easily).
+const MAX_LINE: usize = 8 * 1024 * 1024;
+
+/// Frame writers of the connected devices, by device id.
+type Devices = Arc<Mutex<HashMap<String, mpsc::Sender<String>>>>;
+
+pub struct MaixCamChannel {
+    base: Arc<BaseChannel>,
+    host: String,
+    port: u16,
+    token: String,
+    media_dir: PathBuf,
+    devices: Devices,
+    local_addr: Mutex<Option<SocketAddr>>,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MaixCamChannel {
+    /// Images sent by devices are written to `media_dir`.
+    pub fn new(config: &MaixCamConfig, media_dir: PathBuf, bus: Arc<MessageBus>) -> Self {
+        Self {
+            base: Arc::new(BaseChannel::new("maixcam", config.allow_from.clone(), bus)),
+            host: config.host.clone(),
+            port: config.port,
+            token: config.token.clone(),
+            media_dir,
+            devices: Arc::new(Mutex::new(HashMap::new())),
+            local_addr: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// The address the server is bound to, once started.
+    pub async fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.lock().await
+    }
+
+    /// Ids of the devices connected right now.
+    pub async fn devices(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.devices.lock().await.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Send a command (e.g. `capture`) to a connected device.
+    pub async fn send_command(
+        &self,
+        device_id: &str,
+        command: &str,
+        args: Value,
+    ) -> anyhow::Result<()> {
+        let frame = json!({ "type": "command", "command": command, "args": args });
+        self.write(device_id, frame).await
+    }
+
+    async fn write(&self, device_id: &str, frame: Value) -> anyhow::Result<()> {
+        let writer = self.devices.lock().await.get(device_id).cloned();
+        let Some(writer) = writer else {
+            anyhow::bail!("MaixCam device '{}' is not connected", device_id);
+        };
+        writer
+            .send(frame.to_string())
+            .await
+            .map_err(|_| anyhow::anyhow!("MaixCam device '{}' disconnected", device_id))
+    }
+}
+
+/// Decode a base64 image (optionally a `data:` URL) and save it as a file.
+async fn save_image(dir: &Path, device_id: &str, encoded: &str) -> anyhow::Result<String> {
+    let encoded = match encoded.split_once(";base64,") {
+        Some((_, data)) => data,
+        None => encoded,
+    };
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+    let extension = if bytes.starts_with(b"\x89PNG") {
+        "png"
+    } else {
+        "jpg"
+    };
+    let safe_id: String = device_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = format!(
+        "{}-{}.{}",
+        safe_id,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f"),
+        extension
+    );
+
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(name);
+    tokio::fs::write(&path, bytes).await?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// The text the agent sees for a device event.
+fn describe(event: &Value, device: &str) -> Option<String> {
+    let text = match event["type"].as_str()? {
+        "person_detected" => {
+            let mut text = format!("[{}] Person detected", device);
+            let count = event["count"].as_u64();
+            let confidence = event["confidence"].as_f64();
+            match (count, confidence) {
+                (Some(n), Some(c)) => {
+                    text.push_str(&format!(" (count {}, confidence {:.0}%)", n, c * 100.0))
+                }
+                (Some(n), None) => text.push_str(&format!(" (count {})", n)),
+                (None, Some(c)) => text.push_str(&format!(" (confidence {:.0}%)", c * 100.0)),
+                (None, None) => {}
+            }
+            text
+        }
+        "image_captured" => match event["caption"].as_str().filter(|c| !c.is_empty()) {
+            Some(caption) => format!("[{}] Image captured: {}", device, caption),
+            None => format!("[{}] Image captured", device),
+        },
+        "message" => event["text"].as_str()?.trim().to_string(),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// One device connection.
+struct Connection {
+    base: Arc<BaseChannel>,
+    devices: Devices,
+    token: String,
+    media_dir: PathBuf,
+}
+
+impl Connection {
+    async fn run(self, stream: TcpStream, peer: SocketAddr) {
+        let (read, mut write) = stream.into_split();
+        let (tx, mut rx) = mpsc::channel::<String>(32);
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = rx.recv().await {
+                if write
+                    .write_all(format!("{}\n", frame).as_bytes())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        // Set by a valid hello
+        let mut device_id: Option<String> = None;
+        let mut name = String::new();
+        tracing::info!(channel = "maixcam", peer = %peer, "Device connected");
+
+        let mut reader = BufReader::new(read);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match (&mut reader)
+                .take(MAX_LINE as u64)
+                .read_line(&mut line)
+                .await
+            {
+                Ok(0) => break,
+                Ok(_) if !line.ends_with('\n') && line.len() >= MAX_LINE => {
+                    tracing::warn!(channel = "maixcam", peer = %peer, "Frame too large, disconnecting");
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::debug!(channel = "maixcam", peer = %peer, error = %e, "Read failed");
+                    break;
+                }
+            }
+            let Ok(event) = serde_json::from_str::<Value>(line.trim()) else {
+                continue;
+            };
+
+            match (event["type"].as_str().unwrap_or(""), &device_id) {
+                ("hello", None) => match self.register(&event, &tx).await {
+                    Ok(id) => {
+                        name = event["name"].as_str().unwrap_or("").to_string();
+                        tracing::info!(channel = "maixcam", peer = %peer, device = %id, name = %name, "Device registered");
+                        device_id = Some(id);
+                    }
+                    Err(reason) => {
+                        tracing::warn!(channel = "maixcam", peer = %peer, "Device refused: {}", reason);
+                        let _ = tx
+                            .send(json!({ "type": "error", "error": reason }).to_string())
+                            .await;
+                        break;
+                    }
+                },
+                ("hello", Some(_)) => {}
+                ("ping", _) => {
+                    let _ = tx.send(json!({ "type": "pong" }).to_string()).await;
+                }
+                (_, Some(id)) => self.on_event(&event, id, &name).await,
+                (_, None) => {
+                    tracing::debug!(channel = "maixcam", peer = %peer, "Ignoring event before hello");
+                }
+            }
+        }
+
+        if let Some(id) = &device_id {
+            self.devices.lock().await.remove(id);
+            tracing::info!(channel = "maixcam", device = %id, "Device disconnected");
+        }
+        // Let a refusal reach the peer before the connection closes
+        drop(tx);
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(1), writer).await;
+    }
+
+    /// Check a `hello` and claim its device id for this connection.
+    async fn register(
+        &self,
+        hello: &Value,
+        tx: &mpsc::Sender<String>,
+    ) -> Result<String, &'static str> {
+        let token = hello["token"].as_str().unwrap_or("");
+        // Constant time, so response timing does not reveal the token
+        if self.token.is_empty() || !bool::from(token.as_bytes().ct_eq(self.token.as_bytes())) {
+            return Err("invalid token");
+        }
+        let Some(id) = hello["device_id"].as_str().filter(|id| !id.is_empty()) else {
+            return Err("missing device_id");
+        };
+        let mut devices = self.devices.lock().await;
+        if devices.contains_key(id) {
+            return Err("device id already connected");
+        }
+        devices.insert(id.to_string(), tx.clone());
+        Ok(id.to_string())
+    }
+
+    async fn on_event(&self, event: &Value, device_id: &str, name: &str) {
+        let label = if name.is_empty() { device_id } else { name };
+        let Some(text) = describe(event, label) else {
+            return;
+        };
+        let sender_id = if name.is_empty() {
+            device_id.to_string()
+        } else {
+            format!("{}|{}", device_id, name)
+        };
+        if !self.base.is_allowed(&sender_id) {
+            // Checked here too, so images from unknown devices are never written
+            tracing::warn!(channel = "maixcam", device = %device_id, "Event blocked: device not in allow list");
+            return;
+        }
+
+        let mut media = Vec::new();
+        if let Some(image) = event["image"].as_str().filter(|i| !i.is_empty()) {
+            match save_image(&self.media_dir, device_id, image).await {
+                Ok(path) => media.push(path),
+                Err(e) => {
+                    tracing::warn!(channel = "maixcam", device = %device_id, error = %e, "Could not save image")
+                }
+            }
+        }
+        if text.is_empty() && media.is_empty() {
+            return;
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "event".to_string(),
+            event["type"].as_str().unwrap_or("").to_string(),
+        );
+        for key in ["count", "confidence", "timestamp"] {
+            if !event[key].is_null() {
+                let value = match &event[key] {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                metadata.insert(key.to_string(), value);
+            }
+        }
+
+        self.base
+            .handle_message(&sender_id, device_id, &text, media, metadata)
+            .await;
+    }
+}
+
+#[async_trait]
+impl Channel for MaixCamChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.token.is_empty() {
+            anyhow::bail!("maixcam channel needs a token");
+        }
+        if self.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        // Devices on the network need `host` set to a reachable address
+        let host = if self.host.is_empty() {
+            "127.0.0.1"
+        } else {
+            &self.host
+        };
+        let listener = TcpListener::bind((host, self.port)).await?;
+        let addr = listener.local_addr()?;
+        *self.local_addr.lock().await = Some(addr);
+        tracing::info!(channel = %self.name(), addr = %addr, "Starting MaixCam server");
+
+        let base = self.base.clone();
+        let devices = self.devices.clone();
+        let token = self.token.clone();
+        let media_dir = self.media_dir.clone();
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+        *self.task.lock().await = Some(tokio::spawn(async move {
+            // Dropping the set on stop closes every device connection
+            let mut connections = JoinSet::new();
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, peer)) => {
+                            let conn = Connection {
+                                base: base.clone(),
+                                devices: devices.clone(),
+                                token: token.clone(),
+                                media_dir: media_dir.clone(),
+                            };
+                            connections.spawn(conn.run(stream, peer));
+                        }
+                        Err(e) => {
+                            tracing::warn!(channel = "maixcam", error = %e, "Accept failed");
+                        }
+                    },
+                    Some(_) = connections.join_next() => {}
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        self.devices.lock().await.clear();
+        Ok(())
+    }
+
+    /// Replies go to the device the message came from. A `command`
+    /// metadata entry (with optional JSON `args`) sends a device command
+    /// instead.
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        if let Some(command) = msg.metadata.get("command") {
+            let args = msg
+                .metadata
+                .get("args")
+                .and_then(|a| serde_json::from_str(a).ok())
+                .unwrap_or_else(|| json!({}));
+            return self.send_command(&msg.chat_id, command, args).await;
+        }
+        let frame = json!({ "type": "reply", "text": msg.content });
+        self.write(&msg.chat_id, frame).await
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use tokio::io::{BufReader, Lines};
+    use tokio::net::tcp::OwnedReadHalf;
+
+    async fn send_line(stream: &mut tokio::net::tcp::OwnedWriteHalf, frame: Value) {
+        stream
+            .write_all(format!("{}\n", frame).as_bytes())
+            .await
+            .unwrap();
+    }
+
+    async fn next_frame(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Value {
+        let line = tokio::time::timeout(std::time::Duration::from_secs(5), lines.next_line())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    fn outbound(chat_id: &str, content: &str, metadata: &[(&str, &str)]) -> OutboundMessage {
+        OutboundMessage {
+            channel: "maixcam".into(),
+            chat_id: chat_id.into(),
+            content: content.into(),
+            metadata: metadata
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_describe_events() {
+        let event = json!({ "type": "person_detected", "count": 2, "confidence": 0.876 });
+        assert_eq!(
+            describe(&event, "Door").unwrap(),
+            "[Door] Person detected (count 2, confidence 88%)"
+        );
+        let event = json!({ "type": "image_captured", "caption": "desk" });
+        assert_eq!(
+            describe(&event, "cam").unwrap(),
+            "[cam] Image captured: desk"
+        );
+        assert!(describe(&json!({ "type": "unknown" }), "cam").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_device_events_replies_and_commands() {
+        let media = tempfile::tempdir().unwrap();
+        let config = MaixCamConfig {
+            enabled: true,
+            host: "127.0.0.1".into(),
+            port: 0,
+            token: "s3cret".into(),
+            allow_from: vec!["cam-1".into()],
+        };
+        let bus = Arc::new(MessageBus::new());
+        let open = MaixCamConfig {
+            token: String::new(),
+            ..config.clone()
+        };
+        let channel = MaixCamChannel::new(&open, media.path().to_path_buf(), bus.clone());
+        assert!(channel.start().await.is_err());
+
+        let channel = MaixCamChannel::new(&config, media.path().to_path_buf(), bus.clone());
+        channel.start().await.unwrap();
+        let addr = channel.local_addr().await.unwrap();
+
+        // A device that is not allowed: its events are dropped
+        let (_, mut intruder) = TcpStream::connect(addr).await.unwrap().into_split();
+        send_line(
+            &mut intruder,
+            json!({ "type": "hello", "device_id": "cam-9", "token": "s3cret" }),
+        )
+        .await;
+        send_line(
+            &mut intruder,
+            json!({ "type": "message", "text": "let me in" }),
+        )
+        .await;
+
+        let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut lines = BufReader::new(read).lines();
+        send_line(
+            &mut write,
+            json!({ "type": "hello", "device_id": "cam-1", "name": "Front door", "token": "s3cret" }),
+        )
+        .await;
+        send_line(&mut write, json!({ "type": "ping" })).await;
+        assert_eq!(next_frame(&mut lines).await["type"], "pong");
+
+        // Without the token, or with an id already connected, a peer is
+        // refused and cannot pose as the camera
+        for hello in [
+            json!({ "type": "hello", "device_id": "cam-1", "token": "guess" }),
+            json!({ "type": "hello", "device_id": "cam-1", "token": "s3cret" }),
+        ] {
+            let (read, mut impostor) = TcpStream::connect(addr).await.unwrap().into_split();
+            let mut impostor_lines = BufReader::new(read).lines();
+            send_line(
+                &mut impostor,
+                json!({ "type": "message", "text": "before hello" }),
+            )
+            .await;
+            send_line(&mut impostor, hello).await;
+            assert_eq!(next_frame(&mut impostor_lines).await["type"], "error");
+            assert!(impostor_lines.next_line().await.unwrap().is_none());
+        }
+
+        let jpeg = base64::engine::general_purpose::STANDARD.encode(b"\xff\xd8\xff\xe0fake");
+        send_line(
+            &mut write,
+            json!({ "type": "person_detected", "count": 1, "confidence": 0.93, "image": jpeg }),
+        )
+        .await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "cam-1");
+        assert_eq!(msg.sender_id, "cam-1|Front door");
+        assert_eq!(
+            msg.content,
+            "[Front door] Person detected (count 1, confidence 93%)"
+        );
+        assert_eq!(msg.metadata["event"], "person_detected");
+        assert_eq!(msg.media.len(), 1);
+        assert!(msg.media[0].ends_with(".jpg"));
+        assert_eq!(
+            std::fs::read(&msg.media[0]).unwrap(),
+            b"\xff\xd8\xff\xe0fake"
+        );
+        assert_eq!(std::fs::read_dir(media.path()).unwrap().count(), 1);
+
+        // Replies and commands go back down the same connection
+        channel
+            .send(outbound("cam-1", "Someone is at the door", &[]))
+            .await
+            .unwrap();
+        let frame = next_frame(&mut lines).await;
+        assert_eq!(
+            frame,
+            json!({ "type": "reply", "text": "Someone is at the door" })
+        );
+        channel
+            .send(outbound(
+                "cam-1",
+                "",
+                &[("command", "capture"), ("args", r#"{"resolution":"hd"}"#)],
+            ))
+            .await
+            .unwrap();
+        let frame = next_frame(&mut lines).await;
+        assert_eq!(frame["command"], "capture");
+        assert_eq!(frame["args"]["resolution"], "hd");
+        assert!(channel.send(outbound("cam-2", "hi", &[])).await.is_err());
+        assert!(channel.devices().await.contains(&"cam-1".to_string()));
+
+        // Disconnecting unregisters the device
+        drop(write);
+        drop(lines);
+        for _ in 0..50 {
+            if !channel.devices().await.contains(&"cam-1".to_string()) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert!(!channel.devices().await.contains(&"cam-1".to_string()));
+
+        channel.stop().await.unwrap();
+        assert!(!channel.is_running());
+    }
+}
@@ -1,13 +1,29 @@
 // QuectoClaw — Channel trait and base channel
 
+pub mod dingtalk;
 pub mod discord;
+pub mod email;
+pub mod feishu;
+pub mod format;
+pub mod health;
+pub mod line;
+pub mod maixcam;
+pub mod onebot;
+pub mod outbox;
+pub mod relay;
 pub mod slack;
+pub mod supervisor;
 pub mod telegram;
+pub mod webhook;
+pub mod whatsapp;
 
-use crate::bus::{InboundMessage, MessageBus, OutboundMessage};
+use crate::bus::{Attachment, GroupContext, InboundMessage, MessageBus, OutboundMessage};
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
 /// Channel is the interface that all chat channels must implement.
 #[async_trait]
@@ -17,6 +33,84 @@ pub trait Channel: Send + Sync {
     async fn stop(&self) -> anyhow::Result<()>;
     async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()>;
     fn is_running(&self) -> bool;
+
+    /// Whether the platform connection is up right now. Channels without
+    /// a long-lived connection are connected whenever they run.
+    fn is_connected(&self) -> bool {
+        self.is_running()
+    }
+
+    /// Show that a reply is being written. Channels without typing
+    /// indicators ignore it.
+    async fn send_typing(&self, _chat_id: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Post the placeholder of a streamed reply and return its message id,
+    /// or `None` if this channel cannot edit messages (the reply then
+    /// arrives whole, as a normal message).
+    async fn send_draft(&self, _chat_id: &str, _content: &str) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Replace the text of a placeholder from `send_draft`. `done` marks
+    /// the complete reply, which may need splitting over several messages.
+    async fn edit_draft(
+        &self,
+        _chat_id: &str,
+        _draft_id: &str,
+        _content: &str,
+        _done: bool,
+    ) -> anyhow::Result<()> {
+        anyhow::bail!("{} cannot edit messages", self.name())
+    }
+
+    /// Minimum time between edits of one placeholder (platform rate limits).
+    fn edit_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Upload a file to a chat. Images should show inline where the
+    /// platform supports it.
+    async fn send_file(&self, _chat_id: &str, _attachment: &Attachment) -> anyhow::Result<()> {
+        anyhow::bail!("{} cannot send files", self.name())
+    }
+}
+
+/// A platform refused a send for going too fast. The outbox waits
+/// `retry_after` before trying again.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rate limited, retry after {}s",
+            self.retry_after.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// The `RateLimited` error for an HTTP 429 response, with the wait from
+/// its `Retry-After` header (in seconds; one second if missing).
+pub fn rate_limited(resp: &reqwest::Response) -> Option<RateLimited> {
+    if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let secs = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(1);
+    Some(RateLimited {
+        retry_after: Duration::from_secs(secs),
+    })
 }
 
 /// BaseChannel provides common functionality for all channels.
@@ -81,6 +175,34 @@ impl BaseChannel {
         content: &str,
         media: Vec<String>,
         metadata: HashMap<String, String>,
+    ) {
+        self.publish(sender_id, chat_id, content, media, metadata, None)
+            .await;
+    }
+
+    /// Route a group-chat message. The agent decides whether it is meant
+    /// for the bot and which session it belongs to (`channels.groups`).
+    pub async fn handle_group_message(
+        &self,
+        sender_id: &str,
+        chat_id: &str,
+        content: &str,
+        media: Vec<String>,
+        metadata: HashMap<String, String>,
+        group: GroupContext,
+    ) {
+        self.publish(sender_id, chat_id, content, media, metadata, Some(group))
+            .await;
+    }
+
+    async fn publish(
+        &self,
+        sender_id: &str,
+        chat_id: &str,
+        content: &str,
+        media: Vec<String>,
+        metadata: HashMap<String, String>,
+        group: Option<GroupContext>,
     ) {
         if !self.is_allowed(sender_id) {
             tracing::warn!(
@@ -102,6 +224,7 @@ impl BaseChannel {
                 media,
                 session_key,
                 metadata,
+                group,
             })
             .await;
     }
@@ -119,6 +242,59 @@ impl BaseChannel {
     }
 }
 
+/// Refresh access tokens this long before they expire.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(300);
+
+/// An API access token that expires, fetched again on demand (Feishu
+/// tenant tokens, DingTalk app tokens).
+#[derive(Default)]
+pub struct CachedToken {
+    current: Mutex<Option<(String, Instant)>>,
+}
+
+impl CachedToken {
+    /// Return the cached token, or call `fetch` for a new one. `fetch`
+    /// returns the token and its lifetime.
+    pub async fn get<F, Fut>(&self, fetch: F) -> anyhow::Result<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<(String, Duration)>>,
+    {
+        let mut current = self.current.lock().await;
+        if let Some((token, expires)) = current.as_ref() {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+        let (token, lifetime) = fetch().await?;
+        let usable = lifetime.saturating_sub(TOKEN_REFRESH_MARGIN);
+        *current = Some((token.clone(), Instant::now() + usable));
+        Ok(token)
+    }
+
+    /// Drop the cached token, e.g. after the API rejected it.
+    pub async fn invalidate(&self) {
+        *self.current.lock().await = None;
+    }
+}
+
+/// Whether a reply uses Markdown worth rendering, for channels that send
+/// plain text and Markdown as different message types.
+pub fn looks_like_markdown(text: &str) -> bool {
+    text.contains("```")
+        || text.contains("**")
+        || text.contains("](")
+        || text.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("# ")
+                || line.starts_with("## ")
+                || line.starts_with("### ")
+                || line.starts_with("- ")
+                || line.starts_with("* ")
+                || line.starts_with("> ")
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,4 +334,38 @@ mod tests {
         let ch = BaseChannel::new("test", vec!["@john".into()], bus);
         assert!(ch.is_allowed("john"));
     }
+
+    #[test]
+    fn test_looks_like_markdown() {
+        assert!(looks_like_markdown("## Steps\n1. one"));
+        assert!(looks_like_markdown("use **bold** here"));
+        assert!(looks_like_markdown("see [docs](https://x)"));
+        assert!(!looks_like_markdown("It is 3 * 4 = 12 - easy."));
+    }
+
+    #[tokio::test]
+    async fn test_cached_token_refreshes_when_expired() {
+        let token = CachedToken::default();
+        let first = token
+            .get(|| async { Ok(("a".to_string(), Duration::from_secs(7200))) })
+            .await
+            .unwrap();
+        let cached = token
+            .get(|| async { anyhow::bail!("should not be fetched") })
+            .await
+            .unwrap();
+        assert_eq!((first.as_str(), cached.as_str()), ("a", "a"));
+
+        // A lifetime inside the refresh margin is fetched again every time
+        token.invalidate().await;
+        token
+            .get(|| async { Ok(("b".to_string(), Duration::from_secs(60))) })
+            .await
+            .unwrap();
+        let again = token
+            .get(|| async { Ok(("c".to_string(), Duration::from_secs(7200))) })
+            .await
+            .unwrap();
+        assert_eq!(again, "c");
+    }
 }
@@ -0,0 +1,582 @@
+// QuectoClaw — OneBot v11 channel (QQ via go-cqhttp / NapCat, forward websocket)
+
+use crate::bus::{GroupContext, MessageBus, OutboundMessage};
+use crate::channel::{BaseChannel, Channel};
+use crate::config::OneBotConfig;
+use crate::util::{Correlator, Unanswered};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// How long to wait for the implementation to answer an action.
+const ACTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct OneBotChannel {
+    base: Arc<BaseChannel>,
+    config: OneBotConfig,
+    /// The live connection; actions wait for the reply with their `echo`.
+    conn: Correlator,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl OneBotChannel {
+    pub fn new(config: &OneBotConfig, bus: Arc<MessageBus>) -> Self {
+        Self {
+            base: Arc::new(BaseChannel::new("onebot", config.allow_from.clone(), bus)),
+            config: config.clone(),
+            conn: Correlator::default(),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Call an action (`send_private_msg`, ...) and wait for its result.
+    async fn call(&self, action: &str, params: Value) -> anyhow::Result<Value> {
+        let echo = self.conn.next_id();
+        let frame = json!({ "action": action, "params": params, "echo": echo });
+
+        let reply = match self
+            .conn
+            .request(&echo, frame.to_string(), ACTION_TIMEOUT)
+            .await
+        {
+            Ok(reply) => reply,
+            Err(Unanswered::NotConnected) => anyhow::bail!("OneBot is not connected"),
+            Err(Unanswered::Closed) => anyhow::bail!("OneBot connection closed"),
+            Err(Unanswered::TimedOut) => anyhow::bail!("OneBot {} timed out", action),
+        };
+        if reply["status"] == "failed" || reply["retcode"].as_i64().unwrap_or(0) != 0 {
+            return Err(anyhow!(
+                "OneBot {} failed (retcode {}): {}",
+                action,
+                reply["retcode"],
+                reply["wording"]
+                    .as_str()
+                    .or_else(|| reply["msg"].as_str())
+                    .unwrap_or("unknown error")
+            ));
+        }
+        Ok(reply["data"].clone())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Message parsing
+// ---------------------------------------------------------------------------
+
+/// The parts of a OneBot message we use.
+#[derive(Debug, Default, PartialEq)]
+struct Parsed {
+    text: String,
+    /// QQ ids mentioned with `at` segments.
+    mentions: Vec<String>,
+    /// Image URLs (or file names when no URL is given).
+    images: Vec<String>,
+}
+
+fn unescape_cq(s: &str) -> String {
+    s.replace("&#91;", "[")
+        .replace("&#93;", "]")
+        .replace("&#44;", ",")
+        .replace("&amp;", "&")
+}
+
+/// Parse a message in either array (segment) or string (CQ code) format.
+fn parse_message(message: &Value) -> Parsed {
+    let mut parsed = Parsed::default();
+    let mut add = |kind: &str, data: &dyn Fn(&str) -> Option<String>| match kind {
+        "text" => parsed.text.push_str(&data("text").unwrap_or_default()),
+        "at" => parsed.mentions.extend(data("qq")),
+        "image" => parsed.images.extend(data("url").or_else(|| data("file"))),
+        _ => {}
+    };
+
+    match message {
+        Value::Array(segments) => {
+            for seg in segments {
+                let kind = seg["type"].as_str().unwrap_or("");
+                add(kind, &|key| match &seg["data"][key] {
+                    Value::String(s) => Some(s.clone()),
+                    Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                });
+            }
+        }
+        Value::String(raw) => {
+            let mut rest = raw.as_str();
+            while !rest.is_empty() {
+                let Some(start) = rest.find("[CQ:") else {
+                    add("text", &|_| Some(unescape_cq(rest)));
+                    break;
+                };
+                if start > 0 {
+                    let text = &rest[..start];
+                    add("text", &|_| Some(unescape_cq(text)));
+                }
+                let Some(len) = rest[start..].find(']') else {
+                    break;
+                };
+                let code = &rest[start + 4..start + len];
+                let mut fields = code.split(',');
+                let kind = fields.next().unwrap_or("");
+                let params: HashMap<&str, String> = fields
+                    .filter_map(|f| f.split_once('='))
+                    .map(|(k, v)| (k, unescape_cq(v)))
+                    .collect();
+                add(kind, &|key| params.get(key).cloned());
+                rest = &rest[start + len + 1..];
+            }
+        }
+        _ => {}
+    }
+    parsed.text = parsed.text.trim().to_string();
+    parsed
+}
+
+/// Strip a `group_trigger_prefix` from a group message. A message that
+/// starts with one is addressed to the bot just like an @-mention; whether
+/// the rest are answered is up to `channels.groups`.
+fn group_trigger(text: &str, mentioned: bool, prefixes: &[String]) -> (String, bool) {
+    prefixes
+        .iter()
+        .filter(|p| !p.is_empty())
+        .find_map(|p| text.strip_prefix(p.as_str()))
+        .map(|rest| (rest.trim().to_string(), true))
+        .unwrap_or_else(|| (text.to_string(), mentioned))
+}
+
+/// Split `private:<user_id>` / `group:<group_id>` into the action and its target.
+fn send_target(chat_id: &str) -> anyhow::Result<(&'static str, Value)> {
+    let parse = |id: &str| -> anyhow::Result<i64> {
+        id.parse()
+            .map_err(|_| anyhow!("invalid OneBot chat id '{}'", chat_id))
+    };
+    match chat_id.split_once(':') {
+        Some(("group", id)) => Ok(("send_group_msg", json!({ "group_id": parse(id)? }))),
+        Some(("private", id)) => Ok(("send_private_msg", json!({ "user_id": parse(id)? }))),
+        _ => Ok(("send_private_msg", json!({ "user_id": parse(chat_id)? }))),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Connection loop
+// ---------------------------------------------------------------------------
+
+struct Socket {
+    base: Arc<BaseChannel>,
+    config: OneBotConfig,
+    conn: Correlator,
+    running: Arc<AtomicBool>,
+}
+
+impl Socket {
+    async fn run(self) {
+        let interval = Duration::from_secs(self.config.reconnect_interval.max(1));
+        while self.running.load(Ordering::SeqCst) {
+            match self.session().await {
+                Ok(()) => tracing::warn!(channel = "onebot", "Connection closed"),
+                Err(e) => tracing::warn!(channel = "onebot", error = %e, "Connection failed"),
+            }
+            // Fail actions still waiting on the old connection
+            self.conn.disconnect().await;
+            if !self.running.load(Ordering::SeqCst) {
+                break;
+            }
+            tracing::info!(
+                channel = "onebot",
+                retry_in_secs = interval.as_secs(),
+                "Reconnecting"
+            );
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn session(&self) -> anyhow::Result<()> {
+        let mut request = self.config.ws_url.as_str().into_client_request()?;
+        if !self.config.access_token.is_empty() {
+            request.headers_mut().insert(
+                "Authorization",
+                format!("Bearer {}", self.config.access_token).parse()?,
+            );
+        }
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        tracing::info!(channel = "onebot", url = %self.config.ws_url, "Connected");
+        let (mut write, mut read) = ws.split();
+
+        let (out_tx, mut out_rx) = mpsc::channel::<String>(64);
+        self.conn.connect(out_tx).await;
+
+        loop {
+            tokio::select! {
+                frame = out_rx.recv() => {
+                    let Some(frame) = frame else { return Ok(()) };
+                    write.send(WsMessage::text(frame)).await?;
+                }
+                frame = read.next() => {
+                    let text = match frame {
+                        None | Some(Ok(WsMessage::Close(_))) => return Ok(()),
+                        Some(Err(e)) => return Err(e.into()),
+                        Some(Ok(WsMessage::Text(text))) => text,
+                        Some(Ok(_)) => continue,
+                    };
+                    if let Ok(event) = serde_json::from_str::<Value>(&text) {
+                        self.on_frame(event).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn on_frame(&self, event: Value) {
+        // Action replies carry our echo
+        if let Some(echo) = event.get("echo") {
+            let echo = match echo {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            self.conn.resolve(&echo, event).await;
+            return;
+        }
+        if event["post_type"] != "message" {
+            return;
+        }
+
+        let parsed = parse_message(&event["message"]);
+        let user_id = event["user_id"].to_string();
+        let self_id = event["self_id"].to_string();
+        let nickname = event["sender"]["card"]
+            .as_str()
+            .filter(|c| !c.is_empty())
+            .or_else(|| event["sender"]["nickname"].as_str())
+            .unwrap_or("");
+        // Cards and nicknames are chosen by the sender, so only the QQ
+        // number identifies them (for the allow list and admins)
+        let sender_id = user_id.clone();
+
+        let (chat_id, text, group) = match event["message_type"].as_str() {
+            Some("private") => (format!("private:{}", user_id), parsed.text.clone(), None),
+            Some("group") => {
+                let (text, mentioned) = group_trigger(
+                    &parsed.text,
+                    parsed.mentions.contains(&self_id),
+                    &self.config.group_trigger_prefix,
+                );
+                let group = GroupContext {
+                    mentioned,
+                    sender_name: Some(nickname.to_string()).filter(|n| !n.is_empty()),
+                    ..Default::default()
+                };
+                (format!("group:{}", event["group_id"]), text, Some(group))
+            }
+            _ => return,
+        };
+        if text.is_empty() && parsed.images.is_empty() {
+            return;
+        }
+
+        let mut metadata = HashMap::new();
+        metadata.insert("message_id".to_string(), event["message_id"].to_string());
+        if !nickname.is_empty() {
+            metadata.insert("sender_name".to_string(), nickname.to_string());
+        }
+
+        match group {
+            Some(group) => {
+                self.base
+                    .handle_group_message(
+                        &sender_id,
+                        &chat_id,
+                        &text,
+                        parsed.images,
+                        metadata,
+                        group,
+                    )
+                    .await
+            }
+            None => {
+                self.base
+                    .handle_message(&sender_id, &chat_id, &text, parsed.images, metadata)
+                    .await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for OneBotChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.config.ws_url.is_empty() {
+            anyhow::bail!("OneBot ws_url is not configured");
+        }
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        tracing::info!(channel = %self.name(), "Starting OneBot channel");
+
+        let socket = Socket {
+            base: self.base.clone(),
+            config: self.config.clone(),
+            conn: self.conn.clone(),
+            running: self.running.clone(),
+        };
+        *self.task.lock().await = Some(tokio::spawn(socket.run()));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        self.conn.disconnect().await;
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let (action, mut params) = send_target(&msg.chat_id)?;
+        // Plain text: CQ codes in the reply are not interpreted
+        params["message"] = json!([{ "type": "text", "data": { "text": msg.content } }]);
+        self.call(action, params).await?;
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.conn.is_connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::groups::route;
+    use crate::bus::OutboundKind;
+    use crate::config::GroupsConfig;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tokio_tungstenite::WebSocketStream;
+
+    type Server = WebSocketStream<tokio::net::TcpStream>;
+
+    #[test]
+    fn test_parse_cq_string_and_segments() {
+        let p = parse_message(&json!(
+            "[CQ:at,qq=42] hi &#91;there&#93;[CQ:image,file=a.jpg,url=http://x/a.jpg]"
+        ));
+        assert_eq!(p.text, "hi [there]");
+        assert_eq!(p.mentions, vec!["42"]);
+        assert_eq!(p.images, vec!["http://x/a.jpg"]);
+
+        let p = parse_message(&json!([
+            { "type": "at", "data": { "qq": 42 } },
+            { "type": "text", "data": { "text": " hello" } },
+            { "type": "face", "data": { "id": "1" } }
+        ]));
+        assert_eq!(p.text, "hello");
+        assert_eq!(p.mentions, vec!["42"]);
+    }
+
+    #[test]
+    fn test_group_trigger_and_targets() {
+        let prefixes = vec!["/bot".to_string(), "!".to_string()];
+        assert_eq!(
+            group_trigger("/bot weather", false, &prefixes),
+            ("weather".to_string(), true)
+        );
+        assert_eq!(
+            group_trigger("just chatting", false, &prefixes),
+            ("just chatting".to_string(), false)
+        );
+        assert_eq!(
+            group_trigger("weather?", true, &prefixes),
+            ("weather?".to_string(), true)
+        );
+        // Without prefixes a plain message is not addressed to the bot
+        assert_eq!(
+            group_trigger("anything", false, &[]),
+            ("anything".to_string(), false)
+        );
+
+        assert_eq!(send_target("group:7").unwrap().0, "send_group_msg");
+        assert_eq!(send_target("private:8").unwrap().1, json!({ "user_id": 8 }));
+        assert!(send_target("group:abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_group_message_is_addressed_only_by_mention_without_prefixes() {
+        let bus = Arc::new(MessageBus::new());
+        let socket = Socket {
+            base: Arc::new(BaseChannel::new("onebot", vec!["*".into()], bus.clone())),
+            config: OneBotConfig::default(),
+            conn: Correlator::default(),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+        let groups = GroupsConfig::default();
+
+        socket
+            .on_frame(message("group", json!({ "group_id": 77 }), "just chatting"))
+            .await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert!(!msg.group.as_ref().unwrap().mentioned);
+        assert!(route(&groups, msg).is_none());
+
+        socket
+            .on_frame(message(
+                "group",
+                json!({ "group_id": 77 }),
+                "[CQ:at,qq=42] what time is it",
+            ))
+            .await;
+        let msg = route(&groups, bus.consume_inbound().await.unwrap()).unwrap();
+        assert_eq!(msg.content, "what time is it");
+    }
+
+    async fn accept(listener: &TcpListener) -> Server {
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        tokio_tungstenite::accept_hdr_async(stream, check_token)
+            .await
+            .unwrap()
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check_token(req: &Request, resp: Response) -> Result<Response, ErrorResponse> {
+        assert_eq!(req.headers()["authorization"], "Bearer secret");
+        Ok(resp)
+    }
+
+    async fn push(ws: &mut Server, event: Value) {
+        ws.send(WsMessage::text(event.to_string())).await.unwrap();
+    }
+
+    fn message(kind: &str, extra: Value, text: &str) -> Value {
+        let mut event = json!({
+            "post_type": "message", "message_type": kind, "self_id": 42,
+            "user_id": 1001, "message_id": 9, "message": text,
+            "sender": { "user_id": 1001, "nickname": "alice" }
+        });
+        for (k, v) in extra.as_object().unwrap() {
+            event[k] = v.clone();
+        }
+        event
+    }
+
+    #[tokio::test]
+    async fn test_onebot_receive_send_and_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = OneBotConfig {
+            enabled: true,
+            ws_url: format!("ws://{}", listener.local_addr().unwrap()),
+            access_token: "secret".into(),
+            reconnect_interval: 1,
+            group_trigger_prefix: vec!["/bot".into()],
+            allow_from: vec!["1001".into()],
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = OneBotChannel::new(&config, bus.clone());
+        channel.start().await.unwrap();
+
+        let mut ws = accept(&listener).await;
+        push(
+            &mut ws,
+            json!({ "post_type": "meta_event", "meta_event_type": "heartbeat" }),
+        )
+        .await;
+        push(
+            &mut ws,
+            message("group", json!({ "group_id": 77 }), "not for the bot"),
+        )
+        .await;
+        // A group card that looks like an allowed QQ number is not enough
+        push(
+            &mut ws,
+            message(
+                "group",
+                json!({ "group_id": 77, "user_id": 2002, "sender": { "user_id": 2002, "card": "1001" } }),
+                "/bot spoofed",
+            ),
+        )
+        .await;
+        push(
+            &mut ws,
+            message("group", json!({ "group_id": 77 }), "/bot what time is it"),
+        )
+        .await;
+        // Unprefixed chatter is passed on for `channels.groups` to judge
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.content, "not for the bot");
+        assert!(!msg.group.unwrap().mentioned);
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "group:77");
+        assert_eq!(msg.sender_id, "1001");
+        assert_eq!(msg.content, "what time is it");
+        assert!(msg.group.as_ref().unwrap().mentioned);
+        assert_eq!(msg.session_key, "onebot:group:77");
+        assert_eq!(msg.group.unwrap().sender_name.as_deref(), Some("alice"));
+
+        push(&mut ws, message("private", json!({}), "hello")).await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "private:1001");
+        assert_eq!(msg.content, "hello");
+
+        // Replies are actions on the same socket, matched by echo
+        let send = tokio::spawn(async move {
+            let result = channel
+                .send(OutboundMessage {
+                    channel: "onebot".into(),
+                    chat_id: "group:77".into(),
+                    content: "noon".into(),
+                    metadata: HashMap::new(),
+                    kind: OutboundKind::Message,
+                    attachments: Vec::new(),
+                })
+                .await;
+            (channel, result)
+        });
+        let action: Value = loop {
+            if let Some(Ok(WsMessage::Text(t))) = ws.next().await {
+                break serde_json::from_str(&t).unwrap();
+            }
+        };
+        assert_eq!(action["action"], "send_group_msg");
+        assert_eq!(action["params"]["group_id"], 77);
+        assert_eq!(action["params"]["message"][0]["data"]["text"], "noon");
+        push(
+            &mut ws,
+            json!({ "status": "ok", "retcode": 0, "data": { "message_id": 1 }, "echo": action["echo"] }),
+        )
+        .await;
+        let (channel, result) = send.await.unwrap();
+        result.unwrap();
+
+        // The implementation restarts; we reconnect after the interval
+        drop(ws);
+        let mut ws = accept(&listener).await;
+        push(&mut ws, message("private", json!({}), "back")).await;
+        assert_eq!(bus.consume_inbound().await.unwrap().content, "back");
+        assert!(channel.is_connected());
+
+        channel.stop().await.unwrap();
+        assert!(!channel.is_running());
+        assert!(!channel.is_connected());
+    }
+}
@@ -0,0 +1,704 @@
+// QuectoClaw — Durable outbox: retries failed channel sends, keeps dead letters
+
+use crate::bus::{OutboundKind, OutboundMessage};
+use crate::channel::health::HealthBoard;
+use crate::channel::relay::OutboundRelay;
+use crate::channel::RateLimited;
+use crate::config::OutboxConfig;
+use crate::metrics::{DeliveryOutcome, Metrics};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// How often a channel looks on disk for replayed dead letters.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A reply waiting to be sent, or given up on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Sorts by creation time, so a channel's replies keep their order.
+    pub id: String,
+    pub message: OutboundMessage,
+    /// Failed attempts so far.
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Replies on disk under `<workspace>/outbox`: `pending/<channel>/` until
+/// sent, `dead/` once they ran out of attempts.
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    pub fn new(workspace: &Path) -> Self {
+        Self {
+            dir: workspace.join("outbox"),
+        }
+    }
+
+    fn pending_dir(&self, channel: &str) -> PathBuf {
+        self.dir.join("pending").join(channel)
+    }
+
+    fn dead_dir(&self) -> PathBuf {
+        self.dir.join("dead")
+    }
+
+    /// Store a reply before its first attempt.
+    pub async fn push(&self, message: OutboundMessage) -> anyhow::Result<OutboxEntry> {
+        let now = Utc::now();
+        let uuid = uuid::Uuid::new_v4().simple().to_string();
+        let entry = OutboxEntry {
+            id: format!("{}-{}", now.format("%Y%m%d%H%M%S%6f"), &uuid[..8]),
+            message,
+            attempts: 0,
+            created_at: now,
+            last_error: None,
+        };
+        self.update(&entry).await?;
+        Ok(entry)
+    }
+
+    /// Rewrite a pending entry (after a failed attempt).
+    pub async fn update(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        let dir = self.pending_dir(&entry.message.channel);
+        write_entry(&dir, entry).await
+    }
+
+    /// A channel's pending replies, oldest first.
+    pub async fn pending(&self, channel: &str) -> Vec<OutboxEntry> {
+        read_entries(&self.pending_dir(channel)).await
+    }
+
+    /// Ids of a channel's pending replies, without reading them.
+    async fn pending_ids(&self, channel: &str) -> HashSet<String> {
+        let mut ids = HashSet::new();
+        let Ok(mut files) = tokio::fs::read_dir(self.pending_dir(channel)).await else {
+            return ids;
+        };
+        while let Ok(Some(file)) = files.next_entry().await {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()) {
+                ids.insert(id.to_string());
+            }
+        }
+        ids
+    }
+
+    async fn read_pending(&self, channel: &str, id: &str) -> Option<OutboxEntry> {
+        read_entry(&self.pending_dir(channel).join(format!("{}.json", id))).await
+    }
+
+    /// Pending replies of every channel, oldest first.
+    pub async fn all_pending(&self) -> Vec<OutboxEntry> {
+        let mut entries = Vec::new();
+        if let Ok(mut dirs) = tokio::fs::read_dir(self.dir.join("pending")).await {
+            while let Ok(Some(dir)) = dirs.next_entry().await {
+                entries.extend(read_entries(&dir.path()).await);
+            }
+        }
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        entries
+    }
+
+    /// Forget a sent reply.
+    pub async fn remove(&self, entry: &OutboxEntry) {
+        let path = self
+            .pending_dir(&entry.message.channel)
+            .join(format!("{}.json", entry.id));
+        let _ = tokio::fs::remove_file(path).await;
+    }
+
+    /// Give up on a reply: move it to the dead letters.
+    pub async fn dead_letter(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        write_entry(&self.dead_dir(), entry).await?;
+        self.remove(entry).await;
+        Ok(())
+    }
+
+    /// Replies that ran out of attempts, oldest first.
+    pub async fn dead_letters(&self) -> Vec<OutboxEntry> {
+        read_entries(&self.dead_dir()).await
+    }
+
+    /// Queue a dead letter again, with a fresh set of attempts. A running
+    /// gateway picks it up within a few seconds.
+    pub async fn replay(&self, id: &str) -> anyhow::Result<OutboxEntry> {
+        let mut entry = self.take_dead(id).await?;
+        entry.attempts = 0;
+        entry.last_error = None;
+        self.update(&entry).await?;
+        Ok(entry)
+    }
+
+    /// Delete a dead letter for good.
+    pub async fn discard(&self, id: &str) -> anyhow::Result<OutboxEntry> {
+        self.take_dead(id).await
+    }
+
+    async fn take_dead(&self, id: &str) -> anyhow::Result<OutboxEntry> {
+        // The id names a file, so anything but our own format could reach
+        // outside the dead letters
+        if !is_entry_id(id) {
+            anyhow::bail!("invalid dead letter id '{}'", id);
+        }
+        let path = self.dead_dir().join(format!("{}.json", id));
+        let entry = match tokio::fs::read_to_string(&path).await {
+            Ok(s) => serde_json::from_str::<OutboxEntry>(&s)?,
+            Err(_) => anyhow::bail!("no dead letter with id '{}'", id),
+        };
+        tokio::fs::remove_file(&path).await?;
+        Ok(entry)
+    }
+}
+
+/// Whether `id` has the form `push` gives ids: a 20-digit timestamp, a
+/// dash and 8 hex digits.
+fn is_entry_id(id: &str) -> bool {
+    let Some((time, suffix)) = id.split_once('-') else {
+        return false;
+    };
+    time.len() == 20
+        && time.bytes().all(|b| b.is_ascii_digit())
+        && suffix.len() == 8
+        && suffix
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+async fn write_entry(dir: &Path, entry: &OutboxEntry) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let path = dir.join(format!("{}.json", entry.id));
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_string_pretty(entry)?).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+    Ok(())
+}
+
+async fn read_entries(dir: &Path) -> Vec<OutboxEntry> {
+    let mut entries = Vec::new();
+    let Ok(mut files) = tokio::fs::read_dir(dir).await else {
+        return entries;
+    };
+    while let Ok(Some(file)) = files.next_entry().await {
+        let path = file.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        entries.extend(read_entry(&path).await);
+    }
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    entries
+}
+
+async fn read_entry(path: &Path) -> Option<OutboxEntry> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(s) => match serde_json::from_str(&s) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Failed to parse {}: {}", path.display(), e);
+                None
+            }
+        },
+        Err(e) => {
+            tracing::warn!("Failed to read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Sends one channel's outbound messages. Replies go through the outbox
+/// and are retried with exponential backoff, per chat, so one chat that
+/// cannot be reached does not hold up the others. When the platform
+/// rate-limits, the whole channel waits as long as it asks. Typing and
+/// partial updates are sent directly and dropped while their chat is
+/// backing off; they never touch the disk. Pending replies are kept in
+/// memory, and the directory is only rescanned every `POLL_INTERVAL` for
+/// replayed dead letters.
+pub struct Delivery {
+    channel: String,
+    relay: OutboundRelay,
+    outbox: Arc<Outbox>,
+    metrics: Metrics,
+    health: Option<HealthBoard>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    /// The channel's pending replies by id, so oldest first.
+    queue: BTreeMap<String, OutboxEntry>,
+    /// When the pending directory was last read.
+    scanned_at: Option<Instant>,
+    scan_interval: Duration,
+    /// Chats whose last attempt failed.
+    chats: HashMap<String, ChatBackoff>,
+    /// Set by rate limiting, which applies to the whole channel.
+    paused_until: Option<Instant>,
+}
+
+struct ChatBackoff {
+    /// Consecutive failed attempts in this chat.
+    failures: u32,
+    retry_at: Instant,
+}
+
+impl Delivery {
+    pub fn new(
+        relay: OutboundRelay,
+        outbox: Arc<Outbox>,
+        config: &OutboxConfig,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            channel: relay.channel_name().to_string(),
+            relay,
+            outbox,
+            metrics,
+            health: None,
+            max_attempts: config.max_attempts.max(1),
+            initial_backoff: Duration::from_secs(config.initial_backoff_secs),
+            max_backoff: Duration::from_secs(config.max_backoff_secs),
+            queue: BTreeMap::new(),
+            scanned_at: None,
+            scan_interval: POLL_INTERVAL,
+            chats: HashMap::new(),
+            paused_until: None,
+        }
+    }
+
+    /// Report send failures as the channel's last error.
+    pub fn with_health(mut self, health: HealthBoard) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Deliver until the bus handler closes. Replies left over from an
+    /// earlier run are sent first.
+    pub async fn run(mut self, mut rx: mpsc::Receiver<OutboundMessage>) {
+        self.flush().await;
+        loop {
+            let wake = self
+                .next_retry()
+                .unwrap_or_else(|| Instant::now() + POLL_INTERVAL);
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => self.accept(msg).await,
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(wake) => {}
+            }
+            self.flush().await;
+        }
+        tracing::info!(channel = %self.channel, "Outbound bridge stopped");
+    }
+
+    /// When the earliest waiting retry is due.
+    fn next_retry(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.paused_until
+            .into_iter()
+            .chain(self.chats.values().map(|c| c.retry_at))
+            .filter(|at| *at > now)
+            .min()
+    }
+
+    fn paused(&self) -> bool {
+        self.paused_until.is_some_and(|at| Instant::now() < at)
+    }
+
+    fn backing_off(&self, chat_id: &str) -> bool {
+        self.paused()
+            || self
+                .chats
+                .get(chat_id)
+                .is_some_and(|c| Instant::now() < c.retry_at)
+    }
+
+    async fn accept(&mut self, mut msg: OutboundMessage) {
+        match msg.kind {
+            OutboundKind::Message | OutboundKind::Final { .. } | OutboundKind::Cancelled { .. } => {
+                match self.outbox.push(msg.clone()).await {
+                    Ok(entry) => {
+                        self.queue.insert(entry.id.clone(), entry);
+                    }
+                    Err(e) => {
+                        // Still worth one attempt without the safety net
+                        tracing::warn!(channel = %self.channel, error = %e, "Failed to store reply in the outbox");
+                        if let Err(e) = self.relay.deliver(&mut msg).await {
+                            tracing::error!(channel = %self.channel, "Failed to send message: {}", e);
+                        }
+                    }
+                }
+            }
+            OutboundKind::Typing | OutboundKind::Update { .. } => {
+                if self.backing_off(&msg.chat_id) {
+                    return;
+                }
+                if let Err(e) = self.relay.deliver(&mut msg).await {
+                    tracing::debug!(channel = %self.channel, error = %e, "Failed to send update");
+                }
+            }
+        }
+    }
+
+    /// Bring the queue in line with the disk: pick up replayed dead letters
+    /// and replies left over from an earlier run. Only new files are read.
+    async fn scan(&mut self) {
+        let ids = self.outbox.pending_ids(&self.channel).await;
+        self.queue.retain(|id, _| ids.contains(id));
+        for id in ids {
+            if self.queue.contains_key(&id) {
+                continue;
+            }
+            if let Some(entry) = self.outbox.read_pending(&self.channel, &id).await {
+                self.queue.insert(id, entry);
+            }
+        }
+        self.scanned_at = Some(Instant::now());
+    }
+
+    /// Send pending replies in order. A failure holds back the rest of
+    /// that chat's replies, so they keep their order; other chats go on.
+    async fn flush(&mut self) {
+        if self.paused() {
+            return;
+        }
+        // A chat quiet for a while starts over with a short backoff
+        let now = Instant::now();
+        let max_backoff = self.max_backoff;
+        self.chats.retain(|_, c| c.retry_at + max_backoff > now);
+
+        if self
+            .scanned_at
+            .is_none_or(|at| at.elapsed() >= self.scan_interval)
+        {
+            self.scan().await;
+        }
+
+        let mut held: HashSet<String> = HashSet::new();
+        let ids: Vec<String> = self.queue.keys().cloned().collect();
+        for id in ids {
+            let Some(mut entry) = self.queue.get(&id).cloned() else {
+                continue;
+            };
+            let chat = entry.message.chat_id.clone();
+            if held.contains(&chat) || self.backing_off(&chat) {
+                held.insert(chat);
+                continue;
+            }
+            // A retry sends only the parts that did not go out
+            let error = match self.relay.deliver(&mut entry.message).await {
+                Ok(()) => {
+                    self.queue.remove(&id);
+                    self.outbox.remove(&entry).await;
+                    self.metrics
+                        .record_delivery(&self.channel, DeliveryOutcome::Sent)
+                        .await;
+                    self.chats.remove(&chat);
+                    continue;
+                }
+                Err(e) => e,
+            };
+
+            entry.attempts += 1;
+            entry.last_error = Some(error.to_string());
+            if let Some(health) = &self.health {
+                health.record_error(&self.channel, format!("send failed: {}", error));
+            }
+            let failures = self.chats.get(&chat).map_or(0, |c| c.failures);
+            let limited = error.downcast_ref::<RateLimited>().map(|l| l.retry_after);
+            let wait = limited.unwrap_or_else(|| self.backoff(failures));
+            self.chats.insert(
+                chat.clone(),
+                ChatBackoff {
+                    failures: failures + 1,
+                    retry_at: Instant::now() + wait,
+                },
+            );
+            held.insert(chat);
+
+            if entry.attempts >= self.max_attempts {
+                tracing::error!(channel = %self.channel, id = %entry.id, attempts = entry.attempts, error = %error, "Giving up on message, moved to dead letters");
+                self.queue.remove(&id);
+                if let Err(e) = self.outbox.dead_letter(&entry).await {
+                    tracing::error!(channel = %self.channel, error = %e, "Failed to store dead letter");
+                }
+                self.metrics
+                    .record_delivery(&self.channel, DeliveryOutcome::DeadLettered)
+                    .await;
+            } else {
+                tracing::warn!(channel = %self.channel, id = %entry.id, attempt = entry.attempts, retry_in_ms = wait.as_millis() as u64, error = %error, "Failed to send message, will retry");
+                if let Err(e) = self.outbox.update(&entry).await {
+                    tracing::warn!(channel = %self.channel, error = %e, "Failed to update outbox entry");
+                }
+                self.queue.insert(id, entry);
+                self.metrics
+                    .record_delivery(&self.channel, DeliveryOutcome::Retried)
+                    .await;
+            }
+
+            if limited.is_some() {
+                self.paused_until = Some(Instant::now() + wait);
+                break;
+            }
+        }
+    }
+
+    /// Exponential backoff after `failures` failed attempts in a row.
+    fn backoff(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.min(16));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    /// Fails with the queued errors first, then records what it sends.
+    /// Sends to `unreachable` always fail.
+    #[derive(Default)]
+    struct Flaky {
+        errors: Mutex<Vec<anyhow::Error>>,
+        sent: Mutex<Vec<String>>,
+        unreachable: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl Channel for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+        async fn start(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn stop(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+            if self.unreachable == Some(msg.chat_id.as_str()) {
+                anyhow::bail!("Forbidden: bot was blocked by the user");
+            }
+            if let Some(e) = self.errors.lock().unwrap().pop() {
+                return Err(e);
+            }
+            self.sent.lock().unwrap().push(msg.content);
+            Ok(())
+        }
+        fn is_running(&self) -> bool {
+            true
+        }
+    }
+
+    fn reply(content: &str) -> OutboundMessage {
+        reply_to("c1", content)
+    }
+
+    fn reply_to(chat_id: &str, content: &str) -> OutboundMessage {
+        OutboundMessage {
+            channel: "flaky".into(),
+            chat_id: chat_id.into(),
+            content: content.into(),
+            metadata: HashMap::new(),
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        }
+    }
+
+    fn delivery(
+        channel: Arc<Flaky>,
+        outbox: Arc<Outbox>,
+        metrics: Metrics,
+        max_attempts: u32,
+    ) -> Delivery {
+        let mut delivery = Delivery::new(
+            OutboundRelay::new(channel),
+            outbox,
+            &OutboxConfig {
+                max_attempts,
+                ..Default::default()
+            },
+            metrics,
+        );
+        delivery.initial_backoff = Duration::from_millis(20);
+        delivery
+    }
+
+    async fn wait_until<F: Fn() -> bool>(done: F) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !done() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn test_retries_in_order_and_honours_retry_after() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Arc::new(Outbox::new(dir.path()));
+        // Left over from a previous run
+        outbox.push(reply("first")).await.unwrap();
+
+        let channel = Arc::new(Flaky {
+            // Popped from the end: rate limited, then a network error
+            errors: Mutex::new(vec![
+                anyhow::anyhow!("connection reset"),
+                RateLimited {
+                    retry_after: Duration::from_millis(50),
+                }
+                .into(),
+            ]),
+            ..Default::default()
+        });
+        let metrics = Metrics::new();
+        let (tx, rx) = mpsc::channel(8);
+        let task =
+            tokio::spawn(delivery(channel.clone(), outbox.clone(), metrics.clone(), 5).run(rx));
+        tx.send(reply("second")).await.unwrap();
+
+        wait_until(|| channel.sent.lock().unwrap().len() == 2).await;
+        assert_eq!(*channel.sent.lock().unwrap(), vec!["first", "second"]);
+        assert!(outbox.pending("flaky").await.is_empty());
+
+        let stats = metrics.report().await.deliveries["flaky"].clone();
+        assert_eq!((stats.sent, stats.retried, stats.dead_lettered), (2, 2, 0));
+        drop(tx);
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_and_replay() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Arc::new(Outbox::new(dir.path()));
+        let channel = Arc::new(Flaky {
+            errors: Mutex::new((0..2).map(|_| anyhow::anyhow!("chat not found")).collect()),
+            ..Default::default()
+        });
+        let metrics = Metrics::new();
+        let mut delivery = delivery(channel.clone(), outbox.clone(), metrics.clone(), 2);
+
+        delivery.accept(reply("lost")).await;
+        delivery.flush().await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        delivery.flush().await;
+
+        assert!(outbox.pending("flaky").await.is_empty());
+        let dead = outbox.dead_letters().await;
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].last_error.as_deref(), Some("chat not found"));
+        assert_eq!(metrics.report().await.deliveries["flaky"].dead_lettered, 1);
+
+        // Replayed, it goes out once the chat is past its backoff and the
+        // pending directory is read again
+        let replayed = outbox.replay(&dead[0].id).await.unwrap();
+        assert_eq!(replayed.attempts, 0);
+        assert!(outbox.dead_letters().await.is_empty());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        delivery.flush().await;
+        assert!(channel.sent.lock().unwrap().is_empty());
+        delivery.scan_interval = Duration::ZERO;
+        delivery.flush().await;
+        assert_eq!(*channel.sent.lock().unwrap(), vec!["lost"]);
+        assert!(outbox
+            .replay("20260101000000000000-0123abcd")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dead_letter_ids_cannot_leave_the_dead_letters() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Outbox::new(dir.path());
+        let entry = outbox.push(reply("keep me")).await.unwrap();
+        let victim = dir
+            .path()
+            .join("outbox/pending/flaky")
+            .join(format!("{}.json", entry.id));
+        let escapes = [
+            format!("../pending/flaky/{}", entry.id),
+            victim.with_extension("").to_string_lossy().into_owned(),
+            format!("..\\pending\\flaky\\{}", entry.id),
+            "..".to_string(),
+            format!("{}/../{}", entry.id, entry.id),
+        ];
+        for id in &escapes {
+            let err = outbox.discard(id).await.unwrap_err();
+            assert!(err.to_string().contains("invalid dead letter id"), "{}", id);
+            assert!(outbox.replay(id).await.is_err());
+        }
+        assert!(victim.exists());
+        assert!(is_entry_id(&entry.id));
+    }
+
+    #[tokio::test]
+    async fn test_attachments_are_stored_compactly() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Outbox::new(dir.path());
+        let photo = vec![0xffu8; 30_000];
+        let mut msg = reply("chart");
+        msg.attachments.push(crate::bus::Attachment::from_bytes(
+            "chart.png",
+            photo.clone(),
+        ));
+        let entry = outbox.push(msg).await.unwrap();
+
+        let path = dir
+            .path()
+            .join("outbox/pending/flaky")
+            .join(format!("{}.json", entry.id));
+        let size = std::fs::metadata(&path).unwrap().len() as usize;
+        assert!(size < photo.len() * 4 / 3 + 1_000, "{} bytes on disk", size);
+        let stored = outbox.pending("flaky").await.remove(0);
+        assert_eq!(stored.message.attachments[0].read().await.unwrap(), photo);
+
+        // Entries written before base64 still load
+        let old: crate::bus::AttachmentData = serde_json::from_str(r#"{"bytes":[1,2,3]}"#).unwrap();
+        assert_eq!(old, crate::bus::AttachmentData::Bytes(vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_chat_does_not_hold_up_others() {
+        let dir = TempDir::new().unwrap();
+        let outbox = Arc::new(Outbox::new(dir.path()));
+        let channel = Arc::new(Flaky {
+            unreachable: Some("blocked"),
+            ..Default::default()
+        });
+        let metrics = Metrics::new();
+        let mut delivery = delivery(channel.clone(), outbox.clone(), metrics.clone(), 8);
+
+        delivery.accept(reply_to("blocked", "one")).await;
+        delivery.accept(reply_to("blocked", "two")).await;
+        delivery.accept(reply_to("c1", "hello")).await;
+        delivery.flush().await;
+        delivery.accept(reply_to("c2", "hi")).await;
+        delivery.flush().await;
+
+        assert_eq!(*channel.sent.lock().unwrap(), vec!["hello", "hi"]);
+        // The blocked chat's replies wait, in order, with one attempt made
+        let pending = outbox.pending("flaky").await;
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[1].attempts, 0);
+    }
+}
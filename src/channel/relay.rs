@@ -0,0 +1,423 @@
+// QuectoClaw — Outbound relay: turns bus messages into channel sends, typing and edits
+
+use crate::bus::{Attachment, OutboundKind, OutboundMessage};
+use crate::channel::Channel;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Placeholder of a streamed reply.
+struct Draft {
+    /// Message id to edit; `None` when the channel cannot edit or posting
+    /// the placeholder failed, so updates are skipped.
+    id: Option<String>,
+    last_edit: Instant,
+}
+
+/// Delivers one channel's outbound messages, keeping the placeholders of
+/// streamed replies and rate-limiting their edits, then uploads their files.
+pub struct OutboundRelay {
+    channel: Arc<dyn Channel>,
+    drafts: HashMap<String, Draft>,
+}
+
+impl OutboundRelay {
+    pub fn new(channel: Arc<dyn Channel>) -> Self {
+        Self {
+            channel,
+            drafts: HashMap::new(),
+        }
+    }
+
+    pub fn channel_name(&self) -> &str {
+        self.channel.name()
+    }
+
+    /// Send a message's text, then its files. Whatever went out is taken
+    /// off `msg`, so after an error it holds only what is left to retry and
+    /// the chat does not get the text twice.
+    pub async fn deliver(&mut self, msg: &mut OutboundMessage) -> anyhow::Result<()> {
+        let attachments = std::mem::take(&mut msg.attachments);
+        // Files sent on their own need no empty text message first
+        let files_only = !attachments.is_empty()
+            && msg.kind == OutboundKind::Message
+            && msg.content.trim().is_empty();
+        if !files_only {
+            if let Err(e) = self.deliver_text(msg.clone()).await {
+                msg.attachments = attachments;
+                return Err(e);
+            }
+            msg.content.clear();
+            msg.kind = OutboundKind::Message;
+        }
+        for (i, attachment) in attachments.iter().enumerate() {
+            if let Err(e) = self.deliver_file(&msg.chat_id, attachment).await {
+                msg.attachments = attachments[i..].to_vec();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Upload a file, or tell the chat why it could not be sent.
+    async fn deliver_file(&self, chat_id: &str, attachment: &Attachment) -> anyhow::Result<()> {
+        if let Err(e) = self.channel.send_file(chat_id, attachment).await {
+            tracing::warn!(channel = %self.channel.name(), file = %attachment.filename, error = %e, "Failed to send file");
+            self.channel
+                .send(OutboundMessage {
+                    channel: self.channel.name().to_string(),
+                    chat_id: chat_id.to_string(),
+                    content: format!("📎 {} could not be sent: {}", attachment.filename, e),
+                    metadata: HashMap::new(),
+                    kind: OutboundKind::Message,
+                    attachments: Vec::new(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn deliver_text(&mut self, msg: OutboundMessage) -> anyhow::Result<()> {
+        match msg.kind.clone() {
+            OutboundKind::Message => self.channel.send(msg).await,
+            OutboundKind::Typing => self.channel.send_typing(&msg.chat_id).await,
+            OutboundKind::Update { stream_id } => {
+                if msg.content.trim().is_empty() {
+                    return Ok(());
+                }
+                match self.drafts.get_mut(&stream_id) {
+                    None => {
+                        let id = match self.channel.send_draft(&msg.chat_id, &msg.content).await {
+                            Ok(id) => id,
+                            Err(e) => {
+                                tracing::warn!(channel = %self.channel.name(), error = %e, "Failed to post reply placeholder");
+                                None
+                            }
+                        };
+                        self.drafts.insert(
+                            stream_id,
+                            Draft {
+                                id,
+                                last_edit: Instant::now(),
+                            },
+                        );
+                    }
+                    Some(Draft {
+                        id: Some(id),
+                        last_edit,
+                    }) if last_edit.elapsed() >= self.channel.edit_interval() => {
+                        // A failed intermediate edit is not worth surfacing;
+                        // the final text replaces it anyway
+                        if let Err(e) = self
+                            .channel
+                            .edit_draft(&msg.chat_id, id, &msg.content, false)
+                            .await
+                        {
+                            tracing::debug!(channel = %self.channel.name(), error = %e, "Failed to update reply placeholder");
+                        }
+                        *last_edit = Instant::now();
+                    }
+                    // Too soon after the last edit, or no placeholder
+                    Some(_) => {}
+                }
+                Ok(())
+            }
+            // A channel that could not post a placeholder has nothing to close
+            OutboundKind::Cancelled { stream_id } => {
+                match self.drafts.remove(&stream_id).and_then(|d| d.id) {
+                    Some(id) => {
+                        self.channel
+                            .edit_draft(&msg.chat_id, &id, &msg.content, true)
+                            .await
+                    }
+                    None => Ok(()),
+                }
+            }
+            OutboundKind::Final { stream_id } => {
+                if let Some(id) = self.drafts.remove(&stream_id).and_then(|d| d.id) {
+                    match self
+                        .channel
+                        .edit_draft(&msg.chat_id, &id, &msg.content, true)
+                        .await
+                    {
+                        Ok(()) => return Ok(()),
+                        Err(e) => {
+                            tracing::warn!(channel = %self.channel.name(), error = %e, "Failed to finish reply placeholder, sending the reply instead")
+                        }
+                    }
+                }
+                self.channel
+                    .send(OutboundMessage {
+                        kind: OutboundKind::Message,
+                        ..msg
+                    })
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Records every call; edits and uploads only when `editable`. The
+    /// first `failing_notices` notices about unsent files fail.
+    struct Recorder {
+        editable: bool,
+        failing_notices: Mutex<u32>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn new(editable: bool) -> Arc<Self> {
+            Arc::new(Self {
+                editable,
+                failing_notices: Mutex::new(0),
+                calls: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Channel for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+        async fn start(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn stop(&self) -> anyhow::Result<()> {
+            Ok(())
+        }
+        async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+            let mut failing = self.failing_notices.lock().unwrap();
+            if msg.content.starts_with('📎') && *failing > 0 {
+                *failing -= 1;
+                anyhow::bail!("network error");
+            }
+            drop(failing);
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("send {}", msg.content));
+            Ok(())
+        }
+        fn is_running(&self) -> bool {
+            true
+        }
+        async fn send_typing(&self, chat_id: &str) -> anyhow::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("typing {}", chat_id));
+            Ok(())
+        }
+        async fn send_draft(
+            &self,
+            _chat_id: &str,
+            content: &str,
+        ) -> anyhow::Result<Option<String>> {
+            if !self.editable {
+                return Ok(None);
+            }
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("draft {}", content));
+            Ok(Some("m1".into()))
+        }
+        async fn edit_draft(
+            &self,
+            _chat_id: &str,
+            draft_id: &str,
+            content: &str,
+            done: bool,
+        ) -> anyhow::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("edit {} {} {}", draft_id, content, done));
+            Ok(())
+        }
+        fn edit_interval(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+        async fn send_file(&self, _chat_id: &str, attachment: &Attachment) -> anyhow::Result<()> {
+            if !self.editable {
+                anyhow::bail!("recorder cannot send files");
+            }
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("file {} {}", attachment.filename, attachment.mime));
+            Ok(())
+        }
+    }
+
+    fn msg(kind: OutboundKind, content: &str) -> OutboundMessage {
+        OutboundMessage {
+            channel: "recorder".into(),
+            chat_id: "c1".into(),
+            content: content.into(),
+            metadata: HashMap::new(),
+            kind,
+            attachments: Vec::new(),
+        }
+    }
+
+    fn update(content: &str) -> OutboundMessage {
+        msg(
+            OutboundKind::Update {
+                stream_id: "s1".into(),
+            },
+            content,
+        )
+    }
+
+    fn finish(content: &str) -> OutboundMessage {
+        msg(
+            OutboundKind::Final {
+                stream_id: "s1".into(),
+            },
+            content,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_streamed_reply_edits_placeholder() {
+        let channel = Recorder::new(true);
+        let mut relay = OutboundRelay::new(channel.clone());
+        relay
+            .deliver(&mut msg(OutboundKind::Typing, ""))
+            .await
+            .unwrap();
+        relay.deliver(&mut update("He")).await.unwrap();
+        // Throttled: too soon after posting the placeholder
+        relay.deliver(&mut update("Hell")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        relay.deliver(&mut update("Hello")).await.unwrap();
+        relay.deliver(&mut finish("Hello!")).await.unwrap();
+        assert_eq!(
+            channel.calls(),
+            vec![
+                "typing c1",
+                "draft He",
+                "edit m1 Hello false",
+                "edit m1 Hello! true"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_stream_closes_its_placeholder() {
+        let channel = Recorder::new(true);
+        let mut relay = OutboundRelay::new(channel.clone());
+        relay.deliver(&mut update("Half an ans")).await.unwrap();
+        let cancelled = || {
+            msg(
+                OutboundKind::Cancelled {
+                    stream_id: "s1".into(),
+                },
+                "Half an ans\n\n⏹️ Stopped.",
+            )
+        };
+        relay.deliver(&mut cancelled()).await.unwrap();
+        assert!(relay.drafts.is_empty());
+        assert_eq!(
+            channel.calls(),
+            vec![
+                "draft Half an ans",
+                "edit m1 Half an ans\n\n⏹️ Stopped. true"
+            ]
+        );
+
+        // Without a placeholder nothing is sent
+        let channel = Recorder::new(false);
+        let mut relay = OutboundRelay::new(channel.clone());
+        relay.deliver(&mut update("Half")).await.unwrap();
+        relay.deliver(&mut cancelled()).await.unwrap();
+        assert!(relay.drafts.is_empty());
+        assert!(channel.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_streamed_reply_without_edits_is_sent_whole() {
+        let channel = Recorder::new(false);
+        let mut relay = OutboundRelay::new(channel.clone());
+        relay.deliver(&mut update("He")).await.unwrap();
+        relay.deliver(&mut update("Hello")).await.unwrap();
+        relay.deliver(&mut finish("Hello!")).await.unwrap();
+        relay
+            .deliver(&mut msg(OutboundKind::Message, "next"))
+            .await
+            .unwrap();
+        assert_eq!(channel.calls(), vec!["send Hello!", "send next"]);
+    }
+
+    #[tokio::test]
+    async fn test_attachments_follow_text() {
+        let channel = Recorder::new(true);
+        let mut relay = OutboundRelay::new(channel.clone());
+        let mut with_file = msg(OutboundKind::Message, "Here is the chart");
+        with_file.attachments = vec![Attachment::from_bytes("chart.png", vec![1, 2, 3])];
+        relay.deliver(&mut with_file).await.unwrap();
+        let mut file_only = msg(OutboundKind::Message, "");
+        file_only.attachments = vec![Attachment::from_bytes("report.pdf", vec![4])];
+        relay.deliver(&mut file_only).await.unwrap();
+        assert_eq!(
+            channel.calls(),
+            vec![
+                "send Here is the chart",
+                "file chart.png image/png",
+                "file report.pdf application/pdf"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_attachment_is_reported() {
+        let channel = Recorder::new(false);
+        let mut relay = OutboundRelay::new(channel.clone());
+        let mut file_only = msg(OutboundKind::Message, "");
+        file_only.attachments = vec![Attachment::from_bytes("report.pdf", vec![4])];
+        relay.deliver(&mut file_only).await.unwrap();
+        assert_eq!(
+            channel.calls(),
+            vec!["send 📎 report.pdf could not be sent: recorder cannot send files"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_file_after_text_retries_only_the_file() {
+        let channel = Recorder::new(false);
+        *channel.failing_notices.lock().unwrap() = 1;
+        let mut relay = OutboundRelay::new(channel.clone());
+        let mut reply = msg(OutboundKind::Message, "Here is the report");
+        reply.attachments = vec![
+            Attachment::from_bytes("report.pdf", vec![4]),
+            Attachment::from_bytes("data.csv", vec![5]),
+        ];
+
+        assert!(relay.deliver(&mut reply).await.is_err());
+        // What is left to retry: both files, no text
+        assert_eq!(reply.content, "");
+        assert_eq!(reply.attachments.len(), 2);
+        relay.deliver(&mut reply).await.unwrap();
+        assert_eq!(
+            channel.calls(),
+            vec![
+                "send Here is the report",
+                "send 📎 report.pdf could not be sent: recorder cannot send files",
+                "send 📎 data.csv could not be sent: recorder cannot send files"
+            ]
+        );
+    }
+}
@@ -1,20 +1,37 @@
-// QuectoClaw — Slack channel implementation (Web API for sending, stub for receiving)
+// QuectoClaw — Slack channel implementation (Socket Mode for receiving, Web API for sending)
 
-use crate::bus::{MessageBus, OutboundMessage};
-use crate::channel::{BaseChannel, Channel};
+use crate::bus::{Attachment, GroupContext, MessageBus, OutboundMessage};
+use crate::channel::format::{split_message, to_slack_mrkdwn, SLACK_MAX_CHARS};
+use crate::channel::{rate_limited, BaseChannel, Channel};
+use crate::util::Backoff;
+use anyhow::anyhow;
 use async_trait::async_trait;
-use reqwest::Client;
-use serde_json::json;
-
+use futures_util::{SinkExt, StreamExt};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+const SLACK_API: &str = "https://slack.com/api";
+
+/// How many recent event ids to remember for dropping Slack's redeliveries.
+const SEEN_EVENTS: usize = 256;
 
 pub struct SlackChannel {
-    base: BaseChannel,
+    base: Arc<BaseChannel>,
     bot_token: String,
-    _app_token: String,
+    app_token: String,
+    api_base: String,
     client: Client,
-    running: Arc<Mutex<bool>>,
+    running: Arc<AtomicBool>,
+    /// Set once Socket Mode says hello, cleared when the socket drops.
+    connected: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl SlackChannel {
@@ -25,11 +42,319 @@ impl SlackChannel {
         bus: Arc<MessageBus>,
     ) -> Self {
         Self {
-            base: BaseChannel::new("slack", allow_list, bus),
+            base: Arc::new(BaseChannel::new("slack", allow_list, bus)),
             bot_token: bot_token.to_string(),
-            _app_token: app_token.to_string(),
+            app_token: app_token.to_string(),
+            api_base: SLACK_API.to_string(),
             client: Client::new(),
-            running: Arc::new(Mutex::new(false)),
+            running: Arc::new(AtomicBool::new(false)),
+            connected: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Use a different Web API base URL (for tests).
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Call a Web API method with the bot token.
+    async fn web_api(&self, method: &str, body: Value) -> anyhow::Result<Value> {
+        let request = self
+            .client
+            .post(format!("{}/{}", self.api_base, method))
+            .header("Content-Type", "application/json; charset=utf-8")
+            .json(&body);
+        self.call(request).await
+    }
+
+    /// Send a Web API request with the bot token and check its `ok`.
+    async fn call(&self, request: RequestBuilder) -> anyhow::Result<Value> {
+        let resp = request
+            .header("Authorization", format!("Bearer {}", self.bot_token))
+            .send()
+            .await?;
+        if let Some(limited) = rate_limited(&resp) {
+            return Err(limited.into());
+        }
+
+        let status = resp.status();
+        let resp_body: Value = resp.json().await?;
+
+        if !status.is_success() || !resp_body["ok"].as_bool().unwrap_or(false) {
+            anyhow::bail!(
+                "Slack API error: {}",
+                resp_body["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(resp_body)
+    }
+
+    /// Post one message (at most `SLACK_MAX_CHARS`) and return its `ts`.
+    async fn post_message(
+        &self,
+        channel: &str,
+        thread: Option<&str>,
+        content: &str,
+    ) -> anyhow::Result<String> {
+        let mut body = json!({
+            "channel": channel,
+            "text": slack_text(content),
+        });
+        if let Some(thread) = thread {
+            body["thread_ts"] = json!(thread);
+        }
+        let resp = self.web_api("chat.postMessage", body).await?;
+        Ok(resp["ts"].as_str().unwrap_or_default().to_string())
+    }
+}
+
+/// mrkdwn for a chunk, unless escaping pushed it over the limit.
+fn slack_text(chunk: &str) -> String {
+    let mrkdwn = to_slack_mrkdwn(chunk);
+    if mrkdwn.chars().count() <= SLACK_MAX_CHARS {
+        mrkdwn
+    } else {
+        chunk.to_string()
+    }
+}
+
+/// Split a chat id into the Slack channel and the thread it replies in.
+/// Threaded conversations use `<channel>:<thread_ts>`.
+fn split_chat_id(chat_id: &str) -> (&str, Option<&str>) {
+    match chat_id.split_once(':') {
+        Some((channel, thread)) => (channel, Some(thread)),
+        None => (chat_id, None),
+    }
+}
+
+/// The Socket Mode connection loop, run in the background by `start`.
+struct SocketMode {
+    base: Arc<BaseChannel>,
+    client: Client,
+    api_base: String,
+    app_token: String,
+    bot_token: String,
+    running: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+    bot_user_id: Option<String>,
+    seen: VecDeque<String>,
+    seen_set: HashSet<String>,
+}
+
+/// Why a socket session ended.
+enum SessionEnd {
+    /// Slack asked us to reconnect (or the socket dropped).
+    Reconnect,
+    /// The app was disabled; reconnecting will not help.
+    Disabled,
+}
+
+impl SocketMode {
+    async fn api(&self, method: &str, token: &str) -> anyhow::Result<Value> {
+        let resp: Value = self
+            .client
+            .post(format!("{}/{}", self.api_base, method))
+            .bearer_auth(token)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if !resp["ok"].as_bool().unwrap_or(false) {
+            return Err(anyhow!(
+                "Slack {} failed: {}",
+                method,
+                resp["error"].as_str().unwrap_or("unknown error")
+            ));
+        }
+        Ok(resp)
+    }
+
+    async fn run(mut self) {
+        // Our own user id, to strip mentions and skip our own messages
+        match self.api("auth.test", &self.bot_token.clone()).await {
+            Ok(resp) => self.bot_user_id = resp["user_id"].as_str().map(String::from),
+            Err(e) => tracing::warn!(channel = "slack", "auth.test failed: {}", e),
+        }
+
+        let mut backoff = Backoff::default();
+        while self.running.load(Ordering::SeqCst) {
+            backoff.attempt();
+            let end = self.session().await;
+            self.connected.store(false, Ordering::SeqCst);
+            match end {
+                Ok(SessionEnd::Disabled) => {
+                    tracing::error!(
+                        channel = "slack",
+                        "Socket Mode disabled for this app, stopping"
+                    );
+                    break;
+                }
+                Ok(SessionEnd::Reconnect) => {
+                    tracing::info!(
+                        channel = "slack",
+                        "Socket Mode connection closed, reconnecting"
+                    );
+                    backoff.reset();
+                }
+                Err(e) => {
+                    tracing::warn!(channel = "slack", error = %e, retry_in_secs = backoff.delay().as_secs(), "Socket Mode connection failed");
+                }
+            }
+            if !self.running.load(Ordering::SeqCst) {
+                break;
+            }
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+        self.running.store(false, Ordering::SeqCst);
+    }
+
+    /// Open one websocket and process envelopes until it ends.
+    async fn session(&mut self) -> anyhow::Result<SessionEnd> {
+        let open = self
+            .api("apps.connections.open", &self.app_token.clone())
+            .await?;
+        let url = open["url"]
+            .as_str()
+            .ok_or_else(|| anyhow!("apps.connections.open returned no url"))?;
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+        tracing::debug!(channel = "slack", "Socket Mode connected");
+
+        while let Some(frame) = ws.next().await {
+            let text = match frame? {
+                WsMessage::Text(text) => text,
+                WsMessage::Close(_) => break,
+                _ => continue,
+            };
+            let Ok(envelope) = serde_json::from_str::<Value>(&text) else {
+                continue;
+            };
+
+            // Acknowledge first: Slack redelivers envelopes not acked within 3s
+            if let Some(id) = envelope["envelope_id"].as_str() {
+                ws.send(WsMessage::text(json!({ "envelope_id": id }).to_string()))
+                    .await?;
+            }
+
+            match envelope["type"].as_str().unwrap_or("") {
+                "hello" => {
+                    self.connected.store(true, Ordering::SeqCst);
+                    tracing::info!(channel = "slack", "Socket Mode ready");
+                }
+                "disconnect" => {
+                    return Ok(match envelope["reason"].as_str() {
+                        Some("link_disabled") => SessionEnd::Disabled,
+                        _ => SessionEnd::Reconnect,
+                    });
+                }
+                "events_api" => self.on_event(&envelope["payload"]).await,
+                _ => {}
+            }
+        }
+        Ok(SessionEnd::Reconnect)
+    }
+
+    /// Whether this event was already handled (Slack retries on slow acks).
+    fn already_seen(&mut self, event_id: &str) -> bool {
+        if event_id.is_empty() {
+            return false;
+        }
+        if !self.seen_set.insert(event_id.to_string()) {
+            return true;
+        }
+        self.seen.push_back(event_id.to_string());
+        if self.seen.len() > SEEN_EVENTS {
+            if let Some(old) = self.seen.pop_front() {
+                self.seen_set.remove(&old);
+            }
+        }
+        false
+    }
+
+    async fn on_event(&mut self, payload: &Value) {
+        if self.already_seen(payload["event_id"].as_str().unwrap_or("")) {
+            return;
+        }
+        let event = &payload["event"];
+        let kind = event["type"].as_str().unwrap_or("");
+        let is_dm = event["channel_type"].as_str() == Some("im");
+
+        let mention = self.bot_user_id.as_ref().map(|bot| format!("<@{}>", bot));
+        let raw = event["text"].as_str().unwrap_or("");
+
+        // Mentions and (with the `message.channels` scope) other channel
+        // messages, and direct messages. A channel message that mentions us
+        // also arrives as `app_mention`, so it is handled once, there. Edits,
+        // joins, bot messages (including our own replies) and other subtypes
+        // are skipped.
+        let wanted = match kind {
+            "app_mention" => true,
+            "message" => {
+                event.get("subtype").is_none()
+                    && (is_dm || !mention.as_ref().is_some_and(|m| raw.contains(m.as_str())))
+            }
+            _ => false,
+        };
+        if !wanted || event.get("bot_id").is_some() {
+            return;
+        }
+        let (Some(user), Some(channel)) = (event["user"].as_str(), event["channel"].as_str())
+        else {
+            return;
+        };
+        if self.bot_user_id.as_deref() == Some(user) {
+            return;
+        }
+
+        let text = match &mention {
+            Some(m) => raw.replace(m.as_str(), ""),
+            None => raw.to_string(),
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+
+        // Channel mentions are answered in a thread (the one they are in, or a
+        // new one under the mention); DMs stay flat unless already threaded.
+        let ts = event["ts"].as_str().unwrap_or("");
+        let thread = match event["thread_ts"].as_str() {
+            Some(t) => Some(t),
+            None if !is_dm && !ts.is_empty() => Some(ts),
+            None => None,
+        };
+        let chat_id = match thread {
+            Some(t) => format!("{}:{}", channel, t),
+            None => channel.to_string(),
+        };
+
+        let mut metadata = HashMap::new();
+        metadata.insert("event".to_string(), kind.to_string());
+        metadata.insert("ts".to_string(), ts.to_string());
+        if let Some(t) = thread {
+            metadata.insert("thread_ts".to_string(), t.to_string());
+        }
+        if let Some(team) = payload["team_id"].as_str() {
+            metadata.insert("team_id".to_string(), team.to_string());
+        }
+
+        if is_dm {
+            self.base
+                .handle_message(user, &chat_id, text, vec![], metadata)
+                .await;
+        } else {
+            // Each thread is already its own chat, so no separate thread id
+            let group = GroupContext {
+                mentioned: kind == "app_mention",
+                reply_to_bot: event["parent_user_id"].as_str().is_some()
+                    && event["parent_user_id"].as_str() == self.bot_user_id.as_deref(),
+                thread_id: None,
+                sender_name: None,
+            };
+            self.base
+                .handle_group_message(user, &chat_id, text, vec![], metadata, group)
+                .await;
         }
     }
 }
@@ -41,57 +366,438 @@ impl Channel for SlackChannel {
     }
 
     async fn start(&self) -> anyhow::Result<()> {
-        let mut running = self.running.lock().await;
-        if *running {
+        if self.running.swap(true, Ordering::SeqCst) {
             return Ok(());
         }
+        if self.app_token.is_empty() {
+            tracing::warn!(
+                channel = %self.name(),
+                "No app_token (xapp-...) configured: Slack can send but not receive messages"
+            );
+            return Ok(());
+        }
+        tracing::info!(channel = %self.name(), "Starting Slack channel (Socket Mode)");
 
-        *running = true;
-        tracing::info!(channel = %self.name(), "Starting Slack channel (Web API mode)");
-
-        // In a real implementation, we would start a Socket Mode client here using the app_token.
-        // For now, we'll just log that it's "running" for outbound messages.
-
+        let socket = SocketMode {
+            base: self.base.clone(),
+            client: self.client.clone(),
+            api_base: self.api_base.clone(),
+            app_token: self.app_token.clone(),
+            bot_token: self.bot_token.clone(),
+            running: self.running.clone(),
+            connected: self.connected.clone(),
+            bot_user_id: None,
+            seen: VecDeque::new(),
+            seen_set: HashSet::new(),
+        };
+        *self.task.lock().await = Some(tokio::spawn(socket.run()));
         Ok(())
     }
 
     async fn stop(&self) -> anyhow::Result<()> {
-        let mut running = self.running.lock().await;
-        *running = false;
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        self.connected.store(false, Ordering::SeqCst);
         Ok(())
     }
 
     async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
-        let url = "https://slack.com/api/chat.postMessage";
+        let (channel, thread) = split_chat_id(&msg.chat_id);
+        for chunk in split_message(&msg.content, SLACK_MAX_CHARS) {
+            self.post_message(channel, thread, &chunk).await?;
+        }
+        Ok(())
+    }
 
-        let body = json!({
-            "channel": msg.chat_id,
-            "text": msg.content,
-        });
+    async fn send_draft(&self, chat_id: &str, content: &str) -> anyhow::Result<Option<String>> {
+        let (channel, thread) = split_chat_id(chat_id);
+        let first = split_message(content, SLACK_MAX_CHARS).remove(0);
+        let ts = self.post_message(channel, thread, &first).await?;
+        Ok(Some(ts))
+    }
+
+    async fn edit_draft(
+        &self,
+        chat_id: &str,
+        draft_id: &str,
+        content: &str,
+        done: bool,
+    ) -> anyhow::Result<()> {
+        let (channel, thread) = split_chat_id(chat_id);
+        let mut chunks = split_message(content, SLACK_MAX_CHARS).into_iter();
+        let first = chunks.next().unwrap_or_default();
+        self.web_api(
+            "chat.update",
+            json!({ "channel": channel, "ts": draft_id, "text": slack_text(&first) }),
+        )
+        .await?;
+        // Whatever does not fit the placeholder follows it once complete
+        if done {
+            for chunk in chunks {
+                self.post_message(channel, thread, &chunk).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `chat.update` is rate-limited to about 50 calls a minute.
+    fn edit_interval(&self) -> Duration {
+        Duration::from_millis(1200)
+    }
+
+    /// Upload the way `files.uploadV2` does: get an upload URL, send the
+    /// bytes there, then share the file into the channel (or thread).
+    async fn send_file(&self, chat_id: &str, attachment: &Attachment) -> anyhow::Result<()> {
+        let (channel, thread) = split_chat_id(chat_id);
+        let data = attachment.read().await?;
+
+        let length = data.len().to_string();
+        let form = [
+            ("filename", attachment.filename.as_str()),
+            ("length", length.as_str()),
+        ];
+        let upload = self
+            .call(
+                self.client
+                    .post(format!("{}/files.getUploadURLExternal", self.api_base))
+                    .form(&form),
+            )
+            .await?;
+        let (Some(upload_url), Some(file_id)) =
+            (upload["upload_url"].as_str(), upload["file_id"].as_str())
+        else {
+            anyhow::bail!("Slack API error: no upload URL for {}", attachment.filename);
+        };
 
         let resp = self
             .client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", self.bot_token))
-            .header("Content-Type", "application/json; charset=utf-8")
-            .json(&body)
+            .post(upload_url)
+            .header("Content-Type", attachment.mime.as_str())
+            .body(data)
             .send()
             .await?;
-
-        let status = resp.status();
-        let resp_body: serde_json::Value = resp.json().await?;
-
-        if !status.is_success() || !resp_body["ok"].as_bool().unwrap_or(false) {
-            anyhow::bail!(
-                "Slack API error: {}",
-                resp_body["error"].as_str().unwrap_or("unknown error")
-            );
+        if !resp.status().is_success() {
+            anyhow::bail!("Slack file upload failed: HTTP {}", resp.status());
         }
 
+        let mut body = json!({
+            "files": [{ "id": file_id, "title": attachment.filename }],
+            "channel_id": channel,
+        });
+        if let Some(thread) = thread {
+            body["thread_ts"] = json!(thread);
+        }
+        self.web_api("files.completeUploadExternal", body).await?;
         Ok(())
     }
 
     fn is_running(&self) -> bool {
-        true
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Without an app token there is no socket, and sending still works.
+    fn is_connected(&self) -> bool {
+        self.is_running() && (self.app_token.is_empty() || self.connected.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use crate::channel::RateLimited;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{accept_async, WebSocketStream};
+    use wiremock::matchers::{body_partial_json, body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    type Socket = WebSocketStream<tokio::net::TcpStream>;
+
+    async fn send(ws: &mut Socket, value: Value) {
+        ws.send(WsMessage::text(value.to_string())).await.unwrap();
+    }
+
+    /// Read frames until the ack for `envelope_id` arrives.
+    async fn expect_ack(ws: &mut Socket, envelope_id: &str) {
+        while let Some(Ok(frame)) = ws.next().await {
+            if let WsMessage::Text(text) = frame {
+                let ack: Value = serde_json::from_str(&text).unwrap();
+                if ack["envelope_id"] == envelope_id {
+                    return;
+                }
+            }
+        }
+        panic!("no ack for {}", envelope_id);
+    }
+
+    fn event(envelope_id: &str, event_id: &str, event: Value) -> Value {
+        json!({
+            "envelope_id": envelope_id,
+            "type": "events_api",
+            "accepts_response_payload": false,
+            "payload": { "team_id": "T1", "event_id": event_id, "event": event }
+        })
+    }
+
+    #[test]
+    fn test_split_chat_id() {
+        assert_eq!(split_chat_id("C1"), ("C1", None));
+        assert_eq!(
+            split_chat_id("C1:1700000000.000100"),
+            ("C1", Some("1700000000.000100"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_socket_mode_events_acks_and_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/apps.connections.open"))
+            .and(header("authorization", "Bearer xapp-test"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "url": ws_url })),
+            )
+            .expect(2)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/auth.test"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "user_id": "UBOT" })),
+            )
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .and(body_partial_json(
+                json!({ "channel": "C1", "thread_ts": "1700.1", "text": "*hi* there" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&api)
+            .await;
+
+        let bus = Arc::new(MessageBus::new());
+        let channel = SlackChannel::new("xoxb-test", "xapp-test", vec!["*".into()], bus.clone())
+            .with_api_base(&api.uri());
+        channel.start().await.unwrap();
+        assert!(channel.is_running());
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(stream).await.unwrap();
+        send(&mut ws, json!({ "type": "hello" })).await;
+
+        // A channel mention starts a thread; the mention itself is stripped
+        let mention = json!({
+            "type": "app_mention", "user": "U1", "channel": "C1",
+            "text": "<@UBOT> hello", "ts": "1700.1"
+        });
+        send(&mut ws, event("e1", "Ev1", mention.clone())).await;
+        expect_ack(&mut ws, "e1").await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.sender_id, "U1");
+        assert_eq!(msg.chat_id, "C1:1700.1");
+        assert_eq!(msg.session_key, "slack:C1:1700.1");
+        assert_eq!(msg.content, "hello");
+        assert_eq!(msg.metadata["thread_ts"], "1700.1");
+        assert!(msg.group.unwrap().mentioned);
+
+        // A redelivered event is acked but not handled twice; our own
+        // messages are ignored
+        send(&mut ws, event("e2", "Ev1", mention)).await;
+        expect_ack(&mut ws, "e2").await;
+        let own = json!({
+            "type": "message", "channel_type": "im", "user": "UBOT",
+            "channel": "D1", "text": "echo", "ts": "1700.2"
+        });
+        send(&mut ws, event("e3", "Ev2", own)).await;
+        expect_ack(&mut ws, "e3").await;
+        // The plain `message` copy of a mention is left to `app_mention`
+        let copy = json!({
+            "type": "message", "channel_type": "channel", "user": "U1",
+            "channel": "C1", "text": "<@UBOT> hello", "ts": "1700.1"
+        });
+        send(&mut ws, event("e5", "Ev4", copy)).await;
+        expect_ack(&mut ws, "e5").await;
+
+        // Slack asks us to reconnect; the DM arrives on the new socket
+        send(
+            &mut ws,
+            json!({ "type": "disconnect", "reason": "refresh_requested" }),
+        )
+        .await;
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut ws = accept_async(stream).await.unwrap();
+        let dm = json!({
+            "type": "message", "channel_type": "im", "user": "U2",
+            "channel": "D1", "text": "ping", "ts": "1700.3"
+        });
+        send(&mut ws, event("e4", "Ev3", dm)).await;
+        expect_ack(&mut ws, "e4").await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "D1");
+        assert_eq!(msg.content, "ping");
+        assert!(msg.group.is_none());
+
+        // Replies go to the thread, in mrkdwn
+        channel
+            .send(OutboundMessage {
+                channel: "slack".into(),
+                chat_id: "C1:1700.1".into(),
+                content: "**hi** there".into(),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        channel.stop().await.unwrap();
+        assert!(!channel.is_running());
+    }
+
+    #[tokio::test]
+    async fn test_send_splits_long_replies() {
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(2)
+            .mount(&api)
+            .await;
+
+        let bus = Arc::new(MessageBus::new());
+        let channel = SlackChannel::new("xoxb-test", "xapp-test", vec!["*".into()], bus)
+            .with_api_base(&api.uri());
+        let paragraph = "word ".repeat(500);
+        channel
+            .send(OutboundMessage {
+                channel: "slack".into(),
+                chat_id: "C1".into(),
+                content: format!("{}\n\n{}", paragraph.trim(), paragraph.trim()),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        for request in api.received_requests().await.unwrap() {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            assert!(body["text"].as_str().unwrap().chars().count() <= SLACK_MAX_CHARS);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_reports_retry_after() {
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+            .mount(&api)
+            .await;
+
+        let bus = Arc::new(MessageBus::new());
+        let channel = SlackChannel::new("xoxb-test", "xapp-test", vec!["*".into()], bus)
+            .with_api_base(&api.uri());
+        let err = channel
+            .send(OutboundMessage {
+                channel: "slack".into(),
+                chat_id: "C1".into(),
+                content: "hi".into(),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await
+            .unwrap_err();
+        let limited = err.downcast_ref::<RateLimited>().unwrap();
+        assert_eq!(limited.retry_after, Duration::from_secs(7));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_reply_edits_placeholder() {
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "ts": "1.1" })),
+            )
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat.update"))
+            .and(body_partial_json(
+                json!({ "channel": "C1", "ts": "1.1", "text": "*Sunny*" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&api)
+            .await;
+
+        let bus = Arc::new(MessageBus::new());
+        let channel = SlackChannel::new("xoxb-test", "xapp-test", vec!["*".into()], bus)
+            .with_api_base(&api.uri());
+        let ts = channel.send_draft("C1", "Sun").await.unwrap();
+        assert_eq!(ts.as_deref(), Some("1.1"));
+        channel
+            .edit_draft("C1", "1.1", "**Sunny**", true)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_file_uploads_and_shares() {
+        let api = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/files.getUploadURLExternal"))
+            .and(body_string_contains("filename=chart.png"))
+            .and(body_string_contains("length=3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "ok": true,
+                "upload_url": format!("{}/upload/F1", api.uri()),
+                "file_id": "F1",
+            })))
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/upload/F1"))
+            .and(header("content-type", "image/png"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&api)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/files.completeUploadExternal"))
+            .and(body_partial_json(json!({
+                "files": [{ "id": "F1", "title": "chart.png" }],
+                "channel_id": "C1",
+                "thread_ts": "1.1",
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&api)
+            .await;
+
+        let bus = Arc::new(MessageBus::new());
+        let channel = SlackChannel::new("xoxb-test", "xapp-test", vec!["*".into()], bus)
+            .with_api_base(&api.uri());
+        channel
+            .send_file(
+                "C1:1.1",
+                &Attachment::from_bytes("chart.png", vec![1, 2, 3]),
+            )
+            .await
+            .unwrap();
     }
 }
@@ -0,0 +1,295 @@
+// QuectoClaw — Channel supervisor: restarts channels that fail or stop
+
+use crate::bus::MessageBus;
+use crate::channel::health::{ChannelState, HealthBoard, HealthSnapshot};
+use crate::channel::Channel;
+use crate::util::Backoff;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How often a running channel is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Starts every channel, restarts one with exponential backoff when its
+/// start fails or it stops on its own, and stops them all on shutdown.
+/// Health goes to the board and, if set, a snapshot file.
+pub struct Supervisor {
+    channels: Vec<Arc<dyn Channel>>,
+    bus: Arc<MessageBus>,
+    health: HealthBoard,
+    workspace: Option<PathBuf>,
+    check_interval: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Supervisor {
+    pub fn new(channels: Vec<Arc<dyn Channel>>, bus: Arc<MessageBus>, health: HealthBoard) -> Self {
+        Self {
+            channels,
+            bus,
+            health,
+            workspace: None,
+            check_interval: CHECK_INTERVAL,
+            initial_backoff: Backoff::INITIAL,
+            max_backoff: Backoff::MAX,
+        }
+    }
+
+    /// Keep `<workspace>/channel_health.json` up to date.
+    pub fn with_snapshot(mut self, workspace: PathBuf) -> Self {
+        self.workspace = Some(workspace);
+        self
+    }
+
+    /// Supervise until `shutdown` turns true (or its sender is dropped),
+    /// then stop every channel.
+    pub async fn run(self, shutdown: watch::Receiver<bool>) {
+        let mut set = tokio::task::JoinSet::new();
+        for channel in &self.channels {
+            self.health
+                .set_state(channel.name(), ChannelState::Starting);
+            let watcher = ChannelWatch {
+                channel: channel.clone(),
+                bus: self.bus.clone(),
+                health: self.health.clone(),
+                check_interval: self.check_interval,
+                initial_backoff: self.initial_backoff,
+                max_backoff: self.max_backoff,
+            };
+            set.spawn(watcher.run(shutdown.clone()));
+        }
+
+        let mut shutdown = shutdown;
+        loop {
+            self.save_snapshot(true).await;
+            tokio::select! {
+                _ = wait_for_shutdown(&mut shutdown) => break,
+                _ = tokio::time::sleep(self.check_interval) => {}
+            }
+        }
+
+        while set.join_next().await.is_some() {}
+        self.save_snapshot(false).await;
+        tracing::info!("All channels stopped");
+    }
+
+    async fn save_snapshot(&self, running: bool) {
+        if let Some(workspace) = &self.workspace {
+            if let Err(e) = HealthSnapshot::save(&self.health, workspace, running).await {
+                tracing::debug!(error = %e, "Failed to save channel health");
+            }
+        }
+    }
+}
+
+/// Resolves once shutdown is requested.
+async fn wait_for_shutdown(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+struct ChannelWatch {
+    channel: Arc<dyn Channel>,
+    bus: Arc<MessageBus>,
+    health: HealthBoard,
+    check_interval: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl ChannelWatch {
+    async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        let name = self.channel.name().to_string();
+        let mut backoff = Backoff::new(self.initial_backoff, self.max_backoff);
+
+        loop {
+            self.health.set_state(&name, ChannelState::Starting);
+            match self.channel.start().await {
+                Ok(()) => {
+                    backoff.attempt();
+                    if self.watch(&name, &mut shutdown).await {
+                        break;
+                    }
+                    tracing::warn!(channel = %name, "Channel stopped unexpectedly");
+                    self.health
+                        .record_error(&name, "channel stopped unexpectedly");
+                    // Clean up whatever is left before starting again
+                    if let Err(e) = self.channel.stop().await {
+                        tracing::debug!(channel = %name, error = %e, "Failed to stop channel");
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(channel = %name, "Failed to start channel: {}", e);
+                    self.health.record_error(&name, &e);
+                }
+            }
+
+            self.health.set_state(&name, ChannelState::Reconnecting);
+            let delay = backoff.next_delay();
+            tracing::info!(channel = %name, retry_in_ms = delay.as_millis() as u64, "Restarting channel");
+            tokio::select! {
+                _ = wait_for_shutdown(&mut shutdown) => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+            self.health.record_restart(&name);
+        }
+
+        if let Err(e) = self.channel.stop().await {
+            tracing::warn!(channel = %name, error = %e, "Failed to stop channel");
+        }
+        self.health.set_state(&name, ChannelState::Stopped);
+    }
+
+    /// Follow a running channel and report whether its connection is up;
+    /// true on shutdown, false once it stops.
+    async fn watch(&self, name: &str, shutdown: &mut watch::Receiver<bool>) -> bool {
+        let mut was_connected = false;
+        loop {
+            let connected = self.channel.is_connected();
+            let state = if connected {
+                ChannelState::Connected
+            } else if was_connected {
+                ChannelState::Reconnecting
+            } else {
+                ChannelState::Starting
+            };
+            self.health.set_state(name, state);
+            was_connected |= connected;
+
+            tokio::select! {
+                _ = wait_for_shutdown(shutdown) => return true,
+                _ = tokio::time::sleep(self.check_interval) => {}
+            }
+            if let Some(at) = self.bus.last_inbound(name) {
+                self.health.set_last_message(name, at);
+            }
+            if !self.channel.is_running() {
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{InboundMessage, OutboundMessage};
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+    /// Fails its first start, then runs until `drop_connection`.
+    /// `offline` keeps it running with its connection down.
+    #[derive(Default)]
+    struct FlakyChannel {
+        running: AtomicBool,
+        offline: AtomicBool,
+        starts: AtomicU32,
+        stopped: AtomicBool,
+    }
+
+    impl FlakyChannel {
+        fn drop_connection(&self) {
+            self.running.store(false, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait]
+    impl Channel for FlakyChannel {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+        async fn start(&self) -> anyhow::Result<()> {
+            if self.starts.fetch_add(1, Ordering::SeqCst) == 0 {
+                anyhow::bail!("connection refused");
+            }
+            self.running.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+        async fn stop(&self) -> anyhow::Result<()> {
+            self.running.store(false, Ordering::SeqCst);
+            self.stopped.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+        async fn send(&self, _msg: OutboundMessage) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn is_running(&self) -> bool {
+            self.running.load(Ordering::SeqCst)
+        }
+        fn is_connected(&self) -> bool {
+            self.is_running() && !self.offline.load(Ordering::SeqCst)
+        }
+    }
+
+    async fn wait_until(mut cond: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if cond() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    #[tokio::test]
+    async fn test_restarts_failed_and_stopped_channels() {
+        let dir = tempfile::tempdir().unwrap();
+        let channel = Arc::new(FlakyChannel::default());
+        let bus = Arc::new(MessageBus::new());
+        let health = HealthBoard::new();
+        let mut supervisor = Supervisor::new(vec![channel.clone()], bus.clone(), health.clone())
+            .with_snapshot(dir.path().to_path_buf());
+        supervisor.check_interval = Duration::from_millis(10);
+        supervisor.initial_backoff = Duration::from_millis(10);
+
+        let (stop, shutdown) = watch::channel(false);
+        let task = tokio::spawn(supervisor.run(shutdown));
+
+        // The failed first start is retried
+        wait_until(|| health.get("flaky").is_some_and(|h| h.connected())).await;
+        let h = health.get("flaky").unwrap();
+        assert_eq!(h.restarts, 1);
+        assert_eq!(h.last_error.as_deref(), Some("connection refused"));
+
+        bus.publish_inbound(InboundMessage {
+            channel: "flaky".into(),
+            sender_id: "u1".into(),
+            chat_id: "c1".into(),
+            content: "hi".into(),
+            media: vec![],
+            session_key: "flaky:c1".into(),
+            metadata: HashMap::new(),
+            group: None,
+        })
+        .await;
+        wait_until(|| health.get("flaky").unwrap().last_message.is_some()).await;
+
+        // A lost connection the channel retries itself is not restarted
+        channel.offline.store(true, Ordering::SeqCst);
+        wait_until(|| health.get("flaky").unwrap().state == ChannelState::Reconnecting).await;
+        channel.offline.store(false, Ordering::SeqCst);
+        wait_until(|| health.get("flaky").is_some_and(|h| h.connected())).await;
+        assert_eq!(health.get("flaky").unwrap().restarts, 1);
+
+        // One that dies while running is
+        channel.drop_connection();
+        wait_until(|| health.get("flaky").unwrap().restarts == 2).await;
+        wait_until(|| channel.is_running()).await;
+        assert_eq!(
+            health.get("flaky").unwrap().last_error.as_deref(),
+            Some("channel stopped unexpectedly")
+        );
+
+        stop.send(true).unwrap();
+        task.await.unwrap();
+        assert!(channel.stopped.load(Ordering::SeqCst));
+        assert!(!channel.is_running());
+        assert_eq!(health.get("flaky").unwrap().state, ChannelState::Stopped);
+        let snapshot = HealthSnapshot::load(dir.path()).unwrap();
+        assert!(!snapshot.is_live());
+        assert_eq!(snapshot.channels[0].state, ChannelState::Stopped);
+    }
+}
@@ -2,19 +2,68 @@
 
 #[cfg(feature = "telegram")]
 mod implementation {
-    use crate::bus::{MessageBus, OutboundMessage};
-    use crate::channel::{BaseChannel, Channel};
+    use crate::bus::{Attachment, GroupContext, MessageBus, OutboundMessage};
+    use crate::channel::format::{
+        split_message, telegram_visible_len, to_telegram_html, TELEGRAM_MAX_CHARS,
+    };
+    use crate::channel::{BaseChannel, Channel, RateLimited};
     use async_trait::async_trait;
     use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::Arc;
+    use std::time::Duration;
+    use teloxide::dispatching::ShutdownToken;
     use teloxide::prelude::*;
-    use teloxide::types::ParseMode;
+    use teloxide::types::{ChatAction, InputFile, Me, MessageId, ParseMode, ThreadId};
+    use teloxide::{ApiError, RequestError};
     use tokio::sync::Mutex;
 
+    const TELEGRAM_MAX_PHOTO_BYTES: usize = 10 * 1024 * 1024;
+
+    /// Remove `@bot_username` from a group message; returns the text and
+    /// whether the bot was mentioned.
+    fn strip_mention(text: &str, username: &str) -> (String, bool) {
+        // Usernames are ASCII, and ASCII lowercasing keeps byte offsets
+        let mention = format!("@{}", username).to_ascii_lowercase();
+        match text.to_ascii_lowercase().find(&mention) {
+            Some(at) => {
+                let rest = format!("{}{}", &text[..at], &text[at + mention.len()..]);
+                (rest.trim().to_string(), true)
+            }
+            None => (text.to_string(), false),
+        }
+    }
+
+    /// Split a chat id into the chat and the forum topic it replies in.
+    /// Topics use `<chat_id>:<thread_id>`.
+    fn split_chat_id(chat_id: &str) -> anyhow::Result<(ChatId, Option<ThreadId>)> {
+        let (chat, thread) = match chat_id.split_once(':') {
+            Some((chat, thread)) => (chat, Some(ThreadId(MessageId(thread.parse()?)))),
+            None => (chat_id, None),
+        };
+        Ok((ChatId(chat.parse()?), thread))
+    }
+
+    /// Report flood control as `RateLimited`, so the outbox waits as long
+    /// as Telegram asks.
+    fn send_error(e: RequestError) -> anyhow::Error {
+        match e {
+            RequestError::RetryAfter(wait) => RateLimited {
+                retry_after: wait.duration(),
+            }
+            .into(),
+            e => e.into(),
+        }
+    }
+
     pub struct TelegramChannel {
         base: BaseChannel,
         bot: Bot,
-        running: Arc<Mutex<bool>>,
+        /// For uploads into forum topics (see `upload_to_topic`).
+        http: reqwest::Client,
+        running: Arc<AtomicBool>,
+        /// Stops the dispatcher started by `start`.
+        shutdown: Mutex<Option<ShutdownToken>>,
     }
 
     impl TelegramChannel {
@@ -23,8 +72,93 @@ mod implementation {
             Self {
                 base: BaseChannel::new("telegram", allow_list, bus),
                 bot,
-                running: Arc::new(Mutex::new(false)),
+                http: reqwest::Client::new(),
+                running: Arc::new(AtomicBool::new(false)),
+                shutdown: Mutex::new(None),
+            }
+        }
+
+        /// Send one chunk (at most `TELEGRAM_MAX_CHARS`) as HTML. HTML that
+        /// Telegram cannot parse goes out as plain text; any other error is
+        /// returned as is.
+        async fn send_chunk(
+            &self,
+            chat_id: ChatId,
+            thread: Option<ThreadId>,
+            chunk: String,
+        ) -> anyhow::Result<Message> {
+            let html = to_telegram_html(&chunk);
+            if telegram_visible_len(&html) <= TELEGRAM_MAX_CHARS {
+                let mut request = self
+                    .bot
+                    .send_message(chat_id, html)
+                    .parse_mode(ParseMode::Html);
+                if let Some(thread) = thread {
+                    request = request.message_thread_id(thread);
+                }
+                match request.await {
+                    Ok(sent) => return Ok(sent),
+                    // Rejected markup: try plain text
+                    Err(RequestError::Api(ApiError::CantParseEntities(_))) => {}
+                    Err(e) => return Err(send_error(e)),
+                }
             }
+            let mut request = self.bot.send_message(chat_id, chunk);
+            if let Some(thread) = thread {
+                request = request.message_thread_id(thread);
+            }
+            request.await.map_err(send_error)
+        }
+
+        /// Upload a file into a forum topic with `method` (`sendPhoto` or
+        /// `sendDocument`). teloxide 0.13 cannot encode `message_thread_id`
+        /// in multipart requests, so this one is built by hand.
+        async fn upload_to_topic(
+            &self,
+            method: &str,
+            chat_id: ChatId,
+            thread: ThreadId,
+            attachment: &Attachment,
+            data: Vec<u8>,
+        ) -> anyhow::Result<()> {
+            let field = if method == "sendPhoto" {
+                "photo"
+            } else {
+                "document"
+            };
+            let url = self
+                .bot
+                .api_url()
+                .join(&format!("/bot{}/{}", self.bot.token(), method))?;
+            let file = reqwest::multipart::Part::bytes(data)
+                .file_name(attachment.filename.clone())
+                .mime_str(&attachment.mime)?;
+            let form = reqwest::multipart::Form::new()
+                .text("chat_id", chat_id.to_string())
+                .text("message_thread_id", thread.to_string())
+                .part(field, file);
+            let reply: serde_json::Value = self
+                .http
+                .post(url.as_str())
+                .multipart(form)
+                .send()
+                .await?
+                .json()
+                .await?;
+            if reply["ok"].as_bool() == Some(true) {
+                return Ok(());
+            }
+            if let Some(secs) = reply["parameters"]["retry_after"].as_u64() {
+                return Err(RateLimited {
+                    retry_after: Duration::from_secs(secs),
+                }
+                .into());
+            }
+            anyhow::bail!(
+                "Telegram {} failed: {}",
+                method,
+                reply["description"].as_str().unwrap_or("unknown error")
+            )
         }
     }
 
@@ -35,12 +169,11 @@ mod implementation {
         }
 
         async fn start(&self) -> anyhow::Result<()> {
-            let mut running = self.running.lock().await;
-            if *running {
+            let mut shutdown = self.shutdown.lock().await;
+            if self.running.load(Ordering::SeqCst) {
                 return Ok(());
             }
 
-            *running = true;
             let bot = self.bot.clone();
             let base = Arc::new(BaseChannel::new(
                 self.base.name(),
@@ -50,41 +183,71 @@ mod implementation {
 
             tracing::info!(channel = %self.name(), "Starting Telegram channel");
 
-            // Clone for the background task
-            let running_clone = self.running.clone();
+            // The bot's own account, to recognise mentions and replies
+            let me = bot.get_me().await?;
 
-            tokio::spawn(async move {
-                let handler = Update::filter_message().endpoint(
-                    |_bot: Bot, base: Arc<BaseChannel>, msg: Message| async move {
-                        if let Some(text) = msg.text() {
-                            let sender_id = if let Some(user) = &msg.from {
-                                if let Some(username) = &user.username {
-                                    format!("{}|{}", user.id, username)
-                                } else {
-                                    user.id.to_string()
-                                }
+            let handler = Update::filter_message().endpoint(
+                |_bot: Bot, base: Arc<BaseChannel>, me: Me, msg: Message| async move {
+                    if let Some(text) = msg.text() {
+                        let sender_id = if let Some(user) = &msg.from {
+                            if let Some(username) = &user.username {
+                                format!("{}|{}", user.id, username)
                             } else {
-                                msg.chat.id.to_string()
-                            };
+                                user.id.to_string()
+                            }
+                        } else {
+                            msg.chat.id.to_string()
+                        };
 
-                            let chat_id = msg.chat.id.to_string();
+                        // Forum topics are separate chats, so replies land
+                        // in the topic that asked
+                        let chat_id = match msg.thread_id.filter(|_| msg.is_topic_message) {
+                            Some(thread) => format!("{}:{}", msg.chat.id, thread.0 .0),
+                            None => msg.chat.id.to_string(),
+                        };
 
+                        if msg.chat.is_group() || msg.chat.is_supergroup() {
+                            let (text, mentioned) = strip_mention(text, me.username());
+                            let reply_to_bot = msg
+                                .reply_to_message()
+                                .and_then(|m| m.from.as_ref())
+                                .is_some_and(|u| u.id == me.id);
+                            let group = GroupContext {
+                                mentioned,
+                                reply_to_bot,
+                                // Each topic is already its own chat
+                                thread_id: None,
+                                sender_name: msg.from.as_ref().map(|u| u.full_name()),
+                            };
+                            base.handle_group_message(
+                                &sender_id,
+                                &chat_id,
+                                &text,
+                                vec![],
+                                HashMap::new(),
+                                group,
+                            )
+                            .await;
+                        } else {
                             base.handle_message(&sender_id, &chat_id, text, vec![], HashMap::new())
                                 .await;
                         }
-                        respond(())
-                    },
-                );
-
-                Dispatcher::builder(bot, handler)
-                    .dependencies(dptree::deps![base])
-                    .enable_ctrlc_handler()
-                    .build()
-                    .dispatch()
-                    .await;
+                    }
+                    respond(())
+                },
+            );
+
+            // The gateway handles Ctrl-C and stops us through the token
+            let mut dispatcher = Dispatcher::builder(bot, handler)
+                .dependencies(dptree::deps![base, me])
+                .build();
+            *shutdown = Some(dispatcher.shutdown_token());
+            self.running.store(true, Ordering::SeqCst);
 
-                let mut r = running_clone.lock().await;
-                *r = false;
+            let running = self.running.clone();
+            tokio::spawn(async move {
+                dispatcher.dispatch().await;
+                running.store(false, Ordering::SeqCst);
                 tracing::info!("Telegram dispatcher stopped");
             });
 
@@ -92,36 +255,224 @@ mod implementation {
         }
 
         async fn stop(&self) -> anyhow::Result<()> {
-            let mut running = self.running.lock().await;
-            *running = false;
-            // teloxide handles shutdown via ctrl-c or you can stop the task
-            // For now, we just update the flag.
+            if let Some(token) = self.shutdown.lock().await.take() {
+                // An idle dispatcher has nothing to wait for
+                if let Ok(done) = token.shutdown() {
+                    done.await;
+                }
+            }
+            self.running.store(false, Ordering::SeqCst);
             Ok(())
         }
 
         async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
-            let chat_id: i64 = msg.chat_id.parse()?;
+            let (chat_id, thread) = split_chat_id(&msg.chat_id)?;
 
-            // Format content — convert markdown-ish to something Telegram likes or just send as plain text
-            // For now, we'll use MarkdownV2 if possible, or just raw text.
-            let res = self
-                .bot
-                .send_message(ChatId(chat_id), &msg.content)
-                .parse_mode(ParseMode::MarkdownV2)
-                .await;
+            for chunk in split_message(&msg.content, TELEGRAM_MAX_CHARS) {
+                self.send_chunk(chat_id, thread, chunk).await?;
+            }
 
-            if res.is_err() {
-                // Fallback to plain text if MarkdownV2 fails
-                self.bot.send_message(ChatId(chat_id), &msg.content).await?;
+            Ok(())
+        }
+
+        async fn send_typing(&self, chat_id: &str) -> anyhow::Result<()> {
+            let (chat_id, thread) = split_chat_id(chat_id)?;
+            let mut request = self.bot.send_chat_action(chat_id, ChatAction::Typing);
+            if let Some(thread) = thread {
+                request = request.message_thread_id(thread);
             }
+            request.await?;
+            Ok(())
+        }
+
+        async fn send_draft(&self, chat_id: &str, content: &str) -> anyhow::Result<Option<String>> {
+            let (chat_id, thread) = split_chat_id(chat_id)?;
+            let first = split_message(content, TELEGRAM_MAX_CHARS).remove(0);
+            let sent = self.send_chunk(chat_id, thread, first).await?;
+            Ok(Some(sent.id.0.to_string()))
+        }
+
+        async fn edit_draft(
+            &self,
+            chat_id: &str,
+            draft_id: &str,
+            content: &str,
+            done: bool,
+        ) -> anyhow::Result<()> {
+            let (chat_id, thread) = split_chat_id(chat_id)?;
+            let message_id = MessageId(draft_id.parse()?);
+            let mut chunks = split_message(content, TELEGRAM_MAX_CHARS).into_iter();
+            let first = chunks.next().unwrap_or_default();
+
+            // "Not modified" means the last update already showed this text
+            let html = to_telegram_html(&first);
+            let mut edited = false;
+            if telegram_visible_len(&html) <= TELEGRAM_MAX_CHARS {
+                match self
+                    .bot
+                    .edit_message_text(chat_id, message_id, html)
+                    .parse_mode(ParseMode::Html)
+                    .await
+                {
+                    Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => edited = true,
+                    // Rejected markup: try plain text
+                    Err(RequestError::Api(ApiError::CantParseEntities(_))) => {}
+                    Err(e) => return Err(send_error(e)),
+                }
+            }
+            if !edited {
+                match self.bot.edit_message_text(chat_id, message_id, first).await {
+                    Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                    Err(e) => return Err(send_error(e)),
+                }
+            }
+
+            // Whatever does not fit the placeholder follows it once complete
+            if done {
+                for chunk in chunks {
+                    self.send_chunk(chat_id, thread, chunk).await?;
+                }
+            }
+            Ok(())
+        }
 
+        async fn send_file(&self, chat_id: &str, attachment: &Attachment) -> anyhow::Result<()> {
+            let (chat_id, thread) = split_chat_id(chat_id)?;
+            let data = attachment.read().await?;
+            // Photos over 10 MB are refused; send those as documents
+            let as_photo = attachment.is_image() && data.len() <= TELEGRAM_MAX_PHOTO_BYTES;
+            if let Some(thread) = thread {
+                let method = if as_photo {
+                    "sendPhoto"
+                } else {
+                    "sendDocument"
+                };
+                return self
+                    .upload_to_topic(method, chat_id, thread, attachment, data)
+                    .await;
+            }
+            let file = InputFile::memory(data).file_name(attachment.filename.clone());
+            if as_photo {
+                self.bot
+                    .send_photo(chat_id, file)
+                    .await
+                    .map_err(send_error)?;
+            } else {
+                self.bot
+                    .send_document(chat_id, file)
+                    .await
+                    .map_err(send_error)?;
+            }
             Ok(())
         }
 
         fn is_running(&self) -> bool {
-            // We can't easily check the lock here synchronously if start() is async
-            // but for simplicity we'll just track it.
-            true
+            self.running.load(Ordering::SeqCst)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::bus::{AttachmentData, OutboundKind};
+        use serde_json::json;
+        use wiremock::matchers::{method, path_regex};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        #[test]
+        fn test_split_chat_id() {
+            assert_eq!(split_chat_id("42").unwrap(), (ChatId(42), None));
+            assert_eq!(
+                split_chat_id("-1001:7").unwrap(),
+                (ChatId(-1001), Some(ThreadId(MessageId(7))))
+            );
+            assert!(split_chat_id("-1001:general").is_err());
+        }
+
+        #[tokio::test]
+        async fn test_replies_go_to_the_forum_topic() {
+            let server = MockServer::start().await;
+            let message = json!({
+                "message_id": 5, "message_thread_id": 7, "is_topic_message": true, "date": 0,
+                "chat": { "id": -1001, "type": "supergroup", "title": "t", "is_forum": true },
+                "text": "ok"
+            });
+            Mock::given(method("POST"))
+                .and(path_regex("(?i)/SendChatAction$"))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "result": true })),
+                )
+                .mount(&server)
+                .await;
+            Mock::given(method("POST"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(json!({ "ok": true, "result": message })),
+                )
+                .mount(&server)
+                .await;
+
+            let mut channel = TelegramChannel::new("1:test", vec![], Arc::new(MessageBus::new()));
+            channel.bot = channel.bot.set_api_url(server.uri().parse().unwrap());
+            let topic = "-1001:7";
+            channel
+                .send(OutboundMessage {
+                    channel: "telegram".into(),
+                    chat_id: topic.into(),
+                    content: "hello".into(),
+                    metadata: HashMap::new(),
+                    kind: OutboundKind::Message,
+                    attachments: Vec::new(),
+                })
+                .await
+                .unwrap();
+            channel.send_typing(topic).await.unwrap();
+            let draft = channel
+                .send_draft(topic, "thinking")
+                .await
+                .unwrap()
+                .unwrap();
+            // The part that does not fit the placeholder follows it in the topic
+            let long = format!("{}\n\n{}", "a".repeat(4000), "b".repeat(100));
+            channel
+                .edit_draft(topic, &draft, &long, true)
+                .await
+                .unwrap();
+            let file = Attachment {
+                filename: "report.txt".into(),
+                mime: "text/plain".into(),
+                data: AttachmentData::Bytes(b"report".to_vec()),
+            };
+            channel.send_file(topic, &file).await.unwrap();
+
+            let requests = server.received_requests().await.unwrap();
+            let sent: Vec<(String, String)> = requests
+                .iter()
+                .map(|r| {
+                    let name = r.url.path().rsplit('/').next().unwrap().to_lowercase();
+                    (name, String::from_utf8_lossy(&r.body).into_owned())
+                })
+                .collect();
+            let names: Vec<&str> = sent.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(
+                names,
+                [
+                    "sendmessage",
+                    "sendchataction",
+                    "sendmessage",
+                    "editmessagetext",
+                    "sendmessage",
+                    "senddocument"
+                ]
+            );
+            for (name, body) in &sent {
+                // Edits address the message itself, which is already in the topic
+                if name != "editmessagetext" {
+                    assert!(body.contains("message_thread_id"), "{}: {}", name, body);
+                }
+            }
+            assert!(sent[0].1.contains(r#""message_thread_id":7"#));
+            assert!(sent[5].1.contains("name=\"message_thread_id\"\r\n\r\n7"));
         }
     }
 }
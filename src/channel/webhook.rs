@@ -0,0 +1,563 @@
+// QuectoClaw — Generic HTTP webhook channel (for CI, alerting, ticketing and other internal systems)
+//
+// POST <path> with a JSON body:
+//
+//   {"sender":"ci","chat":"build-42","content":"…","callback_url":"https://…","metadata":{…}}
+//
+// authenticated by `Authorization: Bearer <token>` or by
+// `X-Signature-256: sha256=<hex HMAC-SHA256 of the body, keyed with secret>`.
+// Without `callback_url` the response waits for the agent's reply:
+// 200 {"id","chat","content"}. With one it returns 202 {"id"} and the reply
+// is POSTed to the callback as {"id","chat","content"}, signed the same way
+// when a secret is set. Replies are paired with their request by the
+// `request_id` the agent carries back; messages without one (such as a
+// notice about a file that could not be sent) are dropped.
+
+use crate::bus::{MessageBus, OutboundMessage};
+use crate::channel::{BaseChannel, Channel};
+use crate::config::WebhookConfig;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use axum::Router;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+
+const DEFAULT_PATH: &str = "/webhook";
+const SIGNATURE_HEADER: &str = "x-signature-256";
+
+/// Where the reply to one request goes.
+enum ReplyTo {
+    /// The HTTP request is still waiting (or has given up, if dropped).
+    Response(oneshot::Sender<String>),
+    Callback(String),
+}
+
+/// Requests awaiting a reply, by request id.
+type Waiters = Arc<Mutex<HashMap<String, ReplyTo>>>;
+
+pub struct WebhookChannel {
+    base: Arc<BaseChannel>,
+    config: WebhookConfig,
+    client: Client,
+    waiters: Waiters,
+    local_addr: Mutex<Option<SocketAddr>>,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WebhookChannel {
+    pub fn new(config: &WebhookConfig, bus: Arc<MessageBus>) -> Self {
+        Self {
+            base: Arc::new(BaseChannel::new("webhook", config.allow_from.clone(), bus)),
+            config: config.clone(),
+            client: Client::new(),
+            waiters: Arc::new(Mutex::new(HashMap::new())),
+            local_addr: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// The address the endpoint is bound to, once started.
+    pub async fn local_addr(&self) -> Option<SocketAddr> {
+        *self.local_addr.lock().await
+    }
+}
+
+/// `sha256=<hex>` signature of a body.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn verify_signature(secret: &str, body: &[u8], header: &str) -> bool {
+    let Some(expected) = header
+        .strip_prefix("sha256=")
+        .and_then(|h| hex::decode(h).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Whether a request carries a valid bearer token or signature.
+fn authorized(config: &WebhookConfig, headers: &HeaderMap, body: &[u8]) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if !config.token.is_empty() {
+        // Constant time, so response timing does not reveal the token
+        let expected = format!("Bearer {}", config.token);
+        let matches = |v: &str| bool::from(v.as_bytes().ct_eq(expected.as_bytes()));
+        if header("authorization").is_some_and(matches) {
+            return true;
+        }
+    }
+    if !config.secret.is_empty() {
+        if let Some(signature) = header(SIGNATURE_HEADER) {
+            return verify_signature(&config.secret, body, signature);
+        }
+    }
+    false
+}
+
+#[derive(Deserialize)]
+struct WebhookRequest {
+    sender: String,
+    #[serde(default)]
+    chat: Option<String>,
+    content: String,
+    #[serde(default)]
+    callback_url: Option<String>,
+    #[serde(default)]
+    metadata: HashMap<String, Value>,
+}
+
+/// Shared state of the endpoint.
+struct Endpoint {
+    base: Arc<BaseChannel>,
+    config: WebhookConfig,
+    waiters: Waiters,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+async fn receive(
+    State(endpoint): State<Arc<Endpoint>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !authorized(&endpoint.config, &headers, &body) {
+        return error(StatusCode::UNAUTHORIZED, "invalid token or signature");
+    }
+    let request: WebhookRequest = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("invalid request: {}", e)),
+    };
+    if request.content.trim().is_empty() {
+        return error(StatusCode::BAD_REQUEST, "content is empty");
+    }
+    if !endpoint.base.is_allowed(&request.sender) {
+        return error(StatusCode::FORBIDDEN, "sender is not allowed");
+    }
+    if let Some(url) = &request.callback_url {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return error(StatusCode::BAD_REQUEST, "callback_url must be http(s)");
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let chat = request
+        .chat
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(|| request.sender.clone());
+    let mut metadata: HashMap<String, String> = request
+        .metadata
+        .into_iter()
+        .map(|(k, v)| match v {
+            Value::String(s) => (k, s),
+            other => (k, other.to_string()),
+        })
+        .collect();
+    metadata.insert("request_id".to_string(), id.clone());
+
+    let (reply_to, response) = match request.callback_url {
+        Some(url) => (ReplyTo::Callback(url), None),
+        None => {
+            let (tx, rx) = oneshot::channel();
+            (ReplyTo::Response(tx), Some(rx))
+        }
+    };
+    endpoint.waiters.lock().await.insert(id.clone(), reply_to);
+    endpoint
+        .base
+        .handle_message(
+            &request.sender,
+            &chat,
+            request.content.trim(),
+            Vec::new(),
+            metadata,
+        )
+        .await;
+
+    let Some(response) = response else {
+        return (StatusCode::ACCEPTED, Json(json!({ "id": id }))).into_response();
+    };
+    let timeout = Duration::from_secs(endpoint.config.reply_timeout_secs.max(1));
+    match tokio::time::timeout(timeout, response).await {
+        Ok(Ok(content)) => {
+            Json(json!({ "id": id, "chat": chat, "content": content })).into_response()
+        }
+        _ => {
+            endpoint.waiters.lock().await.remove(&id);
+            error(
+                StatusCode::GATEWAY_TIMEOUT,
+                "no reply from the agent in time",
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for WebhookChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.config.token.is_empty() && self.config.secret.is_empty() {
+            anyhow::bail!("webhook channel needs a token or a secret");
+        }
+        if self.running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let host = if self.config.host.is_empty() {
+            "127.0.0.1"
+        } else {
+            &self.config.host
+        };
+        let path = if self.config.path.is_empty() {
+            DEFAULT_PATH
+        } else {
+            &self.config.path
+        };
+
+        let endpoint = Arc::new(Endpoint {
+            base: self.base.clone(),
+            config: self.config.clone(),
+            waiters: self.waiters.clone(),
+        });
+        let app = Router::new()
+            .route(path, axum::routing::post(receive))
+            .with_state(endpoint);
+
+        let listener = tokio::net::TcpListener::bind((host, self.config.port)).await?;
+        let addr = listener.local_addr()?;
+        *self.local_addr.lock().await = Some(addr);
+        tracing::info!(channel = %self.name(), addr = %addr, path = %path, "Starting webhook endpoint");
+
+        let running = self.running.clone();
+        running.store(true, Ordering::SeqCst);
+        *self.task.lock().await = Some(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!(channel = "webhook", error = %e, "Webhook server failed");
+            }
+            running.store(false, Ordering::SeqCst);
+        }));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let Some(id) = msg.metadata.get("request_id") else {
+            tracing::debug!(channel = "webhook", chat = %msg.chat_id, "Dropping a message that answers no request");
+            return Ok(());
+        };
+        // A callback waiter stays until its POST succeeds, so a retried
+        // reply still goes to the request it answers
+        let url = {
+            let mut waiters = self.waiters.lock().await;
+            match waiters.get(id) {
+                None => {
+                    tracing::warn!(channel = "webhook", id = %id, "Reply to a request that is no longer waiting");
+                    return Ok(());
+                }
+                Some(ReplyTo::Callback(url)) => url.clone(),
+                Some(ReplyTo::Response(_)) => {
+                    if let Some(ReplyTo::Response(tx)) = waiters.remove(id) {
+                        if tx.send(msg.content).is_err() {
+                            tracing::warn!(channel = "webhook", id = %id, "Reply arrived after the request timed out");
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        };
+
+        let body = json!({ "id": id, "chat": msg.chat_id, "content": msg.content }).to_string();
+        let mut request = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json");
+        if !self.config.secret.is_empty() {
+            request = request.header(SIGNATURE_HEADER, sign(&self.config.secret, body.as_bytes()));
+        }
+        let resp = request.body(body).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("webhook callback {} returned {}", url, resp.status());
+        }
+        self.waiters.lock().await.remove(id);
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config() -> WebhookConfig {
+        WebhookConfig {
+            enabled: true,
+            host: "127.0.0.1".into(),
+            port: 0,
+            secret: "s3cret".into(),
+            token: "tok".into(),
+            reply_timeout_secs: 1,
+            allow_from: vec!["ci".into(), "pager".into()],
+            ..Default::default()
+        }
+    }
+
+    fn reply(request_id: &str, chat: &str, content: &str) -> OutboundMessage {
+        OutboundMessage {
+            channel: "webhook".into(),
+            chat_id: chat.into(),
+            content: content.into(),
+            metadata: HashMap::from([("request_id".to_string(), request_id.to_string())]),
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_signatures_and_auth() {
+        let body = br#"{"sender":"ci","content":"hi"}"#;
+        let sig = sign("s3cret", body);
+        assert!(verify_signature("s3cret", body, &sig));
+        assert!(!verify_signature("other", body, &sig));
+        assert!(!verify_signature(
+            "s3cret",
+            body,
+            sig.trim_start_matches("sha256=")
+        ));
+
+        let config = config();
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&config, &headers, body));
+        headers.insert("authorization", "Bearer tol".parse().unwrap());
+        assert!(!authorized(&config, &headers, body));
+        headers.insert("authorization", "Bearer tok".parse().unwrap());
+        assert!(authorized(&config, &headers, body));
+        let mut headers = HeaderMap::new();
+        headers.insert(SIGNATURE_HEADER, sig.parse().unwrap());
+        assert!(authorized(&config, &headers, body));
+        assert!(!authorized(&config, &headers, b"tampered"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_and_callback_replies() {
+        let callback = MockServer::start().await;
+        let bus = Arc::new(MessageBus::new());
+        let channel = Arc::new(WebhookChannel::new(&config(), bus.clone()));
+        channel.start().await.unwrap();
+        let url = format!("http://{}/webhook", channel.local_addr().await.unwrap());
+        let http = Client::new();
+
+        // Unauthenticated and disallowed senders are refused
+        let body = json!({ "sender": "ci", "content": "hi" }).to_string();
+        let resp = http.post(&url).body(body.clone()).send().await.unwrap();
+        assert_eq!(resp.status(), 401);
+        let stranger = json!({ "sender": "mallory", "content": "hi" }).to_string();
+        let resp = http
+            .post(&url)
+            .bearer_auth("tok")
+            .body(stranger)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+
+        // Synchronous: the HTTP response carries the agent's reply
+        let body = json!({
+            "sender": "ci", "chat": "build-42", "content": "Why did build 42 fail?",
+            "metadata": { "job": "test", "attempt": 2 }
+        })
+        .to_string();
+        let request = http
+            .post(&url)
+            .header(SIGNATURE_HEADER, sign("s3cret", body.as_bytes()))
+            .body(body)
+            .send();
+        let agent = {
+            let (bus, channel) = (bus.clone(), channel.clone());
+            tokio::spawn(async move {
+                let msg = bus.consume_inbound().await.unwrap();
+                assert_eq!(msg.session_key, "webhook:build-42");
+                assert_eq!(msg.metadata["job"], "test");
+                assert_eq!(msg.metadata["attempt"], "2");
+                channel
+                    .send(reply(
+                        &msg.metadata["request_id"],
+                        &msg.chat_id,
+                        "A flaky test",
+                    ))
+                    .await
+                    .unwrap();
+            })
+        };
+        let resp = request.await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let answer: Value = resp.json().await.unwrap();
+        assert_eq!(answer["chat"], "build-42");
+        assert_eq!(answer["content"], "A flaky test");
+        agent.await.unwrap();
+
+        // A timed-out request is forgotten, and its late reply dropped
+        let body = json!({ "sender": "ci", "content": "slow" }).to_string();
+        let resp = http
+            .post(&url)
+            .bearer_auth("tok")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 504);
+        let slow = bus.consume_inbound().await.unwrap().metadata["request_id"].clone();
+
+        // Asynchronous: 202 now, signed callback later
+        Mock::given(method("POST"))
+            .and(path("/done"))
+            .and(body_partial_json(
+                json!({ "chat": "ci", "content": "Paged on-call" }),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&callback)
+            .await;
+        let body = json!({
+            "sender": "ci", "content": "disk full on db-1",
+            "callback_url": format!("{}/done", callback.uri())
+        })
+        .to_string();
+        let resp = http
+            .post(&url)
+            .bearer_auth("tok")
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 202);
+        let id = resp.json::<Value>().await.unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.metadata["request_id"], id);
+
+        // Neither a late reply nor a message that answers no request (like a
+        // notice about an unsent file) takes the callback's place
+        channel
+            .send(reply(&slow, "ci", "late answer to slow"))
+            .await
+            .unwrap();
+        let mut notice = reply(&id, "ci", "📎 report.pdf could not be sent");
+        notice.metadata.clear();
+        channel.send(notice).await.unwrap();
+        channel
+            .send(reply(&id, "ci", "Paged on-call"))
+            .await
+            .unwrap();
+        let received = callback.received_requests().await.unwrap();
+        assert_eq!(received.len(), 1);
+        let callback_body = String::from_utf8(received[0].body.clone()).unwrap();
+        assert_eq!(
+            received[0].headers[SIGNATURE_HEADER],
+            sign("s3cret", callback_body.as_bytes()).as_str()
+        );
+        assert!(callback_body.contains(&id));
+
+        // The request is answered; a second reply goes nowhere
+        channel.send(reply(&id, "ci", "extra")).await.unwrap();
+        assert_eq!(callback.received_requests().await.unwrap().len(), 1);
+        channel.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_callback_is_retried_to_the_same_request() {
+        let callback = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/first"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&callback)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/first"))
+            .and(body_partial_json(json!({ "content": "one" })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&callback)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/second"))
+            .and(body_partial_json(json!({ "content": "two" })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&callback)
+            .await;
+
+        let bus = Arc::new(MessageBus::new());
+        let channel = WebhookChannel::new(&config(), bus.clone());
+        channel.start().await.unwrap();
+        let url = format!("http://{}/webhook", channel.local_addr().await.unwrap());
+        let http = Client::new();
+        let mut ids = Vec::new();
+        for name in ["first", "second"] {
+            let body = json!({
+                "sender": "ci", "content": name,
+                "callback_url": format!("{}/{}", callback.uri(), name)
+            })
+            .to_string();
+            let resp = http
+                .post(&url)
+                .bearer_auth("tok")
+                .body(body)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 202);
+            ids.push(bus.consume_inbound().await.unwrap().metadata["request_id"].clone());
+        }
+
+        // Replies go to their own request whatever order they come in
+        channel.send(reply(&ids[1], "ci", "two")).await.unwrap();
+        assert!(channel.send(reply(&ids[0], "ci", "one")).await.is_err());
+        channel.send(reply(&ids[0], "ci", "one")).await.unwrap();
+        channel.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,443 @@
+// QuectoClaw — WhatsApp channel implementation (via a websocket bridge)
+//
+// WhatsApp has no bot API for personal accounts, so a separate bridge
+// process (e.g. built on Baileys or whatsmeow) holds the WhatsApp session
+// and talks to us over a websocket at `bridge_url`. Every frame is one
+// JSON object with a `type`:
+//
+// Bridge → QuectoClaw
+//   {"type":"status","status":"connected"|"disconnected"|"qr","qr":"…"}
+//   {"type":"message","id":"…","chat":"<jid>","from":"<jid or phone>",
+//    "name":"…","text":"…","is_group":false,"mentioned":false,"reply_to_me":false,
+//    "media":[{"url":"…","mime_type":"image/jpeg","caption":"…"}]}
+//   {"type":"ack","ref":"…","ok":true}          (or "ok":false,"error":"…")
+//
+// QuectoClaw → bridge
+//   {"type":"send","ref":"…","to":"<chat jid>","text":"…"}
+//
+// `chat` is where replies go: `<phone>@s.whatsapp.net` for a direct chat,
+// `<id>@g.us` for a group. Each `send` is answered by an `ack` with the
+// same `ref`. Media entries may give a `path` on the bridge's disk
+// instead of a `url`. `mentioned` (we were @-mentioned) and `reply_to_me`
+// (it quotes one of our messages) only matter in groups.
+
+use crate::bus::{GroupContext, MessageBus, OutboundMessage};
+use crate::channel::{BaseChannel, Channel};
+use crate::config::WhatsAppConfig;
+use crate::util::{Backoff, Correlator, Unanswered};
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// How long to wait for the bridge to acknowledge a send.
+const ACK_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub struct WhatsAppChannel {
+    base: Arc<BaseChannel>,
+    bridge_url: String,
+    /// The bridge connection; sends wait for the ack with their `ref`.
+    conn: Correlator,
+    running: Arc<AtomicBool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WhatsAppChannel {
+    pub fn new(config: &WhatsAppConfig, bus: Arc<MessageBus>) -> Self {
+        // Phone numbers are matched in their digits-only form, so
+        // "+1 555-123-4567" in the allow list matches 15551234567
+        let allow_list = config
+            .allow_from
+            .iter()
+            .map(|entry| phone_number(entry).unwrap_or_else(|| entry.clone()))
+            .collect();
+        Self {
+            base: Arc::new(BaseChannel::new("whatsapp", allow_list, bus)),
+            bridge_url: config.bridge_url.clone(),
+            conn: Correlator::default(),
+            running: Arc::new(AtomicBool::new(false)),
+            task: Mutex::new(None),
+        }
+    }
+}
+
+/// The phone number of a JID (`15551234567:12@s.whatsapp.net`) or a
+/// written number (`+1 555-123-4567`), as digits only.
+fn phone_number(id: &str) -> Option<String> {
+    let user = id.split('@').next().unwrap_or(id);
+    let user = user.split(':').next().unwrap_or(user);
+    let digits: String = user.chars().filter(|c| c.is_ascii_digit()).collect();
+    let only_number = user
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | ' ' | '-' | '(' | ')' | '.'));
+    (only_number && !digits.is_empty()).then_some(digits)
+}
+
+/// The bridge connection loop, run in the background by `start`.
+struct Bridge {
+    base: Arc<BaseChannel>,
+    url: String,
+    conn: Correlator,
+    running: Arc<AtomicBool>,
+}
+
+impl Bridge {
+    async fn run(self) {
+        let mut backoff = Backoff::default();
+        while self.running.load(Ordering::SeqCst) {
+            backoff.attempt();
+            match self.session().await {
+                Ok(()) => tracing::warn!(channel = "whatsapp", "Bridge connection closed"),
+                Err(e) => {
+                    tracing::warn!(channel = "whatsapp", error = %e, retry_in_secs = backoff.delay().as_secs(), "Bridge connection failed")
+                }
+            }
+            // Fail sends still waiting on the old connection
+            self.conn.disconnect().await;
+            if !self.running.load(Ordering::SeqCst) {
+                break;
+            }
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+
+    async fn session(&self) -> anyhow::Result<()> {
+        let (ws, _) = tokio_tungstenite::connect_async(self.url.as_str()).await?;
+        tracing::info!(channel = "whatsapp", url = %self.url, "Connected to bridge");
+        let (mut write, mut read) = ws.split();
+
+        let (out_tx, mut out_rx) = mpsc::channel::<String>(64);
+        self.conn.connect(out_tx).await;
+
+        loop {
+            tokio::select! {
+                frame = out_rx.recv() => {
+                    let Some(frame) = frame else { return Ok(()) };
+                    write.send(WsMessage::text(frame)).await?;
+                }
+                frame = read.next() => {
+                    let text = match frame {
+                        None | Some(Ok(WsMessage::Close(_))) => return Ok(()),
+                        Some(Err(e)) => return Err(e.into()),
+                        Some(Ok(WsMessage::Text(text))) => text,
+                        Some(Ok(_)) => continue,
+                    };
+                    if let Ok(frame) = serde_json::from_str::<Value>(&text) {
+                        self.on_frame(frame).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn on_frame(&self, frame: Value) {
+        match frame["type"].as_str().unwrap_or("") {
+            "ack" => {
+                let reference = frame["ref"].as_str().unwrap_or("").to_string();
+                self.conn.resolve(&reference, frame).await;
+            }
+            "status" => match frame["status"].as_str() {
+                Some("qr") => tracing::warn!(
+                    channel = "whatsapp",
+                    "Bridge is not logged in; scan the QR code shown by the bridge"
+                ),
+                status => {
+                    tracing::info!(
+                        channel = "whatsapp",
+                        status = status.unwrap_or(""),
+                        "Bridge status"
+                    )
+                }
+            },
+            "message" => self.on_message(&frame).await,
+            _ => {}
+        }
+    }
+
+    async fn on_message(&self, frame: &Value) {
+        let Some(chat) = frame["chat"].as_str() else {
+            return;
+        };
+        let from = frame["from"].as_str().unwrap_or(chat);
+        let Some(phone) = phone_number(from) else {
+            tracing::debug!(channel = "whatsapp", from = %from, "Message without a phone number");
+            return;
+        };
+        // The push name is whatever the sender chose, so only the phone
+        // number identifies them
+        let name = frame["name"].as_str().unwrap_or("");
+        let sender_id = phone;
+
+        let mut text = frame["text"].as_str().unwrap_or("").trim().to_string();
+        let mut media = Vec::new();
+        for item in frame["media"].as_array().into_iter().flatten() {
+            if let Some(location) = item["url"].as_str().or_else(|| item["path"].as_str()) {
+                media.push(location.to_string());
+            }
+            if let Some(caption) = item["caption"].as_str().filter(|c| !c.is_empty()) {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(caption);
+            }
+        }
+        if text.is_empty() && media.is_empty() {
+            return;
+        }
+
+        let mut metadata = HashMap::new();
+        if let Some(id) = frame["id"].as_str() {
+            metadata.insert("message_id".to_string(), id.to_string());
+        }
+        if !name.is_empty() {
+            metadata.insert("sender_name".to_string(), name.to_string());
+        }
+        let is_group = frame["is_group"]
+            .as_bool()
+            .unwrap_or_else(|| chat.ends_with("@g.us"));
+        metadata.insert("is_group".to_string(), is_group.to_string());
+
+        if is_group {
+            let group = GroupContext {
+                mentioned: frame["mentioned"].as_bool().unwrap_or(false),
+                reply_to_bot: frame["reply_to_me"].as_bool().unwrap_or(false),
+                sender_name: Some(name.to_string()).filter(|n| !n.is_empty()),
+                ..Default::default()
+            };
+            self.base
+                .handle_group_message(&sender_id, chat, &text, media, metadata, group)
+                .await;
+        } else {
+            self.base
+                .handle_message(&sender_id, chat, &text, media, metadata)
+                .await;
+        }
+    }
+}
+
+#[async_trait]
+impl Channel for WhatsAppChannel {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn start(&self) -> anyhow::Result<()> {
+        if self.bridge_url.is_empty() {
+            anyhow::bail!("WhatsApp bridge_url is not configured");
+        }
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        tracing::info!(channel = %self.name(), "Starting WhatsApp channel (bridge)");
+
+        let bridge = Bridge {
+            base: self.base.clone(),
+            url: self.bridge_url.clone(),
+            conn: self.conn.clone(),
+            running: self.running.clone(),
+        };
+        *self.task.lock().await = Some(tokio::spawn(bridge.run()));
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(task) = self.task.lock().await.take() {
+            task.abort();
+        }
+        self.conn.disconnect().await;
+        Ok(())
+    }
+
+    async fn send(&self, msg: OutboundMessage) -> anyhow::Result<()> {
+        let reference = self.conn.next_id();
+        let frame = json!({
+            "type": "send",
+            "ref": reference,
+            "to": msg.chat_id,
+            "text": msg.content,
+        });
+
+        let ack = match self
+            .conn
+            .request(&reference, frame.to_string(), ACK_TIMEOUT)
+            .await
+        {
+            Ok(ack) => ack,
+            Err(Unanswered::NotConnected) => anyhow::bail!("WhatsApp bridge is not connected"),
+            Err(Unanswered::Closed) => anyhow::bail!("WhatsApp bridge connection closed"),
+            Err(Unanswered::TimedOut) => {
+                anyhow::bail!("WhatsApp bridge did not acknowledge the message")
+            }
+        };
+        if !ack["ok"].as_bool().unwrap_or(false) {
+            anyhow::bail!(
+                "WhatsApp bridge error: {}",
+                ack["error"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.conn.is_connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::OutboundKind;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::WebSocketStream;
+
+    type Socket = WebSocketStream<tokio::net::TcpStream>;
+
+    async fn accept(listener: &TcpListener) -> Socket {
+        let (stream, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        tokio_tungstenite::accept_async(stream).await.unwrap()
+    }
+
+    async fn push(ws: &mut Socket, frame: Value) {
+        ws.send(WsMessage::text(frame.to_string())).await.unwrap();
+    }
+
+    async fn next_frame(ws: &mut Socket) -> Value {
+        loop {
+            if let Some(Ok(WsMessage::Text(text))) = ws.next().await {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn outbound(chat_id: &str, content: &str) -> OutboundMessage {
+        OutboundMessage {
+            channel: "whatsapp".into(),
+            chat_id: chat_id.into(),
+            content: content.into(),
+            metadata: HashMap::new(),
+            kind: OutboundKind::Message,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_phone_number() {
+        assert_eq!(
+            phone_number("15551234567@s.whatsapp.net").unwrap(),
+            "15551234567"
+        );
+        assert_eq!(
+            phone_number("15551234567:12@s.whatsapp.net").unwrap(),
+            "15551234567"
+        );
+        assert_eq!(phone_number("+1 (555) 123-4567").unwrap(), "15551234567");
+        assert!(phone_number("alice").is_none());
+        assert!(phone_number("").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bridge_messages_media_and_sends() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = WhatsAppConfig {
+            enabled: true,
+            bridge_url: format!("ws://{}", listener.local_addr().unwrap()),
+            allow_from: vec!["+1 555-123-4567".into()],
+        };
+        let bus = Arc::new(MessageBus::new());
+        let channel = WhatsAppChannel::new(&config, bus.clone());
+        assert!(channel
+            .send(outbound("15551234567@s.whatsapp.net", "early"))
+            .await
+            .is_err());
+        channel.start().await.unwrap();
+        let mut ws = accept(&listener).await;
+
+        push(&mut ws, json!({ "type": "status", "status": "connected" })).await;
+        // Not on the allow list
+        push(
+            &mut ws,
+            json!({ "type": "message", "chat": "4915@s.whatsapp.net", "from": "4915@s.whatsapp.net", "text": "spam" }),
+        )
+        .await;
+        // Nor is a push name that looks like an allowed number
+        push(
+            &mut ws,
+            json!({ "type": "message", "chat": "4915@s.whatsapp.net", "from": "4915@s.whatsapp.net", "name": "+1 555-123-4567", "text": "spoofed" }),
+        )
+        .await;
+        push(
+            &mut ws,
+            json!({
+                "type": "message", "id": "wamid.1", "chat": "120363@g.us",
+                "from": "15551234567:3@s.whatsapp.net", "name": "Ann", "text": "look", "mentioned": true,
+                "media": [{ "url": "https://bridge/media/1.jpg", "mime_type": "image/jpeg", "caption": "our cat" }]
+            }),
+        )
+        .await;
+        let msg = bus.consume_inbound().await.unwrap();
+        assert_eq!(msg.chat_id, "120363@g.us");
+        assert_eq!(msg.sender_id, "15551234567");
+        assert_eq!(msg.metadata["sender_name"], "Ann");
+        assert_eq!(msg.content, "look\nour cat");
+        assert_eq!(msg.media, vec!["https://bridge/media/1.jpg"]);
+        assert_eq!(msg.metadata["is_group"], "true");
+        assert!(msg.group.unwrap().mentioned);
+
+        // Sends wait for the bridge's ack
+        let sender = tokio::spawn(async move {
+            let ok = channel.send(outbound("120363@g.us", "cute")).await;
+            let failed = channel.send(outbound("120363@g.us", "again")).await;
+            (channel, ok, failed)
+        });
+        let send = next_frame(&mut ws).await;
+        assert_eq!(send["type"], "send");
+        assert_eq!(send["to"], "120363@g.us");
+        assert_eq!(send["text"], "cute");
+        push(
+            &mut ws,
+            json!({ "type": "ack", "ref": send["ref"], "ok": true }),
+        )
+        .await;
+        let send = next_frame(&mut ws).await;
+        push(
+            &mut ws,
+            json!({ "type": "ack", "ref": send["ref"], "ok": false, "error": "not in group" }),
+        )
+        .await;
+        let (channel, ok, failed) = sender.await.unwrap();
+        ok.unwrap();
+        assert!(failed.unwrap_err().to_string().contains("not in group"));
+
+        // The bridge restarts; we reconnect
+        drop(ws);
+        let mut ws = accept(&listener).await;
+        push(
+            &mut ws,
+            json!({ "type": "message", "chat": "15551234567@s.whatsapp.net", "from": "+15551234567", "text": "back" }),
+        )
+        .await;
+        assert_eq!(
+            bus.consume_inbound().await.unwrap().sender_id,
+            "15551234567"
+        );
+        assert!(channel.is_connected());
+
+        channel.stop().await.unwrap();
+        assert!(!channel.is_running());
+        assert!(!channel.is_connected());
+    }
+}
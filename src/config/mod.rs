@@ -42,6 +42,8 @@ pub struct Config {
     #[serde(default)]
     pub heartbeat: HeartbeatConfig,
     #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
     pub devices: DevicesConfig,
     #[serde(default)]
     pub mcp: MCPConfig,
@@ -53,6 +55,8 @@ pub struct Config {
     pub wasm: WasmConfig,
     #[serde(default)]
     pub marketplace: MarketConfig,
+    #[serde(default)]
+    pub plugins: PluginsConfig,
 }
 
 // ---------------------------------------------------------------------------
@@ -63,6 +67,29 @@ pub struct Config {
 pub struct AgentsConfig {
     #[serde(default)]
     pub defaults: AgentDefaults,
+    /// Named specialist subagents selectable through the `subagent` tool.
+    #[serde(default)]
+    pub subagents: HashMap<String, SubagentConfig>,
+}
+
+/// A specialist subagent: its own model, tool set and instructions.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SubagentConfig {
+    /// Shown to the parent model so it can pick the right specialist.
+    #[serde(default)]
+    pub description: String,
+    /// Model override; empty inherits the parent's model.
+    #[serde(default)]
+    pub model: String,
+    /// Extra instructions appended to the subagent's system prompt.
+    #[serde(default)]
+    pub instructions: String,
+    /// Tool restrictions, applied on top of the caller's own tool view.
+    #[serde(default)]
+    pub tools: ToolProfile,
+    /// Overrides `defaults.max_tool_iterations` for this subagent.
+    #[serde(default)]
+    pub max_iterations: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -150,6 +177,134 @@ pub struct ChannelsConfig {
     pub onebot: OneBotConfig,
     #[serde(default)]
     pub maixcam: MaixCamConfig,
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+    #[serde(default)]
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub streaming: StreamingConfig,
+    #[serde(default)]
+    pub groups: GroupsConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+}
+
+/// How chat channels show a reply in progress. Partial replies and tool
+/// progress edit a placeholder message, on channels that can edit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamingConfig {
+    /// Show a typing indicator while the agent works.
+    #[serde(default = "default_true")]
+    pub typing: bool,
+    /// Stream the reply's text as it is generated.
+    #[serde(default)]
+    pub partial_replies: bool,
+    /// Show which tool is running (e.g. "🔧 running web_search…").
+    #[serde(default)]
+    pub tool_progress: bool,
+    /// Minimum time between placeholder updates.
+    #[serde(default = "default_stream_update_interval")]
+    pub update_interval_ms: u64,
+}
+
+fn default_stream_update_interval() -> u64 {
+    1000
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            typing: true,
+            partial_replies: false,
+            tool_progress: false,
+            update_interval_ms: default_stream_update_interval(),
+        }
+    }
+}
+
+/// Which group-chat messages the bot answers, and how group conversations
+/// are split into sessions. Direct chats are always answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupsConfig {
+    /// Answer messages that @-mention the bot.
+    #[serde(default = "default_true")]
+    pub mention: bool,
+    /// Answer replies to the bot's messages.
+    #[serde(default = "default_true")]
+    pub reply: bool,
+    /// Answer messages starting with one of these (e.g. "!ai"); the prefix
+    /// is stripped.
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    /// Answer every message, ignoring the triggers above.
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub session_scope: SessionScope,
+    /// Prefix messages with the sender's name so the model can tell
+    /// speakers apart.
+    #[serde(default = "default_true")]
+    pub sender_names: bool,
+}
+
+impl Default for GroupsConfig {
+    fn default() -> Self {
+        Self {
+            mention: true,
+            reply: true,
+            prefixes: Vec::new(),
+            all: false,
+            session_scope: SessionScope::default(),
+            sender_names: true,
+        }
+    }
+}
+
+/// What a group conversation's session covers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionScope {
+    /// The whole group shares one conversation.
+    #[default]
+    Chat,
+    /// One conversation per thread or topic (the whole group outside threads).
+    Thread,
+    /// One conversation per member.
+    User,
+}
+
+/// Retries of replies a channel failed to send. They wait in
+/// `<workspace>/outbox`, so a restart does not lose them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxConfig {
+    /// Failed attempts before a message is dead-lettered.
+    #[serde(default = "default_outbox_attempts")]
+    pub max_attempts: u32,
+    /// Wait after the first failure; it doubles with every failure after.
+    #[serde(default = "default_outbox_backoff")]
+    pub initial_backoff_secs: u64,
+    #[serde(default = "default_outbox_max_backoff")]
+    pub max_backoff_secs: u64,
+}
+
+fn default_outbox_attempts() -> u32 {
+    8
+}
+fn default_outbox_backoff() -> u64 {
+    2
+}
+fn default_outbox_max_backoff() -> u64 {
+    300
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_outbox_attempts(),
+            initial_backoff_secs: default_outbox_backoff(),
+            max_backoff_secs: default_outbox_max_backoff(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -200,7 +355,7 @@ pub struct WhatsAppConfig {
     pub allow_from: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FeishuConfig {
     #[serde(default)]
     pub enabled: bool,
@@ -215,10 +370,40 @@ pub struct FeishuConfig {
     #[serde(default)]
     #[serde(skip_serializing)]
     pub verification_token: String,
+    /// `feishu` (open.feishu.cn) or `lark` (open.larksuite.com).
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default)]
+    pub webhook_host: String,
+    #[serde(default = "default_feishu_port")]
+    pub webhook_port: u16,
+    #[serde(default)]
+    pub webhook_path: String,
     #[serde(default)]
     pub allow_from: Vec<String>,
 }
 
+fn default_feishu_port() -> u16 {
+    18792
+}
+
+impl Default for FeishuConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            app_id: String::new(),
+            app_secret: String::new(),
+            encrypt_key: String::new(),
+            verification_token: String::new(),
+            domain: String::new(),
+            webhook_host: String::new(),
+            webhook_port: default_feishu_port(),
+            webhook_path: String::new(),
+            allow_from: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DingTalkConfig {
     #[serde(default)]
@@ -277,6 +462,7 @@ fn default_reconnect() -> u64 {
     5
 }
 
+/// MaixCam device server. Devices must present `token` in their `hello`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct MaixCamConfig {
     #[serde(default)]
@@ -286,6 +472,9 @@ pub struct MaixCamConfig {
     #[serde(default = "default_maixcam_port")]
     pub port: u16,
     #[serde(default)]
+    #[serde(skip_serializing)]
+    pub token: String,
+    #[serde(default)]
     pub allow_from: Vec<String>,
 }
 
@@ -293,6 +482,146 @@ fn default_maixcam_port() -> u16 {
     18790
 }
 
+/// Generic HTTP endpoint for internal systems (CI, alerting, ticketing).
+/// Requests must be signed with `secret` (HMAC-SHA256 of the body) or
+/// carry `token` as a bearer token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default = "default_webhook_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub secret: String,
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub token: String,
+    /// How long a synchronous request waits for the agent's reply.
+    #[serde(default = "default_webhook_reply_timeout")]
+    pub reply_timeout_secs: u64,
+    #[serde(default)]
+    pub allow_from: Vec<String>,
+}
+
+fn default_webhook_port() -> u16 {
+    18793
+}
+
+fn default_webhook_reply_timeout() -> u64 {
+    120
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: default_webhook_port(),
+            path: String::new(),
+            secret: String::new(),
+            token: String::new(),
+            reply_timeout_secs: default_webhook_reply_timeout(),
+            allow_from: Vec::new(),
+        }
+    }
+}
+
+/// Email channel: polls an IMAP mailbox and replies over SMTP.
+/// `imap_security` is `tls` or `none`; `smtp_security` is `starttls`,
+/// `tls` or `none`. `allow_from` lists sender addresses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub imap_host: String,
+    #[serde(default = "default_imap_port")]
+    pub imap_port: u16,
+    #[serde(default = "default_imap_security")]
+    pub imap_security: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    #[serde(default)]
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default = "default_smtp_security")]
+    pub smtp_security: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub password: String,
+    /// Address replies are sent from; defaults to `username`.
+    #[serde(default)]
+    pub from_address: String,
+    #[serde(default = "default_email_poll_interval")]
+    pub poll_interval_secs: u64,
+    #[serde(default)]
+    pub allow_from: Vec<String>,
+    /// Only accept mail whose `Authentication-Results` show `dmarc=pass`
+    /// or an aligned `dkim=pass` for the From domain. `From:` alone can be
+    /// forged by anyone.
+    #[serde(default = "default_true")]
+    pub require_auth: bool,
+    /// The receiving server's authserv-id (e.g. `mx.example.com`). When set,
+    /// only `Authentication-Results` headers it added are trusted; otherwise
+    /// the topmost one is.
+    #[serde(default)]
+    pub auth_serv_id: String,
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_imap_security() -> String {
+    "tls".to_string()
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_security() -> String {
+    "starttls".to_string()
+}
+
+fn default_email_poll_interval() -> u64 {
+    30
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            imap_host: String::new(),
+            imap_port: default_imap_port(),
+            imap_security: default_imap_security(),
+            mailbox: default_mailbox(),
+            smtp_host: String::new(),
+            smtp_port: default_smtp_port(),
+            smtp_security: default_smtp_security(),
+            username: String::new(),
+            password: String::new(),
+            from_address: String::new(),
+            poll_interval_secs: default_email_poll_interval(),
+            allow_from: Vec::new(),
+            require_auth: true,
+            auth_serv_id: String::new(),
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Providers
 // ---------------------------------------------------------------------------
@@ -393,6 +722,22 @@ pub struct GatewayConfig {
     #[serde(default)]
     #[serde(skip_serializing)]
     pub dashboard_token: String,
+    /// Chat users who may run admin commands (`/model`, `/sessions`,
+    /// `/metrics`), as `channel:sender_id`.
+    #[serde(default)]
+    pub admins: Vec<String>,
+}
+
+impl GatewayConfig {
+    /// Whether a channel sender is listed in `admins`. Compound senders
+    /// (`id|username`) match on the id only: names can be changed.
+    pub fn is_admin(&self, channel: &str, sender_id: &str) -> bool {
+        let id = sender_id.split('|').next().unwrap_or(sender_id);
+        self.admins.iter().any(|admin| match admin.split_once(':') {
+            Some((ch, admin_id)) => ch == channel && admin_id == id,
+            None => false,
+        })
+    }
 }
 
 impl Default for GatewayConfig {
@@ -404,6 +749,7 @@ impl Default for GatewayConfig {
             rate_limit_seconds: default_rate_limit_seconds(),
             allow_public_bind: false,
             dashboard_token: String::new(),
+            admins: Vec::new(),
         }
     }
 }
@@ -435,6 +781,80 @@ pub struct ToolsConfig {
     /// Paths that are always blocked for filesystem and exec tools.
     #[serde(default = "default_forbidden_paths")]
     pub forbidden_paths: Vec<String>,
+    /// Named tool profiles restricting which tools a caller may see and run.
+    #[serde(default)]
+    pub profiles: HashMap<String, ToolProfile>,
+    /// Profile applied when no channel or session rule matches (empty = no restriction).
+    #[serde(default)]
+    pub default_profile: String,
+    /// Profile per channel name (e.g. "discord" -> "public").
+    #[serde(default)]
+    pub channel_profiles: HashMap<String, String>,
+    /// Profile per session key prefix; the longest matching prefix wins.
+    #[serde(default)]
+    pub session_profiles: HashMap<String, String>,
+}
+
+impl ToolsConfig {
+    /// Resolve the profile name for a session key.
+    /// Precedence: session prefix, then channel (the part before the first `:`), then default.
+    pub fn profile_for_session(&self, session_key: &str) -> Option<&str> {
+        if let Some((_, name)) = self
+            .session_profiles
+            .iter()
+            .filter(|(prefix, _)| session_key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+        {
+            return Some(name);
+        }
+
+        if let Some((channel, _)) = session_key.split_once(':') {
+            if let Some(name) = self.channel_profiles.get(channel) {
+                return Some(name);
+            }
+        }
+
+        if self.default_profile.is_empty() {
+            None
+        } else {
+            Some(&self.default_profile)
+        }
+    }
+}
+
+/// A tool profile: glob patterns (`*`, `?`) over tool names.
+/// An empty `allow` list allows every tool; `deny` always wins.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ToolProfile {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ToolProfile {
+    /// A profile that denies every tool (used when a referenced profile is missing).
+    pub fn deny_all() -> Self {
+        Self {
+            allow: vec![],
+            deny: vec!["*".into()],
+        }
+    }
+
+    pub fn allows(&self, tool_name: &str) -> bool {
+        if self
+            .deny
+            .iter()
+            .any(|p| crate::tool::glob_match(p, tool_name))
+        {
+            return false;
+        }
+        self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|p| crate::tool::glob_match(p, tool_name))
+    }
 }
 
 fn default_forbidden_paths() -> Vec<String> {
@@ -481,8 +901,20 @@ fn default_max_results() -> usize {
 pub struct HeartbeatConfig {
     #[serde(default = "default_true")]
     pub enabled: bool,
+    /// Minutes between checks of HEARTBEAT.md.
     #[serde(default = "default_heartbeat_interval")]
     pub interval: u64,
+    /// Channel that receives heartbeat results (empty = log only).
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub chat_id: String,
+    /// IANA timezone for quiet hours and due times (empty = system local time).
+    #[serde(default)]
+    pub timezone: String,
+    /// Window with no heartbeat runs, e.g. "22:00-07:00" (empty = always active).
+    #[serde(default)]
+    pub quiet_hours: String,
 }
 
 impl Default for HeartbeatConfig {
@@ -490,6 +922,52 @@ impl Default for HeartbeatConfig {
         Self {
             enabled: true,
             interval: default_heartbeat_interval(),
+            channel: String::new(),
+            chat_id: String::new(),
+            timezone: String::new(),
+            quiet_hours: String::new(),
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Parsed timezone; `None` means system local time.
+    pub fn tz(&self) -> Result<Option<chrono_tz::Tz>, ConfigError> {
+        if self.timezone.is_empty() {
+            return Ok(None);
+        }
+        self.timezone.parse().map(Some).map_err(|_| {
+            ConfigError::Other(format!("unknown heartbeat timezone '{}'", self.timezone))
+        })
+    }
+
+    /// Parsed quiet window `(start, end)`; it may wrap past midnight.
+    pub fn quiet_window(
+        &self,
+    ) -> Result<Option<(chrono::NaiveTime, chrono::NaiveTime)>, ConfigError> {
+        if self.quiet_hours.is_empty() {
+            return Ok(None);
+        }
+        let invalid = || {
+            ConfigError::Other(format!(
+                "invalid heartbeat quiet_hours '{}' (expected HH:MM-HH:MM)",
+                self.quiet_hours
+            ))
+        };
+        let (start, end) = self.quiet_hours.split_once('-').ok_or_else(invalid)?;
+        let parse = |t: &str| chrono::NaiveTime::parse_from_str(t.trim(), "%H:%M");
+        Ok(Some((
+            parse(start).map_err(|_| invalid())?,
+            parse(end).map_err(|_| invalid())?,
+        )))
+    }
+
+    /// Whether `time` (wall clock in the configured timezone) is inside quiet hours.
+    pub fn is_quiet_at(&self, time: chrono::NaiveTime) -> bool {
+        match self.quiet_window() {
+            Ok(Some((start, end))) if start <= end => time >= start && time < end,
+            Ok(Some((start, end))) => time >= start || time < end,
+            _ => false,
         }
     }
 }
@@ -498,6 +976,41 @@ fn default_heartbeat_interval() -> u64 {
     30
 }
 
+// ---------------------------------------------------------------------------
+// Scheduler
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerConfig {
+    /// Register the scheduling tools and run due jobs in gateway mode.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// How often (seconds) the gateway checks for due jobs.
+    #[serde(default = "default_scheduler_tick")]
+    pub tick_interval: u64,
+    /// Maximum pending jobs per chat.
+    #[serde(default = "default_max_jobs")]
+    pub max_jobs: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            tick_interval: default_scheduler_tick(),
+            max_jobs: default_max_jobs(),
+        }
+    }
+}
+
+fn default_scheduler_tick() -> u64 {
+    30
+}
+
+fn default_max_jobs() -> usize {
+    20
+}
+
 // ---------------------------------------------------------------------------
 // Devices
 // ---------------------------------------------------------------------------
@@ -522,11 +1035,95 @@ pub struct MCPConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MCPServerConfig {
+    /// Command for stdio servers.
+    #[serde(default)]
     pub command: String,
     #[serde(default)]
     pub args: Vec<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Transport; defaults to `http` when `url` is set, otherwise `stdio`.
+    #[serde(default)]
+    pub transport: Option<MCPTransportKind>,
+    /// Endpoint for `http` (Streamable HTTP) and `sse` servers.
+    #[serde(default)]
+    pub url: String,
+    /// Extra HTTP headers sent with every request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Sent as `Authorization: Bearer <token>`.
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    pub bearer_token: String,
+    /// Seconds to wait for a response before the request is cancelled.
+    #[serde(default = "default_mcp_timeout")]
+    pub timeout: u64,
+    /// Restart the server with backoff when its connection dies.
+    #[serde(default = "default_true")]
+    pub restart: bool,
+    /// Whether the server may ask our LLM for completions (`sampling/createMessage`).
+    #[serde(default)]
+    pub sampling: MCPSamplingPolicy,
+    /// Upper bound on `maxTokens` for this server's sampling requests.
+    #[serde(default = "default_sampling_max_tokens")]
+    pub sampling_max_tokens: usize,
+}
+
+impl Default for MCPServerConfig {
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            transport: None,
+            url: String::new(),
+            headers: HashMap::new(),
+            bearer_token: String::new(),
+            timeout: default_mcp_timeout(),
+            restart: true,
+            sampling: MCPSamplingPolicy::default(),
+            sampling_max_tokens: default_sampling_max_tokens(),
+        }
+    }
+}
+
+fn default_mcp_timeout() -> u64 {
+    10
+}
+
+fn default_sampling_max_tokens() -> usize {
+    1024
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MCPSamplingPolicy {
+    Allow,
+    #[default]
+    Deny,
+    /// Ask the user for each request (interactive CLI only; denied elsewhere).
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MCPTransportKind {
+    Stdio,
+    /// Streamable HTTP (protocol 2025-03-26).
+    #[serde(alias = "streamable_http")]
+    Http,
+    /// Legacy HTTP+SSE (protocol 2024-11-05).
+    Sse,
+}
+
+impl MCPServerConfig {
+    pub fn transport_kind(&self) -> MCPTransportKind {
+        self.transport.unwrap_or(if self.url.is_empty() {
+            MCPTransportKind::Stdio
+        } else {
+            MCPTransportKind::Http
+        })
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -668,6 +1265,33 @@ fn default_wasm_fuel() -> u64 {
     1_000_000
 }
 
+// ---------------------------------------------------------------------------
+// Plugin hot-reload
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginsConfig {
+    /// Watch the plugin directories in gateway mode and apply changes live.
+    #[serde(default = "default_true")]
+    pub hot_reload: bool,
+    /// Polling interval in seconds.
+    #[serde(default = "default_reload_interval")]
+    pub reload_interval: u64,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            hot_reload: true,
+            reload_interval: default_reload_interval(),
+        }
+    }
+}
+
+fn default_reload_interval() -> u64 {
+    2
+}
+
 // ---------------------------------------------------------------------------
 // Config loading
 // ---------------------------------------------------------------------------
@@ -915,6 +1539,10 @@ impl Config {
             tracing::warn!("Discord channel is enabled but token is missing");
         }
 
+        // 4. Heartbeat schedule
+        self.heartbeat.tz()?;
+        self.heartbeat.quiet_window()?;
+
         Ok(())
     }
 }
@@ -975,6 +1603,75 @@ mod tests {
         assert_eq!(key, "sk-test");
         assert_eq!(name, "gpt");
     }
+
+    #[test]
+    fn test_tool_profile_allow_deny() {
+        let profile = ToolProfile {
+            allow: vec!["web_*".into(), "read_file".into(), "sqlite_*".into()],
+            deny: vec!["sqlite_write*".into()],
+        };
+        assert!(profile.allows("web_search"));
+        assert!(profile.allows("read_file"));
+        assert!(profile.allows("sqlite_query"));
+        assert!(!profile.allows("sqlite_write_table"));
+        assert!(!profile.allows("exec"));
+        assert!(!ToolProfile::deny_all().allows("read_file"));
+        assert!(ToolProfile::default().allows("exec"));
+    }
+
+    #[test]
+    fn test_heartbeat_quiet_hours() {
+        let t = |s: &str| chrono::NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+        let mut hb = HeartbeatConfig {
+            quiet_hours: "22:00-07:00".into(),
+            timezone: "Europe/Berlin".into(),
+            ..Default::default()
+        };
+        assert!(hb.tz().unwrap().is_some());
+        assert!(hb.is_quiet_at(t("23:30")));
+        assert!(hb.is_quiet_at(t("06:59")));
+        assert!(!hb.is_quiet_at(t("07:00")));
+        assert!(!hb.is_quiet_at(t("12:00")));
+
+        hb.quiet_hours = "12:00-13:00".into();
+        assert!(hb.is_quiet_at(t("12:30")));
+        assert!(!hb.is_quiet_at(t("13:30")));
+
+        hb.quiet_hours = "noon".into();
+        assert!(hb.quiet_window().is_err());
+        hb.timezone = "Mars/Olympus".into();
+        assert!(hb.tz().is_err());
+    }
+
+    #[test]
+    fn test_profile_for_session() {
+        let json = r#"{
+            "tools": {
+                "profiles": {"public": {"deny": ["exec"]}, "ops": {}},
+                "default_profile": "safe",
+                "channel_profiles": {"discord": "public"},
+                "session_profiles": {"discord:42": "ops", "cron:": "ops"}
+            }
+        }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(cfg.tools.profile_for_session("discord:1"), Some("public"));
+        assert_eq!(cfg.tools.profile_for_session("discord:42"), Some("ops"));
+        assert_eq!(cfg.tools.profile_for_session("cron:daily"), Some("ops"));
+        assert_eq!(cfg.tools.profile_for_session("default"), Some("safe"));
+    }
+
+    #[test]
+    fn test_gateway_admins() {
+        let json = r#"{ "gateway": { "admins": ["telegram:12345", "slack:U024BE7LH"] } }"#;
+        let cfg: Config = serde_json::from_str(json).unwrap();
+        assert!(cfg.gateway.is_admin("telegram", "12345"));
+        assert!(cfg.gateway.is_admin("telegram", "12345|bob"));
+        assert!(cfg.gateway.is_admin("slack", "U024BE7LH"));
+        // A display name is not an identity
+        assert!(!cfg.gateway.is_admin("telegram", "999|12345"));
+        assert!(!cfg.gateway.is_admin("discord", "12345"));
+        assert!(!cfg.gateway.is_admin("telegram", "999"));
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
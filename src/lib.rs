@@ -12,9 +12,11 @@ pub mod market;
 pub mod mcp;
 pub mod metrics;
 pub mod provider;
+pub mod scheduler;
 pub mod session;
 pub mod tool;
 pub mod tui;
+pub mod util;
 pub mod vectordb;
 pub mod web;
 pub mod workflow;
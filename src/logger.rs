@@ -6,6 +6,7 @@ use std::sync::Mutex;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter, Layer};
 
 static DASHBOARD_ACTIVE: AtomicBool = AtomicBool::new(false);
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
 static TUI_STATE: Mutex<Option<TuiState>> = Mutex::new(None);
 
 /// Set whether the dashboard is active (to silence stdout logs).
@@ -13,6 +14,12 @@ pub fn set_dashboard_active(active: bool) {
     DASHBOARD_ACTIVE.store(active, Ordering::Relaxed);
 }
 
+/// Write console logs to stderr instead of stdout (stdout carries protocol
+/// traffic in `mcp serve`).
+pub fn set_log_to_stderr(enabled: bool) {
+    LOG_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
 /// Initialize the global tracing subscriber.
 ///
 /// Log level is controlled by the `QUECTOCLAW_LOG` env var (default: `info`).
@@ -29,6 +36,13 @@ pub fn init() {
         .with_file(false)
         .with_line_number(false)
         .compact()
+        .with_writer(|| -> Box<dyn std::io::Write> {
+            if LOG_TO_STDERR.load(Ordering::Relaxed) {
+                Box::new(std::io::stderr())
+            } else {
+                Box::new(std::io::stdout())
+            }
+        })
         .with_filter(tracing_subscriber::filter::filter_fn(|_| {
             !DASHBOARD_ACTIVE.load(Ordering::Relaxed)
         }));
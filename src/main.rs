@@ -6,9 +6,14 @@ use clap::{Parser, Subcommand};
 use quectoclaw::agent::AgentLoop;
 use quectoclaw::bus::MessageBus;
 use quectoclaw::config::Config;
+use quectoclaw::mcp::sampling::{SamplingApprover, SamplingRequest};
 use quectoclaw::provider::factory::create_provider;
+use quectoclaw::scheduler::JobStore;
 use quectoclaw::tool::exec::ExecTool;
 use quectoclaw::tool::filesystem::*;
+use quectoclaw::tool::maixcam_command::MaixCamCommandTool;
+use quectoclaw::tool::schedule::{CancelTaskTool, ListTasksTool, ScheduleTaskTool};
+use quectoclaw::tool::send_file::SendFileTool;
 use quectoclaw::tool::subagent::SubagentTool;
 use quectoclaw::tool::vectordb_index::VectorIndexTool;
 use quectoclaw::tool::vectordb_search::VectorSearchTool;
@@ -115,6 +120,46 @@ enum Commands {
         #[command(subcommand)]
         action: PluginAction,
     },
+    /// Model Context Protocol utilities
+    Mcp {
+        #[command(subcommand)]
+        action: McpAction,
+    },
+    /// Inspect and replay replies that channels failed to send
+    Outbox {
+        #[command(subcommand)]
+        action: OutboxAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum OutboxAction {
+    /// List replies waiting for a retry and dead letters
+    List {
+        /// Config file path
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Queue dead letters for sending again (a running gateway picks them up)
+    Replay {
+        /// Id of the dead letter
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+        /// Replay every dead letter
+        #[arg(long)]
+        all: bool,
+        /// Config file path
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+    /// Delete a dead letter
+    Discard {
+        /// Id of the dead letter
+        id: String,
+        /// Config file path
+        #[arg(short, long)]
+        config: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -141,6 +186,28 @@ enum PluginAction {
     },
 }
 
+#[derive(Subcommand)]
+enum McpAction {
+    /// Serve the tool registry to MCP clients (stdio by default)
+    Serve {
+        /// Also offer a `chat` tool that runs the full agent
+        #[arg(long)]
+        chat: bool,
+        /// Tool profile to expose (default: resolved for session `mcp:<session>`)
+        #[arg(short, long)]
+        profile: Option<String>,
+        /// Session key for tool calls and chat history
+        #[arg(short, long, default_value = "default")]
+        session: String,
+        /// Serve Streamable HTTP on this address instead of stdio (e.g. 127.0.0.1:3100)
+        #[arg(long)]
+        http: Option<std::net::SocketAddr>,
+        /// Config file path
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+}
+
 // ---------------------------------------------------------------------------
 // Entry point
 // ---------------------------------------------------------------------------
@@ -195,6 +262,12 @@ async fn main() {
         Some(Commands::Plugin { action }) => {
             plugin_cmd(action).await;
         }
+        Some(Commands::Mcp { action }) => {
+            mcp_cmd(action).await;
+        }
+        Some(Commands::Outbox { action }) => {
+            outbox_cmd(action).await;
+        }
         None => {
             // Default: run in interactive agent mode
             agent_cmd(None, "default".into(), None).await;
@@ -248,6 +321,12 @@ async fn agent_cmd(message: Option<String>, session: String, config_path: Option
     tools
         .register(Arc::new(SubagentTool::new(agent_clone)))
         .await;
+    agent.attach_mcp_sampling().await;
+    if let Some(mcp) = tools.get_mcp().await {
+        mcp.sampling()
+            .set_approver(Arc::new(TerminalApprover::default()))
+            .await;
+    }
 
     tracing::info!(
         workspace = %ws_str,
@@ -257,6 +336,13 @@ async fn agent_cmd(message: Option<String>, session: String, config_path: Option
     match message {
         Some(msg) => {
             // One-shot mode
+            let msg = match agent.expand_input(&msg, &session).await {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("{} Error: {}", LOGO, e);
+                    std::process::exit(1);
+                }
+            };
             match agent.process_direct(&msg, &session).await {
                 Ok(response) => {
                     println!("{}", response);
@@ -283,7 +369,9 @@ async fn interactive_mode(agent: Arc<AgentLoop>, session: &str) {
         LOGO,
         quectoclaw::VERSION
     );
-    println!("Type your message and press Enter. Type 'exit' or Ctrl+D to quit.\n");
+    println!(
+        "Type your message and press Enter. Type /help for commands, 'exit' or Ctrl+D to quit.\n"
+    );
 
     let mut rl = match rustyline::DefaultEditor::new() {
         Ok(r) => r,
@@ -308,70 +396,25 @@ async fn interactive_mode(agent: Arc<AgentLoop>, session: &str) {
 
                 let _ = rl.add_history_entry(trimmed);
 
-                // Handle slash commands
-                if trimmed.starts_with('/') {
-                    let parts: Vec<&str> = trimmed.splitn(2, ' ').collect();
-                    match parts[0] {
-                        "/fork" => {
-                            let new_name =
-                                parts.get(1).map(|s| s.to_string()).unwrap_or_else(|| {
-                                    format!("{}-fork-{}", session, chrono::Utc::now().timestamp())
-                                });
-                            if agent.fork_session(session, &new_name).await {
-                                println!("✂️  Forked session to: {}", new_name);
-                                println!("   (Restart with --session {} to use it)\n", new_name);
-                            } else {
-                                println!("⚠️  Nothing to fork — session is empty.\n");
-                            }
-                            continue;
-                        }
-                        "/clear" => {
-                            agent
-                                .fork_session(
-                                    session,
-                                    &format!(
-                                        "{}-backup-{}",
-                                        session,
-                                        chrono::Utc::now().timestamp()
-                                    ),
-                                )
-                                .await;
-                            println!("🗑️  Session cleared. (Backup saved)\n");
-                            continue;
-                        }
-                        "/help" => {
-                            println!("Commands:");
-                            println!("  /fork [name]  — Branch this conversation");
-                            println!("  /clear        — Clear session (auto-backs up)");
-                            println!("  /metrics      — Show performance metrics");
-                            println!("  /cost         — Show cost breakdown");
-                            println!("  /help         — Show this help");
-                            println!("  exit          — Quit\n");
-                            continue;
-                        }
-                        "/metrics" => {
-                            let report = agent.metrics().format_report().await;
-                            println!("{}\n", report);
-                            continue;
-                        }
-                        "/cost" => {
-                            let report = agent.metrics().format_cost_report().await;
-                            println!("{}\n", report);
-                            continue;
-                        }
-                        _ => {
-                            println!("Unknown command: {} (try /help)\n", parts[0]);
-                            continue;
-                        }
-                    }
+                // Slash commands (the local user is an admin)
+                if let Some(reply) = agent.run_command(trimmed, session, true).await {
+                    println!("{}\n", reply);
+                    continue;
                 }
 
+                let msg = match agent.expand_input(trimmed, session).await {
+                    Ok(m) => m,
+                    Err(e) => {
+                        println!("⚠️  {}\n", e);
+                        continue;
+                    }
+                };
+
                 // Stream response tokens
                 let (tx, mut rx) =
                     tokio::sync::mpsc::channel::<quectoclaw::provider::StreamEvent>(256);
 
                 let agent_clone = agent.clone();
-                let msg = trimmed.to_string();
                 let sess = session.to_string();
 
                 let handle = tokio::spawn(async move {
@@ -393,6 +436,10 @@ async fn interactive_mode(agent: Arc<AgentLoop>, session: &str) {
                                 std::io::stdout().flush().ok();
                             }
                         }
+                        quectoclaw::provider::StreamEvent::ToolProgress(p) => {
+                            print!("\n   ↳ {}", p.describe());
+                            std::io::stdout().flush().ok();
+                        }
                         quectoclaw::provider::StreamEvent::Done(_) => {
                             break;
                         }
@@ -445,8 +492,19 @@ async fn simple_interactive_mode(agent: Arc<AgentLoop>, session: &str) {
                 if trimmed == "exit" || trimmed == "quit" {
                     break;
                 }
+                if let Some(reply) = agent.run_command(&trimmed, session, true).await {
+                    println!("\n{}\n", reply);
+                    continue;
+                }
 
-                match agent.process_direct(&trimmed, session).await {
+                let msg = match agent.expand_input(&trimmed, session).await {
+                    Ok(m) => m,
+                    Err(e) => {
+                        eprintln!("\n{} Error: {}\n", LOGO, e);
+                        continue;
+                    }
+                };
+                match agent.process_direct(&msg, session).await {
                     Ok(response) => println!("\n{}\n", response),
                     Err(e) => eprintln!("\n{} Error: {}\n", LOGO, e),
                 }
@@ -522,7 +580,7 @@ async fn onboard_cmd() {
         ("AGENTS.md", "# Agent Behavior\n- Think step by step\n- Use tools when needed\n- Ask for clarification when uncertain\n- Keep responses concise\n"),
         ("TOOLS.md", "# Tool Usage\n- Use `exec` for shell commands\n- Use `read_file` / `write_file` / `edit_file` for file operations\n- Use `web_search` to find information online\n- Use `list_dir` to explore directory structure\n"),
         ("USER.md", "# User Preferences\n(Add your preferences here)\n"),
-        ("HEARTBEAT.md", "# Heartbeat Tasks\n(Add periodic tasks here — the agent will check this file every 30 minutes)\n"),
+        ("HEARTBEAT.md", "# Heartbeat Tasks\n<!-- The gateway checks this file every 30 minutes. Add checklist items such as\n- [ ] Check the backup job\n- [ ] Call the dentist @2025-01-15 09:00\nDue items are run once and then ticked off. -->\n"),
     ];
 
     for (filename, content) in &templates {
@@ -705,6 +763,21 @@ async fn create_tool_registry(
         )))
         .await;
 
+    // Register scheduling tools (jobs run in gateway mode)
+    if cfg.scheduler.enabled {
+        let jobs = JobStore::shared(std::path::Path::new(workspace));
+        registry
+            .register(Arc::new(ScheduleTaskTool::new(
+                jobs.clone(),
+                cfg.scheduler.max_jobs,
+            )))
+            .await;
+        registry
+            .register(Arc::new(ListTasksTool::new(jobs.clone())))
+            .await;
+        registry.register(Arc::new(CancelTaskTool::new(jobs))).await;
+    }
+
     // Load plugins from workspace/plugins/ directory
     let plugins_dir = std::path::Path::new(workspace).join("plugins");
     let plugins = quectoclaw::tool::plugin::load_plugins(&plugins_dir).await;
@@ -782,14 +855,34 @@ async fn gateway_cmd(config_path: Option<String>, use_dashboard: bool) {
     tools
         .register(Arc::new(SubagentTool::new(agent_clone)))
         .await;
+    agent_arc.attach_mcp_sampling().await;
+
+    // Register send_file (only chat channels can receive files)
+    tools
+        .register(Arc::new(SendFileTool::new(
+            ws_str.clone(),
+            restrict,
+            bus.clone(),
+        )))
+        .await;
+    if cfg.channels.maixcam.enabled {
+        tools
+            .register(Arc::new(MaixCamCommandTool::new(bus.clone())))
+            .await;
+    }
 
-    let gateway = quectoclaw::agent::gateway::Gateway::new(cfg.clone(), agent_arc, bus);
+    let gateway = Arc::new(quectoclaw::agent::gateway::Gateway::new(
+        cfg.clone(),
+        agent_arc,
+        bus,
+    ));
 
     if let Some(state) = tui_state {
         // Run Gateway in background
         println!("{} Starting Gateway with integrated dashboard...", LOGO);
-        tokio::spawn(async move {
-            if let Err(e) = gateway.run().await {
+        let background = gateway.clone();
+        let handle = tokio::spawn(async move {
+            if let Err(e) = background.run().await {
                 tracing::error!("Gateway error: {}", e);
             }
         });
@@ -798,6 +891,10 @@ async fn gateway_cmd(config_path: Option<String>, use_dashboard: bool) {
         if let Err(e) = quectoclaw::tui::run(state, cfg).await {
             eprintln!("{} Dashboard error: {}", LOGO, e);
         }
+
+        // Quitting the dashboard stops the channels cleanly
+        gateway.shutdown();
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(10), handle).await;
     } else {
         // Run Gateway normally in foreground
         println!("{} QuectoClaw Gateway starting...", LOGO);
@@ -857,6 +954,7 @@ async fn webui_cmd(config_path: Option<String>, port: u16) {
     tools
         .register(Arc::new(SubagentTool::new(agent_clone)))
         .await;
+    agent_arc.attach_mcp_sampling().await;
 
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
     println!(
@@ -882,6 +980,7 @@ async fn plugin_cmd(action: PluginAction) {
         .workspace_path()
         .unwrap_or_else(|_| std::path::PathBuf::from("workspace"));
     let plugins_dir = workspace.join("plugins");
+    let wasm_plugins_dir = workspace.join("wasm_plugins");
 
     let market = quectoclaw::market::PluginMarket::new(cfg.marketplace.registry_url.clone());
 
@@ -904,7 +1003,14 @@ async fn plugin_cmd(action: PluginAction) {
         }
         PluginAction::List { .. } => {
             println!("{} Installed plugins:", LOGO);
-            match quectoclaw::market::PluginMarket::list_installed(&plugins_dir) {
+            let installed = quectoclaw::market::PluginMarket::list_installed(&plugins_dir)
+                .and_then(|mut shell| {
+                    shell.extend(quectoclaw::market::PluginMarket::list_installed(
+                        &wasm_plugins_dir,
+                    )?);
+                    Ok(shell)
+                });
+            match installed {
                 Ok(installed) => {
                     if installed.is_empty() {
                         println!("  (none)");
@@ -923,8 +1029,15 @@ async fn plugin_cmd(action: PluginAction) {
                 Ok(registry) => {
                     if let Some(plugin) = registry.plugins.iter().find(|p| p.name == name) {
                         println!("{} Installing {} v{}...", LOGO, plugin.name, plugin.version);
-                        match market.install_plugin(plugin, &plugins_dir).await {
-                            Ok(_) => println!("{} Plugin '{}' installed successfully.", LOGO, name),
+                        let target_dir = match plugin.r#type {
+                            quectoclaw::market::PluginType::Shell => &plugins_dir,
+                            quectoclaw::market::PluginType::Wasm => &wasm_plugins_dir,
+                        };
+                        match market.install_plugin(plugin, target_dir).await {
+                            Ok(_) => {
+                                println!("{} Plugin '{}' installed successfully.", LOGO, name);
+                                println!("   A running gateway picks it up automatically.");
+                            }
                             Err(e) => println!("{} Failed to install: {}", LOGO, e),
                         }
                     } else {
@@ -1012,6 +1125,180 @@ async fn audit_cmd(limit: usize, follow: bool, config_path: Option<String>) {
     }
 }
 
+async fn outbox_cmd(action: OutboxAction) {
+    let config_path = match &action {
+        OutboxAction::List { config } => config.clone(),
+        OutboxAction::Replay { config, .. } => config.clone(),
+        OutboxAction::Discard { config, .. } => config.clone(),
+    };
+    let cfg = load_config(config_path.as_deref());
+    let workspace = cfg
+        .workspace_path()
+        .unwrap_or_else(|_| std::path::PathBuf::from("workspace"));
+    let outbox = quectoclaw::channel::outbox::Outbox::new(&workspace);
+
+    match action {
+        OutboxAction::List { .. } => {
+            let print = |entries: &[quectoclaw::channel::outbox::OutboxEntry]| {
+                if entries.is_empty() {
+                    println!("  (none)");
+                }
+                for e in entries {
+                    let preview: String = e.message.content.chars().take(50).collect();
+                    println!(
+                        "  {}  {}:{}  {} attempts  {}",
+                        e.id,
+                        e.message.channel,
+                        e.message.chat_id,
+                        e.attempts,
+                        preview.replace('\n', " ")
+                    );
+                    if let Some(err) = &e.last_error {
+                        println!("      last error: {}", err);
+                    }
+                }
+            };
+            println!("{} Waiting for a retry:", LOGO);
+            print(&outbox.all_pending().await);
+            println!("{} Dead letters:", LOGO);
+            print(&outbox.dead_letters().await);
+        }
+        OutboxAction::Replay { id, all, .. } => {
+            let ids: Vec<String> = if all {
+                outbox
+                    .dead_letters()
+                    .await
+                    .into_iter()
+                    .map(|e| e.id)
+                    .collect()
+            } else {
+                id.into_iter().collect()
+            };
+            for id in ids {
+                match outbox.replay(&id).await {
+                    Ok(e) => println!(
+                        "{} Queued {} for {}:{}",
+                        LOGO, e.id, e.message.channel, e.message.chat_id
+                    ),
+                    Err(e) => println!("{} {}", LOGO, e),
+                }
+            }
+        }
+        OutboxAction::Discard { id, .. } => match outbox.discard(&id).await {
+            Ok(e) => println!("{} Discarded {}", LOGO, e.id),
+            Err(e) => println!("{} {}", LOGO, e),
+        },
+    }
+}
+
+/// Asks on the terminal before an MCP server with `"sampling": "ask"` may
+/// use the model. One question at a time.
+#[derive(Default)]
+struct TerminalApprover {
+    lock: tokio::sync::Mutex<()>,
+}
+
+#[async_trait::async_trait]
+impl SamplingApprover for TerminalApprover {
+    async fn approve(&self, server: &str, request: &SamplingRequest) -> bool {
+        let _guard = self.lock.lock().await;
+        eprintln!(
+            "\n{} MCP server '{}' wants to use the model (up to {} tokens):\n   {}",
+            LOGO,
+            server,
+            request.max_tokens,
+            request.preview()
+        );
+        eprint!("   Allow? [y/N] ");
+        tokio::task::spawn_blocking(|| {
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer).is_ok()
+                && matches!(answer.trim(), "y" | "Y" | "yes")
+        })
+        .await
+        .unwrap_or(false)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// MCP command
+// ---------------------------------------------------------------------------
+
+async fn mcp_cmd(action: McpAction) {
+    let McpAction::Serve {
+        chat,
+        profile,
+        session,
+        http,
+        config,
+    } = action;
+
+    // stdout is the protocol channel in stdio mode
+    if http.is_none() {
+        quectoclaw::logger::set_log_to_stderr(true);
+    }
+
+    let cfg = load_config(config.as_deref());
+    if let Err(e) = cfg.validate() {
+        eprintln!("{} Configuration Error: {}", LOGO, e);
+        std::process::exit(1);
+    }
+
+    let provider = match create_provider(&cfg) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{} Error: {}", LOGO, e);
+            std::process::exit(1);
+        }
+    };
+
+    let workspace = cfg
+        .workspace_path()
+        .unwrap_or_else(|_| PathBuf::from("/tmp/quectoclaw"));
+    if let Err(e) = std::fs::create_dir_all(&workspace) {
+        eprintln!("Failed to create workspace: {}", e);
+        std::process::exit(1);
+    }
+    let ws_str = workspace.to_string_lossy().to_string();
+    let restrict = cfg.agents.defaults.restrict_to_workspace;
+
+    let session_key = format!("mcp:{}", session);
+    let profile = profile.or_else(|| {
+        cfg.tools
+            .profile_for_session(&session_key)
+            .map(String::from)
+    });
+    let tools = create_tool_registry(&ws_str, restrict, &cfg, provider.clone()).await;
+    let bus = Arc::new(MessageBus::new());
+    let agent = Arc::new(AgentLoop::new(cfg.clone(), provider, tools.clone(), bus));
+    tools
+        .register(Arc::new(SubagentTool::new(agent.clone())))
+        .await;
+    agent.attach_mcp_sampling().await;
+
+    let view = agent.tools_for_profile(profile.as_deref());
+    let mut server = quectoclaw::mcp::serve::MCPToolServer::new(view, &session_key);
+    if chat {
+        server = server.with_chat(agent);
+    }
+    let server = Arc::new(server);
+
+    tracing::info!(
+        session = %session_key,
+        profile = profile.as_deref().unwrap_or("-"),
+        chat,
+        "Serving tools over MCP"
+    );
+    let result = match http {
+        Some(addr) => quectoclaw::mcp::serve::serve_http(server, addr, &cfg).await,
+        None => server.serve_stdio().await,
+    };
+    if let Err(e) = result {
+        eprintln!("{} MCP server error: {}", LOGO, e);
+        std::process::exit(1);
+    }
+}
+
 async fn run_workflow_cmd(
     path: String,
     args: Vec<String>,
@@ -1042,6 +1329,7 @@ async fn run_workflow_cmd(
     let tools = create_tool_registry(&ws_str, restrict, &cfg, provider.clone()).await;
     let bus = Arc::new(MessageBus::new());
     let agent = Arc::new(AgentLoop::new(cfg, provider, tools.clone(), bus));
+    agent.attach_mcp_sampling().await;
 
     // Parse arguments into a HashMap
     let mut parsed_args = std::collections::HashMap::new();
@@ -52,6 +52,10 @@ impl PluginMarket {
     }
 
     /// Install a plugin from the marketplace.
+    ///
+    /// Shell plugins go into `plugins_dir`, WASM plugins into the WASM plugin
+    /// directory; files are written atomically so a running gateway's plugin
+    /// watcher never picks up a half-written manifest.
     pub async fn install_plugin(
         &self,
         plugin: &MarketPlugin,
@@ -74,7 +78,7 @@ impl PluginMarket {
             PluginType::Shell => {
                 // For shell plugins, we expect a single JSON file for now
                 let file_path = target_dir.join(format!("{}.json", plugin.name));
-                std::fs::write(file_path, bytes)?;
+                write_atomic(&file_path, &bytes)?;
             }
             PluginType::Wasm => {
                 // For WASM plugins, we expect a zip or individual files
@@ -83,7 +87,7 @@ impl PluginMarket {
                 // Let's assume the download_url points to a .wasm file if it ends in .wasm.
                 if plugin.download_url.ends_with(".wasm") {
                     let file_path = target_dir.join(format!("{}.wasm", plugin.name));
-                    std::fs::write(file_path, bytes)?;
+                    write_atomic(&file_path, &bytes)?;
 
                     // Create a basic manifest.json if it doesn't exist
                     let manifest_path = target_dir.join("manifest.json");
@@ -95,7 +99,10 @@ impl PluginMarket {
                             "parameters": [],
                             "fuel": 1000000
                         });
-                        std::fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+                        write_atomic(
+                            &manifest_path,
+                            serde_json::to_string_pretty(&manifest)?.as_bytes(),
+                        )?;
                     }
                 } else {
                     return Err(anyhow::anyhow!(
@@ -127,6 +134,13 @@ impl PluginMarket {
     }
 }
 
+/// Write to a temporary sibling file, then rename into place.
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, bytes)?;
+    std::fs::rename(&tmp_path, path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
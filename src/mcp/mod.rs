@@ -6,13 +6,21 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, Command};
-use tokio::sync::{mpsc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch, Mutex, RwLock};
 
-use crate::tool::{Tool, ToolResult};
+pub mod sampling;
+pub mod serve;
+pub mod server;
+pub mod transport;
+
+use crate::config::{MCPServerConfig, MCPTransportKind};
+use crate::tool::{Tool, ToolContext, ToolProgress, ToolResult};
+pub use server::MCPServer;
+use transport::{
+    build_headers, MCPTransport, SseTransport, StdioTransport, StreamableHttpTransport,
+};
 
 // ---------------------------------------------------------------------------
 // JSON-RPC Types
@@ -31,7 +39,9 @@ pub struct JsonRpcRequest {
 pub struct JsonRpcResponse {
     pub jsonrpc: String,
     pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<JsonRpcError>,
 }
 
@@ -39,11 +49,21 @@ pub struct JsonRpcResponse {
 pub struct JsonRpcError {
     pub code: i32,
     pub message: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Value::is_null")]
     pub data: Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl JsonRpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: Value::Null,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcNotification {
     pub jsonrpc: String,
     pub method: String,
@@ -55,103 +75,176 @@ pub struct JsonRpcNotification {
 // MCP Client
 // ---------------------------------------------------------------------------
 
+type Pending = Arc<Mutex<HashMap<String, mpsc::Sender<Result<Value>>>>>;
+/// `notifications/progress` params, routed by progress token.
+type ProgressSinks = Arc<Mutex<HashMap<String, mpsc::Sender<Value>>>>;
+
+/// Default request timeout for clients created without a config.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Answers requests the server sends to us, such as `sampling/createMessage`.
+#[async_trait]
+pub trait MCPRequestHandler: Send + Sync {
+    async fn handle_request(&self, method: &str, params: Value) -> Result<Value, JsonRpcError>;
+}
+
+type SharedHandler = Arc<std::sync::RwLock<Option<Arc<dyn MCPRequestHandler>>>>;
+
 pub struct MCPClient {
-    #[allow(dead_code)]
-    child: Child,
-    tx: mpsc::Sender<String>,
-    pending: Arc<Mutex<HashMap<String, mpsc::Sender<Result<Value>>>>>,
+    name: String,
+    transport: Arc<dyn MCPTransport>,
+    pending: Pending,
+    progress: ProgressSinks,
     next_id: Arc<Mutex<u64>>,
+    timeout: Duration,
+    closed: Arc<watch::Sender<bool>>,
+    notifications: broadcast::Sender<JsonRpcNotification>,
+    handler: SharedHandler,
+    /// Keeps the inbound queue open for transports that only hold it weakly (HTTP).
+    _inbound: Option<transport::Inbound>,
 }
 
 impl MCPClient {
+    /// Spawn a stdio server.
     pub async fn spawn(
         name: &str,
         command: &str,
         args: &[String],
         env: &HashMap<String, String>,
     ) -> Result<Self> {
-        let mut child = Command::new(command)
-            .args(args)
-            .envs(env)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .kill_on_drop(true)
-            .spawn()?;
-
-        let stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| anyhow!("Failed to open stdin"))?;
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("Failed to open stdout"))?;
-        let stderr = child
-            .stderr
-            .take()
-            .ok_or_else(|| anyhow!("Failed to open stderr"))?;
-
-        let (tx, mut rx) = mpsc::channel::<String>(32);
-        let pending: Arc<Mutex<HashMap<String, mpsc::Sender<Result<Value>>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let pending_clone = pending.clone();
-
-        // Stdin writer task
-        tokio::spawn(async move {
-            let mut stdin = stdin;
-            while let Some(msg) = rx.recv().await {
-                if let Err(e) = stdin.write_all(format!("{}\n", msg).as_bytes()).await {
-                    tracing::error!("MCP stdin error: {}", e);
-                    break;
-                }
-                let _ = stdin.flush().await;
-            }
-        });
-
-        // Stdout reader task
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
-                if let Ok(resp) = serde_json::from_str::<JsonRpcResponse>(&line) {
-                    let id_str = match &resp.id {
-                        Value::String(s) => s.clone(),
-                        Value::Number(n) => n.to_string(),
-                        _ => continue,
-                    };
+        let (inbound_tx, inbound_rx) = mpsc::channel(64);
+        let transport = StdioTransport::spawn(name, command, args, env, inbound_tx)?;
+        Ok(Self::with_transport(name, Arc::new(transport), inbound_rx))
+    }
 
-                    let mut pending = pending_clone.lock().await;
-                    if let Some(chan) = pending.remove(&id_str) {
-                        if let Some(err) = resp.error {
-                            let _ = chan
-                                .send(Err(anyhow!("MCP Error ({}): {}", err.code, err.message)))
-                                .await;
-                        } else {
-                            let _ = chan.send(Ok(resp.result.unwrap_or(Value::Null))).await;
-                        }
-                    }
-                }
+    /// Connect to a server using the transport selected in its config.
+    pub async fn connect(name: &str, cfg: &MCPServerConfig) -> Result<Self> {
+        let (inbound_tx, inbound_rx) = mpsc::channel(64);
+        let mut keep_inbound = None;
+        let transport: Arc<dyn MCPTransport> = match cfg.transport_kind() {
+            MCPTransportKind::Stdio => Arc::new(StdioTransport::spawn(
+                name,
+                &cfg.command,
+                &cfg.args,
+                &cfg.env,
+                inbound_tx,
+            )?),
+            MCPTransportKind::Http => {
+                let headers = build_headers(&cfg.headers, &cfg.bearer_token)?;
+                let transport = StreamableHttpTransport::new(&cfg.url, headers, &inbound_tx)?;
+                keep_inbound = Some(inbound_tx);
+                Arc::new(transport)
             }
-        });
-
-        // Stderr logger task
-        let name_str = name.to_string();
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stderr).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
-                tracing::debug!("MCP [{}] stderr: {}", name_str, line);
+            MCPTransportKind::Sse => {
+                let headers = build_headers(&cfg.headers, &cfg.bearer_token)?;
+                Arc::new(SseTransport::connect(&cfg.url, headers, inbound_tx).await?)
             }
-        });
+        };
+        let mut client = Self::with_transport(name, transport, inbound_rx)
+            .with_timeout(Duration::from_secs(cfg.timeout.max(1)));
+        client._inbound = keep_inbound;
+        Ok(client)
+    }
 
-        Ok(Self {
-            child,
-            tx,
+    /// Build a client over any transport. `inbound` yields the server's messages.
+    pub fn with_transport(
+        name: &str,
+        transport: Arc<dyn MCPTransport>,
+        inbound: mpsc::Receiver<String>,
+    ) -> Self {
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let progress: ProgressSinks = Arc::new(Mutex::new(HashMap::new()));
+        let closed = Arc::new(watch::channel(false).0);
+        let (notifications, _) = broadcast::channel(64);
+        let handler: SharedHandler = Arc::default();
+        tokio::spawn(dispatch(
+            name.to_string(),
+            transport.clone(),
+            inbound,
+            pending.clone(),
+            progress.clone(),
+            closed.clone(),
+            notifications.clone(),
+            handler.clone(),
+        ));
+        Self {
+            name: name.to_string(),
+            transport,
             pending,
+            progress,
             next_id: Arc::new(Mutex::new(1)),
-        })
+            timeout: DEFAULT_TIMEOUT,
+            closed,
+            notifications,
+            handler,
+            _inbound: None,
+        }
+    }
+
+    /// Route the server's requests to `handler`; without one they get
+    /// "method not found".
+    pub fn set_request_handler(&self, handler: Arc<dyn MCPRequestHandler>) {
+        if let Ok(mut slot) = self.handler.write() {
+            *slot = Some(handler);
+        }
+    }
+
+    /// Set how long `call` waits for a response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Server name from the config.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Transport label (`stdio`, `http`, `sse`).
+    pub fn transport_kind(&self) -> &'static str {
+        self.transport.kind()
+    }
+
+    /// Whether the connection is gone (process exited, stream ended, send failed).
+    pub fn is_closed(&self) -> bool {
+        *self.closed.borrow()
+    }
+
+    /// Resolve once the connection is gone.
+    pub async fn closed(&self) {
+        let mut rx = self.closed.subscribe();
+        let _ = rx.wait_for(|closed| *closed).await;
+    }
+
+    /// Notifications sent by the server (except progress, which is routed to its call).
+    pub fn subscribe(&self) -> broadcast::Receiver<JsonRpcNotification> {
+        self.notifications.subscribe()
     }
 
     pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        self.request(method, params, None).await
+    }
+
+    /// Call with a progress token; the server's `notifications/progress`
+    /// params for this request are sent to `progress`.
+    pub async fn call_with_progress(
+        &self,
+        method: &str,
+        params: Value,
+        progress: mpsc::Sender<Value>,
+    ) -> Result<Value> {
+        self.request(method, params, Some(progress)).await
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        mut params: Value,
+        progress: Option<mpsc::Sender<Value>>,
+    ) -> Result<Value> {
+        if self.is_closed() {
+            return Err(anyhow!("MCP connection closed"));
+        }
         let id = {
             let mut next_id = self.next_id.lock().await;
             let id = *next_id;
@@ -159,6 +252,14 @@ impl MCPClient {
             id.to_string()
         };
 
+        if let Some(sink) = progress {
+            if !params.is_object() {
+                params = json!({});
+            }
+            params["_meta"] = json!({ "progressToken": id });
+            self.progress.lock().await.insert(id.clone(), sink);
+        }
+
         let req = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
             id: json!(id),
@@ -169,19 +270,52 @@ impl MCPClient {
         let (resp_tx, mut resp_rx) = mpsc::channel(1);
         {
             let mut pending = self.pending.lock().await;
-            pending.insert(id, resp_tx);
+            pending.insert(id.clone(), resp_tx);
         }
+        // From here on, dropping this future tells the server to stop.
+        let mut guard = InFlight {
+            client: self,
+            id: id.clone(),
+            cancel_reason: Some("request cancelled by client"),
+        };
+
+        // The timeout covers the send too: an HTTP server can stall there
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let timed_out = || anyhow!("MCP request timed out after {}s", self.timeout.as_secs());
 
         let msg = serde_json::to_string(&req)?;
-        self.tx
-            .send(msg)
-            .await
-            .map_err(|e| anyhow!("Failed to send to MCP: {}", e))?;
+        match tokio::time::timeout_at(deadline, self.transport.send(msg)).await {
+            Ok(Ok(())) => {}
+            // An HTTP error fails this request only
+            Ok(Err(e)) if e.downcast_ref::<transport::Rejected>().is_some() => {
+                guard.cancel_reason = None;
+                return Err(e);
+            }
+            Ok(Err(e)) => {
+                // The server never saw the request; treat the connection as dead.
+                guard.cancel_reason = None;
+                self.closed.send_replace(true);
+                return Err(e);
+            }
+            Err(_) => {
+                guard.cancel_reason = Some("request timed out");
+                return Err(timed_out());
+            }
+        }
 
-        match tokio::time::timeout(tokio::time::Duration::from_secs(10), resp_rx.recv()).await {
-            Ok(Some(res)) => res,
-            Ok(None) => Err(anyhow!("MCP connection closed")),
-            Err(_) => Err(anyhow!("MCP request timed out")),
+        match tokio::time::timeout_at(deadline, resp_rx.recv()).await {
+            Ok(Some(res)) => {
+                guard.cancel_reason = None;
+                res
+            }
+            Ok(None) => {
+                guard.cancel_reason = None;
+                Err(anyhow!("MCP connection closed"))
+            }
+            Err(_) => {
+                guard.cancel_reason = Some("request timed out");
+                Err(timed_out())
+            }
         }
     }
 
@@ -193,12 +327,160 @@ impl MCPClient {
         };
 
         let msg = serde_json::to_string(&req)?;
-        self.tx
-            .send(msg)
-            .await
-            .map_err(|e| anyhow!("Failed to send to MCP: {}", e))?;
-        Ok(())
+        self.transport.send(msg).await
+    }
+}
+
+impl Drop for MCPClient {
+    /// Stop the dispatch task; it would otherwise wait on streams the server may keep open.
+    fn drop(&mut self) {
+        self.closed.send_replace(true);
+    }
+}
+
+/// An outstanding request. On drop it releases the request's routing
+/// entries and, unless a response arrived, sends `notifications/cancelled`.
+struct InFlight<'a> {
+    client: &'a MCPClient,
+    id: String,
+    cancel_reason: Option<&'static str>,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let id = std::mem::take(&mut self.id);
+        let reason = self.cancel_reason;
+        let pending = self.client.pending.clone();
+        let progress = self.client.progress.clone();
+        let transport = self.client.transport.clone();
+        let closed = self.client.is_closed();
+        runtime.spawn(async move {
+            pending.lock().await.remove(&id);
+            progress.lock().await.remove(&id);
+            if let (Some(reason), false) = (reason, closed) {
+                let note = json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/cancelled",
+                    "params": { "requestId": id, "reason": reason }
+                });
+                let _ = transport.send(note.to_string()).await;
+            }
+        });
+    }
+}
+
+/// Route server messages: responses to their pending calls, progress to
+/// the call that asked for it, other notifications to subscribers, and
+/// server requests to the request handler.
+#[allow(clippy::too_many_arguments)]
+async fn dispatch(
+    name: String,
+    transport: Arc<dyn MCPTransport>,
+    mut inbound: mpsc::Receiver<String>,
+    pending: Pending,
+    progress: ProgressSinks,
+    closed: Arc<watch::Sender<bool>>,
+    notifications: broadcast::Sender<JsonRpcNotification>,
+    handler: SharedHandler,
+) {
+    let mut closing = closed.subscribe();
+    loop {
+        let line = tokio::select! {
+            line = inbound.recv() => match line {
+                Some(line) => line,
+                None => break,
+            },
+            _ = closing.wait_for(|closed| *closed) => break,
+        };
+        let Ok(msg) = serde_json::from_str::<Value>(&line) else {
+            tracing::debug!(server = %name, "MCP: ignoring non-JSON line");
+            continue;
+        };
+
+        if let Some(method) = msg.get("method").and_then(|m| m.as_str()) {
+            match msg.get("id") {
+                Some(id) => {
+                    let id = id.clone();
+                    let method = method.to_string();
+                    let params = msg.get("params").cloned().unwrap_or_default();
+                    let handler = handler.read().ok().and_then(|h| h.clone());
+                    let transport = transport.clone();
+                    let name = name.clone();
+                    // Answer off the read loop: handlers may take a while
+                    tokio::spawn(async move {
+                        let result = match (method.as_str(), handler) {
+                            ("ping", _) => Ok(json!({})),
+                            (_, Some(handler)) => handler.handle_request(&method, params).await,
+                            (_, None) => Err(JsonRpcError::new(-32601, "Method not found")),
+                        };
+                        if let Err(e) = &result {
+                            tracing::debug!(server = %name, method = %method, error = %e.message, "MCP: server request failed");
+                        }
+                        let (result, error) = match result {
+                            Ok(r) => (Some(r), None),
+                            Err(e) => (None, Some(e)),
+                        };
+                        let reply = JsonRpcResponse {
+                            jsonrpc: "2.0".into(),
+                            id,
+                            result,
+                            error,
+                        };
+                        if let Ok(reply) = serde_json::to_string(&reply) {
+                            let _ = transport.send(reply).await;
+                        }
+                    });
+                }
+                None if method == "notifications/progress" => {
+                    let params = msg.get("params").cloned().unwrap_or_default();
+                    let token = match params.get("progressToken") {
+                        Some(Value::String(s)) => s.clone(),
+                        Some(Value::Number(n)) => n.to_string(),
+                        _ => continue,
+                    };
+                    if let Some(sink) = progress.lock().await.get(&token) {
+                        let _ = sink.try_send(params);
+                    }
+                }
+                None => {
+                    tracing::debug!(server = %name, method = %method, "MCP notification");
+                    if let Ok(note) = serde_json::from_value::<JsonRpcNotification>(msg) {
+                        let _ = notifications.send(note);
+                    }
+                }
+            }
+            continue;
+        }
+
+        let Ok(resp) = serde_json::from_value::<JsonRpcResponse>(msg) else {
+            continue;
+        };
+        let id_str = match &resp.id {
+            Value::String(s) => s.clone(),
+            Value::Number(n) => n.to_string(),
+            _ => continue,
+        };
+
+        let chan = pending.lock().await.remove(&id_str);
+        if let Some(chan) = chan {
+            if let Some(err) = resp.error {
+                let _ = chan
+                    .send(Err(anyhow!("MCP Error ({}): {}", err.code, err.message)))
+                    .await;
+            } else {
+                let _ = chan.send(Ok(resp.result.unwrap_or(Value::Null))).await;
+            }
+        }
     }
+
+    // Connection gone: fail every outstanding call.
+    closed.send_replace(true);
+    pending.lock().await.clear();
+    progress.lock().await.clear();
+    tracing::debug!(server = %name, "MCP connection closed");
 }
 
 // ---------------------------------------------------------------------------
@@ -206,7 +488,7 @@ impl MCPClient {
 // ---------------------------------------------------------------------------
 
 pub struct MCPTool {
-    client: Arc<MCPClient>,
+    server: Arc<MCPServer>,
     mcp_name: String,
     full_name: String, // e.g. "sqlite_query"
     description: String,
@@ -214,21 +496,39 @@ pub struct MCPTool {
 }
 
 impl MCPTool {
-    pub fn new(
-        client: Arc<MCPClient>,
-        server_name: &str,
-        mcp_name: &str,
-        desc: &str,
-        params: Value,
-    ) -> Self {
+    pub fn new(server: Arc<MCPServer>, mcp_name: &str, desc: &str, params: Value) -> Self {
+        let full_name = format!("{}_{}", server.name(), mcp_name);
         Self {
-            client,
+            server,
             mcp_name: mcp_name.to_string(),
-            full_name: format!("{}_{}", server_name, mcp_name),
+            full_name,
             description: desc.to_string(),
             parameters: params,
         }
     }
+
+    fn to_result(result: Result<Value>) -> ToolResult {
+        match result {
+            Ok(result) => {
+                // MCP tool results are usually { content: [{ type: "text", text: "..." }] }
+                if result.get("content").and_then(|c| c.as_array()).is_some() {
+                    let combined = content_text(&result);
+                    let is_error = result
+                        .get("isError")
+                        .and_then(|e| e.as_bool())
+                        .unwrap_or(false);
+                    if is_error {
+                        ToolResult::error(combined)
+                    } else {
+                        ToolResult::success(combined)
+                    }
+                } else {
+                    ToolResult::success(serde_json::to_string_pretty(&result).unwrap_or_default())
+                }
+            }
+            Err(e) => ToolResult::error(format!("MCP execution failed: {}", e)),
+        }
+    }
 }
 
 #[async_trait]
@@ -250,96 +550,652 @@ impl Tool for MCPTool {
             "name": self.mcp_name,
             "arguments": args,
         });
+        Self::to_result(self.server.call("tools/call", params).await)
+    }
 
-        match self.client.call("tools/call", params).await {
-            Ok(result) => {
-                // MCP tool results are usually { content: [{ type: "text", text: "..." }] }
-                if let Some(content) = result.get("content").and_then(|c| c.as_array()) {
-                    let mut combined = String::new();
-                    for item in content {
-                        if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
-                            combined.push_str(text);
-                        }
-                    }
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        let Some(sink) = ctx.progress.clone() else {
+            return self.execute(args).await;
+        };
+        let params = json!({
+            "name": self.mcp_name,
+            "arguments": args,
+        });
 
-                    let is_error = result
-                        .get("isError")
-                        .and_then(|e| e.as_bool())
-                        .unwrap_or(false);
-                    if is_error {
-                        ToolResult::error(combined)
-                    } else {
-                        ToolResult::success(combined)
-                    }
-                } else {
-                    ToolResult::success(serde_json::to_string_pretty(&result).unwrap_or_default())
-                }
+        // Forward the server's progress notifications for this call
+        let (tx, mut rx) = mpsc::channel::<Value>(16);
+        let tool = self.full_name.clone();
+        let forward = tokio::spawn(async move {
+            while let Some(p) = rx.recv().await {
+                let update = ToolProgress {
+                    tool: tool.clone(),
+                    progress: p.get("progress").and_then(|v| v.as_f64()).unwrap_or(0.0),
+                    total: p.get("total").and_then(|v| v.as_f64()),
+                    message: p.get("message").and_then(|v| v.as_str()).map(String::from),
+                };
+                let _ = sink.send(update).await;
             }
+        });
+        let result = self
+            .server
+            .call_with_progress("tools/call", params, tx)
+            .await;
+        let _ = forward.await;
+        Self::to_result(result)
+    }
+}
+
+/// Concatenate the text parts of a `content` array.
+fn content_text(result: &Value) -> String {
+    let mut combined = String::new();
+    for item in result
+        .get("content")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
+            combined.push_str(text);
+        }
+    }
+    combined
+}
+
+// ---------------------------------------------------------------------------
+// Resources
+// ---------------------------------------------------------------------------
+
+/// Render a `resources/read` result: text contents verbatim, binary ones summarized.
+fn resource_text(result: &Value) -> String {
+    let mut parts = Vec::new();
+    for item in result
+        .get("contents")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
+            parts.push(text.to_string());
+        } else if let Some(blob) = item.get("blob").and_then(|b| b.as_str()) {
+            parts.push(format!(
+                "[binary resource {} ({}), {} bytes base64]",
+                item.get("uri").and_then(|u| u.as_str()).unwrap_or(""),
+                item.get("mimeType")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("application/octet-stream"),
+                blob.len()
+            ));
+        }
+    }
+    parts.join("\n")
+}
+
+/// `<server>_list_resources`: list the resources a server exposes.
+pub struct MCPListResourcesTool {
+    server: Arc<MCPServer>,
+    full_name: String,
+    description: String,
+}
+
+impl MCPListResourcesTool {
+    pub fn new(server: Arc<MCPServer>) -> Self {
+        Self {
+            full_name: format!("{}_list_resources", server.name()),
+            description: format!(
+                "List the resources (files, records, documents) offered by the '{}' MCP server",
+                server.name()
+            ),
+            server,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for MCPListResourcesTool {
+    fn name(&self) -> &str {
+        &self.full_name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Value {
+        json!({ "type": "object", "properties": {} })
+    }
+
+    async fn execute(&self, _args: HashMap<String, Value>) -> ToolResult {
+        let resources = match self.server.list("resources/list", "resources").await {
+            Ok(r) => r,
+            Err(e) => return ToolResult::error(format!("MCP execution failed: {}", e)),
+        };
+        if resources.is_empty() {
+            return ToolResult::success("No resources.");
+        }
+        let lines: Vec<String> = resources
+            .iter()
+            .map(|r| {
+                let field = |k: &str| r.get(k).and_then(|v| v.as_str()).unwrap_or("");
+                let mut line = format!("- {}", field("uri"));
+                if !field("name").is_empty() {
+                    line.push_str(&format!(" — {}", field("name")));
+                }
+                if !field("mimeType").is_empty() {
+                    line.push_str(&format!(" ({})", field("mimeType")));
+                }
+                if !field("description").is_empty() {
+                    line.push_str(&format!(": {}", field("description")));
+                }
+                line
+            })
+            .collect();
+        ToolResult::success(lines.join("\n"))
+    }
+}
+
+/// `<server>_read_resource`: read one resource by URI.
+pub struct MCPReadResourceTool {
+    server: Arc<MCPServer>,
+    full_name: String,
+    description: String,
+}
+
+impl MCPReadResourceTool {
+    pub fn new(server: Arc<MCPServer>) -> Self {
+        Self {
+            full_name: format!("{}_read_resource", server.name()),
+            description: format!(
+                "Read a resource from the '{}' MCP server by URI",
+                server.name()
+            ),
+            server,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for MCPReadResourceTool {
+    fn name(&self) -> &str {
+        &self.full_name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "uri": { "type": "string", "description": "Resource URI from the list" }
+            },
+            "required": ["uri"]
+        })
+    }
+
+    async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        let Some(uri) = args.get("uri").and_then(|v| v.as_str()) else {
+            return ToolResult::error("uri is required");
+        };
+        match self
+            .server
+            .call("resources/read", json!({ "uri": uri }))
+            .await
+        {
+            Ok(result) => ToolResult::success(resource_text(&result)),
             Err(e) => ToolResult::error(format!("MCP execution failed: {}", e)),
         }
     }
 }
 
+// ---------------------------------------------------------------------------
+// Prompts
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MCPPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A prompt template offered by a server, invoked as `/server:name`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MCPPrompt {
+    #[serde(skip)]
+    pub server: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<MCPPromptArgument>,
+}
+
+impl MCPPrompt {
+    /// Slash command name, without the leading `/`.
+    pub fn command(&self) -> String {
+        format!("{}:{}", self.server, self.name)
+    }
+
+    /// The tool name a profile must allow for this prompt to be used,
+    /// like `<server>_read_resource` for resource mentions.
+    pub fn permission(&self) -> String {
+        format!("{}_get_prompt", self.server)
+    }
+
+    /// `/server:name <required> [optional]`
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.command());
+        for arg in &self.arguments {
+            if arg.required {
+                usage.push_str(&format!(" <{}>", arg.name));
+            } else {
+                usage.push_str(&format!(" [{}]", arg.name));
+            }
+        }
+        usage
+    }
+
+    /// Fill arguments from a command line. `name=value` sets an argument by
+    /// name; remaining words fill the other arguments in order, the last one
+    /// taking the rest of the line. Double quotes group words.
+    pub fn parse_args(&self, line: &str) -> Result<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        let mut positional = Vec::new();
+        for word in split_words(line) {
+            match word.split_once('=') {
+                Some((k, v)) if self.arguments.iter().any(|a| a.name == k) => {
+                    values.insert(k.to_string(), v.to_string());
+                }
+                _ => positional.push(word),
+            }
+        }
+
+        let free: Vec<&MCPPromptArgument> = self
+            .arguments
+            .iter()
+            .filter(|a| !values.contains_key(&a.name))
+            .collect();
+        let mut words = positional.into_iter();
+        for (i, arg) in free.iter().enumerate() {
+            let value = if i + 1 == free.len() {
+                words.by_ref().collect::<Vec<_>>().join(" ")
+            } else {
+                words.next().unwrap_or_default()
+            };
+            if !value.is_empty() {
+                values.insert(arg.name.clone(), value);
+            }
+        }
+        if words.next().is_some() {
+            return Err(anyhow!("too many arguments. Usage: {}", self.usage()));
+        }
+        if let Some(missing) = self
+            .arguments
+            .iter()
+            .find(|a| a.required && !values.contains_key(&a.name))
+        {
+            return Err(anyhow!(
+                "missing argument '{}'. Usage: {}",
+                missing.name,
+                self.usage()
+            ));
+        }
+        Ok(values)
+    }
+}
+
+/// Split on whitespace, keeping "double quoted" runs together.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    words.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        words.push(current);
+    }
+    words
+}
+
+/// Flatten a `prompts/get` result into a single user message.
+fn prompt_text(result: &Value) -> String {
+    let mut parts = Vec::new();
+    for msg in result
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+    {
+        let Some(content) = msg.get("content") else {
+            continue;
+        };
+        if let Some(text) = content.get("text").and_then(|t| t.as_str()) {
+            parts.push(text.to_string());
+        } else if let Some(resource) = content.get("resource") {
+            parts.push(resource_text(&json!({ "contents": [resource] })));
+        }
+    }
+    parts.join("\n\n")
+}
+
+// ---------------------------------------------------------------------------
+// Server Registry
+// ---------------------------------------------------------------------------
+
+/// Configured servers, kept for `@server:uri` mentions and prompt commands.
+#[derive(Default)]
+pub struct MCPServers {
+    servers: RwLock<HashMap<String, Arc<MCPServer>>>,
+    sampling: Arc<sampling::Sampling>,
+}
+
+impl MCPServers {
+    /// Where the servers' sampling requests go.
+    pub fn sampling(&self) -> &Arc<sampling::Sampling> {
+        &self.sampling
+    }
+
+    pub async fn insert(&self, server: Arc<MCPServer>) {
+        self.servers
+            .write()
+            .await
+            .insert(server.name().to_string(), server);
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Arc<MCPServer>> {
+        self.servers.read().await.get(name).cloned()
+    }
+
+    /// All prompts, sorted by command name.
+    pub async fn prompts(&self) -> Vec<MCPPrompt> {
+        let servers: Vec<Arc<MCPServer>> = self.servers.read().await.values().cloned().collect();
+        let mut prompts = Vec::new();
+        for server in servers {
+            prompts.extend(server.prompts().await);
+        }
+        prompts.sort_by_key(|p| p.command());
+        prompts
+    }
+
+    /// Look up a prompt by `server:name`, or by bare name when unambiguous.
+    pub async fn find_prompt(&self, command: &str) -> Option<MCPPrompt> {
+        let prompts = self.prompts().await;
+        if let Some(p) = prompts.iter().find(|p| p.command() == command) {
+            return Some(p.clone());
+        }
+        let mut matches = prompts.into_iter().filter(|p| p.name == command);
+        match (matches.next(), matches.next()) {
+            (Some(p), None) => Some(p),
+            _ => None,
+        }
+    }
+
+    /// Expand a `/prompt args...` line into the prompt's text.
+    /// Returns `None` if the line is not a prompt command. Prompts are only
+    /// expanded if `tools` permits `<server>_get_prompt`.
+    pub async fn expand_command(
+        &self,
+        line: &str,
+        tools: &crate::tool::ToolRegistry,
+    ) -> Option<Result<String>> {
+        let line = line.trim().strip_prefix('/')?;
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let prompt = self.find_prompt(command).await?;
+        if !tools.is_permitted(&prompt.permission()) {
+            return Some(Err(anyhow!(
+                "/{} is not allowed in this session",
+                prompt.command()
+            )));
+        }
+        Some(self.get_prompt(&prompt, rest).await)
+    }
+
+    async fn get_prompt(&self, prompt: &MCPPrompt, line: &str) -> Result<String> {
+        let args = prompt.parse_args(line)?;
+        let server = self
+            .get(&prompt.server)
+            .await
+            .ok_or_else(|| anyhow!("MCP server '{}' is not configured", prompt.server))?;
+        let result = server
+            .call(
+                "prompts/get",
+                json!({ "name": prompt.name, "arguments": args }),
+            )
+            .await?;
+        Ok(prompt_text(&result))
+    }
+
+    /// Read a resource from a server that advertises resources.
+    pub async fn read_resource(&self, server: &str, uri: &str) -> Result<String> {
+        let server = match self.get(server).await {
+            Some(s) if s.has_resources().await => s,
+            _ => return Err(anyhow!("no MCP server '{}' with resources", server)),
+        };
+        let result = server.call("resources/read", json!({ "uri": uri })).await?;
+        Ok(resource_text(&result))
+    }
+
+    /// Append the content of each `@server:uri` mention to the message.
+    /// Mentions are only resolved if `tools` permits `<server>_read_resource`.
+    pub async fn inline_mentions(&self, text: &str, tools: &crate::tool::ToolRegistry) -> String {
+        let mut mentions: Vec<(String, String)> = Vec::new();
+        for word in text.split_whitespace() {
+            let Some((server, uri)) = word.strip_prefix('@').and_then(|w| w.split_once(':')) else {
+                continue;
+            };
+            let uri = uri.trim_end_matches(['.', ',', ';', '!', '?', ')', '"', '\'']);
+            let mention = (server.to_string(), uri.to_string());
+            if uri.is_empty()
+                || mentions.contains(&mention)
+                || !tools.is_permitted(&format!("{}_read_resource", server))
+            {
+                continue;
+            }
+            if let Some(s) = self.get(server).await {
+                if s.has_resources().await {
+                    mentions.push(mention);
+                }
+            }
+        }
+
+        let mut out = text.to_string();
+        for (server, uri) in mentions {
+            match self.read_resource(&server, &uri).await {
+                Ok(content) => {
+                    out.push_str(&format!("\n\n[Resource @{}:{}]\n{}", server, uri, content))
+                }
+                Err(e) => {
+                    tracing::warn!(server = %server, uri = %uri, error = %e, "Failed to read MCP resource");
+                    out.push_str(&format!(
+                        "\n\n[Resource @{}:{} unavailable: {}]",
+                        server, uri, e
+                    ));
+                }
+            }
+        }
+        out
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Initialization
 // ---------------------------------------------------------------------------
 
+/// Upper bound on pages fetched from a paginated list method.
+const MAX_LIST_PAGES: usize = 20;
+
+/// Fetch every page of a list method (`tools/list`, `resources/list`, ...).
+async fn list_all(client: &MCPClient, method: &str, key: &str) -> Result<Vec<Value>> {
+    let mut items = Vec::new();
+    let mut cursor: Option<String> = None;
+    for _ in 0..MAX_LIST_PAGES {
+        let params = match &cursor {
+            Some(c) => json!({ "cursor": c }),
+            None => json!({}),
+        };
+        let page = client.call(method, params).await?;
+        if let Some(list) = page.get(key).and_then(|v| v.as_array()) {
+            items.extend(list.iter().cloned());
+        }
+        cursor = page
+            .get("nextCursor")
+            .and_then(|c| c.as_str())
+            .map(String::from);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(items)
+}
+
+/// Start every configured server and supervise it in the background.
+/// A server that fails to start is logged and, if `restart` is set, retried.
 pub async fn init_mcp_servers(
     config: &crate::config::Config,
     registry: &crate::tool::ToolRegistry,
 ) -> Result<()> {
+    let servers = Arc::new(MCPServers::default());
     for (name, server_cfg) in &config.mcp.servers {
-        tracing::info!(server = %name, command = %server_cfg.command, "Spawning MCP server");
-        let client =
-            MCPClient::spawn(name, &server_cfg.command, &server_cfg.args, &server_cfg.env).await?;
-        let client_arc = Arc::new(client);
-
-        // 1. Initialize
-        let init_params = json!({
-            "protocolVersion": "2024-11-05",
-            "capabilities": {},
-            "clientInfo": { "name": "QuectoClaw", "version": crate::VERSION }
-        });
-
-        match client_arc.call("initialize", init_params).await {
-            Ok(_) => {
-                let _ = client_arc
-                    .notify("notifications/initialized", json!({}))
-                    .await;
-                tracing::info!(server = %name, "MCP server initialized");
-
-                // 2. List tools
-                match client_arc.call("tools/list", json!({})).await {
-                    Ok(tools_list) => {
-                        if let Some(tools) = tools_list.get("tools").and_then(|t| t.as_array()) {
-                            for tool_def in tools {
-                                let mcp_name =
-                                    tool_def.get("name").and_then(|n| n.as_str()).unwrap_or("");
-                                let desc = tool_def
-                                    .get("description")
-                                    .and_then(|d| d.as_str())
-                                    .unwrap_or("");
-                                let params = tool_def
-                                    .get("inputSchema")
-                                    .cloned()
-                                    .unwrap_or(json!({"type": "object"}));
-
-                                let adapter =
-                                    MCPTool::new(client_arc.clone(), name, mcp_name, desc, params);
-                                registry.register(Arc::new(adapter)).await;
-                                tracing::debug!(server = %name, tool = %mcp_name, "Registered MCP tool");
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!(server = %name, error = %e, "Failed to list MCP tools");
-                    }
-                }
-            }
+        tracing::info!(
+            server = %name,
+            transport = ?server_cfg.transport_kind(),
+            "Connecting to MCP server"
+        );
+        let server = MCPServer::new(
+            name,
+            server_cfg.clone(),
+            registry.clone(),
+            servers.sampling().clone(),
+        );
+        let notifications = match server.start().await {
+            Ok(rx) => Some(rx),
             Err(e) => {
-                tracing::error!(server = %name, error = %e, "Failed to initialize MCP server");
+                tracing::error!(server = %name, error = %e, "Failed to start MCP server");
+                None
             }
+        };
+        servers.insert(server.clone()).await;
+        if notifications.is_some() || server_cfg.restart {
+            tokio::spawn(server.supervise(notifications));
         }
     }
+    registry.set_mcp(servers).await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(args: &[(&str, bool)]) -> MCPPrompt {
+        MCPPrompt {
+            server: "git".into(),
+            name: "review".into(),
+            description: None,
+            arguments: args
+                .iter()
+                .map(|(name, required)| MCPPromptArgument {
+                    name: name.to_string(),
+                    description: None,
+                    required: *required,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_prompt_args_positional_and_named() {
+        let p = prompt(&[("branch", true), ("focus", false)]);
+        assert_eq!(p.usage(), "/git:review <branch> [focus]");
+
+        let args = p.parse_args("main security and error handling").unwrap();
+        assert_eq!(args["branch"], "main");
+        assert_eq!(args["focus"], "security and error handling");
+
+        let args = p.parse_args("focus=\"tests only\" dev").unwrap();
+        assert_eq!(args["branch"], "dev");
+        assert_eq!(args["focus"], "tests only");
+
+        let err = p.parse_args("").unwrap_err().to_string();
+        assert!(err.contains("missing argument 'branch'"), "{}", err);
+
+        assert!(prompt(&[]).parse_args("extra").is_err());
+        assert!(prompt(&[]).parse_args("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prompt_and_resource_text() {
+        let result = json!({
+            "messages": [
+                { "role": "user", "content": { "type": "text", "text": "Review this:" } },
+                { "role": "user", "content": { "type": "resource",
+                    "resource": { "uri": "file:///a.rs", "text": "fn main() {}" } } }
+            ]
+        });
+        assert_eq!(prompt_text(&result), "Review this:\n\nfn main() {}");
+
+        let blob =
+            json!({ "contents": [{ "uri": "x://img", "mimeType": "image/png", "blob": "AAAA" }] });
+        assert_eq!(
+            resource_text(&blob),
+            "[binary resource x://img (image/png), 4 bytes base64]"
+        );
+    }
+
+    /// A transport that keeps its own inbound sender, as the HTTP one once did.
+    struct Loopback(transport::Inbound);
+
+    #[async_trait]
+    impl MCPTransport for Loopback {
+        async fn send(&self, message: String) -> Result<()> {
+            Ok(self.0.send(message).await?)
+        }
+
+        fn kind(&self) -> &'static str {
+            "loopback"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_client_releases_its_transport() {
+        let (tx, rx) = mpsc::channel(1);
+        let transport = Arc::new(Loopback(tx));
+        let weak = Arc::downgrade(&transport);
+        let client = MCPClient::with_transport("loop", transport, rx);
+        let mut closed = client.closed.subscribe();
+        drop(client);
+
+        assert!(*closed.borrow_and_update());
+        for _ in 0..50 {
+            if weak.upgrade().is_none() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("dispatch task still holds the transport");
+    }
+}
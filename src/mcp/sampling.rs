@@ -0,0 +1,298 @@
+// QuectoClaw — MCP sampling (servers requesting completions from our LLM)
+
+use super::JsonRpcError;
+use crate::config::MCPSamplingPolicy;
+use crate::provider::Message;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Error code the spec uses when the user (or policy) rejects a request.
+const REJECTED: i32 = -1;
+
+/// A `sampling/createMessage` request, reduced to what our provider can run.
+#[derive(Debug, Clone)]
+pub struct SamplingRequest {
+    pub messages: Vec<Message>,
+    pub system_prompt: Option<String>,
+    pub max_tokens: usize,
+    pub temperature: Option<f64>,
+    pub stop_sequences: Vec<String>,
+}
+
+impl SamplingRequest {
+    pub fn from_params(params: &Value) -> Result<Self> {
+        let raw = params
+            .get("messages")
+            .and_then(|m| m.as_array())
+            .filter(|m| !m.is_empty())
+            .ok_or_else(|| anyhow!("messages is required"))?;
+
+        let mut messages = Vec::with_capacity(raw.len());
+        for msg in raw {
+            let text = content_text(msg.get("content").unwrap_or(&Value::Null));
+            match msg.get("role").and_then(|r| r.as_str()) {
+                Some("user") => messages.push(Message::user(text)),
+                Some("assistant") => messages.push(Message::assistant(text)),
+                other => return Err(anyhow!("unsupported message role {:?}", other)),
+            }
+        }
+
+        Ok(Self {
+            messages,
+            system_prompt: params
+                .get("systemPrompt")
+                .and_then(|s| s.as_str())
+                .filter(|s| !s.is_empty())
+                .map(String::from),
+            max_tokens: params
+                .get("maxTokens")
+                .and_then(|m| m.as_u64())
+                .map_or(usize::MAX, |m| m as usize),
+            temperature: params.get("temperature").and_then(|t| t.as_f64()),
+            stop_sequences: params
+                .get("stopSequences")
+                .and_then(|s| s.as_array())
+                .map(|s| {
+                    s.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Short description of the request for approval prompts.
+    pub fn preview(&self) -> String {
+        let last = self.messages.last().map_or("", |m| m.content.as_str());
+        let mut preview: String = last.chars().take(200).collect();
+        if preview.len() < last.len() {
+            preview.push('…');
+        }
+        preview
+    }
+}
+
+/// Text of a sampling message's content; non-text parts are noted, not sent.
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::Array(parts) => parts
+            .iter()
+            .map(content_text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(_) => match content.get("type").and_then(|t| t.as_str()) {
+            Some("text") => content
+                .get("text")
+                .and_then(|t| t.as_str())
+                .unwrap_or("")
+                .to_string(),
+            Some(kind) => format!("[{} content omitted]", kind),
+            None => String::new(),
+        },
+        Value::String(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+/// The completion returned to the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplingResult {
+    pub text: String,
+    pub model: String,
+    /// `endTurn`, `maxTokens` or `stopSequence`.
+    pub stop_reason: String,
+}
+
+impl SamplingResult {
+    pub fn to_value(&self) -> Value {
+        json!({
+            "role": "assistant",
+            "content": { "type": "text", "text": self.text },
+            "model": self.model,
+            "stopReason": self.stop_reason,
+        })
+    }
+}
+
+/// Runs sampling requests (implemented by the agent loop).
+#[async_trait]
+pub trait SamplingHandler: Send + Sync {
+    async fn create_message(
+        &self,
+        server: &str,
+        request: SamplingRequest,
+    ) -> Result<SamplingResult>;
+}
+
+/// Decides `ask`-policy requests, e.g. by prompting on the terminal.
+#[async_trait]
+pub trait SamplingApprover: Send + Sync {
+    async fn approve(&self, server: &str, request: &SamplingRequest) -> bool;
+}
+
+/// Where sampling requests go; shared by every configured server.
+#[derive(Default)]
+pub struct Sampling {
+    handler: RwLock<Option<Arc<dyn SamplingHandler>>>,
+    approver: RwLock<Option<Arc<dyn SamplingApprover>>>,
+}
+
+impl Sampling {
+    pub async fn set_handler(&self, handler: Arc<dyn SamplingHandler>) {
+        *self.handler.write().await = Some(handler);
+    }
+
+    pub async fn set_approver(&self, approver: Arc<dyn SamplingApprover>) {
+        *self.approver.write().await = Some(approver);
+    }
+
+    /// Answer a server's `sampling/createMessage` under its policy.
+    pub async fn create_message(
+        &self,
+        server: &str,
+        policy: MCPSamplingPolicy,
+        max_tokens: usize,
+        params: Value,
+    ) -> Result<Value, JsonRpcError> {
+        if policy == MCPSamplingPolicy::Deny {
+            return Err(JsonRpcError::new(
+                REJECTED,
+                format!("Sampling is not allowed for MCP server '{}'", server),
+            ));
+        }
+        let mut request = SamplingRequest::from_params(&params)
+            .map_err(|e| JsonRpcError::new(-32602, format!("Invalid sampling request: {}", e)))?;
+        request.max_tokens = request.max_tokens.min(max_tokens);
+
+        if policy == MCPSamplingPolicy::Ask {
+            let approver = self.approver.read().await.clone();
+            let approved = match approver {
+                Some(approver) => approver.approve(server, &request).await,
+                None => {
+                    tracing::warn!(server = %server, "MCP sampling needs approval but no one can be asked, rejecting");
+                    false
+                }
+            };
+            if !approved {
+                return Err(JsonRpcError::new(
+                    REJECTED,
+                    "User rejected sampling request",
+                ));
+            }
+        }
+
+        let handler = self
+            .handler
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| JsonRpcError::new(REJECTED, "Sampling is not available"))?;
+        tracing::info!(server = %server, max_tokens = request.max_tokens, "MCP sampling request");
+        handler
+            .create_message(server, request)
+            .await
+            .map(|result| result.to_value())
+            .map_err(|e| JsonRpcError::new(-32603, format!("Sampling failed: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    #[async_trait]
+    impl SamplingHandler for Echo {
+        async fn create_message(
+            &self,
+            _server: &str,
+            request: SamplingRequest,
+        ) -> Result<SamplingResult> {
+            Ok(SamplingResult {
+                text: format!("{} ({})", request.preview(), request.max_tokens),
+                model: "test".into(),
+                stop_reason: "endTurn".into(),
+            })
+        }
+    }
+
+    struct Never;
+
+    #[async_trait]
+    impl SamplingApprover for Never {
+        async fn approve(&self, _server: &str, _request: &SamplingRequest) -> bool {
+            false
+        }
+    }
+
+    fn params() -> Value {
+        json!({
+            "messages": [
+                { "role": "user", "content": { "type": "text", "text": "hi" } },
+                { "role": "assistant", "content": { "type": "image", "data": "…", "mimeType": "image/png" } },
+                { "role": "user", "content": [{ "type": "text", "text": "summarize" }] }
+            ],
+            "systemPrompt": "Be brief",
+            "maxTokens": 5000,
+            "stopSequences": ["END"]
+        })
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = SamplingRequest::from_params(&params()).unwrap();
+        assert_eq!(req.messages.len(), 3);
+        assert_eq!(req.messages[1].role, "assistant");
+        assert_eq!(req.messages[1].content, "[image content omitted]");
+        assert_eq!(req.system_prompt.as_deref(), Some("Be brief"));
+        assert_eq!(req.max_tokens, 5000);
+        assert_eq!(req.stop_sequences, vec!["END"]);
+        assert_eq!(req.preview(), "summarize");
+
+        assert!(SamplingRequest::from_params(&json!({ "messages": [] })).is_err());
+        assert!(SamplingRequest::from_params(
+            &json!({ "messages": [{ "role": "system", "content": "x" }] })
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_policies() {
+        let sampling = Sampling::default();
+        let deny = sampling
+            .create_message("s", MCPSamplingPolicy::Deny, 100, params())
+            .await
+            .unwrap_err();
+        assert_eq!(deny.code, REJECTED);
+
+        // Allowed, but nothing to run it yet
+        assert!(sampling
+            .create_message("s", MCPSamplingPolicy::Allow, 100, params())
+            .await
+            .is_err());
+
+        sampling.set_handler(Arc::new(Echo)).await;
+        let ok = sampling
+            .create_message("s", MCPSamplingPolicy::Allow, 100, params())
+            .await
+            .unwrap();
+        assert_eq!(ok["content"]["text"], "summarize (100)");
+        assert_eq!(ok["stopReason"], "endTurn");
+
+        // `ask` without an approver, then with one that says no
+        assert!(sampling
+            .create_message("s", MCPSamplingPolicy::Ask, 100, params())
+            .await
+            .is_err());
+        sampling.set_approver(Arc::new(Never)).await;
+        let rejected = sampling
+            .create_message("s", MCPSamplingPolicy::Ask, 100, params())
+            .await
+            .unwrap_err();
+        assert_eq!(rejected.message, "User rejected sampling request");
+    }
+}
@@ -0,0 +1,462 @@
+// QuectoClaw — MCP server mode (expose the ToolRegistry to other MCP clients)
+
+use super::{JsonRpcError, JsonRpcRequest, JsonRpcResponse};
+use crate::agent::{AgentLoop, RunOptions};
+use crate::tool::{ToolContext, ToolRegistry, ToolResult};
+use anyhow::Result;
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Protocol versions we can speak; the newest is offered by default.
+const SUPPORTED_VERSIONS: &[&str] = &["2025-03-26", "2024-11-05"];
+
+/// Name of the optional tool that runs the full agent.
+const CHAT_TOOL: &str = "chat";
+
+/// Serves a (profile-restricted) tool registry over MCP.
+pub struct MCPToolServer {
+    tools: ToolRegistry,
+    /// Session that tool calls and `chat` turns run in.
+    session_key: String,
+    /// Set to offer the `chat` tool.
+    agent: Option<Arc<AgentLoop>>,
+}
+
+impl MCPToolServer {
+    /// `tools` is the view to expose; apply the caller's profile beforehand.
+    pub fn new(tools: ToolRegistry, session_key: &str) -> Self {
+        Self {
+            tools,
+            session_key: session_key.to_string(),
+            agent: None,
+        }
+    }
+
+    /// Also offer a `chat` tool that runs a full agent turn with the same tools.
+    pub fn with_chat(mut self, agent: Arc<AgentLoop>) -> Self {
+        self.agent = Some(agent);
+        self
+    }
+
+    /// Handle one JSON-RPC message or batch. Returns `None` when nothing
+    /// needs to be sent back (notifications only).
+    pub async fn handle_message(&self, msg: Value) -> Option<Value> {
+        match msg {
+            Value::Array(batch) => {
+                let mut replies = Vec::new();
+                for item in batch {
+                    if let Some(reply) = self.handle_single(item).await {
+                        replies.push(reply);
+                    }
+                }
+                (!replies.is_empty()).then_some(Value::Array(replies))
+            }
+            single => self.handle_single(single).await,
+        }
+    }
+
+    async fn handle_single(&self, msg: Value) -> Option<Value> {
+        // Notifications (initialized, cancelled, ...) have no id and get no answer
+        let raw_id = msg.get("id")?.clone();
+        let reply = match serde_json::from_value::<JsonRpcRequest>(msg.clone()) {
+            Ok(req) => {
+                let id = req.id.clone();
+                match self.handle_request(req).await {
+                    Ok(result) => JsonRpcResponse {
+                        jsonrpc: "2.0".into(),
+                        id,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(error) => JsonRpcResponse {
+                        jsonrpc: "2.0".into(),
+                        id,
+                        result: None,
+                        error: Some(error),
+                    },
+                }
+            }
+            Err(e) => JsonRpcResponse {
+                jsonrpc: "2.0".into(),
+                id: raw_id,
+                result: None,
+                error: Some(JsonRpcError::new(-32600, format!("Invalid request: {}", e))),
+            },
+        };
+        serde_json::to_value(reply).ok()
+    }
+
+    async fn handle_request(&self, req: JsonRpcRequest) -> Result<Value, JsonRpcError> {
+        match req.method.as_str() {
+            "initialize" => {
+                let requested = req
+                    .params
+                    .get("protocolVersion")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+                let version = SUPPORTED_VERSIONS
+                    .iter()
+                    .find(|v| **v == requested)
+                    .unwrap_or(&SUPPORTED_VERSIONS[0]);
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": { "listChanged": false } },
+                    "serverInfo": { "name": "quectoclaw", "version": crate::VERSION }
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.list_tools().await })),
+            "tools/call" => {
+                let name = req
+                    .params
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .ok_or_else(|| JsonRpcError::new(-32602, "tools/call requires a tool name"))?;
+                let args: HashMap<String, Value> = match req.params.get("arguments") {
+                    None | Some(Value::Null) => HashMap::new(),
+                    Some(v) => serde_json::from_value(v.clone())
+                        .map_err(|_| JsonRpcError::new(-32602, "arguments must be an object"))?,
+                };
+                let result = self.call_tool(name, args).await;
+                Ok(json!({
+                    "content": [{ "type": "text", "text": result.for_llm }],
+                    "isError": result.is_error
+                }))
+            }
+            other => Err(JsonRpcError::new(
+                -32601,
+                format!("Method not found: {}", other),
+            )),
+        }
+    }
+
+    async fn list_tools(&self) -> Vec<Value> {
+        let mut defs = self.tools.get_definitions().await;
+        defs.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        let mut tools: Vec<Value> = defs
+            .into_iter()
+            .filter(|d| !(self.agent.is_some() && d.function.name == CHAT_TOOL))
+            .map(|d| {
+                json!({
+                    "name": d.function.name,
+                    "description": d.function.description,
+                    "inputSchema": d.function.parameters,
+                })
+            })
+            .collect();
+        if self.agent.is_some() {
+            tools.push(json!({
+                "name": CHAT_TOOL,
+                "description": "Ask the QuectoClaw agent. It plans and uses its tools to answer; \
+                                turns in the same session share conversation history.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "message": { "type": "string", "description": "What to ask or do" },
+                        "session": {
+                            "type": "string",
+                            "description": "Conversation to continue (default: the server's session)"
+                        }
+                    },
+                    "required": ["message"]
+                }
+            }));
+        }
+        tools
+    }
+
+    async fn call_tool(&self, name: &str, args: HashMap<String, Value>) -> ToolResult {
+        if let (CHAT_TOOL, Some(agent)) = (name, &self.agent) {
+            let Some(message) = args.get("message").and_then(|m| m.as_str()) else {
+                return ToolResult::error("message is required");
+            };
+            let session = match args.get("session").and_then(|s| s.as_str()) {
+                Some(s) if !s.is_empty() => format!("{}:{}", self.session_key, s),
+                _ => self.session_key.clone(),
+            };
+            // The agent sees the same (restricted) tools as MCP clients do
+            let opts = RunOptions {
+                tools: Some(self.tools.clone()),
+                ..Default::default()
+            };
+            return match agent.process_direct_with(message, &session, opts).await {
+                Ok(reply) => ToolResult::success(reply),
+                Err(e) => ToolResult::error(format!("Agent failed: {}", e)),
+            };
+        }
+
+        let ctx = ToolContext {
+            session_key: self.session_key.clone(),
+            tools: Some(self.tools.clone()),
+            ..Default::default()
+        };
+        self.tools.execute_with_context(name, args, &ctx).await
+    }
+
+    // -----------------------------------------------------------------------
+    // Transports
+    // -----------------------------------------------------------------------
+
+    /// Serve newline-delimited JSON-RPC on stdin/stdout until stdin closes.
+    pub async fn serve_stdio(self: Arc<Self>) -> Result<()> {
+        self.serve_io(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Serve newline-delimited JSON-RPC over any byte stream. Requests are
+    /// handled concurrently; replies are written as they complete.
+    pub async fn serve_io<R, W>(self: Arc<Self>, reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (out_tx, mut out_rx) = mpsc::channel::<String>(64);
+        let write_task = tokio::spawn(async move {
+            while let Some(line) = out_rx.recv().await {
+                writer.write_all(line.as_bytes()).await?;
+                writer.write_all(b"\n").await?;
+                writer.flush().await?;
+            }
+            Ok::<_, std::io::Error>(())
+        });
+
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let this = self.clone();
+            let out = out_tx.clone();
+            tokio::spawn(async move {
+                let reply = match serde_json::from_str::<Value>(&line) {
+                    Ok(msg) => this.handle_message(msg).await,
+                    Err(e) => Some(parse_error(e)),
+                };
+                if let Some(reply) = reply {
+                    let _ = out.send(reply.to_string()).await;
+                }
+            });
+        }
+
+        // Let in-flight calls finish writing their replies
+        drop(out_tx);
+        write_task.await??;
+        Ok(())
+    }
+
+    /// Streamable HTTP endpoint at `POST /mcp`, guarded by a bearer token
+    /// (empty = no auth). Replies are plain JSON; there is no server stream.
+    pub fn router(self: Arc<Self>, token: String) -> Router {
+        let token = Arc::new(token);
+        Router::new()
+            .route(
+                "/mcp",
+                axum::routing::post(http_post).get(|| async { StatusCode::METHOD_NOT_ALLOWED }),
+            )
+            .layer(middleware::from_fn(move |req, next| {
+                bearer_auth(token.clone(), req, next)
+            }))
+            .with_state(self)
+    }
+}
+
+/// Serve the HTTP endpoint on `addr`. Same binding and token rules as the
+/// web dashboard: no 0.0.0.0 without `gateway.allow_public_bind`, and the
+/// `gateway.dashboard_token` (or a generated one) is required.
+pub async fn serve_http(
+    server: Arc<MCPToolServer>,
+    addr: SocketAddr,
+    config: &crate::config::Config,
+) -> Result<()> {
+    if addr.ip().is_unspecified() && !config.gateway.allow_public_bind {
+        anyhow::bail!(
+            "Refusing to bind MCP server to 0.0.0.0 — set gateway.allow_public_bind = true in config to allow public binding"
+        );
+    }
+    let token = if config.gateway.dashboard_token.is_empty() {
+        let token = uuid::Uuid::new_v4().to_string();
+        tracing::info!(token = %token, "Generated MCP bearer token (use Authorization: Bearer <token>)");
+        token
+    } else {
+        config.gateway.dashboard_token.clone()
+    };
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!(url = %format!("http://{}/mcp", addr), "MCP server listening");
+    axum::serve(listener, server.router(token)).await?;
+    Ok(())
+}
+
+async fn http_post(State(server): State<Arc<MCPToolServer>>, body: String) -> Response {
+    let reply = match serde_json::from_str::<Value>(&body) {
+        Ok(msg) => server.handle_message(msg).await,
+        Err(e) => Some(parse_error(e)),
+    };
+    match reply {
+        Some(reply) => Json(reply).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+async fn bearer_auth(token: Arc<String>, request: Request, next: Next) -> Response {
+    if token.is_empty() {
+        return next.run(request).await;
+    }
+    let provided = request
+        .headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if provided == Some(token.as_str()) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            "Authorization: Bearer <token> required",
+        )
+            .into_response()
+    }
+}
+
+fn parse_error(e: serde_json::Error) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": -32700, "message": format!("Parse error: {}", e) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToolProfile;
+    use crate::tool::Tool;
+    use async_trait::async_trait;
+
+    struct Shout;
+
+    #[async_trait]
+    impl Tool for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+        fn description(&self) -> &str {
+            "Upper-case the text"
+        }
+        fn parameters(&self) -> Value {
+            json!({ "type": "object", "properties": { "text": { "type": "string" } } })
+        }
+        async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+            let text = args.get("text").and_then(|t| t.as_str()).unwrap_or("");
+            ToolResult::success(text.to_uppercase())
+        }
+    }
+
+    async fn server(profile: Option<ToolProfile>) -> MCPToolServer {
+        let registry = ToolRegistry::new();
+        registry.register(Arc::new(Shout)).await;
+        let tools = match profile {
+            Some(p) => registry.with_profile("ide", p),
+            None => registry,
+        };
+        MCPToolServer::new(tools, "mcp:test")
+    }
+
+    #[tokio::test]
+    async fn test_initialize_list_and_call() {
+        let s = server(None).await;
+        let init = s
+            .handle_message(json!({
+                "jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": { "protocolVersion": "2024-11-05" }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(init["result"]["protocolVersion"], "2024-11-05");
+        assert!(init.get("error").is_none());
+
+        assert!(s
+            .handle_message(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await
+            .is_none());
+
+        let list = s
+            .handle_message(json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }))
+            .await
+            .unwrap();
+        assert_eq!(list["result"]["tools"][0]["name"], "shout");
+        assert_eq!(list["result"]["tools"][0]["inputSchema"]["type"], "object");
+
+        let call = s
+            .handle_message(json!({
+                "jsonrpc": "2.0", "id": "c", "method": "tools/call",
+                "params": { "name": "shout", "arguments": { "text": "hi" } }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(call["id"], "c");
+        assert_eq!(call["result"]["content"][0]["text"], "HI");
+        assert_eq!(call["result"]["isError"], false);
+
+        let unknown = s
+            .handle_message(json!({ "jsonrpc": "2.0", "id": 3, "method": "resources/list" }))
+            .await
+            .unwrap();
+        assert_eq!(unknown["error"]["code"], -32601);
+    }
+
+    #[tokio::test]
+    async fn test_profile_hides_and_denies_tools() {
+        let s = server(Some(ToolProfile {
+            deny: vec!["shout".into()],
+            ..Default::default()
+        }))
+        .await;
+        let list = s
+            .handle_message(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+            .await
+            .unwrap();
+        assert_eq!(list["result"]["tools"], json!([]));
+
+        let call = s
+            .handle_message(json!({
+                "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+                "params": { "name": "shout", "arguments": { "text": "hi" } }
+            }))
+            .await
+            .unwrap();
+        assert_eq!(call["result"]["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn test_serve_io_batches_and_parse_errors() {
+        let s = Arc::new(server(None).await);
+        let (mut client_write, server_read) = tokio::io::duplex(4096);
+        let (server_write, client_read) = tokio::io::duplex(4096);
+        let task = tokio::spawn(s.serve_io(server_read, server_write));
+
+        client_write
+            .write_all(b"not json\n[{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"ping\"}]\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(client_read).lines();
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let line = lines.next_line().await.unwrap().unwrap();
+            replies.push(serde_json::from_str::<Value>(&line).unwrap());
+        }
+        assert!(replies.iter().any(|r| r["error"]["code"] == -32700));
+        assert!(replies.contains(&json!([{ "jsonrpc": "2.0", "id": 7, "result": {} }])));
+
+        drop(client_write);
+        task.await.unwrap().unwrap();
+    }
+}
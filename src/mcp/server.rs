@@ -0,0 +1,340 @@
+// QuectoClaw — Supervised MCP server (restart with backoff, dynamic tool lists)
+
+use super::sampling::Sampling;
+use super::{
+    list_all, JsonRpcError, JsonRpcNotification, MCPClient, MCPListResourcesTool, MCPPrompt,
+    MCPReadResourceTool, MCPRequestHandler, MCPTool,
+};
+use crate::config::{MCPSamplingPolicy, MCPServerConfig};
+use crate::tool::ToolRegistry;
+use crate::util::Backoff;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::{Arc, Weak};
+use tokio::sync::{broadcast, mpsc, RwLock};
+
+/// What the current connection registered.
+#[derive(Default)]
+struct Discovered {
+    tools: Vec<String>,
+    resources: bool,
+    prompts: Vec<MCPPrompt>,
+}
+
+/// A configured MCP server: the live connection plus the tools, resources
+/// and prompts it offers. Tools call through the server rather than a
+/// fixed client, so they keep working across restarts.
+pub struct MCPServer {
+    name: String,
+    config: MCPServerConfig,
+    registry: ToolRegistry,
+    client: RwLock<Option<Arc<MCPClient>>>,
+    discovered: RwLock<Discovered>,
+    sampling: Arc<Sampling>,
+}
+
+impl MCPServer {
+    pub fn new(
+        name: &str,
+        config: MCPServerConfig,
+        registry: ToolRegistry,
+        sampling: Arc<Sampling>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.to_string(),
+            config,
+            registry,
+            client: RwLock::new(None),
+            discovered: RwLock::new(Discovered::default()),
+            sampling,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        self.client
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|c| !c.is_closed())
+    }
+
+    async fn client(&self) -> Result<Arc<MCPClient>> {
+        match self.client.read().await.as_ref() {
+            Some(c) if !c.is_closed() => Ok(c.clone()),
+            _ => Err(anyhow!(
+                "MCP server '{}' is not connected (restarting)",
+                self.name
+            )),
+        }
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        self.client().await?.call(method, params).await
+    }
+
+    pub async fn call_with_progress(
+        &self,
+        method: &str,
+        params: Value,
+        progress: mpsc::Sender<Value>,
+    ) -> Result<Value> {
+        self.client()
+            .await?
+            .call_with_progress(method, params, progress)
+            .await
+    }
+
+    /// Fetch every page of a list method.
+    pub async fn list(&self, method: &str, key: &str) -> Result<Vec<Value>> {
+        list_all(&*self.client().await?, method, key).await
+    }
+
+    pub async fn has_resources(&self) -> bool {
+        self.discovered.read().await.resources
+    }
+
+    pub async fn prompts(&self) -> Vec<MCPPrompt> {
+        self.discovered.read().await.prompts.clone()
+    }
+
+    /// Connect, initialize, and register the server's tools, resources and
+    /// prompts. Returns the connection's notification stream.
+    pub async fn start(self: &Arc<Self>) -> Result<broadcast::Receiver<JsonRpcNotification>> {
+        let client = Arc::new(MCPClient::connect(&self.name, &self.config).await?);
+        let notifications = client.subscribe();
+        client.set_request_handler(Arc::new(ServerRequests(Arc::downgrade(self))));
+
+        let client_capabilities = if self.config.sampling == MCPSamplingPolicy::Deny {
+            json!({})
+        } else {
+            json!({ "sampling": {} })
+        };
+        let init_params = json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": client_capabilities,
+            "clientInfo": { "name": "QuectoClaw", "version": crate::VERSION }
+        });
+        let init = client
+            .call("initialize", init_params)
+            .await
+            .map_err(|e| anyhow!("initialize failed: {}", e))?;
+        let _ = client.notify("notifications/initialized", json!({})).await;
+        tracing::info!(server = %self.name, transport = client.transport_kind(), "MCP server initialized");
+        let capabilities = init.get("capabilities").cloned().unwrap_or_default();
+        *self.client.write().await = Some(client);
+
+        self.refresh_tools().await;
+
+        // Resources, as tools and `@server:uri` mentions
+        let resources = capabilities.get("resources").is_some();
+        let list_name = format!("{}_list_resources", self.name);
+        let read_name = format!("{}_read_resource", self.name);
+        if resources {
+            self.registry
+                .register(Arc::new(MCPListResourcesTool::new(self.clone())))
+                .await;
+            self.registry
+                .register(Arc::new(MCPReadResourceTool::new(self.clone())))
+                .await;
+        } else {
+            self.registry.unregister(&list_name).await;
+            self.registry.unregister(&read_name).await;
+        }
+        self.discovered.write().await.resources = resources;
+
+        // Prompts, as slash commands
+        if capabilities.get("prompts").is_some() {
+            self.refresh_prompts().await;
+        } else {
+            self.discovered.write().await.prompts.clear();
+        }
+
+        let discovered = self.discovered.read().await;
+        tracing::info!(
+            server = %self.name,
+            tools = discovered.tools.len(),
+            resources,
+            prompts = discovered.prompts.len(),
+            "MCP server ready"
+        );
+        Ok(notifications)
+    }
+
+    /// Re-list tools, registering new ones and dropping those that disappeared.
+    async fn refresh_tools(self: &Arc<Self>) {
+        let client = match self.client().await {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        let defs = match list_all(&client, "tools/list", "tools").await {
+            Ok(defs) => defs,
+            Err(e) => {
+                tracing::error!(server = %self.name, error = %e, "Failed to list MCP tools");
+                return;
+            }
+        };
+
+        let mut names = Vec::new();
+        for tool_def in defs {
+            let mcp_name = tool_def.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            let desc = tool_def
+                .get("description")
+                .and_then(|d| d.as_str())
+                .unwrap_or("");
+            let params = tool_def
+                .get("inputSchema")
+                .cloned()
+                .unwrap_or(json!({"type": "object"}));
+
+            let adapter = MCPTool::new(self.clone(), mcp_name, desc, params);
+            names.push(crate::tool::Tool::name(&adapter).to_string());
+            self.registry.register(Arc::new(adapter)).await;
+            tracing::debug!(server = %self.name, tool = %mcp_name, "Registered MCP tool");
+        }
+
+        let mut discovered = self.discovered.write().await;
+        for stale in discovered.tools.iter().filter(|n| !names.contains(n)) {
+            self.registry.unregister(stale).await;
+            tracing::debug!(server = %self.name, tool = %stale, "Removed MCP tool");
+        }
+        discovered.tools = names;
+    }
+
+    async fn refresh_prompts(&self) {
+        let client = match self.client().await {
+            Ok(c) => c,
+            Err(_) => return,
+        };
+        let mut prompts = Vec::new();
+        match list_all(&client, "prompts/list", "prompts").await {
+            Ok(list) => {
+                for def in list {
+                    match serde_json::from_value::<MCPPrompt>(def) {
+                        Ok(mut prompt) => {
+                            prompt.server = self.name.clone();
+                            prompts.push(prompt);
+                        }
+                        Err(e) => {
+                            tracing::warn!(server = %self.name, error = %e, "Invalid MCP prompt")
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!(server = %self.name, error = %e, "Failed to list MCP prompts");
+                return;
+            }
+        }
+        self.discovered.write().await.prompts = prompts;
+    }
+
+    async fn on_notification(self: &Arc<Self>, note: JsonRpcNotification) {
+        match note.method.as_str() {
+            "notifications/tools/list_changed" => {
+                tracing::info!(server = %self.name, "MCP tool list changed, refreshing");
+                self.refresh_tools().await;
+            }
+            "notifications/prompts/list_changed" => {
+                tracing::info!(server = %self.name, "MCP prompt list changed, refreshing");
+                self.refresh_prompts().await;
+            }
+            "notifications/message" => {
+                tracing::info!(server = %self.name, params = %note.params, "MCP server log");
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep the server running: handle its notifications, and restart it
+    /// with exponential backoff whenever the connection dies. `notifications`
+    /// is the stream of an already started connection, if any.
+    pub async fn supervise(
+        self: Arc<Self>,
+        mut notifications: Option<broadcast::Receiver<JsonRpcNotification>>,
+    ) {
+        let mut backoff = Backoff::default();
+        loop {
+            let mut rx = match notifications.take() {
+                Some(rx) => rx,
+                None => match self.start().await {
+                    Ok(rx) => rx,
+                    Err(e) => {
+                        if !self.config.restart {
+                            return;
+                        }
+                        tracing::warn!(
+                            server = %self.name,
+                            error = %e,
+                            retry_in_secs = backoff.delay().as_secs(),
+                            "MCP server start failed"
+                        );
+                        tokio::time::sleep(backoff.next_delay()).await;
+                        continue;
+                    }
+                },
+            };
+            // A server that exits right after initialize counts as a lost
+            // connection, so it is not respawned in a tight loop
+            if let Ok(client) = self.client().await {
+                backoff.attempt();
+                loop {
+                    tokio::select! {
+                        _ = client.closed() => break,
+                        note = rx.recv() => match note {
+                            Ok(note) => self.on_notification(note).await,
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => {
+                                client.closed().await;
+                                break;
+                            }
+                        },
+                    }
+                }
+            }
+
+            if !self.config.restart {
+                tracing::error!(server = %self.name, "MCP server connection lost (restart disabled)");
+                return;
+            }
+            tracing::warn!(
+                server = %self.name,
+                retry_in_secs = backoff.delay().as_secs(),
+                "MCP server connection lost, restarting"
+            );
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+}
+
+/// Requests from a server's connection. Holds the server weakly so an old
+/// connection does not keep it alive.
+struct ServerRequests(Weak<MCPServer>);
+
+#[async_trait]
+impl MCPRequestHandler for ServerRequests {
+    async fn handle_request(&self, method: &str, params: Value) -> Result<Value, JsonRpcError> {
+        let server = self
+            .0
+            .upgrade()
+            .ok_or_else(|| JsonRpcError::new(-32603, "MCP server is shutting down"))?;
+        match method {
+            "sampling/createMessage" => {
+                server
+                    .sampling
+                    .create_message(
+                        &server.name,
+                        server.config.sampling,
+                        server.config.sampling_max_tokens,
+                        params,
+                    )
+                    .await
+            }
+            _ => Err(JsonRpcError::new(-32601, "Method not found")),
+        }
+    }
+}
@@ -0,0 +1,486 @@
+// QuectoClaw — MCP transports (stdio, Streamable HTTP, legacy HTTP+SSE)
+// Spec: https://modelcontextprotocol.io/specification/2025-03-26/basic/transports
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+
+/// Messages received from the server are pushed here, one JSON-RPC message per item.
+/// Dropping every sender signals that the connection is gone.
+pub type Inbound = mpsc::Sender<String>;
+
+/// A bidirectional JSON-RPC message pipe to an MCP server.
+#[async_trait]
+pub trait MCPTransport: Send + Sync {
+    /// Send one serialized JSON-RPC message.
+    async fn send(&self, message: String) -> Result<()>;
+
+    /// Short label for logs.
+    fn kind(&self) -> &'static str;
+}
+
+// ---------------------------------------------------------------------------
+// stdio
+// ---------------------------------------------------------------------------
+
+/// Child process speaking newline-delimited JSON-RPC on stdin/stdout.
+pub struct StdioTransport {
+    tx: mpsc::Sender<String>,
+    _child: Mutex<Child>,
+}
+
+impl StdioTransport {
+    pub fn spawn(
+        name: &str,
+        command: &str,
+        args: &[String],
+        env: &HashMap<String, String>,
+        inbound: Inbound,
+    ) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open stdout"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open stderr"))?;
+
+        let (tx, mut rx) = mpsc::channel::<String>(32);
+
+        // Stdin writer task
+        tokio::spawn(async move {
+            let mut stdin = stdin;
+            while let Some(msg) = rx.recv().await {
+                if let Err(e) = stdin.write_all(format!("{}\n", msg).as_bytes()).await {
+                    tracing::error!("MCP stdin error: {}", e);
+                    break;
+                }
+                let _ = stdin.flush().await;
+            }
+        });
+
+        // Stdout reader task
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                if inbound.send(line).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Stderr logger task
+        let name_str = name.to_string();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                tracing::debug!("MCP [{}] stderr: {}", name_str, line);
+            }
+        });
+
+        Ok(Self {
+            tx,
+            _child: Mutex::new(child),
+        })
+    }
+}
+
+#[async_trait]
+impl MCPTransport for StdioTransport {
+    async fn send(&self, message: String) -> Result<()> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|e| anyhow!("Failed to send to MCP: {}", e))
+    }
+
+    fn kind(&self) -> &'static str {
+        "stdio"
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP helpers
+// ---------------------------------------------------------------------------
+
+/// Build request headers from config: extra headers plus optional bearer token.
+pub fn build_headers(headers: &HashMap<String, String>, bearer: &str) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (k, v) in headers {
+        map.insert(
+            HeaderName::from_bytes(k.as_bytes()).map_err(|e| anyhow!("bad header {}: {}", k, e))?,
+            HeaderValue::from_str(v).map_err(|e| anyhow!("bad header value for {}: {}", k, e))?,
+        );
+    }
+    if !bearer.is_empty() {
+        map.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", bearer))?,
+        );
+    }
+    Ok(map)
+}
+
+/// The server answered a message with an HTTP error status. Only that
+/// message failed; the connection is still usable.
+#[derive(Debug)]
+pub struct Rejected {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MCP HTTP {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+fn http_client() -> Result<reqwest::Client> {
+    // No overall timeout: SSE streams stay open. Requests are bounded by the client.
+    Ok(reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .build()?)
+}
+
+/// One server-sent event.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+/// Incremental `text/event-stream` parser.
+#[derive(Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feed raw bytes; returns the events completed by this chunk.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: std::mem::take(&mut self.event),
+                        data: self.data.join("\n"),
+                    });
+                    self.data.clear();
+                }
+                self.event.clear();
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+/// Read an SSE response to the end, forwarding `message` events in order.
+/// The first `endpoint` event (legacy SSE) is handed to `endpoint`.
+async fn pump_sse(
+    resp: reqwest::Response,
+    inbound: Inbound,
+    mut endpoint: Option<oneshot::Sender<String>>,
+) {
+    let mut parser = SseParser::default();
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let Ok(chunk) = chunk else { break };
+        for ev in parser.push(&chunk) {
+            match ev.event.as_str() {
+                "endpoint" => {
+                    if let Some(tx) = endpoint.take() {
+                        let _ = tx.send(ev.data);
+                    }
+                }
+                "" | "message" => forward_json(&ev.data, &inbound).await,
+                other => tracing::debug!(event = %other, "MCP SSE: ignoring event"),
+            }
+        }
+        if inbound.is_closed() {
+            return;
+        }
+    }
+}
+
+/// Forward a JSON body (single message or batch) to the inbound queue.
+async fn forward_json(body: &str, inbound: &Inbound) {
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(items)) => {
+            for item in items {
+                let _ = inbound.send(item.to_string()).await;
+            }
+        }
+        Ok(_) => {
+            let _ = inbound.send(body.to_string()).await;
+        }
+        Err(e) => tracing::warn!("MCP: ignoring non-JSON response body: {}", e),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Streamable HTTP
+// ---------------------------------------------------------------------------
+
+/// Streamable HTTP: every message is POSTed to a single endpoint, and the reply
+/// is either a JSON body or an SSE stream. A GET stream carries server-initiated
+/// messages when the server offers one.
+pub struct StreamableHttpTransport {
+    client: reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+    session_id: Arc<RwLock<Option<String>>>,
+    /// Held weakly: the owner of the connection keeps the queue open, and
+    /// only the SSE pumps spawned for it hold it strongly.
+    inbound: mpsc::WeakSender<String>,
+    listening: AtomicBool,
+}
+
+impl StreamableHttpTransport {
+    pub fn new(url: &str, headers: HeaderMap, inbound: &Inbound) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            url: url.to_string(),
+            headers,
+            session_id: Arc::new(RwLock::new(None)),
+            inbound: inbound.downgrade(),
+            listening: AtomicBool::new(false),
+        })
+    }
+
+    fn inbound(&self) -> Result<Inbound> {
+        self.inbound
+            .upgrade()
+            .ok_or_else(|| anyhow!("MCP connection closed"))
+    }
+
+    async fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let builder = builder.headers(self.headers.clone());
+        match self.session_id.read().await.as_deref() {
+            Some(id) => builder.header("Mcp-Session-Id", id),
+            None => builder,
+        }
+    }
+
+    /// Open the optional GET stream for server-initiated messages (once).
+    async fn listen(&self) {
+        let Ok(inbound) = self.inbound() else {
+            return;
+        };
+        if self.listening.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let req = self
+            .request(self.client.get(&self.url))
+            .await
+            .header(ACCEPT, "text/event-stream");
+        tokio::spawn(async move {
+            match req.send().await {
+                Ok(resp) if resp.status().is_success() => pump_sse(resp, inbound, None).await,
+                Ok(resp) => {
+                    tracing::debug!(status = %resp.status(), "MCP server offers no GET stream")
+                }
+                Err(e) => tracing::debug!("MCP GET stream failed: {}", e),
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl MCPTransport for StreamableHttpTransport {
+    async fn send(&self, message: String) -> Result<()> {
+        let inbound = self.inbound()?;
+        let had_session = self.session_id.read().await.is_some();
+        let resp = self
+            .request(self.client.post(&self.url))
+            .await
+            .header(ACCEPT, "application/json, text/event-stream")
+            .header(CONTENT_TYPE, "application/json")
+            .body(message)
+            .send()
+            .await?;
+
+        if let Some(id) = resp
+            .headers()
+            .get("mcp-session-id")
+            .and_then(|v| v.to_str().ok())
+        {
+            *self.session_id.write().await = Some(id.to_string());
+        }
+
+        let status = resp.status();
+        if status == reqwest::StatusCode::NOT_FOUND && had_session {
+            // The session has expired; the client must start a new one
+            return Err(anyhow!("MCP session expired (HTTP 404)"));
+        }
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(Rejected { status, body }.into());
+        }
+
+        let is_sse = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("text/event-stream"));
+        if is_sse {
+            tokio::spawn(pump_sse(resp, inbound, None));
+        } else if status != reqwest::StatusCode::ACCEPTED {
+            let body = resp.text().await?;
+            if !body.trim().is_empty() {
+                forward_json(&body, &inbound).await;
+            }
+        }
+
+        self.listen().await;
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "http"
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Legacy HTTP+SSE (protocol 2024-11-05)
+// ---------------------------------------------------------------------------
+
+/// Legacy SSE: a long-lived GET stream delivers an `endpoint` event, then
+/// `message` events; client messages are POSTed to that endpoint.
+pub struct SseTransport {
+    client: reqwest::Client,
+    endpoint: String,
+    headers: HeaderMap,
+}
+
+impl SseTransport {
+    pub async fn connect(url: &str, headers: HeaderMap, inbound: Inbound) -> Result<Self> {
+        let client = http_client()?;
+        let resp = client
+            .get(url)
+            .headers(headers.clone())
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("MCP SSE connect failed: HTTP {}", resp.status()));
+        }
+
+        let (endpoint_tx, endpoint_rx) = oneshot::channel::<String>();
+        tokio::spawn(async move {
+            pump_sse(resp, inbound, Some(endpoint_tx)).await;
+            tracing::debug!("MCP SSE stream closed");
+        });
+
+        let endpoint = tokio::time::timeout(std::time::Duration::from_secs(10), endpoint_rx)
+            .await
+            .map_err(|_| anyhow!("MCP SSE server sent no endpoint event"))?
+            .map_err(|_| anyhow!("MCP SSE stream closed before endpoint event"))?;
+        let endpoint = url::Url::parse(url)?.join(&endpoint)?.to_string();
+
+        Ok(Self {
+            client,
+            endpoint,
+            headers,
+        })
+    }
+}
+
+#[async_trait]
+impl MCPTransport for SseTransport {
+    async fn send(&self, message: String) -> Result<()> {
+        let resp = self
+            .client
+            .post(&self.endpoint)
+            .headers(self.headers.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(message)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(Rejected { status, body }.into());
+        }
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "sse"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut p = SseParser::default();
+        assert!(p.push(b"event: endpoint\r\nda").is_empty());
+        let events = p.push(b"ta: /messages?id=1\r\n\r\n: keep-alive\n\ndata: {\"a\":\n");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: "endpoint".into(),
+                data: "/messages?id=1".into()
+            }]
+        );
+        let events = p.push(b"data: 1}\n\n");
+        assert_eq!(events[0].event, "");
+        assert_eq!(events[0].data, "{\"a\":\n1}");
+    }
+
+    #[test]
+    fn test_build_headers() {
+        let mut extra = HashMap::new();
+        extra.insert("X-Team".to_string(), "infra".to_string());
+        let h = build_headers(&extra, "s3cret").unwrap();
+        assert_eq!(h.get("x-team").unwrap(), "infra");
+        assert_eq!(h.get(AUTHORIZATION).unwrap(), "Bearer s3cret");
+
+        extra.insert("bad header".to_string(), "x".to_string());
+        assert!(build_headers(&extra, "").is_err());
+    }
+}
@@ -41,6 +41,37 @@ struct MetricsInner {
     model_costs: HashMap<String, f64>,
     /// Total accumulated cost in USD.
     total_cost: f64,
+    /// Usage attributed to each session (subagent work is billed to its parent).
+    session_usage: HashMap<String, SessionUsage>,
+    /// Outbound delivery results per channel.
+    deliveries: HashMap<String, DeliveryStats>,
+}
+
+/// What became of one attempt to send a reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    Sent,
+    /// Failed; it stays in the outbox for another try.
+    Retried,
+    /// Failed for the last time; moved to the dead letters.
+    DeadLettered,
+}
+
+/// Outbound delivery counts of one channel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeliveryStats {
+    pub sent: u64,
+    pub retried: u64,
+    pub dead_lettered: u64,
+}
+
+/// Tokens and cost attributed to one session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionUsage {
+    pub llm_requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
 }
 
 /// Budget alert returned when cost exceeds threshold.
@@ -75,14 +106,14 @@ impl Metrics {
         *m.model_requests.entry(model.to_string()).or_insert(0) += 1;
     }
 
-    /// Record cost for an LLM call using pricing info.
+    /// Record cost for an LLM call using pricing info. Returns the call's cost.
     pub async fn record_cost(
         &self,
         model: &str,
         prompt_tokens: usize,
         completion_tokens: usize,
         pricing: &ModelPricing,
-    ) {
+    ) -> f64 {
         let prompt_cost = (prompt_tokens as f64 / 1000.0) * pricing.prompt_per_1k;
         let completion_cost = (completion_tokens as f64 / 1000.0) * pricing.completion_per_1k;
         let call_cost = prompt_cost + completion_cost;
@@ -90,6 +121,34 @@ impl Metrics {
         let mut m = self.inner.write().await;
         *m.model_costs.entry(model.to_string()).or_insert(0.0) += call_cost;
         m.total_cost += call_cost;
+        call_cost
+    }
+
+    /// Attribute an LLM call's tokens and cost to a session.
+    pub async fn record_session_usage(
+        &self,
+        session_key: &str,
+        prompt_tokens: usize,
+        completion_tokens: usize,
+        cost: f64,
+    ) {
+        let mut m = self.inner.write().await;
+        let usage = m.session_usage.entry(session_key.to_string()).or_default();
+        usage.llm_requests += 1;
+        usage.prompt_tokens += prompt_tokens as u64;
+        usage.completion_tokens += completion_tokens as u64;
+        usage.cost += cost;
+    }
+
+    /// Usage attributed to a session so far.
+    pub async fn session_usage(&self, session_key: &str) -> SessionUsage {
+        self.inner
+            .read()
+            .await
+            .session_usage
+            .get(session_key)
+            .cloned()
+            .unwrap_or_default()
     }
 
     /// Check if budget threshold is exceeded.
@@ -131,6 +190,17 @@ impl Metrics {
         *m.channel_messages.entry(channel.to_string()).or_insert(0) += 1;
     }
 
+    /// Record the outcome of an attempt to send a reply.
+    pub async fn record_delivery(&self, channel: &str, outcome: DeliveryOutcome) {
+        let mut m = self.inner.write().await;
+        let stats = m.deliveries.entry(channel.to_string()).or_default();
+        match outcome {
+            DeliveryOutcome::Sent => stats.sent += 1,
+            DeliveryOutcome::Retried => stats.retried += 1,
+            DeliveryOutcome::DeadLettered => stats.dead_lettered += 1,
+        }
+    }
+
     /// Get uptime.
     pub fn uptime(&self) -> Duration {
         self.start_time.elapsed()
@@ -143,11 +213,7 @@ impl Metrics {
 
         let total_tool_calls: u64 = m.tool_calls.values().sum();
         let total_tool_errors: u64 = m.tool_errors.values().sum();
-        let avg_llm_ms = if m.llm_requests > 0 {
-            m.llm_total_ms / m.llm_requests
-        } else {
-            0
-        };
+        let avg_llm_ms = m.llm_total_ms.checked_div(m.llm_requests).unwrap_or(0);
 
         let mut tool_stats: Vec<ToolStat> = m
             .tool_calls
@@ -155,7 +221,7 @@ impl Metrics {
             .map(|(name, &count)| {
                 let errors = m.tool_errors.get(name).copied().unwrap_or(0);
                 let total_ms = m.tool_duration_ms.get(name).copied().unwrap_or(0);
-                let avg_ms = if count > 0 { total_ms / count } else { 0 };
+                let avg_ms = total_ms.checked_div(count).unwrap_or(0);
                 ToolStat {
                     name: name.clone(),
                     calls: count,
@@ -164,7 +230,7 @@ impl Metrics {
                 }
             })
             .collect();
-        tool_stats.sort_by(|a, b| b.calls.cmp(&a.calls));
+        tool_stats.sort_by_key(|s| std::cmp::Reverse(s.calls));
 
         MetricsReport {
             uptime_secs: uptime.as_secs(),
@@ -180,6 +246,7 @@ impl Metrics {
             channel_messages: m.channel_messages.clone(),
             total_cost: m.total_cost,
             model_costs: m.model_costs.clone(),
+            deliveries: m.deliveries.clone(),
         }
     }
 
@@ -244,6 +311,16 @@ impl Metrics {
             }
         }
 
+        if !r.deliveries.is_empty() {
+            out.push_str("\n─── Delivery ───\n");
+            for (ch, d) in &r.deliveries {
+                out.push_str(&format!(
+                    "  {:<20} {:>4} sent  {:>3} retried  {:>3} dead\n",
+                    ch, d.sent, d.retried, d.dead_lettered
+                ));
+            }
+        }
+
         out
     }
 
@@ -298,6 +375,7 @@ pub struct MetricsReport {
     pub channel_messages: HashMap<String, u64>,
     pub total_cost: f64,
     pub model_costs: HashMap<String, f64>,
+    pub deliveries: HashMap<String, DeliveryStats>,
 }
 
 /// Per-tool statistics.
@@ -330,6 +408,12 @@ mod tests {
             .record_tool_call("exec", false, Duration::from_millis(5000))
             .await;
         metrics.record_channel_message("telegram").await;
+        metrics
+            .record_delivery("telegram", DeliveryOutcome::Retried)
+            .await;
+        metrics
+            .record_delivery("telegram", DeliveryOutcome::Sent)
+            .await;
 
         let report = metrics.report().await;
         assert_eq!(report.llm_requests, 2);
@@ -339,6 +423,14 @@ mod tests {
         assert_eq!(report.avg_llm_ms, 400);
         assert_eq!(report.total_tool_calls, 2);
         assert_eq!(report.total_tool_errors, 1);
+        assert_eq!(
+            report.deliveries["telegram"],
+            DeliveryStats {
+                sent: 1,
+                retried: 1,
+                dead_lettered: 0
+            }
+        );
     }
 
     #[tokio::test]
@@ -374,6 +466,23 @@ mod tests {
         assert!(report.model_costs.contains_key("gpt-4"));
     }
 
+    #[tokio::test]
+    async fn test_session_usage() {
+        let metrics = Metrics::new();
+        metrics.record_session_usage("cli:a", 100, 20, 0.5).await;
+        metrics.record_session_usage("cli:a", 50, 10, 0.25).await;
+
+        let usage = metrics.session_usage("cli:a").await;
+        assert_eq!(usage.llm_requests, 2);
+        assert_eq!(usage.prompt_tokens, 150);
+        assert_eq!(usage.completion_tokens, 30);
+        assert!((usage.cost - 0.75).abs() < 1e-9);
+        assert_eq!(
+            metrics.session_usage("cli:b").await,
+            SessionUsage::default()
+        );
+    }
+
     #[tokio::test]
     async fn test_budget_alert() {
         let metrics = Metrics::new();
@@ -170,6 +170,8 @@ pub enum StreamEvent {
         name: Option<String>,
         arguments: String,
     },
+    /// Progress reported by a running tool call.
+    ToolProgress(crate::tool::ToolProgress),
     /// Stream is done, final response.
     Done(LLMResponse),
     /// An error occurred.
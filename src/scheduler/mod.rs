@@ -0,0 +1,417 @@
+// QuectoClaw — Task scheduler (cron and one-shot jobs persisted in the workspace)
+
+use crate::agent::AgentLoop;
+use crate::bus::{MessageBus, OutboundKind, OutboundMessage};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+// ---------------------------------------------------------------------------
+// Jobs
+// ---------------------------------------------------------------------------
+
+/// When a job fires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobSchedule {
+    /// Cron expression evaluated in local time. Five fields
+    /// (`min hour dom mon dow`) or six with leading seconds.
+    Cron { expr: String },
+    /// A single run at a fixed time.
+    At { at: DateTime<Utc> },
+}
+
+impl JobSchedule {
+    /// First fire time strictly after `after`, or `None` if the schedule is exhausted.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            JobSchedule::Cron { expr } => parse_cron(expr)
+                .ok()?
+                .after(&after.with_timezone(&Local))
+                .next()
+                .map(|t| t.with_timezone(&Utc)),
+            JobSchedule::At { at } => (*at > after).then_some(*at),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            JobSchedule::Cron { expr } => format!("cron `{}`", expr),
+            JobSchedule::At { at } => format!(
+                "once at {}",
+                at.with_timezone(&Local).format("%Y-%m-%d %H:%M %Z")
+            ),
+        }
+    }
+}
+
+/// Parse a cron expression, accepting the common five-field form.
+pub fn parse_cron(expr: &str) -> anyhow::Result<cron::Schedule> {
+    let expr = expr.trim();
+    let full = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    cron::Schedule::from_str(&full).map_err(|e| anyhow::anyhow!("invalid cron '{}': {}", expr, e))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    /// Prompt run by the agent when the job fires.
+    pub task: String,
+    pub schedule: JobSchedule,
+    /// Session that created the job; the job runs in it.
+    pub session_key: String,
+    /// Where results are delivered.
+    pub channel: String,
+    pub chat_id: String,
+    pub created_at: DateTime<Utc>,
+    pub next_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+impl Job {
+    /// Create a job owned by `session_key` (`channel:chat_id`).
+    pub fn new(task: &str, schedule: JobSchedule, session_key: &str) -> anyhow::Result<Self> {
+        if let JobSchedule::Cron { expr } = &schedule {
+            parse_cron(expr)?;
+        }
+        let now = Utc::now();
+        let next_run = schedule
+            .next_after(now)
+            .ok_or_else(|| anyhow::anyhow!("schedule never fires (time is in the past?)"))?;
+        let (channel, chat_id) = session_key.split_once(':').unwrap_or(("cli", session_key));
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string()[..8].to_string(),
+            task: task.to_string(),
+            schedule,
+            session_key: session_key.to_string(),
+            channel: channel.to_string(),
+            chat_id: chat_id.to_string(),
+            created_at: now,
+            next_run: Some(next_run),
+            last_run: None,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Persistence
+// ---------------------------------------------------------------------------
+
+/// Job list stored as JSON in `<workspace>/schedule/jobs.json`.
+///
+/// Every operation re-reads the file, so jobs added by another process
+/// (e.g. `quectoclaw agent`) are picked up by a running gateway.
+pub struct JobStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JobStore {
+    pub fn new(workspace: &Path) -> Self {
+        Self {
+            path: workspace.join("schedule").join("jobs.json"),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// The process-wide store for a workspace, so the tools and the
+    /// gateway's runner serialize on the same lock.
+    pub fn shared(workspace: &Path) -> Arc<Self> {
+        static STORES: OnceLock<std::sync::Mutex<HashMap<PathBuf, Arc<JobStore>>>> =
+            OnceLock::new();
+        let mut stores = STORES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        stores
+            .entry(workspace.to_path_buf())
+            .or_insert_with(|| Arc::new(Self::new(workspace)))
+            .clone()
+    }
+
+    /// Read the jobs. A file that does not parse is renamed aside, so the
+    /// next save cannot overwrite the jobs it still holds.
+    async fn load(&self) -> Vec<Job> {
+        let Ok(s) = tokio::fs::read_to_string(&self.path).await else {
+            return Vec::new();
+        };
+        match serde_json::from_str(&s) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                let aside = self.path.with_extension(format!(
+                    "json.corrupt-{}",
+                    Utc::now().format("%Y%m%dT%H%M%S")
+                ));
+                match tokio::fs::rename(&self.path, &aside).await {
+                    Ok(()) => tracing::error!(
+                        "Failed to parse {}: {} (moved to {})",
+                        self.path.display(),
+                        e,
+                        aside.display()
+                    ),
+                    Err(re) => tracing::error!(
+                        "Failed to parse {}: {} (could not move it aside: {})",
+                        self.path.display(),
+                        e,
+                        re
+                    ),
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    async fn save(&self, jobs: &[Job]) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, serde_json::to_string_pretty(jobs)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+
+    /// All jobs, or only those owned by `session_key`.
+    pub async fn list(&self, session_key: Option<&str>) -> Vec<Job> {
+        let _guard = self.lock.lock().await;
+        self.load()
+            .await
+            .into_iter()
+            .filter(|j| session_key.is_none_or(|s| j.session_key == s))
+            .collect()
+    }
+
+    pub async fn add(&self, job: Job) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut jobs = self.load().await;
+        jobs.push(job);
+        self.save(&jobs).await
+    }
+
+    /// Remove a job. With `session_key` set, only that session's jobs can be removed.
+    pub async fn cancel(&self, id: &str, session_key: Option<&str>) -> anyhow::Result<bool> {
+        let _guard = self.lock.lock().await;
+        let mut jobs = self.load().await;
+        let before = jobs.len();
+        jobs.retain(|j| !(j.id == id && session_key.is_none_or(|s| j.session_key == s)));
+        if jobs.len() == before {
+            return Ok(false);
+        }
+        self.save(&jobs).await?;
+        Ok(true)
+    }
+
+    /// Return jobs due at `now`, advancing recurring jobs and dropping finished ones.
+    pub async fn take_due(&self, now: DateTime<Utc>) -> anyhow::Result<Vec<Job>> {
+        let _guard = self.lock.lock().await;
+        let mut jobs = self.load().await;
+        let mut due = Vec::new();
+        for job in jobs.iter_mut() {
+            if job.next_run.is_some_and(|t| t <= now) {
+                due.push(job.clone());
+                job.last_run = Some(now);
+                job.next_run = job.schedule.next_after(now);
+            }
+        }
+        if due.is_empty() {
+            return Ok(due);
+        }
+        jobs.retain(|j| j.next_run.is_some());
+        self.save(&jobs).await?;
+        Ok(due)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Runner
+// ---------------------------------------------------------------------------
+
+/// Fires due jobs through the agent and delivers results to their channel.
+pub struct Scheduler {
+    store: Arc<JobStore>,
+    agent: Arc<AgentLoop>,
+    bus: Arc<MessageBus>,
+}
+
+impl Scheduler {
+    pub fn new(store: Arc<JobStore>, agent: Arc<AgentLoop>, bus: Arc<MessageBus>) -> Self {
+        Self { store, agent, bus }
+    }
+
+    /// Check for due jobs every `tick` forever.
+    pub async fn run(self, tick: Duration) {
+        tracing::info!(tick_secs = tick.as_secs(), "Scheduler started");
+        let this = Arc::new(self);
+        let mut ticker = tokio::time::interval(tick);
+        loop {
+            ticker.tick().await;
+            let due = match this.store.take_due(Utc::now()).await {
+                Ok(due) => due,
+                Err(e) => {
+                    tracing::error!("Scheduler failed to update jobs: {}", e);
+                    continue;
+                }
+            };
+            for job in due {
+                let this = this.clone();
+                tokio::spawn(async move { this.run_job(job).await });
+            }
+        }
+    }
+
+    /// Run one job in its owning session, after any turn already running
+    /// there, and publish the result.
+    pub async fn run_job(&self, job: Job) {
+        tracing::info!(job = %job.id, session = %job.session_key, "Running scheduled task");
+        let prompt = format!(
+            "[Scheduled task {} — {}]\n{}",
+            job.id,
+            job.schedule.describe(),
+            job.task
+        );
+        let content = match self.agent.process_turn(&prompt, &job.session_key).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::error!(job = %job.id, "Scheduled task failed: {}", e);
+                format!("⚠️ Scheduled task {} failed: {}", job.id, e)
+            }
+        };
+
+        let mut metadata = HashMap::new();
+        metadata.insert("job_id".to_string(), job.id.clone());
+        self.bus
+            .publish_outbound(OutboundMessage {
+                channel: job.channel,
+                chat_id: job.chat_id,
+                content,
+                metadata,
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_cron_five_and_six_fields() {
+        assert!(parse_cron("0 9 * * *").is_ok());
+        assert!(parse_cron("30 0 9 * * *").is_ok());
+        assert!(parse_cron("not a cron").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let at = Utc.with_ymd_and_hms(2030, 1, 1, 9, 0, 0).unwrap();
+        let once = JobSchedule::At { at };
+        assert_eq!(once.next_after(at - chrono::Duration::hours(1)), Some(at));
+        assert_eq!(once.next_after(at), None);
+
+        let every_minute = JobSchedule::Cron {
+            expr: "* * * * *".into(),
+        };
+        let next = every_minute.next_after(at).unwrap();
+        assert_eq!(next, at + chrono::Duration::minutes(1));
+    }
+
+    #[test]
+    fn test_job_origin_from_session_key() {
+        let at = Utc::now() + chrono::Duration::hours(1);
+        let job = Job::new("ping", JobSchedule::At { at }, "telegram:42").unwrap();
+        assert_eq!(job.channel, "telegram");
+        assert_eq!(job.chat_id, "42");
+
+        let past = Utc::now() - chrono::Duration::hours(1);
+        assert!(Job::new("ping", JobSchedule::At { at: past }, "cli:x").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_store_take_due_advances_and_drops() {
+        let tmp = TempDir::new().unwrap();
+        let store = JobStore::new(tmp.path());
+        let now = Utc::now();
+
+        let once = Job::new(
+            "once",
+            JobSchedule::At {
+                at: now + chrono::Duration::minutes(5),
+            },
+            "slack:C1",
+        )
+        .unwrap();
+        let cron = Job::new(
+            "daily",
+            JobSchedule::Cron {
+                expr: "0 9 * * *".into(),
+            },
+            "slack:C1",
+        )
+        .unwrap();
+        store.add(once.clone()).await.unwrap();
+        store.add(cron.clone()).await.unwrap();
+
+        assert!(store.take_due(now).await.unwrap().is_empty());
+
+        let later = now + chrono::Duration::days(2);
+        let due = store.take_due(later).await.unwrap();
+        assert_eq!(due.len(), 2);
+
+        // The one-shot job is gone; the cron job is rescheduled
+        let remaining = store.list(None).await;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, cron.id);
+        assert!(remaining[0].next_run.unwrap() > later);
+
+        assert!(!store.cancel(&cron.id, Some("discord:1")).await.unwrap());
+        assert!(store.cancel(&cron.id, Some("slack:C1")).await.unwrap());
+        assert!(store.list(None).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_store_is_moved_aside() {
+        let tmp = TempDir::new().unwrap();
+        let store = JobStore::new(tmp.path());
+        let dir = tmp.path().join("schedule");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("jobs.json"), "[{\"id\": ").unwrap();
+
+        assert!(store.list(None).await.is_empty());
+        let job = Job::new(
+            "new",
+            JobSchedule::At {
+                at: Utc::now() + chrono::Duration::hours(1),
+            },
+            "slack:C1",
+        )
+        .unwrap();
+        store.add(job).await.unwrap();
+
+        // The unreadable jobs are kept next to the new file
+        let aside: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|n| n.starts_with("jobs.json.corrupt-"))
+            .collect();
+        assert_eq!(aside.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.join(&aside[0])).unwrap(),
+            "[{\"id\": "
+        );
+        assert_eq!(store.list(None).await.len(), 1);
+    }
+}
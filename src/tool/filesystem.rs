@@ -10,7 +10,11 @@ use std::path::{Path, PathBuf};
 // Path validation
 // ---------------------------------------------------------------------------
 
-fn validate_path(path: &str, workspace: &str, restrict: bool) -> Result<PathBuf, String> {
+pub(super) fn validate_path(
+    path: &str,
+    workspace: &str,
+    restrict: bool,
+) -> Result<PathBuf, String> {
     // Block null byte injection
     if path.contains('\0') || workspace.contains('\0') {
         return Err("access denied: null byte in path".into());
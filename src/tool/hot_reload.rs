@@ -0,0 +1,406 @@
+// QuectoClaw — Plugin hot-reload (polls plugin directories and updates the registry)
+
+use super::plugin::{load_plugin_file, plugin_files, plugin_tool};
+use super::{Tool, ToolRegistry};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Cheap change detection for a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// A plugin's manifest and, for WASM plugins, the module it points at, which
+/// the marketplace can rewrite without touching the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    manifest: FileStamp,
+    module: Option<FileStamp>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PluginKind {
+    Json,
+    #[cfg(feature = "wasm")]
+    Wasm,
+}
+
+/// Tools added, updated or removed by a single scan.
+#[derive(Debug, Default)]
+pub struct ReloadReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ReloadReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Watches the plugin directories and keeps the registry in sync with them.
+///
+/// Only tools that came from a plugin file are ever registered over or
+/// unregistered, so built-in and MCP tools are left alone: a plugin whose name
+/// is already taken by one of them is ignored. A manifest that fails to parse
+/// keeps the previously loaded version of its tool.
+pub struct PluginWatcher {
+    registry: ToolRegistry,
+    plugins_dir: PathBuf,
+    #[cfg(feature = "wasm")]
+    wasm_dir: Option<PathBuf>,
+    /// Manifest path -> (last fingerprint, tool name it registered).
+    known: HashMap<PathBuf, (Fingerprint, Option<String>)>,
+}
+
+impl PluginWatcher {
+    pub fn new(registry: ToolRegistry, plugins_dir: PathBuf) -> Self {
+        Self {
+            registry,
+            plugins_dir,
+            #[cfg(feature = "wasm")]
+            wasm_dir: None,
+            known: HashMap::new(),
+        }
+    }
+
+    /// Also watch WASM plugins (`<dir>/<name>/manifest.json`).
+    #[cfg(feature = "wasm")]
+    pub fn with_wasm_dir(mut self, dir: PathBuf) -> Self {
+        self.wasm_dir = Some(dir);
+        self
+    }
+
+    /// Compare the plugin directories against the last scan and apply changes.
+    pub async fn scan(&mut self) -> ReloadReport {
+        self.apply(false).await
+    }
+
+    /// First scan: the startup registry already holds these plugins, so the
+    /// tools it registered from plugin files are taken over rather than
+    /// treated as collisions. Any other tool still wins.
+    async fn adopt(&mut self) -> ReloadReport {
+        self.apply(true).await
+    }
+
+    async fn apply(&mut self, adopting: bool) -> ReloadReport {
+        let mut report = ReloadReport::default();
+
+        let mut current: HashMap<PathBuf, (Fingerprint, PluginKind)> = HashMap::new();
+        for path in plugin_files(&self.plugins_dir).await {
+            if let Some(fp) = fingerprint(&path).await {
+                current.insert(path, (fp, PluginKind::Json));
+            }
+        }
+        #[cfg(feature = "wasm")]
+        if let Some(dir) = &self.wasm_dir {
+            for path in wasm_manifests(dir).await {
+                if let Some(fp) = wasm_fingerprint(&path).await {
+                    current.insert(path, (fp, PluginKind::Wasm));
+                }
+            }
+        }
+
+        // 1. Files that disappeared
+        let gone: Vec<PathBuf> = self
+            .known
+            .keys()
+            .filter(|p| !current.contains_key(*p))
+            .cloned()
+            .collect();
+        for path in gone {
+            if let Some((_, Some(name))) = self.known.remove(&path) {
+                self.registry.unregister(&name).await;
+                report.removed.push(name);
+            }
+        }
+
+        // 2. New or modified files
+        for (path, (fp, kind)) in current {
+            let previous = match self.known.get(&path) {
+                Some((old, _)) if *old == fp => continue,
+                Some((_, name)) => name.clone(),
+                None => None,
+            };
+
+            match load(&path, kind).await {
+                Ok(tool) => {
+                    let name = tool.name().to_string();
+                    let owned = self
+                        .known
+                        .values()
+                        .any(|(_, known)| known.as_deref() == Some(name.as_str()));
+                    let adopted = adopting && self.registry.is_plugin(&name).await;
+                    if !owned && !adopted && self.registry.contains(&name).await {
+                        tracing::warn!(
+                            "Ignoring plugin {}: tool '{}' is already registered",
+                            path.display(),
+                            name
+                        );
+                        self.known.insert(path, (fp, previous));
+                        continue;
+                    }
+                    if let Some(old) = previous.as_ref().filter(|old| **old != name) {
+                        self.registry.unregister(old).await;
+                        report.removed.push(old.clone());
+                    }
+                    self.registry.replace_plugin(tool).await;
+                    if previous.is_some() {
+                        report.updated.push(name.clone());
+                    } else {
+                        report.added.push(name.clone());
+                    }
+                    self.known.insert(path, (fp, Some(name)));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to reload plugin {}: {} (keeping previous version)",
+                        path.display(),
+                        e
+                    );
+                    self.known.insert(path, (fp, previous));
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Poll the plugin directories forever.
+    pub async fn run(mut self, interval: Duration) {
+        let initial = self.adopt().await;
+        tracing::info!(
+            dir = %self.plugins_dir.display(),
+            plugins = initial.added.len(),
+            "Plugin hot-reload watching"
+        );
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let report = self.scan().await;
+            if !report.is_empty() {
+                tracing::info!(
+                    added = ?report.added,
+                    updated = ?report.updated,
+                    removed = ?report.removed,
+                    "Plugins reloaded"
+                );
+            }
+        }
+    }
+}
+
+async fn stamp(path: &Path) -> Option<FileStamp> {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    Some(FileStamp {
+        modified: meta.modified().ok(),
+        len: meta.len(),
+    })
+}
+
+async fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    Some(Fingerprint {
+        manifest: stamp(path).await?,
+        module: None,
+    })
+}
+
+/// Fingerprint of a WASM manifest plus the module file it names.
+#[cfg(feature = "wasm")]
+async fn wasm_fingerprint(path: &Path) -> Option<Fingerprint> {
+    let manifest = stamp(path).await?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let module = match super::wasm_plugin::load_wasm_manifest(path).await {
+        Ok(config) => stamp(&dir.join(config.wasm_file)).await,
+        Err(_) => None,
+    };
+    Some(Fingerprint { manifest, module })
+}
+
+async fn load(path: &Path, kind: PluginKind) -> anyhow::Result<Arc<dyn Tool>> {
+    match kind {
+        PluginKind::Json => Ok(plugin_tool(load_plugin_file(path).await?)),
+        #[cfg(feature = "wasm")]
+        PluginKind::Wasm => {
+            let config = super::wasm_plugin::load_wasm_manifest(path).await?;
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            Ok(Arc::new(super::wasm_plugin::WasmPluginTool::new(
+                config, dir,
+            )?))
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+async fn wasm_manifests(dir: &Path) -> Vec<PathBuf> {
+    let mut manifests = Vec::new();
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return manifests;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let manifest = entry.path().join("manifest.json");
+        if manifest.is_file() {
+            manifests.push(manifest);
+        }
+    }
+    manifests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_plugin(dir: &Path, file: &str, name: &str, description: &str) {
+        let json = serde_json::json!({
+            "name": name,
+            "description": description,
+            "command": "echo hi"
+        });
+        std::fs::write(dir.join(file), json.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_adds_updates_and_removes() {
+        let tmp = TempDir::new().unwrap();
+        let registry = ToolRegistry::new();
+        let mut watcher = PluginWatcher::new(registry.clone(), tmp.path().to_path_buf());
+
+        write_plugin(tmp.path(), "a.json", "alpha", "first");
+        let report = watcher.scan().await;
+        assert_eq!(report.added, vec!["alpha"]);
+        assert!(registry.list().await.contains(&"alpha".to_string()));
+
+        // Unchanged files are not reloaded
+        assert!(watcher.scan().await.is_empty());
+
+        // Renaming the tool inside the manifest swaps it in the registry
+        write_plugin(tmp.path(), "a.json", "beta", "renamed tool");
+        let report = watcher.scan().await;
+        assert_eq!(report.removed, vec!["alpha"]);
+        assert_eq!(report.updated, vec!["beta"]);
+        assert_eq!(registry.list().await, vec!["beta".to_string()]);
+
+        std::fs::remove_file(tmp.path().join("a.json")).unwrap();
+        let report = watcher.scan().await;
+        assert_eq!(report.removed, vec!["beta"]);
+        assert_eq!(registry.count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_manifest_keeps_previous_tool() {
+        let tmp = TempDir::new().unwrap();
+        let registry = ToolRegistry::new();
+        let mut watcher = PluginWatcher::new(registry.clone(), tmp.path().to_path_buf());
+
+        write_plugin(tmp.path(), "a.json", "alpha", "first");
+        watcher.scan().await;
+
+        std::fs::write(tmp.path().join("a.json"), "{ not json").unwrap();
+        assert!(watcher.scan().await.is_empty());
+        assert!(registry.list().await.contains(&"alpha".to_string()));
+    }
+
+    #[cfg(feature = "wasm")]
+    #[tokio::test]
+    async fn test_wasm_module_change_is_detected() {
+        let tmp = TempDir::new().unwrap();
+        let plugin = tmp.path().join("calc");
+        std::fs::create_dir(&plugin).unwrap();
+        let manifest = plugin.join("manifest.json");
+        let json = serde_json::json!({
+            "name": "calc",
+            "description": "calculator",
+            "wasm_file": "calc.wasm"
+        });
+        std::fs::write(&manifest, json.to_string()).unwrap();
+        std::fs::write(plugin.join("calc.wasm"), b"v1").unwrap();
+        let before = wasm_fingerprint(&manifest).await.unwrap();
+
+        // A marketplace update rewrites only the module
+        std::fs::write(plugin.join("calc.wasm"), b"version 2").unwrap();
+        assert_ne!(wasm_fingerprint(&manifest).await.unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn test_plugin_cannot_shadow_builtin_tool() {
+        let tmp = TempDir::new().unwrap();
+        let registry = ToolRegistry::new();
+        write_plugin(tmp.path(), "exec.json", "exec", "builtin");
+        let plugins = crate::tool::plugin::load_plugins(tmp.path()).await;
+        crate::tool::plugin::register_plugins(&registry, plugins).await;
+        std::fs::remove_file(tmp.path().join("exec.json")).unwrap();
+        let mut watcher = PluginWatcher::new(registry.clone(), tmp.path().to_path_buf());
+        assert!(watcher.scan().await.is_empty());
+
+        // A plugin dropped in later under the same name is refused...
+        write_plugin(tmp.path(), "evil.json", "exec", "impostor");
+        assert!(watcher.scan().await.is_empty());
+        let summaries = registry.get_summaries().await;
+        assert!(
+            summaries.iter().any(|s| s.contains("builtin")),
+            "{:?}",
+            summaries
+        );
+
+        // ...and deleting it does not take the original tool with it
+        std::fs::remove_file(tmp.path().join("evil.json")).unwrap();
+        assert!(watcher.scan().await.is_empty());
+        assert_eq!(registry.list().await, vec!["exec".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_startup_plugin_cannot_shadow_builtin_tool() {
+        let tmp = TempDir::new().unwrap();
+        let registry = ToolRegistry::new();
+        let builtin = plugin_tool(
+            serde_json::from_value(serde_json::json!({
+                "name": "exec", "description": "builtin", "command": "true"
+            }))
+            .unwrap(),
+        );
+        registry.register(builtin).await;
+
+        // Present at startup: refused at registration and on the first scan
+        write_plugin(tmp.path(), "evil.json", "exec", "impostor");
+        write_plugin(tmp.path(), "a.json", "alpha", "first");
+        let plugins = crate::tool::plugin::load_plugins(tmp.path()).await;
+        crate::tool::plugin::register_plugins(&registry, plugins).await;
+        let mut watcher = PluginWatcher::new(registry.clone(), tmp.path().to_path_buf());
+        assert_eq!(watcher.adopt().await.added, vec!["alpha"]);
+        let summaries = registry.get_summaries().await;
+        assert!(
+            summaries.iter().any(|s| s.contains("builtin")),
+            "{:?}",
+            summaries
+        );
+
+        // Deleting it leaves the built-in; the adopted plugin is removed
+        std::fs::remove_file(tmp.path().join("evil.json")).unwrap();
+        std::fs::remove_file(tmp.path().join("a.json")).unwrap();
+        assert_eq!(watcher.scan().await.removed, vec!["alpha"]);
+        assert_eq!(registry.list().await, vec!["exec".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_scan_leaves_other_tools_alone() {
+        let tmp = TempDir::new().unwrap();
+        let registry = ToolRegistry::new();
+        write_plugin(tmp.path(), "builtin.json", "builtin", "x");
+        let plugins = crate::tool::plugin::load_plugins(tmp.path()).await;
+        crate::tool::plugin::register_plugins(&registry, plugins).await;
+        std::fs::remove_file(tmp.path().join("builtin.json")).unwrap();
+
+        // Never seen by the watcher, so never removed by it
+        let mut watcher = PluginWatcher::new(registry.clone(), tmp.path().to_path_buf());
+        assert!(watcher.scan().await.is_empty());
+        assert_eq!(registry.count().await, 1);
+    }
+}
@@ -0,0 +1,86 @@
+// QuectoClaw — maixcam_command tool (send a command to a MaixCam device)
+
+use super::{Tool, ToolContext, ToolResult};
+use crate::bus::{MessageBus, OutboundKind, OutboundMessage};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct MaixCamCommandTool {
+    bus: Arc<MessageBus>,
+}
+
+impl MaixCamCommandTool {
+    pub fn new(bus: Arc<MessageBus>) -> Self {
+        Self { bus }
+    }
+}
+
+#[async_trait]
+impl Tool for MaixCamCommandTool {
+    fn name(&self) -> &str {
+        "maixcam_command"
+    }
+
+    fn description(&self) -> &str {
+        "Send a command (e.g. 'capture') to a connected MaixCam device. The device defaults to the one this conversation is with."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "device": { "type": "string", "description": "Device id (default: the current device)" },
+                "command": { "type": "string", "description": "Command name, e.g. 'capture'" },
+                "args": { "type": "object", "description": "Optional command arguments" }
+            },
+            "required": ["command"]
+        })
+    }
+
+    async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        self.execute_with_context(args, &ToolContext::default())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        let command = match args.get("command").and_then(|v| v.as_str()) {
+            Some(c) if !c.trim().is_empty() => c.trim(),
+            _ => return ToolResult::error("command is required"),
+        };
+        let device = match args.get("device").and_then(|v| v.as_str()) {
+            Some(d) if !d.trim().is_empty() => d.trim().to_string(),
+            _ => match ctx.reply_chat() {
+                Some(chat) if chat.channel == "maixcam" => chat.chat_id,
+                _ => return ToolResult::error("device is required outside a MaixCam session"),
+            },
+        };
+        if !self.bus.has_handler("maixcam").await {
+            return ToolResult::error("the MaixCam channel is not running");
+        }
+
+        let mut metadata = HashMap::from([("command".to_string(), command.to_string())]);
+        if let Some(cmd_args) = args.get("args").filter(|a| !a.is_null()) {
+            if !cmd_args.is_object() {
+                return ToolResult::error("args must be an object");
+            }
+            metadata.insert("args".to_string(), cmd_args.to_string());
+        }
+        self.bus
+            .publish_outbound(OutboundMessage {
+                channel: "maixcam".to_string(),
+                chat_id: device.clone(),
+                content: String::new(),
+                metadata,
+                kind: OutboundKind::Message,
+                attachments: Vec::new(),
+            })
+            .await;
+        ToolResult::success(format!("Sent '{}' to {}", command, device))
+    }
+}
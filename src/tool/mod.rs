@@ -2,7 +2,11 @@
 
 pub mod exec;
 pub mod filesystem;
+pub mod hot_reload;
+pub mod maixcam_command;
 pub mod plugin;
+pub mod schedule;
+pub mod send_file;
 pub mod subagent;
 pub mod vectordb_index;
 pub mod vectordb_search;
@@ -11,9 +15,11 @@ pub mod web;
 #[cfg(feature = "wasm")]
 pub mod wasm_plugin;
 
+use crate::bus::ChatTarget;
+use crate::config::ToolProfile;
 use async_trait::async_trait;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -80,22 +86,138 @@ impl ToolResult {
 // Tool trait
 // ---------------------------------------------------------------------------
 
+/// Calling context passed to tools that need to know who invoked them.
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    /// Session the tool call belongs to.
+    pub session_key: String,
+    /// The caller's (possibly profile-restricted) tool view; `None` = unrestricted.
+    pub tools: Option<ToolRegistry>,
+    /// Subagent nesting depth of the caller (0 = top-level agent).
+    pub depth: u32,
+    /// Session that usage of work spawned by this call is attributed to.
+    pub bill_to: String,
+    /// Where long-running tools report progress (CLI/web stream, TUI).
+    pub progress: Option<tokio::sync::mpsc::Sender<ToolProgress>>,
+    /// Chat the turn answers, when it came from a channel.
+    pub chat: Option<ChatTarget>,
+}
+
+/// A progress update from a running tool call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolProgress {
+    pub tool: String,
+    pub progress: f64,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+impl ToolProgress {
+    /// `3/10 Indexing files`; the total and message are optional.
+    pub fn describe(&self) -> String {
+        let amount = match self.total {
+            Some(total) if total > 0.0 => format!("{}/{}", self.progress, total),
+            _ => format!("{}", self.progress),
+        };
+        match &self.message {
+            Some(m) if !m.is_empty() => format!("{} {}", amount, m),
+            _ => amount,
+        }
+    }
+}
+
+impl ToolContext {
+    /// The chat to send results to: the channel's chat, else one named by a
+    /// `channel:chat_id` session key (e.g. `schedule_task` from the CLI).
+    pub fn reply_chat(&self) -> Option<ChatTarget> {
+        if let Some(chat) = &self.chat {
+            return Some(chat.clone());
+        }
+        let (channel, chat_id) = self.root_session().split_once(':')?;
+        Some(ChatTarget {
+            channel: channel.to_string(),
+            chat_id: chat_id.to_string(),
+        })
+    }
+
+    /// The session that started this call tree (the user-facing conversation).
+    pub fn root_session(&self) -> &str {
+        if self.bill_to.is_empty() {
+            &self.session_key
+        } else {
+            &self.bill_to
+        }
+    }
+}
+
 #[async_trait]
 pub trait Tool: Send + Sync {
     fn name(&self) -> &str;
     fn description(&self) -> &str;
     fn parameters(&self) -> Value;
     async fn execute(&self, args: HashMap<String, Value>) -> ToolResult;
+
+    /// Execute with the caller's context. Tools that spawn further work
+    /// (e.g. subagents) override this to propagate the profile; the
+    /// default ignores the context.
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        _ctx: &ToolContext,
+    ) -> ToolResult {
+        self.execute(args).await
+    }
+}
+
+/// Match a tool name against a glob pattern supporting `*` and `?`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == '*')
 }
 
 // ---------------------------------------------------------------------------
 // Tool registry
 // ---------------------------------------------------------------------------
 
+/// The tool set is shared between clones; a clone created with
+/// [`ToolRegistry::with_profile`] is a restricted view of the same tools.
+/// Views stack: a tool must be allowed by every profile in the chain.
 #[derive(Clone)]
 pub struct ToolRegistry {
     tools: Arc<RwLock<HashMap<String, Arc<dyn Tool>>>>,
     vector_store: Arc<RwLock<Option<Arc<RwLock<crate::vectordb::VectorStore>>>>>,
+    mcp: Arc<RwLock<Option<Arc<crate::mcp::MCPServers>>>>,
+    /// Names of the tools that were loaded from plugin files.
+    plugins: Arc<RwLock<HashSet<String>>>,
+    profiles: Vec<Arc<(String, ToolProfile)>>,
+}
+
+impl std::fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolRegistry")
+            .field("profiles", &self.profile_names())
+            .finish()
+    }
 }
 
 impl ToolRegistry {
@@ -103,9 +225,36 @@ impl ToolRegistry {
         Self {
             tools: Arc::new(RwLock::new(HashMap::new())),
             vector_store: Arc::new(RwLock::new(None)),
+            mcp: Arc::new(RwLock::new(None)),
+            plugins: Arc::new(RwLock::new(HashSet::new())),
+            profiles: Vec::new(),
         }
     }
 
+    /// Return a view of this registry further restricted by the given profile.
+    /// Registration through the view still affects the shared tool set.
+    pub fn with_profile(&self, name: &str, profile: ToolProfile) -> Self {
+        let mut profiles = self.profiles.clone();
+        profiles.push(Arc::new((name.to_string(), profile)));
+        Self {
+            tools: self.tools.clone(),
+            vector_store: self.vector_store.clone(),
+            mcp: self.mcp.clone(),
+            plugins: self.plugins.clone(),
+            profiles,
+        }
+    }
+
+    /// Names of the profiles this view enforces, outermost first.
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.iter().map(|p| p.0.as_str()).collect()
+    }
+
+    /// Whether every active profile permits the named tool.
+    pub fn is_permitted(&self, name: &str) -> bool {
+        self.profiles.iter().all(|p| p.1.allows(name))
+    }
+
     pub async fn set_vector_store(&self, store: Arc<RwLock<crate::vectordb::VectorStore>>) {
         *self.vector_store.write().await = Some(store);
     }
@@ -114,18 +263,79 @@ impl ToolRegistry {
         self.vector_store.read().await.clone()
     }
 
+    /// Connected MCP servers, for resource mentions and prompt commands.
+    pub async fn set_mcp(&self, servers: Arc<crate::mcp::MCPServers>) {
+        *self.mcp.write().await = Some(servers);
+    }
+
+    pub async fn get_mcp(&self) -> Option<Arc<crate::mcp::MCPServers>> {
+        self.mcp.read().await.clone()
+    }
+
+    /// Register a tool, replacing any existing tool with the same name.
     pub async fn register(&self, tool: Arc<dyn Tool>) {
+        self.replace(tool).await;
+    }
+
+    /// Register a tool and return the one it replaced, if any.
+    /// Calls already running against the old tool finish on the old instance.
+    pub async fn replace(&self, tool: Arc<dyn Tool>) -> Option<Arc<dyn Tool>> {
         let name = tool.name().to_string();
-        self.tools.write().await.insert(name, tool);
+        self.plugins.write().await.remove(&name);
+        self.tools.write().await.insert(name, tool)
+    }
+
+    /// Like [`ToolRegistry::replace`], for a tool loaded from a plugin file.
+    pub async fn replace_plugin(&self, tool: Arc<dyn Tool>) -> Option<Arc<dyn Tool>> {
+        let name = tool.name().to_string();
+        self.plugins.write().await.insert(name.clone());
+        self.tools.write().await.insert(name, tool)
+    }
+
+    /// Whether the named tool was loaded from a plugin file.
+    pub async fn is_plugin(&self, name: &str) -> bool {
+        self.plugins.read().await.contains(name)
+    }
+
+    /// Whether a tool with this name is registered.
+    pub async fn contains(&self, name: &str) -> bool {
+        self.tools.read().await.contains_key(name)
+    }
+
+    /// Remove a tool by name. Returns the removed tool, if it was registered.
+    pub async fn unregister(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.plugins.write().await.remove(name);
+        self.tools.write().await.remove(name)
     }
 
     pub async fn execute(&self, name: &str, args: HashMap<String, Value>) -> ToolResult {
-        let tools = self.tools.read().await;
-        match tools.get(name) {
+        let ctx = ToolContext {
+            tools: Some(self.clone()),
+            ..Default::default()
+        };
+        self.execute_with_context(name, args, &ctx).await
+    }
+
+    /// Execute a tool on behalf of a caller, enforcing the active profile.
+    pub async fn execute_with_context(
+        &self,
+        name: &str,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        if !self.is_permitted(name) {
+            tracing::warn!(tool = %name, profiles = ?self.profile_names(), "Tool denied by profile");
+            return ToolResult::error(format!("tool '{}' is not available in this context", name));
+        }
+
+        // Clone the tool out so the lock isn't held while it runs;
+        // this lets plugins be replaced or removed mid-execution.
+        let tool = self.tools.read().await.get(name).cloned();
+        match tool {
             Some(tool) => {
                 tracing::info!(tool = %name, "Executing tool");
                 let start = std::time::Instant::now();
-                let result = tool.execute(args).await;
+                let result = tool.execute_with_context(args, ctx).await;
                 let duration = start.elapsed();
 
                 if result.is_error {
@@ -148,6 +358,7 @@ impl ToolRegistry {
         let tools = self.tools.read().await;
         tools
             .values()
+            .filter(|tool| self.is_permitted(tool.name()))
             .map(|tool| crate::provider::ToolDefinition {
                 def_type: "function".to_string(),
                 function: crate::provider::ToolFunctionDefinition {
@@ -160,17 +371,24 @@ impl ToolRegistry {
     }
 
     pub async fn list(&self) -> Vec<String> {
-        self.tools.read().await.keys().cloned().collect()
+        self.tools
+            .read()
+            .await
+            .keys()
+            .filter(|name| self.is_permitted(name))
+            .cloned()
+            .collect()
     }
 
     pub async fn count(&self) -> usize {
-        self.tools.read().await.len()
+        self.list().await.len()
     }
 
     pub async fn get_summaries(&self) -> Vec<String> {
         let tools = self.tools.read().await;
         tools
             .values()
+            .filter(|t| self.is_permitted(t.name()))
             .map(|t| format!("- `{}` - {}", t.name(), t.description()))
             .collect()
     }
@@ -181,3 +399,130 @@ impl Default for ToolRegistry {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct NamedTool(&'static str);
+
+    #[async_trait]
+    impl Tool for NamedTool {
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn description(&self) -> &str {
+            "test tool"
+        }
+        fn parameters(&self) -> Value {
+            json!({"type": "object"})
+        }
+        async fn execute(&self, _args: HashMap<String, Value>) -> ToolResult {
+            ToolResult::success(self.0)
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "exec"));
+        assert!(glob_match("web_*", "web_search"));
+        assert!(glob_match("*_query", "sqlite_query"));
+        assert!(glob_match("read_fil?", "read_file"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("web_*", "exec"));
+        assert!(!glob_match("read_file", "read_files"));
+    }
+
+    #[tokio::test]
+    async fn test_profile_view_filters_definitions_and_execution() {
+        let registry = ToolRegistry::new();
+        for name in ["exec", "web_search", "sqlite_query"] {
+            registry.register(Arc::new(NamedTool(name))).await;
+        }
+
+        let view = registry.with_profile(
+            "public",
+            ToolProfile {
+                allow: vec!["web_*".into(), "sqlite_*".into()],
+                deny: vec![],
+            },
+        );
+
+        let mut names: Vec<String> = view
+            .get_definitions()
+            .await
+            .into_iter()
+            .map(|d| d.function.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["sqlite_query", "web_search"]);
+        assert_eq!(view.count().await, 2);
+        assert_eq!(registry.count().await, 3);
+
+        assert!(view.execute("exec", HashMap::new()).await.is_error);
+        assert!(!view.execute("web_search", HashMap::new()).await.is_error);
+        assert!(!registry.execute("exec", HashMap::new()).await.is_error);
+
+        // Stacked views only narrow
+        let narrower = view.with_profile(
+            "no-sql",
+            ToolProfile {
+                allow: vec![],
+                deny: vec!["sqlite_*".into()],
+            },
+        );
+        assert_eq!(narrower.list().await, vec!["web_search".to_string()]);
+        assert_eq!(narrower.profile_names(), vec!["public", "no-sql"]);
+    }
+
+    struct SlowTool;
+
+    #[async_trait]
+    impl Tool for SlowTool {
+        fn name(&self) -> &str {
+            "slow"
+        }
+        fn description(&self) -> &str {
+            "sleeps"
+        }
+        fn parameters(&self) -> Value {
+            json!({"type": "object"})
+        }
+        async fn execute(&self, _args: HashMap<String, Value>) -> ToolResult {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            ToolResult::success("done")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unregister_during_execute() {
+        let registry = ToolRegistry::new();
+        registry.register(Arc::new(SlowTool)).await;
+
+        let reg = registry.clone();
+        let running = tokio::spawn(async move { reg.execute("slow", HashMap::new()).await });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        // Must not wait for the running call to finish.
+        let removed = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            registry.unregister("slow"),
+        )
+        .await
+        .expect("unregister blocked on a running tool");
+        assert!(removed.is_some());
+
+        let result = running.await.unwrap();
+        assert_eq!(result.for_llm, "done");
+        assert!(registry.execute("slow", HashMap::new()).await.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_replace_returns_previous() {
+        let registry = ToolRegistry::new();
+        assert!(registry.replace(Arc::new(NamedTool("a"))).await.is_none());
+        assert!(registry.replace(Arc::new(NamedTool("a"))).await.is_some());
+        assert_eq!(registry.count().await, 1);
+    }
+}
@@ -5,7 +5,7 @@ use async_trait::async_trait;
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
 
@@ -115,32 +115,60 @@ impl Tool for PluginTool {
     }
 }
 
-/// Load plugin configs from a directory of JSON files.
-pub async fn load_plugins(dir: &Path) -> Vec<PluginConfig> {
-    let mut plugins = Vec::new();
+/// List plugin manifest files: `dir/*.json` plus `dir/<name>/*.json`
+/// (the layout used by `quectoclaw plugin install`). WASM `manifest.json`
+/// files are skipped.
+pub async fn plugin_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
 
     let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
-        return plugins;
+        return files;
     };
 
     while let Ok(Some(entry)) = entries.next_entry().await {
         let path = entry.path();
-        if path.extension().and_then(|e| e.to_str()) != Some("json") {
-            continue;
+        if path.is_dir() {
+            let Ok(mut sub) = tokio::fs::read_dir(&path).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = sub.next_entry().await {
+                let path = entry.path();
+                if is_plugin_manifest(&path) {
+                    files.push(path);
+                }
+            }
+        } else if is_plugin_manifest(&path) {
+            files.push(path);
         }
+    }
 
-        match tokio::fs::read_to_string(&path).await {
-            Ok(content) => match serde_json::from_str::<PluginConfig>(&content) {
-                Ok(plugin) => {
-                    tracing::info!(name = %plugin.name, "Loaded plugin: {}", path.display());
-                    plugins.push(plugin);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to parse plugin {}: {}", path.display(), e);
-                }
-            },
+    files.sort();
+    files
+}
+
+fn is_plugin_manifest(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some("json")
+        && path.file_name().and_then(|n| n.to_str()) != Some("manifest.json")
+}
+
+/// Read and parse a single plugin file.
+pub async fn load_plugin_file(path: &Path) -> anyhow::Result<PluginConfig> {
+    let content = tokio::fs::read_to_string(path).await?;
+    Ok(serde_json::from_str::<PluginConfig>(&content)?)
+}
+
+/// Load plugin configs from a directory of JSON files.
+pub async fn load_plugins(dir: &Path) -> Vec<PluginConfig> {
+    let mut plugins = Vec::new();
+
+    for path in plugin_files(dir).await {
+        match load_plugin_file(&path).await {
+            Ok(plugin) => {
+                tracing::info!(name = %plugin.name, "Loaded plugin: {}", path.display());
+                plugins.push(plugin);
+            }
             Err(e) => {
-                tracing::warn!("Failed to read plugin file {}: {}", path.display(), e);
+                tracing::warn!("Failed to load plugin {}: {}", path.display(), e);
             }
         }
     }
@@ -151,38 +179,45 @@ pub async fn load_plugins(dir: &Path) -> Vec<PluginConfig> {
 /// Register loaded plugins as tools in the registry.
 pub async fn register_plugins(registry: &ToolRegistry, plugins: Vec<PluginConfig>) {
     for plugin in plugins {
-        // Build JSON schema for parameters
-        let mut properties = serde_json::Map::new();
-        let mut required = Vec::new();
-
-        for param in &plugin.parameters {
-            let mut prop = serde_json::Map::new();
-            prop.insert("type".into(), Value::String(param.param_type.clone()));
-            prop.insert(
-                "description".into(),
-                Value::String(param.description.clone()),
-            );
-            properties.insert(param.name.clone(), Value::Object(prop));
-            if param.required {
-                required.push(Value::String(param.name.clone()));
-            }
+        let name = plugin.name.clone();
+        if registry.contains(&name).await {
+            tracing::warn!("Ignoring plugin '{}': the tool is already registered", name);
+            continue;
         }
+        registry.replace_plugin(plugin_tool(plugin)).await;
+        tracing::info!(name = %name, "Registered plugin tool");
+    }
+}
 
-        let schema = serde_json::json!({
-            "type": "object",
-            "properties": properties,
-            "required": required,
-        });
+/// Build the tool for a plugin definition.
+pub fn plugin_tool(plugin: PluginConfig) -> Arc<dyn Tool> {
+    // Build JSON schema for parameters
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+
+    for param in &plugin.parameters {
+        let mut prop = serde_json::Map::new();
+        prop.insert("type".into(), Value::String(param.param_type.clone()));
+        prop.insert(
+            "description".into(),
+            Value::String(param.description.clone()),
+        );
+        properties.insert(param.name.clone(), Value::Object(prop));
+        if param.required {
+            required.push(Value::String(param.name.clone()));
+        }
+    }
 
-        let name = plugin.name.clone();
-        let tool = PluginTool {
-            config: plugin,
-            schema,
-        };
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    });
 
-        registry.register(Arc::new(tool)).await;
-        tracing::info!(name = %name, "Registered plugin tool");
-    }
+    Arc::new(PluginTool {
+        config: plugin,
+        schema,
+    })
 }
 
 #[cfg(test)]
@@ -260,6 +295,24 @@ mod tests {
         assert_eq!(plugins[0].name, "test-plugin");
     }
 
+    #[tokio::test]
+    async fn test_load_plugins_from_install_subdirs() {
+        let tmp = TempDir::new().unwrap();
+        let installed = tmp.path().join("greeter");
+        std::fs::create_dir_all(&installed).unwrap();
+        std::fs::write(
+            installed.join("greeter.json"),
+            r#"{"name": "greeter", "description": "d", "command": "echo hi"}"#,
+        )
+        .unwrap();
+        // WASM manifests live alongside but are not shell plugins
+        std::fs::write(installed.join("manifest.json"), "{}").unwrap();
+
+        let plugins = load_plugins(tmp.path()).await;
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].name, "greeter");
+    }
+
     #[tokio::test]
     async fn test_load_plugins_empty_dir() {
         let tmp = TempDir::new().unwrap();
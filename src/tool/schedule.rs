@@ -0,0 +1,366 @@
+// QuectoClaw — Scheduling tools (schedule_task, list_tasks, cancel_task)
+
+use super::{Tool, ToolContext, ToolResult};
+use crate::scheduler::{Job, JobSchedule, JobStore};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Jobs are owned by the session that created them; calls without a
+/// session (e.g. direct registry use) act on the CLI's default session.
+fn owner(ctx: &ToolContext) -> &str {
+    match ctx.root_session() {
+        "" => "default",
+        s => s,
+    }
+}
+
+/// Parse an RFC 3339 timestamp or a local `YYYY-MM-DD HH:MM[:SS]`.
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Some(t.with_timezone(&Utc));
+    }
+    [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+    .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+    .map(|t| t.with_timezone(&Utc))
+}
+
+// ---------------------------------------------------------------------------
+// schedule_task
+// ---------------------------------------------------------------------------
+
+pub struct ScheduleTaskTool {
+    store: Arc<JobStore>,
+    max_jobs: usize,
+}
+
+impl ScheduleTaskTool {
+    pub fn new(store: Arc<JobStore>, max_jobs: usize) -> Self {
+        Self { store, max_jobs }
+    }
+}
+
+#[async_trait]
+impl Tool for ScheduleTaskTool {
+    fn name(&self) -> &str {
+        "schedule_task"
+    }
+
+    fn description(&self) -> &str {
+        "Schedule a task to run later, once or on a recurring cron schedule. When it fires, the task is run as a prompt and the answer is sent to this chat."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "task": {
+                    "type": "string",
+                    "description": "What to do when the job fires, e.g. 'Remind the user to call Bob'"
+                },
+                "at": {
+                    "type": "string",
+                    "description": format!(
+                        "One-shot time, RFC 3339 or local 'YYYY-MM-DD HH:MM'. Current local time: {}",
+                        Local::now().format("%Y-%m-%d %H:%M %:z (%A)")
+                    )
+                },
+                "delay_seconds": {
+                    "type": "integer",
+                    "description": "One-shot delay from now, in seconds"
+                },
+                "cron": {
+                    "type": "string",
+                    "description": "Recurring schedule in local time, 'min hour day month weekday' (e.g. '0 9 * * Mon-Fri')"
+                }
+            },
+            "required": ["task"]
+        })
+    }
+
+    async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        self.execute_with_context(args, &ToolContext::default())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        let task = match args.get("task").and_then(|v| v.as_str()) {
+            Some(t) if !t.trim().is_empty() => t,
+            _ => return ToolResult::error("task is required"),
+        };
+
+        let at = args.get("at").and_then(|v| v.as_str());
+        let delay = args.get("delay_seconds").and_then(|v| v.as_i64());
+        let cron = args.get("cron").and_then(|v| v.as_str());
+        let schedule = match (at, delay, cron) {
+            (Some(at), None, None) => match parse_time(at) {
+                Some(at) => JobSchedule::At { at },
+                None => return ToolResult::error(format!("could not parse time '{}'", at)),
+            },
+            (None, Some(secs), None) if secs > 0 => JobSchedule::At {
+                at: Utc::now() + chrono::Duration::seconds(secs),
+            },
+            (None, None, Some(expr)) => JobSchedule::Cron {
+                expr: expr.to_string(),
+            },
+            _ => {
+                return ToolResult::error(
+                    "provide exactly one of 'at', 'delay_seconds' (> 0) or 'cron'",
+                )
+            }
+        };
+
+        let session = owner(ctx);
+        if self.store.list(Some(session)).await.len() >= self.max_jobs {
+            return ToolResult::error(format!(
+                "this chat already has {} scheduled tasks; cancel one first",
+                self.max_jobs
+            ));
+        }
+
+        let mut job = match Job::new(task, schedule, session) {
+            Ok(job) => job,
+            Err(e) => return ToolResult::error(e.to_string()),
+        };
+        // Deliver to the chat the request came from, whatever the session
+        // key (group sessions may be per thread or per user)
+        if let Some(chat) = &ctx.chat {
+            job.channel = chat.channel.clone();
+            job.chat_id = chat.chat_id.clone();
+        }
+        let summary = format!(
+            "Scheduled task {} ({}), next run {}",
+            job.id,
+            job.schedule.describe(),
+            job.next_run
+                .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default()
+        );
+        match self.store.add(job).await {
+            Ok(()) => ToolResult::success(summary),
+            Err(e) => ToolResult::error(format!("failed to save task: {}", e)),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// list_tasks
+// ---------------------------------------------------------------------------
+
+pub struct ListTasksTool {
+    store: Arc<JobStore>,
+}
+
+impl ListTasksTool {
+    pub fn new(store: Arc<JobStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl Tool for ListTasksTool {
+    fn name(&self) -> &str {
+        "list_tasks"
+    }
+
+    fn description(&self) -> &str {
+        "List the scheduled tasks of this chat."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({ "type": "object", "properties": {} })
+    }
+
+    async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        self.execute_with_context(args, &ToolContext::default())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        _args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        let jobs = self.store.list(Some(owner(ctx))).await;
+        if jobs.is_empty() {
+            return ToolResult::success("No scheduled tasks.");
+        }
+        let lines: Vec<String> = jobs
+            .iter()
+            .map(|j| {
+                format!(
+                    "- {} [{}] next: {} — {}",
+                    j.id,
+                    j.schedule.describe(),
+                    j.next_run
+                        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_else(|| "-".into()),
+                    j.task
+                )
+            })
+            .collect();
+        ToolResult::success(lines.join("\n"))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// cancel_task
+// ---------------------------------------------------------------------------
+
+pub struct CancelTaskTool {
+    store: Arc<JobStore>,
+}
+
+impl CancelTaskTool {
+    pub fn new(store: Arc<JobStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl Tool for CancelTaskTool {
+    fn name(&self) -> &str {
+        "cancel_task"
+    }
+
+    fn description(&self) -> &str {
+        "Cancel a scheduled task of this chat by id."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Task id from list_tasks" }
+            },
+            "required": ["id"]
+        })
+    }
+
+    async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        self.execute_with_context(args, &ToolContext::default())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        let Some(id) = args.get("id").and_then(|v| v.as_str()) else {
+            return ToolResult::error("id is required");
+        };
+        match self.store.cancel(id, Some(owner(ctx))).await {
+            Ok(true) => ToolResult::success(format!("Cancelled task {}", id)),
+            Ok(false) => ToolResult::error(format!("no task '{}' in this chat", id)),
+            Err(e) => ToolResult::error(format!("failed to cancel task: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn ctx(session: &str) -> ToolContext {
+        ToolContext {
+            session_key: session.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn args(v: Value) -> HashMap<String, Value> {
+        serde_json::from_value(v).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_schedule_list_cancel_scoped_to_chat() {
+        let tmp = TempDir::new().unwrap();
+        let store = Arc::new(JobStore::new(tmp.path()));
+        let schedule = ScheduleTaskTool::new(store.clone(), 10);
+        let list = ListTasksTool::new(store.clone());
+        let cancel = CancelTaskTool::new(store.clone());
+
+        let res = schedule
+            .execute_with_context(
+                args(json!({"task": "stand-up reminder", "cron": "0 9 * * Mon-Fri"})),
+                &ctx("telegram:42"),
+            )
+            .await;
+        assert!(!res.is_error, "{}", res.for_llm);
+
+        let jobs = store.list(None).await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(
+            (jobs[0].channel.as_str(), jobs[0].chat_id.as_str()),
+            ("telegram", "42")
+        );
+
+        // Other chats neither see nor cancel it
+        let other = list
+            .execute_with_context(HashMap::new(), &ctx("discord:7"))
+            .await;
+        assert_eq!(other.for_llm, "No scheduled tasks.");
+        let id = jobs[0].id.clone();
+        assert!(
+            cancel
+                .execute_with_context(args(json!({"id": id})), &ctx("discord:7"))
+                .await
+                .is_error
+        );
+
+        let mine = list
+            .execute_with_context(HashMap::new(), &ctx("telegram:42"))
+            .await;
+        assert!(mine.for_llm.contains("stand-up reminder"));
+        assert!(
+            !cancel
+                .execute_with_context(args(json!({"id": id})), &ctx("telegram:42"))
+                .await
+                .is_error
+        );
+        assert!(store.list(None).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_schedule_validation() {
+        let tmp = TempDir::new().unwrap();
+        let store = Arc::new(JobStore::new(tmp.path()));
+        let schedule = ScheduleTaskTool::new(store.clone(), 1);
+        let c = ctx("slack:C1");
+
+        for bad in [
+            json!({"task": "x"}),
+            json!({"task": "x", "cron": "0 9 * * *", "delay_seconds": 5}),
+            json!({"task": "x", "cron": "whenever"}),
+            json!({"task": "x", "at": "2001-01-01 09:00"}),
+            json!({"task": "x", "at": "tomorrow-ish"}),
+        ] {
+            assert!(schedule.execute_with_context(args(bad), &c).await.is_error);
+        }
+
+        let ok = schedule
+            .execute_with_context(args(json!({"task": "x", "delay_seconds": 60})), &c)
+            .await;
+        assert!(!ok.is_error);
+        // Per-chat limit
+        let over = schedule
+            .execute_with_context(args(json!({"task": "y", "delay_seconds": 60})), &c)
+            .await;
+        assert!(over.is_error);
+    }
+}
@@ -0,0 +1,175 @@
+// QuectoClaw — send_file tool (attach a workspace file to the reply)
+
+use super::filesystem::validate_path;
+use super::{Tool, ToolContext, ToolResult};
+use crate::bus::{Attachment, MessageBus, OutboundKind, OutboundMessage};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The smallest upload limit of the supported platforms (Telegram bots).
+const MAX_FILE_BYTES: u64 = 50 * 1024 * 1024;
+
+pub struct SendFileTool {
+    workspace: String,
+    restrict: bool,
+    bus: Arc<MessageBus>,
+}
+
+impl SendFileTool {
+    pub fn new(workspace: String, restrict: bool, bus: Arc<MessageBus>) -> Self {
+        Self {
+            workspace,
+            restrict,
+            bus,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for SendFileTool {
+    fn name(&self) -> &str {
+        "send_file"
+    }
+
+    fn description(&self) -> &str {
+        "Send a file from the workspace (image, chart, report, document) to the user in this chat. Images are shown inline where the chat app supports it."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Path to the file to send" },
+                "caption": { "type": "string", "description": "Optional text sent with the file" }
+            },
+            "required": ["path"]
+        })
+    }
+
+    async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        self.execute_with_context(args, &ToolContext::default())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
+        let path = match args.get("path").and_then(|v| v.as_str()) {
+            Some(p) => p,
+            None => return ToolResult::error("path is required"),
+        };
+        let caption = args
+            .get("caption")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+
+        let chat = match ctx.reply_chat() {
+            Some(chat) if self.bus.has_handler(&chat.channel).await => chat,
+            _ => return ToolResult::error("files can only be sent to a chat channel"),
+        };
+
+        let resolved = match validate_path(path, &self.workspace, self.restrict) {
+            Ok(p) => p,
+            Err(e) => return ToolResult::error(e),
+        };
+        match tokio::fs::metadata(&resolved).await {
+            Ok(meta) if !meta.is_file() => {
+                return ToolResult::error(format!("{} is not a file", path))
+            }
+            Ok(meta) if meta.len() > MAX_FILE_BYTES => {
+                return ToolResult::error(format!(
+                    "{} is too large to send ({} MB, limit {} MB)",
+                    path,
+                    meta.len() / (1024 * 1024),
+                    MAX_FILE_BYTES / (1024 * 1024)
+                ))
+            }
+            Ok(_) => {}
+            Err(e) => return ToolResult::error(format!("failed to read file: {}", e)),
+        }
+
+        let attachment = Attachment::from_path(resolved);
+        let filename = attachment.filename.clone();
+        self.bus
+            .publish_outbound(OutboundMessage {
+                channel: chat.channel,
+                chat_id: chat.chat_id,
+                content: caption.to_string(),
+                metadata: HashMap::new(),
+                kind: OutboundKind::Message,
+                attachments: vec![attachment],
+            })
+            .await;
+        ToolResult::success(format!("Sent {} to the chat", filename))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::AttachmentData;
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    fn args(path: &str) -> HashMap<String, Value> {
+        HashMap::from([
+            ("path".to_string(), json!(path)),
+            ("caption".to_string(), json!("Q3 revenue")),
+        ])
+    }
+
+    fn context(session: &str) -> ToolContext {
+        ToolContext {
+            session_key: session.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sends_workspace_file_to_chat() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("chart.png"), [1, 2, 3]).unwrap();
+        let bus = Arc::new(MessageBus::new());
+        let (tx, mut rx) = mpsc::channel(4);
+        bus.register_handler("telegram", tx).await;
+        let tool = SendFileTool::new(dir.path().to_string_lossy().into(), true, bus);
+
+        let result = tool
+            .execute_with_context(args("chart.png"), &context("telegram:42"))
+            .await;
+        assert!(!result.is_error, "{}", result.for_llm);
+
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(
+            (msg.chat_id.as_str(), msg.content.as_str()),
+            ("42", "Q3 revenue")
+        );
+        let attachment = &msg.attachments[0];
+        assert_eq!(attachment.filename, "chart.png");
+        assert_eq!(attachment.mime, "image/png");
+        assert!(matches!(&attachment.data, AttachmentData::Path(p) if p.ends_with("chart.png")));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_cli_sessions_and_outside_paths() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("chart.png"), [1, 2, 3]).unwrap();
+        let bus = Arc::new(MessageBus::new());
+        let (tx, _rx) = mpsc::channel(4);
+        bus.register_handler("telegram", tx).await;
+        let tool = SendFileTool::new(dir.path().to_string_lossy().into(), true, bus);
+
+        let result = tool
+            .execute_with_context(args("chart.png"), &context("cli:default"))
+            .await;
+        assert!(result.for_llm.contains("chat channel"));
+        let result = tool
+            .execute_with_context(args("/etc/passwd"), &context("telegram:42"))
+            .await;
+        assert!(result.for_llm.contains("outside the workspace"));
+    }
+}
@@ -1,7 +1,8 @@
 // QuectoClaw — Subagent tool (allows an agent to spawn another agent task)
 
-use super::{Tool, ToolResult};
-use crate::agent::AgentLoop;
+use super::{Tool, ToolContext, ToolResult};
+use crate::agent::{AgentLoop, RunOptions};
+use crate::config::SubagentConfig;
 use async_trait::async_trait;
 use serde_json::{json, Value};
 use std::collections::HashMap;
@@ -13,24 +14,58 @@ const MAX_SUBAGENT_DEPTH: u32 = 3;
 
 pub struct SubagentTool {
     agent: Arc<AgentLoop>,
-    current_depth: Arc<AtomicU32>,
+    /// Configured specialists, sorted by name for a stable tool schema.
+    specialists: Vec<(String, SubagentConfig)>,
+    description: String,
+    /// Depth added to the caller's (see `with_depth`).
+    base_depth: u32,
 }
 
 impl SubagentTool {
     pub fn new(agent: Arc<AgentLoop>) -> Self {
+        let mut specialists: Vec<(String, SubagentConfig)> = agent
+            .config()
+            .agents
+            .subagents
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        specialists.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut description = String::from(
+            "Spawn a subagent to solve a specific sub-task. Returns the subagent's final answer. \
+             Call it several times in one turn to run subagents in parallel.",
+        );
+        if !specialists.is_empty() {
+            description.push_str(" Available specialists (pass as `agent`):");
+            for (name, def) in &specialists {
+                description.push_str(&format!("\n- {}: {}", name, def.description));
+            }
+        }
+
         Self {
             agent,
-            current_depth: Arc::new(AtomicU32::new(0)),
+            specialists,
+            description,
+            base_depth: 0,
         }
     }
 
-    /// Create a subagent tool with an inherited depth counter.
+    /// Create a subagent tool that starts at an inherited depth.
+    #[deprecated(note = "depth now travels with the call tree in `ToolContext::depth`; use `new`")]
     pub fn with_depth(agent: Arc<AgentLoop>, depth: Arc<AtomicU32>) -> Self {
         Self {
-            agent,
-            current_depth: depth,
+            base_depth: depth.load(Ordering::SeqCst),
+            ..Self::new(agent)
         }
     }
+
+    fn specialist(&self, name: &str) -> Option<&SubagentConfig> {
+        self.specialists
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, def)| def)
+    }
 }
 
 #[async_trait]
@@ -40,44 +75,96 @@ impl Tool for SubagentTool {
     }
 
     fn description(&self) -> &str {
-        "Spawn a subagent to solve a specific sub-task. Returns the subagent's final answer."
+        &self.description
     }
 
     fn parameters(&self) -> Value {
+        let mut properties = json!({
+            "task": {
+                "type": "string",
+                "description": "The specific task for the subagent to solve"
+            }
+        });
+        if !self.specialists.is_empty() {
+            let names: Vec<&str> = self.specialists.iter().map(|(n, _)| n.as_str()).collect();
+            properties["agent"] = json!({
+                "type": "string",
+                "enum": names,
+                "description": "Specialist to run the task (omit for a general-purpose subagent)"
+            });
+        }
         json!({
             "type": "object",
-            "properties": {
-                "task": {
-                    "type": "string",
-                    "description": "The specific task for the subagent to solve"
-                }
-            },
+            "properties": properties,
             "required": ["task"]
         })
     }
 
     async fn execute(&self, args: HashMap<String, Value>) -> ToolResult {
+        self.execute_with_context(args, &ToolContext::default())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        args: HashMap<String, Value>,
+        ctx: &ToolContext,
+    ) -> ToolResult {
         let task = match args.get("task").and_then(|v| v.as_str()) {
             Some(t) => t,
             None => return ToolResult::error("task is required"),
         };
 
-        // Check recursion depth
-        let depth = self.current_depth.fetch_add(1, Ordering::SeqCst);
+        // Depth travels with the call tree, so parallel subagents don't share a counter
+        let depth = ctx.depth + self.base_depth;
         if depth >= MAX_SUBAGENT_DEPTH {
-            self.current_depth.fetch_sub(1, Ordering::SeqCst);
             return ToolResult::error(format!(
                 "Subagent recursion limit reached (max depth: {}). Break the task into smaller pieces or solve directly.",
                 MAX_SUBAGENT_DEPTH
             ));
         }
 
-        tracing::info!(task = %task, depth = depth + 1, "Spawning subagent");
+        // The subagent never sees more tools than its caller.
+        let caller_tools = ctx
+            .tools
+            .clone()
+            .unwrap_or_else(|| self.agent.tools().clone());
+        let agent_name = args.get("agent").and_then(|v| v.as_str());
+        let mut opts = match agent_name {
+            Some(name) => {
+                let Some(def) = self.specialist(name) else {
+                    return ToolResult::error(format!("unknown subagent '{}'", name));
+                };
+                RunOptions {
+                    tools: Some(
+                        caller_tools.with_profile(&format!("subagent:{}", name), def.tools.clone()),
+                    ),
+                    model: Some(def.model.clone()).filter(|m| !m.is_empty()),
+                    instructions: Some(def.instructions.clone()).filter(|i| !i.is_empty()),
+                    max_iterations: def.max_iterations,
+                    ..Default::default()
+                }
+            }
+            None => RunOptions {
+                tools: Some(caller_tools),
+                ..Default::default()
+            },
+        };
+        opts.depth = depth + 1;
+        opts.chat = ctx.chat.clone();
+        // Usage rolls up to the session that started the call tree
+        opts.bill_to = Some(ctx.root_session().to_string()).filter(|s| !s.is_empty());
+
+        tracing::info!(task = %task, agent = ?agent_name, depth = opts.depth, "Spawning subagent");
 
         // Create a unique session key for this subagent task
         let subagent_session = format!("subagent:{}", uuid::Uuid::new_v4());
 
-        let result = match self.agent.process_direct(task, &subagent_session).await {
+        match self
+            .agent
+            .process_direct_with(task, &subagent_session, opts)
+            .await
+        {
             Ok(response) => {
                 tracing::info!(session = %subagent_session, "Subagent task completed");
                 ToolResult::success(response)
@@ -86,9 +173,140 @@ impl Tool for SubagentTool {
                 tracing::error!(session = %subagent_session, error = %e, "Subagent task failed");
                 ToolResult::error(format!("Subagent failed: {}", e))
             }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MessageBus;
+    use crate::config::Config;
+    use crate::provider::http::HTTPProvider;
+    use crate::tool::ToolRegistry;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn answer(text: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": text },
+                "finish_reason": "stop"
+            }]
+        }))
+    }
+
+    fn subagent_tool(api_base: &str, workspace: &std::path::Path) -> SubagentTool {
+        let mut config = Config::default();
+        config.agents.defaults.workspace = workspace.to_string_lossy().into();
+        config.agents.subagents.insert(
+            "coder".into(),
+            SubagentConfig {
+                description: "Writes code".into(),
+                model: "coder-model".into(),
+                ..Default::default()
+            },
+        );
+        config.agents.subagents.insert(
+            "analyst".into(),
+            SubagentConfig {
+                description: "Reads data".into(),
+                ..Default::default()
+            },
+        );
+        let provider = Arc::new(
+            HTTPProvider::new("test-key".into(), api_base.into(), None, "gpt-4o".into()).unwrap(),
+        );
+        let agent = AgentLoop::new(
+            config,
+            provider,
+            ToolRegistry::new(),
+            Arc::new(MessageBus::new()),
+        );
+        SubagentTool::new(Arc::new(agent))
+    }
+
+    fn args(task: &str, agent: Option<&str>) -> HashMap<String, Value> {
+        let mut args = HashMap::from([("task".to_string(), json!(task))]);
+        if let Some(agent) = agent {
+            args.insert("agent".to_string(), json!(agent));
+        }
+        args
+    }
+
+    #[tokio::test]
+    async fn test_depth_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        // Nothing listens here: a rejected call never reaches the provider
+        let tool = subagent_tool("http://127.0.0.1:9", dir.path());
+        let ctx = ToolContext {
+            depth: MAX_SUBAGENT_DEPTH,
+            ..Default::default()
+        };
+        let result = tool.execute_with_context(args("go", None), &ctx).await;
+        assert!(result.is_error);
+        assert!(
+            result.for_llm.contains("recursion limit"),
+            "{}",
+            result.for_llm
+        );
+
+        #[allow(deprecated)]
+        let inherited = SubagentTool::with_depth(
+            tool.agent.clone(),
+            Arc::new(AtomicU32::new(MAX_SUBAGENT_DEPTH)),
+        );
+        let result = inherited
+            .execute_with_context(args("go", None), &ToolContext::default())
+            .await;
+        assert!(
+            result.for_llm.contains("recursion limit"),
+            "{}",
+            result.for_llm
+        );
+    }
+
+    #[tokio::test]
+    async fn test_specialist_selection() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_string_contains("\"model\":\"coder-model\""))
+            .respond_with(answer("from coder"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(answer("from default"))
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let tool = subagent_tool(&server.uri(), dir.path());
+
+        // Specialists are offered by name, in a stable order
+        assert_eq!(
+            tool.parameters()["properties"]["agent"]["enum"],
+            json!(["analyst", "coder"])
+        );
+        assert!(tool.description().contains("- coder: Writes code"));
+
+        let ctx = ToolContext {
+            depth: MAX_SUBAGENT_DEPTH - 1,
+            ..Default::default()
         };
+        let result = tool
+            .execute_with_context(args("fix it", Some("coder")), &ctx)
+            .await;
+        assert_eq!(result.for_llm, "from coder");
+        let result = tool.execute_with_context(args("look", None), &ctx).await;
+        assert_eq!(result.for_llm, "from default");
 
-        self.current_depth.fetch_sub(1, Ordering::SeqCst);
-        result
+        let result = tool
+            .execute_with_context(args("fix it", Some("poet")), &ctx)
+            .await;
+        assert!(result.is_error);
+        assert_eq!(result.for_llm, "unknown subagent 'poet'");
     }
 }
@@ -219,6 +219,12 @@ pub async fn load_wasm_plugins(dir: &Path) -> Vec<(WasmPluginConfig, PathBuf)> {
     plugins
 }
 
+/// Read and parse a single WASM plugin manifest.
+pub async fn load_wasm_manifest(manifest_path: &Path) -> anyhow::Result<WasmPluginConfig> {
+    let content = tokio::fs::read_to_string(manifest_path).await?;
+    Ok(serde_json::from_str::<WasmPluginConfig>(&content)?)
+}
+
 /// Register loaded WASM plugins as tools in the registry.
 pub async fn register_wasm_plugins(
     registry: &ToolRegistry,
@@ -228,7 +234,14 @@ pub async fn register_wasm_plugins(
         match WasmPluginTool::new(config, &manifest_dir) {
             Ok(tool) => {
                 let name = tool.name().to_string();
-                registry.register(Arc::new(tool)).await;
+                if registry.contains(&name).await {
+                    tracing::warn!(
+                        "Ignoring WASM plugin '{}': the tool is already registered",
+                        name
+                    );
+                    continue;
+                }
+                registry.replace_plugin(Arc::new(tool)).await;
                 tracing::info!(name = %name, "Registered WASM plugin tool");
             }
             Err(e) => {
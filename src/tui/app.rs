@@ -1,5 +1,6 @@
 // QuectoClaw — TUI application state and event loop.
 
+use crate::channel::health::ChannelHealth;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -22,6 +23,8 @@ pub enum TuiEvent {
         success: bool,
         duration_ms: u64,
     },
+    /// A running tool reported progress.
+    ToolProgress(crate::tool::ToolProgress),
     /// An LLM request was made.
     LlmRequest { model: String, messages: usize },
     /// An LLM response was received.
@@ -44,6 +47,7 @@ struct TuiStateInner {
     logs: VecDeque<LogEntry>,
     active_sessions: Vec<SessionInfo>,
     stats: DashboardStats,
+    channels: Vec<ChannelHealth>,
     is_running: bool,
 }
 
@@ -102,6 +106,7 @@ impl TuiState {
                 logs: VecDeque::with_capacity(MAX_LOG_ENTRIES),
                 active_sessions: Vec::new(),
                 stats: DashboardStats::default(),
+                channels: Vec::new(),
                 is_running: true,
             })),
         }
@@ -147,6 +152,10 @@ impl TuiState {
         self.inner.read().await.active_sessions.clone()
     }
 
+    pub async fn get_channels(&self) -> Vec<ChannelHealth> {
+        self.inner.read().await.channels.clone()
+    }
+
     pub async fn is_running(&self) -> bool {
         self.inner.read().await.is_running
     }
@@ -210,6 +219,16 @@ impl TuiState {
                     message: format!("{} {} ({}ms)", status, tool, duration_ms),
                 });
             }
+            TuiEvent::ToolProgress(p) => {
+                if inner.logs.len() >= MAX_LOG_ENTRIES {
+                    inner.logs.pop_front();
+                }
+                inner.logs.push_back(LogEntry {
+                    timestamp: chrono::Local::now().format("%H:%M:%S").to_string(),
+                    level: LogLevel::Tool,
+                    message: format!("… {} {}", p.tool, p.describe()),
+                });
+            }
             TuiEvent::LlmRequest { model, messages } => {
                 inner.stats.total_requests += 1;
                 if inner.logs.len() >= MAX_LOG_ENTRIES {
@@ -265,8 +284,11 @@ impl TuiState {
         self.inner.write().await.stats.uptime_secs = secs;
     }
 
-    pub async fn set_active_channels(&self, count: usize) {
-        self.inner.write().await.stats.active_channels = count;
+    /// Replace the channel health shown, counting the connected ones.
+    pub async fn set_channels(&self, channels: Vec<ChannelHealth>) {
+        let mut inner = self.inner.write().await;
+        inner.stats.active_channels = channels.iter().filter(|c| c.connected()).count();
+        inner.channels = channels;
     }
 }
 
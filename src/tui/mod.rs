@@ -1,6 +1,7 @@
 pub mod app;
 pub mod ui;
 
+use crate::channel::health::HealthSnapshot;
 use crate::config::Config;
 use crate::tui::app::{LogLevel, TuiState};
 use crossterm::{
@@ -15,18 +16,10 @@ use std::io;
 /// Run the TUI dashboard loop in the context of the current terminal.
 /// This will take over the terminal and block until the user quits.
 pub async fn run(state: TuiState, cfg: Config) -> anyhow::Result<()> {
-    // Count enabled channels
-    let mut channel_count = 0;
-    if cfg.channels.telegram.enabled {
-        channel_count += 1;
-    }
-    if cfg.channels.discord.enabled {
-        channel_count += 1;
-    }
-    if cfg.channels.slack.enabled {
-        channel_count += 1;
-    }
-    state.set_active_channels(channel_count).await;
+    // Channel health comes from the gateway's snapshot, which may be
+    // written by another process
+    let workspace = cfg.workspace_path().ok();
+    let mut health_checked: Option<std::time::Instant> = None;
 
     // Push initial log if empty
     state
@@ -47,14 +40,27 @@ pub async fn run(state: TuiState, cfg: Config) -> anyhow::Result<()> {
         // Update uptime
         state.update_uptime(start_time.elapsed().as_secs()).await;
 
+        // Refresh channel health about once a second
+        if health_checked.is_none_or(|at| at.elapsed().as_secs() >= 1) {
+            health_checked = Some(std::time::Instant::now());
+            let channels = workspace
+                .as_deref()
+                .and_then(HealthSnapshot::load)
+                .filter(|s| s.is_live())
+                .map(|s| s.channels)
+                .unwrap_or_default();
+            state.set_channels(channels).await;
+        }
+
         // Collect state for rendering
         let logs = state.get_logs().await;
         let sessions = state.get_sessions().await;
         let stats = state.get_stats().await;
+        let channels = state.get_channels().await;
 
         // Draw
         terminal.draw(|frame| {
-            ui::render(frame, &logs, &sessions, &stats);
+            ui::render(frame, &logs, &sessions, &channels, &stats);
         })?;
 
         // Poll for events (200ms timeout for smooth animation)
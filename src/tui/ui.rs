@@ -1,6 +1,7 @@
 // QuectoClaw — TUI rendering (ratatui widgets and layout).
 
 use super::app::{DashboardStats, LogEntry, LogLevel, SessionInfo};
+use crate::channel::health::{ChannelHealth, ChannelState};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
@@ -17,6 +18,7 @@ pub fn render(
     frame: &mut Frame,
     logs: &[LogEntry],
     sessions: &[SessionInfo],
+    channels: &[ChannelHealth],
     stats: &DashboardStats,
 ) {
     let area = frame.area();
@@ -32,7 +34,7 @@ pub fn render(
         .split(area);
 
     render_header(frame, outer[0]);
-    render_body(frame, outer[1], logs, sessions, stats);
+    render_body(frame, outer[1], logs, sessions, channels, stats);
     render_footer(frame, outer[2], stats);
 }
 
@@ -66,6 +68,7 @@ fn render_body(
     area: Rect,
     logs: &[LogEntry],
     sessions: &[SessionInfo],
+    channels: &[ChannelHealth],
     stats: &DashboardStats,
 ) {
     // Horizontal split: stats+sessions (30%) | logs (70%)
@@ -74,14 +77,20 @@ fn render_body(
         .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
         .split(area);
 
-    // Left column: stats on top, sessions below
+    // Left column: stats on top, then channels, sessions below
+    let channel_rows = channels.len().max(1) as u16;
     let left = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(9), Constraint::Min(4)])
+        .constraints([
+            Constraint::Length(9),
+            Constraint::Length(channel_rows + 2),
+            Constraint::Min(4),
+        ])
         .split(columns[0]);
 
     render_stats(frame, left[0], stats);
-    render_sessions(frame, left[1], sessions);
+    render_channels(frame, left[1], channels);
+    render_sessions(frame, left[2], sessions);
 
     // Right column: logs
     render_logs(frame, columns[1], logs);
@@ -159,6 +168,56 @@ fn render_stats(frame: &mut Frame, area: Rect, stats: &DashboardStats) {
     frame.render_widget(widget, area);
 }
 
+fn render_channels(frame: &mut Frame, area: Rect, channels: &[ChannelHealth]) {
+    let block = Block::default()
+        .title(" ◉ Channels ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Blue));
+
+    if channels.is_empty() {
+        let widget = Paragraph::new("  No gateway running")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(block);
+        frame.render_widget(widget, area);
+        return;
+    }
+
+    let lines: Vec<Line> = channels
+        .iter()
+        .map(|c| {
+            let color = match c.state {
+                ChannelState::Connected => Color::Green,
+                ChannelState::Starting | ChannelState::Reconnecting => Color::Yellow,
+                ChannelState::Stopped => Color::DarkGray,
+            };
+            let last = c
+                .last_message
+                .map(|at| at.with_timezone(&chrono::Local).format("%H:%M").to_string())
+                .unwrap_or_else(|| "--:--".into());
+            let mut spans = vec![
+                Span::styled("  ● ", Style::default().fg(color)),
+                Span::styled(format!("{:<10}", c.name), Style::default().fg(Color::Gray)),
+                Span::styled(
+                    format!("{:<13}", c.state.label()),
+                    Style::default().fg(color),
+                ),
+                Span::styled(last, Style::default().fg(Color::DarkGray)),
+            ];
+            if c.state != ChannelState::Connected {
+                if let Some(error) = &c.last_error {
+                    spans.push(Span::styled(
+                        format!("  {}", error),
+                        Style::default().fg(Color::Red),
+                    ));
+                }
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
 fn render_sessions(frame: &mut Frame, area: Rect, sessions: &[SessionInfo]) {
     if sessions.is_empty() {
         let widget = Paragraph::new("  No active sessions")
@@ -0,0 +1,79 @@
+// QuectoClaw — Exponential backoff for reconnecting and restarting
+
+use std::time::{Duration, Instant};
+
+/// Reconnect delay for a long-lived connection: starts at `initial` and
+/// doubles after each failure up to `max`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+    connected_at: Option<Instant>,
+}
+
+impl Backoff {
+    pub const INITIAL: Duration = Duration::from_secs(1);
+    pub const MAX: Duration = Duration::from_secs(60);
+
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+            connected_at: None,
+        }
+    }
+
+    /// Note that a connection attempt starts now.
+    pub fn attempt(&mut self) {
+        self.connected_at = Some(Instant::now());
+    }
+
+    /// Start over from the initial delay.
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+
+    /// The next delay. A connection that stayed up for a while earns a
+    /// fresh backoff.
+    pub fn delay(&self) -> Duration {
+        match self.connected_at {
+            Some(at) if at.elapsed() >= self.max => self.initial,
+            _ => self.current,
+        }
+    }
+
+    /// Take the next delay and double the one after it.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay();
+        self.current = (delay * 2).min(self.max);
+        self.connected_at = None;
+        delay
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Self::INITIAL, Self::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_resets_after_long_connection() {
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(40));
+        let delays: Vec<u128> = (0..4).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, vec![10, 20, 40, 40]);
+
+        // A connection that lasted longer than the cap starts over
+        backoff.attempt();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(10));
+        backoff.attempt();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(20));
+    }
+}
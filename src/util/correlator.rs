@@ -0,0 +1,127 @@
+// QuectoClaw — Request/reply correlation over a shared socket
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+/// Why a correlated request got no reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unanswered {
+    NotConnected,
+    /// The connection dropped while waiting.
+    Closed,
+    TimedOut,
+}
+
+/// A socket shared by its reader loop and any number of senders: frames
+/// to write, and requests waiting for the reply that carries their id
+/// (OneBot `echo`, WhatsApp bridge `ref`).
+#[derive(Clone, Default)]
+pub struct Correlator {
+    outgoing: Arc<Mutex<Option<mpsc::Sender<String>>>>,
+    pending: Arc<Mutex<HashMap<String, oneshot::Sender<serde_json::Value>>>>,
+    connected: Arc<AtomicBool>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Correlator {
+    /// A fresh request id.
+    pub fn next_id(&self) -> String {
+        (self.next_id.fetch_add(1, Ordering::Relaxed) + 1).to_string()
+    }
+
+    /// A connection is up; frames go to `outgoing`.
+    pub async fn connect(&self, outgoing: mpsc::Sender<String>) {
+        *self.outgoing.lock().await = Some(outgoing);
+        self.connected.store(true, Ordering::SeqCst);
+    }
+
+    /// The connection is gone: refuse new frames and fail waiting requests.
+    pub async fn disconnect(&self) {
+        self.connected.store(false, Ordering::SeqCst);
+        *self.outgoing.lock().await = None;
+        self.pending.lock().await.clear();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Write `frame` and wait up to `timeout` for the reply to `id`.
+    pub async fn request(
+        &self,
+        id: &str,
+        frame: String,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, Unanswered> {
+        let Some(outgoing) = self.outgoing.lock().await.clone() else {
+            return Err(Unanswered::NotConnected);
+        };
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(id.to_string(), tx);
+        if outgoing.send(frame).await.is_err() {
+            self.pending.lock().await.remove(id);
+            return Err(Unanswered::Closed);
+        }
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(Unanswered::Closed),
+            Err(_) => {
+                self.pending.lock().await.remove(id);
+                Err(Unanswered::TimedOut)
+            }
+        }
+    }
+
+    /// Hand `reply` to the request waiting for `id`, if any.
+    pub async fn resolve(&self, id: &str, reply: serde_json::Value) {
+        if let Some(tx) = self.pending.lock().await.remove(id) {
+            let _ = tx.send(reply);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_correlator_matches_replies_by_id() {
+        let conn = Correlator::default();
+        assert!(!conn.is_connected());
+        assert_eq!(
+            conn.request("1", "x".into(), Duration::from_secs(1)).await,
+            Err(Unanswered::NotConnected)
+        );
+
+        let (tx, mut rx) = mpsc::channel(4);
+        conn.connect(tx).await;
+        assert!(conn.is_connected());
+        let id = conn.next_id();
+        let replier = {
+            let conn = conn.clone();
+            tokio::spawn(async move {
+                let frame = rx.recv().await.unwrap();
+                conn.resolve("other", serde_json::json!("wrong")).await;
+                conn.resolve(&frame, serde_json::json!({ "ok": true }))
+                    .await;
+                rx
+            })
+        };
+        let reply = conn.request(&id, id.clone(), Duration::from_secs(5)).await;
+        assert_eq!(reply, Ok(serde_json::json!({ "ok": true })));
+
+        // Dropping the connection fails requests still waiting
+        let mut rx = replier.await.unwrap();
+        let waiting = {
+            let conn = conn.clone();
+            tokio::spawn(async move { conn.request("9", "y".into(), Duration::from_secs(5)).await })
+        };
+        rx.recv().await.unwrap();
+        conn.disconnect().await;
+        assert_eq!(waiting.await.unwrap(), Err(Unanswered::Closed));
+        assert!(!conn.is_connected());
+    }
+}
@@ -0,0 +1,7 @@
+// QuectoClaw — Helpers shared by channels, MCP and other long-lived connections
+
+pub mod backoff;
+pub mod correlator;
+
+pub use backoff::Backoff;
+pub use correlator::{Correlator, Unanswered};
@@ -1,6 +1,7 @@
 // QuectoClaw — Web UI handlers
 
 use super::WebState;
+use crate::channel::health::HealthSnapshot;
 use axum::extract::{Query, State};
 use axum::response::sse::{Event, Sse};
 use axum::response::{Html, IntoResponse, Json};
@@ -54,6 +55,13 @@ pub async fn api_metrics(State(state): State<Arc<WebState>>) -> Json<serde_json:
 pub async fn api_status(State(state): State<Arc<WebState>>) -> Json<serde_json::Value> {
     let provider = state.config.resolve_provider();
     let model = &state.config.agents.defaults.model;
+    // Written by the gateway, which runs as its own process
+    let gateway = state
+        .config
+        .workspace_path()
+        .ok()
+        .and_then(|ws| HealthSnapshot::load(&ws));
+    let gateway_running = gateway.as_ref().is_some_and(|g| g.is_live());
 
     Json(serde_json::json!({
         "status": "online",
@@ -64,6 +72,8 @@ pub async fn api_status(State(state): State<Arc<WebState>>) -> Json<serde_json::
         "routing_enabled": state.config.routing.enabled,
         "cost_tracking_enabled": state.config.cost.enabled,
         "budget_limit": state.config.cost.budget_limit,
+        "gateway_running": gateway_running,
+        "channels": gateway.map(|g| g.channels).unwrap_or_default(),
     }))
 }
 
@@ -114,6 +124,15 @@ pub async fn api_chat_stream(
                     let data = serde_json::json!({ "tool": name }).to_string();
                     return Some((Ok::<Event, std::convert::Infallible>(Event::default().data(data)), (rx, session)));
                 }
+                crate::provider::StreamEvent::ToolProgress(p) => {
+                    let data = serde_json::json!({
+                        "tool": p.tool,
+                        "progress": p.progress,
+                        "total": p.total,
+                        "message": p.message,
+                    }).to_string();
+                    return Some((Ok::<Event, std::convert::Infallible>(Event::default().data(data)), (rx, session)));
+                }
                 crate::provider::StreamEvent::Done(resp) => {
                     let data = serde_json::json!({
                         "done": true,
@@ -14,4 +14,7 @@ pub struct Step {
     pub prompt: String,
     #[serde(default)]
     pub variables: std::collections::HashMap<String, String>,
+    /// Tool profile for this step (overrides the session's profile).
+    #[serde(default)]
+    pub profile: Option<String>,
 }
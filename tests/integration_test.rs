@@ -234,3 +234,747 @@ async fn test_tool_failure_handling() {
 
     assert!(result.contains("does not exist"));
 }
+
+fn completion(message: serde_json::Value, prompt: usize, completion: usize) -> serde_json::Value {
+    json!({
+        "object": "chat.completion",
+        "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }],
+        "usage": {
+            "prompt_tokens": prompt,
+            "completion_tokens": completion,
+            "total_tokens": prompt + completion
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_specialist_subagents_run_in_parallel_and_bill_parent() {
+    use quectoclaw::config::SubagentConfig;
+    use quectoclaw::tool::subagent::SubagentTool;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    let body_has = |needle: &'static str| {
+        move |req: &wiremock::Request| String::from_utf8_lossy(&req.body).contains(needle)
+    };
+
+    // Parent, first turn: fan out to two specialists at once
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_has("\"model\":\"gpt-4o\""))
+        .and(|req: &wiremock::Request| {
+            !String::from_utf8_lossy(&req.body).contains("\"role\":\"tool\"")
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion(
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [
+                    {
+                        "id": "call_a",
+                        "type": "function",
+                        "function": {
+                            "name": "subagent",
+                            "arguments": "{\"task\":\"research A\",\"agent\":\"researcher\"}"
+                        }
+                    },
+                    {
+                        "id": "call_b",
+                        "type": "function",
+                        "function": {
+                            "name": "subagent",
+                            "arguments": "{\"task\":\"research B\",\"agent\":\"researcher\"}"
+                        }
+                    }
+                ]
+            }),
+            10,
+            5,
+        )))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // Specialists run on their own model with their own instructions
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_has("\"model\":\"cheap-model\""))
+        .and(body_has("Cite your sources."))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion(
+            json!({ "role": "assistant", "content": "finding" }),
+            100,
+            20,
+        )))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    // Parent, second turn: summarize the tool results
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(body_has("\"model\":\"gpt-4o\""))
+        .and(body_has("\"role\":\"tool\""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(completion(
+            json!({ "role": "assistant", "content": "Both findings collected" }),
+            30,
+            5,
+        )))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    config.agents.defaults.model = "gpt-4o".into();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    config.agents.subagents.insert(
+        "researcher".into(),
+        SubagentConfig {
+            description: "Looks things up".into(),
+            model: "cheap-model".into(),
+            instructions: "Cite your sources.".into(),
+            ..Default::default()
+        },
+    );
+
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let registry = ToolRegistry::new();
+    let bus = Arc::new(MessageBus::new());
+    let agent = Arc::new(AgentLoop::new(config, provider, registry.clone(), bus));
+    registry
+        .register(Arc::new(SubagentTool::new(agent.clone())))
+        .await;
+
+    let result = agent
+        .run_agent_loop("research A and B", "cli:parent", false, None)
+        .await
+        .unwrap();
+    assert_eq!(result, "Both findings collected");
+
+    let usage = agent.metrics().session_usage("cli:parent").await;
+    assert_eq!(usage.llm_requests, 4);
+    assert_eq!(usage.prompt_tokens, 10 + 2 * 100 + 30);
+    assert_eq!(usage.completion_tokens, 5 + 2 * 20 + 5);
+}
+
+#[tokio::test]
+async fn test_scheduled_job_result_is_delivered_to_origin_chat() {
+    use quectoclaw::bus::{InboundMessage, OutboundMessage};
+    use quectoclaw::scheduler::{Job, JobSchedule, JobStore, Scheduler};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "Stretch your legs",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "⏰ Time to stretch!" },
+                "finish_reason": "stop"
+            }]
+        })))
+        // The job's request also carries the slow question from history
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains("slow question"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(Duration::from_millis(500))
+                .set_body_json(json!({
+                    "object": "chat.completion",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": "Slow answer" },
+                        "finish_reason": "stop"
+                    }]
+                })),
+        )
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    config.channels.streaming.typing = false;
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutboundMessage>(4);
+    bus.register_handler("telegram", tx).await;
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        ToolRegistry::new(),
+        bus.clone(),
+    ));
+
+    tokio::spawn(agent.clone().run());
+
+    // A job firing mid-turn waits for the turn, so they do not interleave
+    // writes to the session's history
+    bus.publish_inbound(InboundMessage {
+        channel: "telegram".into(),
+        sender_id: "7".into(),
+        chat_id: "42".into(),
+        content: "slow question".into(),
+        media: vec![],
+        session_key: "telegram:42".into(),
+        metadata: HashMap::new(),
+        group: None,
+    })
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let at = chrono::Utc::now() + chrono::Duration::minutes(1);
+    let job = Job::new("Stretch your legs", JobSchedule::At { at }, "telegram:42").unwrap();
+    let scheduler = Scheduler::new(Arc::new(JobStore::new(tmp_dir.path())), agent, bus);
+    scheduler.run_job(job.clone()).await;
+
+    assert_eq!(rx.recv().await.unwrap().content, "Slow answer");
+    let msg = rx.recv().await.unwrap();
+    assert_eq!(msg.channel, "telegram");
+    assert_eq!(msg.chat_id, "42");
+    assert_eq!(msg.content, "⏰ Time to stretch!");
+    assert_eq!(msg.metadata.get("job_id"), Some(&job.id));
+}
+
+#[tokio::test]
+async fn test_agent_sends_maixcam_command() {
+    use quectoclaw::bus::OutboundMessage;
+    use quectoclaw::tool::maixcam_command::MaixCamCommandTool;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "\"role\":\"user\"",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_cam",
+                        "type": "function",
+                        "function": {
+                            "name": "maixcam_command",
+                            "arguments": "{\"command\":\"capture\",\"args\":{\"resolution\":\"hd\"}}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        })))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "\"role\":\"tool\"",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Capturing a photo." },
+                "finish_reason": "stop"
+            }]
+        })))
+        .up_to_n_times(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutboundMessage>(4);
+    bus.register_handler("maixcam", tx).await;
+    let registry = ToolRegistry::new();
+    registry
+        .register(Arc::new(MaixCamCommandTool::new(bus.clone())))
+        .await;
+    let agent = AgentLoop::new(config, provider, registry, bus);
+
+    // The device defaults to the one the conversation is with
+    let result = agent
+        .run_agent_loop("take a picture", "maixcam:cam-1", true, None)
+        .await
+        .unwrap();
+    assert_eq!(result, "Capturing a photo.");
+
+    let msg = rx.recv().await.unwrap();
+    assert_eq!(msg.channel, "maixcam");
+    assert_eq!(msg.chat_id, "cam-1");
+    assert_eq!(msg.metadata["command"], "capture");
+    let args: serde_json::Value = serde_json::from_str(&msg.metadata["args"]).unwrap();
+    assert_eq!(args, json!({ "resolution": "hd" }));
+}
+
+#[tokio::test]
+async fn test_heartbeat_runs_due_items_once_and_delivers() {
+    use quectoclaw::agent::heartbeat::HeartbeatService;
+    use quectoclaw::bus::OutboundMessage;
+    use quectoclaw::config::HeartbeatConfig;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "Items due now:\\n- Water plants",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "🌱 Remember to water the plants." },
+                "finish_reason": "stop"
+            }]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    let heartbeat_md = tmp_dir.path().join("HEARTBEAT.md");
+    std::fs::write(
+        &heartbeat_md,
+        "- [ ] Water plants\n- [ ] Book flights @2999-01-01\n",
+    )
+    .unwrap();
+
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutboundMessage>(4);
+    bus.register_handler("telegram", tx).await;
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        ToolRegistry::new(),
+        bus.clone(),
+    ));
+
+    let hb_config = HeartbeatConfig {
+        channel: "telegram".into(),
+        chat_id: "42".into(),
+        timezone: "UTC".into(),
+        quiet_hours: "00:00-06:00".into(),
+        ..Default::default()
+    };
+    let mut heartbeat = HeartbeatService::new(agent, bus, hb_config);
+    let noon = chrono::DateTime::parse_from_rfc3339("2030-01-01T12:00:00Z")
+        .unwrap()
+        .with_timezone(&chrono::Utc);
+
+    // Quiet hours: no LLM call at all
+    let night = noon - chrono::Duration::hours(9);
+    assert!(heartbeat.tick(night).await.is_none());
+
+    let reply = heartbeat.tick(noon).await.unwrap();
+    assert!(reply.contains("water the plants"));
+    let msg = rx.recv().await.unwrap();
+    assert_eq!(
+        (msg.channel.as_str(), msg.chat_id.as_str()),
+        ("telegram", "42")
+    );
+
+    // The item is ticked off, so the next tick skips the LLM
+    let md = std::fs::read_to_string(&heartbeat_md).unwrap();
+    assert!(md.contains("- [x] Water plants"));
+    assert!(md.contains("- [ ] Book flights"));
+    assert!(heartbeat.tick(noon).await.is_none());
+
+    // An edit runs the file once even though nothing is due. The session
+    // history still holds the first prompt, so this mock must win.
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains("Buy milk"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "HEARTBEAT_OK" },
+                "finish_reason": "stop"
+            }]
+        })))
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    std::fs::write(&heartbeat_md, md + "- [ ] Buy milk @2999-02-01\n").unwrap();
+    assert_eq!(heartbeat.tick(noon).await.as_deref(), Some("HEARTBEAT_OK"));
+    assert!(heartbeat.tick(noon).await.is_none());
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_heartbeat_ok_leaves_due_items_unchecked() {
+    use quectoclaw::agent::heartbeat::HeartbeatService;
+    use quectoclaw::config::HeartbeatConfig;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "HEARTBEAT_OK" },
+                "finish_reason": "stop"
+            }]
+        })))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    let heartbeat_md = tmp_dir.path().join("HEARTBEAT.md");
+    std::fs::write(
+        &heartbeat_md,
+        "- [ ] Check the backups
+",
+    )
+    .unwrap();
+
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        ToolRegistry::new(),
+        bus.clone(),
+    ));
+    let hb_config = HeartbeatConfig {
+        timezone: "UTC".into(),
+        ..Default::default()
+    };
+    let mut heartbeat = HeartbeatService::new(agent, bus, hb_config);
+    let noon = chrono::DateTime::parse_from_rfc3339("2030-01-01T12:00:00Z")
+        .unwrap()
+        .with_timezone(&chrono::Utc);
+
+    // Nothing was done, so the item stays due and runs again next tick
+    assert_eq!(heartbeat.tick(noon).await.as_deref(), Some("HEARTBEAT_OK"));
+    let md = std::fs::read_to_string(&heartbeat_md).unwrap();
+    assert_eq!(md, "- [ ] Check the backups\n");
+    assert_eq!(heartbeat.tick(noon).await.as_deref(), Some("HEARTBEAT_OK"));
+}
+
+#[tokio::test]
+async fn test_gateway_commands_stop_and_switch_model() {
+    use quectoclaw::bus::{InboundMessage, OutboundMessage};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "\"model\":\"gpt-4o-mini\"",
+        ))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(Duration::from_secs(30))
+                .set_body_json(json!({
+                    "object": "chat.completion",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": "Too late" },
+                        "finish_reason": "stop"
+                    }]
+                })),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "\"model\":\"cheap-model\"",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Answered by the cheap model" },
+                "finish_reason": "stop"
+            }]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    config.channels.streaming.typing = false;
+    config.gateway.admins = vec!["telegram:1".into()];
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutboundMessage>(8);
+    bus.register_handler("telegram", tx).await;
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        ToolRegistry::new(),
+        bus.clone(),
+    ));
+    tokio::spawn(agent.clone().run());
+
+    let say = |sender: &str, content: &str| InboundMessage {
+        channel: "telegram".into(),
+        sender_id: sender.into(),
+        chat_id: "42".into(),
+        content: content.into(),
+        media: vec![],
+        session_key: "telegram:42".into(),
+        metadata: HashMap::new(),
+        group: None,
+    };
+    async fn reply(rx: &mut tokio::sync::mpsc::Receiver<OutboundMessage>) -> String {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no reply")
+            .unwrap()
+            .content
+    }
+
+    bus.publish_inbound(say("2", "slow question")).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // The running turn would save its history over a reset
+    bus.publish_inbound(say("2", "/reset")).await;
+    assert_eq!(
+        reply(&mut rx).await,
+        "⏳ A reply is still in progress. Wait for it or /stop it before /reset."
+    );
+    bus.publish_inbound(say("2", "/stop")).await;
+    assert_eq!(reply(&mut rx).await, "⏹️ Stopped.");
+
+    bus.publish_inbound(say("2", "/model cheap-model")).await;
+    assert_eq!(reply(&mut rx).await, "⛔ /model is for admins only.");
+    bus.publish_inbound(say("1", "/model cheap-model")).await;
+    assert_eq!(
+        reply(&mut rx).await,
+        "Model for this conversation: cheap-model"
+    );
+
+    bus.publish_inbound(say("2", "hello")).await;
+    assert_eq!(reply(&mut rx).await, "Answered by the cheap model");
+
+    // Only admins may fork into a session of their choosing
+    bus.publish_inbound(say("2", "/fork telegram:99")).await;
+    assert_eq!(
+        reply(&mut rx).await,
+        "✂️ Forked session to: telegram:42-fork-telegram:99"
+    );
+    bus.publish_inbound(say("1", "/fork telegram:99")).await;
+    assert_eq!(reply(&mut rx).await, "✂️ Forked session to: telegram:99");
+}
+
+#[tokio::test]
+async fn test_webhook_turns_always_answer_their_request() {
+    use quectoclaw::bus::{InboundMessage, OutboundMessage};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains("broken question"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("bad request"))
+        .mount(&mock_server)
+        .await;
+    // The failed question stays in the history, so this one must win
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains("slow question"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(Duration::from_secs(30))
+                .set_body_json(json!({
+                    "object": "chat.completion",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": "Too late" },
+                        "finish_reason": "stop"
+                    }]
+                })),
+        )
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    config.channels.streaming.typing = false;
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutboundMessage>(8);
+    bus.register_handler("webhook", tx).await;
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        ToolRegistry::new(),
+        bus.clone(),
+    ));
+    tokio::spawn(agent.clone().run());
+
+    let request = |id: &str, content: &str| InboundMessage {
+        channel: "webhook".into(),
+        sender_id: "ci".into(),
+        chat_id: "build-42".into(),
+        content: content.into(),
+        media: vec![],
+        session_key: "webhook:build-42".into(),
+        metadata: HashMap::from([("request_id".to_string(), id.to_string())]),
+        group: None,
+    };
+    async fn reply(rx: &mut tokio::sync::mpsc::Receiver<OutboundMessage>) -> (String, String) {
+        let msg = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no reply")
+            .unwrap();
+        (msg.metadata["request_id"].clone(), msg.content)
+    }
+
+    // A failed turn still answers its request
+    bus.publish_inbound(request("r1", "broken question")).await;
+    let (id, content) = reply(&mut rx).await;
+    assert_eq!(id, "r1");
+    assert!(content.starts_with("⚠️"), "{}", content);
+
+    // So does a stopped one, besides the answer to /stop itself
+    bus.publish_inbound(request("r2", "slow question")).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    bus.publish_inbound(request("r3", "/stop")).await;
+    assert_eq!(reply(&mut rx).await, ("r2".into(), "⏹️ Stopped.".into()));
+    assert_eq!(reply(&mut rx).await, ("r3".into(), "⏹️ Stopped.".into()));
+}
+
+#[tokio::test]
+async fn test_stop_mid_stream_closes_the_placeholder() {
+    use quectoclaw::bus::{InboundMessage, OutboundKind, OutboundMessage};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    init_tracing();
+    let mock_server = MockServer::start().await;
+    // The answer after the tool result never arrives in time
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .and(wiremock::matchers::body_string_contains(
+            "\"role\":\"tool\"",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+    let sse = [
+        json!({ "choices": [{ "index": 0, "delta": { "content": "Let me check" } }] }),
+        json!({ "choices": [{ "index": 0, "delta": { "tool_calls": [{
+            "index": 0, "id": "call_1",
+            "function": { "name": "lookup", "arguments": "{}" }
+        }] }, "finish_reason": "tool_calls" }] }),
+    ]
+    .iter()
+    .map(|chunk| format!("data: {}\n\n", chunk))
+    .collect::<String>()
+        + "data: [DONE]\n\n";
+    Mock::given(method("POST"))
+        .and(path("/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream"))
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::default();
+    let tmp_dir = tempfile::tempdir().unwrap();
+    config.agents.defaults.workspace = tmp_dir.path().to_string_lossy().to_string();
+    config.channels.streaming.typing = false;
+    config.channels.streaming.partial_replies = true;
+    config.channels.streaming.tool_progress = true;
+    config.channels.streaming.update_interval_ms = 20;
+    let provider = Arc::new(
+        HTTPProvider::new("test-key".into(), mock_server.uri(), None, "gpt-4o".into()).unwrap(),
+    );
+    let bus = Arc::new(MessageBus::new());
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<OutboundMessage>(64);
+    bus.register_handler("telegram", tx).await;
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        ToolRegistry::new(),
+        bus.clone(),
+    ));
+    tokio::spawn(agent.clone().run());
+
+    let say = |content: &str| InboundMessage {
+        channel: "telegram".into(),
+        sender_id: "1".into(),
+        chat_id: "42".into(),
+        content: content.into(),
+        media: vec![],
+        session_key: "telegram:42".into(),
+        metadata: HashMap::new(),
+        group: None,
+    };
+    async fn next(rx: &mut tokio::sync::mpsc::Receiver<OutboundMessage>) -> OutboundMessage {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no message")
+            .unwrap()
+    }
+
+    bus.publish_inbound(say("what's the weather?")).await;
+    // Wait until the placeholder shows the tool running
+    let stream_id = loop {
+        let msg = next(&mut rx).await;
+        if let OutboundKind::Update { stream_id } = msg.kind {
+            if msg.content.contains("running lookup") {
+                break stream_id;
+            }
+        }
+    };
+
+    bus.publish_inbound(say("/stop")).await;
+    let mut seen = [next(&mut rx).await, next(&mut rx).await];
+    seen.sort_by_key(|msg| msg.kind == OutboundKind::Message);
+    assert_eq!(seen[0].kind, OutboundKind::Cancelled { stream_id });
+    assert_eq!(seen[0].content, "⏹️ Stopped.");
+    assert_eq!(seen[1].content, "⏹️ Stopped.");
+}
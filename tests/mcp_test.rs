@@ -1,11 +1,16 @@
-use quectoclaw::mcp::MCPClient;
+use quectoclaw::agent::AgentLoop;
+use quectoclaw::bus::MessageBus;
+use quectoclaw::config::{Config, MCPServerConfig, MCPTransportKind, ToolProfile};
+use quectoclaw::mcp::{init_mcp_servers, MCPClient};
+use quectoclaw::provider::http::HTTPProvider;
+use quectoclaw::tool::{ToolContext, ToolProgress, ToolRegistry};
 use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 #[tokio::test]
 async fn test_mcp_client_mock() {
-    let mock_script = "/home/pain/Desktop/QuectoClaw/tests/mock_mcp.py";
+    let mock_script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/mock_mcp.py");
     let client = MCPClient::spawn(
         "mock",
         "python3",
@@ -53,3 +58,571 @@ async fn test_mcp_client_mock() {
     let text = content[0].get("text").and_then(|t| t.as_str()).unwrap();
     assert_eq!(text, "MOCK_ECHO: hello mcp");
 }
+
+/// Start `tests/mock_mcp_http.py` and return it with its base URL.
+async fn start_http_mock() -> (tokio::process::Child, String) {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/mock_mcp_http.py");
+    let mut child = tokio::process::Command::new("python3")
+        .arg(script)
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let port = BufReader::new(stdout)
+        .lines()
+        .next_line()
+        .await
+        .unwrap()
+        .unwrap();
+    (child, format!("http://127.0.0.1:{}", port.trim()))
+}
+
+fn http_server_config(url: String, kind: MCPTransportKind) -> MCPServerConfig {
+    MCPServerConfig {
+        transport: Some(kind),
+        url,
+        bearer_token: "test-token".into(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_mcp_http_transports_register_tools() {
+    let (_server, base) = start_http_mock().await;
+
+    let mut config = Config::default();
+    config.mcp.servers.insert(
+        "remote".into(),
+        http_server_config(format!("{}/mcp", base), MCPTransportKind::Http),
+    );
+    config.mcp.servers.insert(
+        "legacy".into(),
+        http_server_config(format!("{}/sse", base), MCPTransportKind::Sse),
+    );
+
+    let registry = ToolRegistry::new();
+    init_mcp_servers(&config, &registry).await.unwrap();
+
+    // Streamable HTTP: the tools/call reply arrives as an SSE stream
+    let mut args = HashMap::new();
+    args.insert("text".to_string(), json!("over http"));
+    let result = registry.execute("remote_echo", args.clone()).await;
+    assert!(!result.is_error, "{}", result.for_llm);
+    assert_eq!(result.for_llm, "HTTP_ECHO: over http");
+
+    // Legacy SSE: replies arrive on the GET stream
+    let result = registry.execute("legacy_echo", args).await;
+    assert!(!result.is_error, "{}", result.for_llm);
+    assert_eq!(result.for_llm, "HTTP_ECHO: over http");
+}
+
+#[tokio::test]
+async fn test_mcp_http_rejects_missing_token() {
+    let (_server, base) = start_http_mock().await;
+
+    let mut cfg = http_server_config(format!("{}/mcp", base), MCPTransportKind::Http);
+    cfg.bearer_token.clear();
+    let client = MCPClient::connect("remote", &cfg).await.unwrap();
+    let err = client.call("tools/list", json!({})).await.unwrap_err();
+    assert!(err.to_string().contains("401"), "{}", err);
+    // Only that request failed
+    assert!(!client.is_closed());
+
+    let mut cfg = http_server_config(format!("{}/sse", base), MCPTransportKind::Sse);
+    cfg.bearer_token.clear();
+    assert!(MCPClient::connect("legacy", &cfg).await.is_err());
+}
+
+#[tokio::test]
+async fn test_mcp_http_expired_session_closes_the_connection() {
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_string_contains("initialize"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Mcp-Session-Id", "s1")
+                .set_body_json(json!({ "jsonrpc": "2.0", "id": "1", "result": {} })),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    // The server restarted and forgot the session
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let cfg = http_server_config(format!("{}/mcp", server.uri()), MCPTransportKind::Http);
+    let client = MCPClient::connect("restarted", &cfg).await.unwrap();
+    client.call("initialize", json!({})).await.unwrap();
+    assert!(!client.is_closed());
+
+    let err = client.call("tools/list", json!({})).await.unwrap_err();
+    assert!(err.to_string().contains("expired"), "{}", err);
+    assert!(client.is_closed());
+}
+
+#[tokio::test]
+async fn test_mcp_http_timeout_covers_a_stalled_post() {
+    use std::time::{Duration, Instant};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(Duration::from_secs(30))
+                .set_body_json(json!({ "jsonrpc": "2.0", "id": "1", "result": {} })),
+        )
+        .mount(&server)
+        .await;
+
+    let mut cfg = http_server_config(format!("{}/mcp", server.uri()), MCPTransportKind::Http);
+    cfg.timeout = 1;
+    let client = MCPClient::connect("stalled", &cfg).await.unwrap();
+    let started = Instant::now();
+    let err = client.call("tools/list", json!({})).await.unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{}", err);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_mcp_resources_and_prompts() {
+    let mock_script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/mock_mcp.py");
+    let mut config = Config::default();
+    config.mcp.servers.insert(
+        "mock".into(),
+        MCPServerConfig {
+            command: "python3".into(),
+            args: vec![mock_script.to_string()],
+            ..Default::default()
+        },
+    );
+
+    let registry = ToolRegistry::new();
+    init_mcp_servers(&config, &registry).await.unwrap();
+
+    // Resources are exposed as tools
+    let listed = registry
+        .execute("mock_list_resources", HashMap::new())
+        .await;
+    assert!(listed
+        .for_llm
+        .contains("file:///notes.txt — notes (text/plain)"));
+    let mut args = HashMap::new();
+    args.insert("uri".to_string(), json!("file:///notes.txt"));
+    let read = registry.execute("mock_read_resource", args).await;
+    assert_eq!(read.for_llm, "buy milk");
+
+    // Telegram sessions may not use the mock server
+    config.tools.profiles.insert(
+        "locked".into(),
+        ToolProfile {
+            allow: vec![],
+            deny: vec!["mock_*".into()],
+        },
+    );
+    config
+        .tools
+        .channel_profiles
+        .insert("telegram".into(), "locked".into());
+
+    let bus = Arc::new(MessageBus::new());
+    let provider = Arc::new(
+        HTTPProvider::new(
+            "test-key".into(),
+            "http://127.0.0.1:9".into(),
+            None,
+            "gpt-4o".into(),
+        )
+        .unwrap(),
+    );
+    let agent = AgentLoop::new(config, provider, registry, bus);
+
+    // `@server:uri` mentions are inlined
+    let expanded = agent
+        .expand_input("Summarize @mock:file:///notes.txt, please", "cli:test")
+        .await
+        .unwrap();
+    assert_eq!(
+        expanded,
+        "Summarize @mock:file:///notes.txt, please\n\n[Resource @mock:file:///notes.txt]\nbuy milk"
+    );
+
+    // Prompts become slash commands, by full or bare name
+    let prompts = agent.mcp_prompts("cli:test").await;
+    assert_eq!(prompts.len(), 1);
+    assert_eq!(prompts[0].usage(), "/mock:greet <name> [style]");
+    let expanded = agent
+        .expand_input("/mock:greet Ada cheerful", "cli:test")
+        .await
+        .unwrap();
+    assert_eq!(expanded, "Greet Ada in a cheerful style.");
+    let expanded = agent.expand_input("/greet Bob", "cli:test").await.unwrap();
+    assert_eq!(expanded, "Greet Bob in a plain style.");
+    assert!(agent.expand_input("/greet", "cli:test").await.is_err());
+
+    // Prompts follow the session's tool profile, like mentions
+    assert!(agent.mcp_prompts("telegram:1").await.is_empty());
+    let denied = agent
+        .expand_input("/mock:greet Ada", "telegram:1")
+        .await
+        .unwrap_err();
+    assert!(denied.to_string().contains("not allowed"), "{}", denied);
+
+    // Other slash text passes through untouched
+    assert_eq!(
+        agent.expand_input("/unknown x", "cli:test").await.unwrap(),
+        "/unknown x"
+    );
+}
+
+/// Poll `check` every 100ms for up to 10s.
+async fn eventually<F, Fut>(mut check: F) -> bool
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    for _ in 0..100 {
+        if check().await {
+            return true;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_mcp_supervisor_restarts_and_refreshes_tools() {
+    let mock_script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/mock_mcp.py");
+    let mut config = Config::default();
+    config.mcp.servers.insert(
+        "mock".into(),
+        MCPServerConfig {
+            command: "python3".into(),
+            args: vec![mock_script.to_string()],
+            timeout: 1,
+            ..Default::default()
+        },
+    );
+    let registry = ToolRegistry::new();
+    init_mcp_servers(&config, &registry).await.unwrap();
+
+    // tools/list_changed re-lists the server's tools
+    let mut args = HashMap::new();
+    args.insert("name".to_string(), json!("extra"));
+    assert_eq!(
+        registry.execute("mock_add_tool", args).await.for_llm,
+        "added"
+    );
+    assert!(
+        eventually(|| async { registry.list().await.contains(&"mock_extra".to_string()) }).await
+    );
+
+    // Progress is forwarded; the per-server timeout cancels the request
+    let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    let ctx = ToolContext {
+        progress: Some(tx),
+        ..Default::default()
+    };
+    let mut args = HashMap::new();
+    args.insert("seconds".to_string(), json!(2));
+    let result = registry.execute_with_context("mock_slow", args, &ctx).await;
+    assert!(result.is_error);
+    assert!(
+        result.for_llm.contains("timed out after 1s"),
+        "{}",
+        result.for_llm
+    );
+    assert_eq!(
+        rx.recv().await.unwrap(),
+        ToolProgress {
+            tool: "mock_slow".into(),
+            progress: 1.0,
+            total: Some(2.0),
+            message: Some("step 1".into()),
+        }
+    );
+
+    // ...and the server was told with notifications/cancelled
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    let cancelled = registry.execute("mock_cancelled", HashMap::new()).await;
+    assert!(
+        cancelled.for_llm.starts_with("[\""),
+        "{}",
+        cancelled.for_llm
+    );
+
+    // A crashed server is restarted and its tools work again
+    let crashed = registry.execute("mock_crash", HashMap::new()).await;
+    assert!(crashed.is_error);
+    let mut args = HashMap::new();
+    args.insert("text".to_string(), json!("back"));
+    assert!(
+        eventually(|| async {
+            registry.execute("mock_echo", args.clone()).await.for_llm == "MOCK_ECHO: back"
+        })
+        .await
+    );
+    // The fresh process no longer offers the tool added at runtime
+    assert!(!registry.list().await.contains(&"mock_extra".to_string()));
+}
+
+#[tokio::test]
+async fn test_mcp_supervisor_backs_off_when_server_exits_after_initialize() {
+    // Answers initialize, then exits before the tools are listed
+    const SCRIPT: &str = r#"
+import json, sys
+open(sys.argv[1], "a").write("start\n")
+req = json.loads(sys.stdin.readline())
+print(json.dumps({"jsonrpc": "2.0", "id": req["id"], "result": {"capabilities": {}}}), flush=True)
+"#;
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    for (name, restart) in [("once", false), ("again", true)] {
+        config.mcp.servers.insert(
+            name.into(),
+            MCPServerConfig {
+                command: "python3".into(),
+                args: vec![
+                    "-c".into(),
+                    SCRIPT.into(),
+                    tmp_dir.path().join(name).to_string_lossy().into_owned(),
+                ],
+                restart,
+                ..Default::default()
+            },
+        );
+    }
+    let registry = ToolRegistry::new();
+    init_mcp_servers(&config, &registry).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    let starts = |name: &str| {
+        std::fs::read_to_string(tmp_dir.path().join(name))
+            .unwrap()
+            .lines()
+            .count()
+    };
+    // restart = false is honoured, and restarts wait for the backoff
+    assert_eq!(starts("once"), 1);
+    let again = starts("again");
+    assert!((1..=3).contains(&again), "restarted {} times", again);
+}
+
+#[tokio::test]
+async fn test_mcp_serve_over_http_with_chat_and_profile() {
+    use quectoclaw::config::ToolProfile;
+    use quectoclaw::mcp::serve::MCPToolServer;
+    use quectoclaw::tool::exec::ExecTool;
+    use quectoclaw::tool::filesystem::ReadFileTool;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let llm = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hello from the agent" },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 4, "total_tokens": 9 }
+        })))
+        .mount(&llm)
+        .await;
+
+    let workspace = tempfile::tempdir().unwrap();
+    let ws = workspace.path().to_string_lossy().to_string();
+    std::fs::write(workspace.path().join("notes.txt"), "served file").unwrap();
+    let registry = ToolRegistry::new();
+    registry
+        .register(Arc::new(ExecTool::new(ws.clone(), true, vec![], vec![])))
+        .await;
+    registry
+        .register(Arc::new(ReadFileTool::new(ws.clone(), true)))
+        .await;
+
+    let mut config = Config::default();
+    config.agents.defaults.workspace = ws;
+    let provider =
+        Arc::new(HTTPProvider::new("test-key".into(), llm.uri(), None, "gpt-4o".into()).unwrap());
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        registry.clone(),
+        Arc::new(MessageBus::new()),
+    ));
+
+    let view = registry.with_profile(
+        "ide",
+        ToolProfile {
+            deny: vec!["exec".into()],
+            ..Default::default()
+        },
+    );
+    let server = Arc::new(MCPToolServer::new(view, "mcp:test").with_chat(agent));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = server.router("test-token".into());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    // Our own client talks to our own server
+    let client = MCPClient::connect(
+        "self",
+        &http_server_config(format!("http://{}/mcp", addr), MCPTransportKind::Http),
+    )
+    .await
+    .unwrap();
+    let init = client
+        .call("initialize", json!({ "protocolVersion": "2024-11-05" }))
+        .await
+        .unwrap();
+    assert_eq!(init["serverInfo"]["name"], "quectoclaw");
+
+    let list = client.call("tools/list", json!({})).await.unwrap();
+    let names: Vec<&str> = list["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|t| t["name"].as_str())
+        .collect();
+    assert_eq!(names, vec!["read_file", "chat"]);
+
+    let read = client
+        .call(
+            "tools/call",
+            json!({ "name": "read_file", "arguments": { "path": "notes.txt" } }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(read["isError"], false);
+    assert!(read["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("served file"));
+
+    let denied = client
+        .call(
+            "tools/call",
+            json!({ "name": "exec", "arguments": { "command": "echo hi" } }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(denied["isError"], true);
+
+    let chat = client
+        .call(
+            "tools/call",
+            json!({ "name": "chat", "arguments": { "message": "hi" } }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(chat["content"][0]["text"], "Hello from the agent");
+
+    // Requests without the token are rejected
+    let no_auth = MCPClient::connect(
+        "anon",
+        &MCPServerConfig {
+            transport: Some(MCPTransportKind::Http),
+            url: format!("http://{}/mcp", addr),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(no_auth.call("initialize", json!({})).await.is_err());
+}
+
+#[tokio::test]
+async fn test_mcp_sampling_uses_provider_under_policy() {
+    use quectoclaw::config::MCPSamplingPolicy;
+    use wiremock::matchers::{body_partial_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // Only a request capped at `sampling_max_tokens`, with the server's
+    // system prompt, gets an answer
+    let llm = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({
+            "max_tokens": 1024,
+            "messages": [
+                { "role": "system", "content": "You are helpful" },
+                { "role": "user", "content": "capital of France?" }
+            ]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Paris" },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 1000, "completion_tokens": 100, "total_tokens": 1100 }
+        })))
+        .mount(&llm)
+        .await;
+
+    let mock_script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/mock_mcp.py");
+    let mut config = Config::default();
+    config.agents.defaults.model = "gpt-4o".into();
+    config.cost.enabled = true;
+    for (name, policy) in [
+        ("allowed", MCPSamplingPolicy::Allow),
+        ("blocked", MCPSamplingPolicy::Deny),
+        ("asking", MCPSamplingPolicy::Ask),
+    ] {
+        config.mcp.servers.insert(
+            name.into(),
+            MCPServerConfig {
+                command: "python3".into(),
+                args: vec![mock_script.to_string()],
+                sampling: policy,
+                ..Default::default()
+            },
+        );
+    }
+    let registry = ToolRegistry::new();
+    init_mcp_servers(&config, &registry).await.unwrap();
+    let provider =
+        Arc::new(HTTPProvider::new("test-key".into(), llm.uri(), None, "gpt-4o".into()).unwrap());
+    let agent = Arc::new(AgentLoop::new(
+        config,
+        provider,
+        registry.clone(),
+        Arc::new(MessageBus::new()),
+    ));
+    agent.attach_mcp_sampling().await;
+
+    let ask = |server: &str| {
+        let registry = registry.clone();
+        let tool = format!("{}_ask_llm", server);
+        async move {
+            let mut args = HashMap::new();
+            args.insert("prompt".to_string(), json!("capital of France?"));
+            registry.execute(&tool, args).await.for_llm
+        }
+    };
+
+    assert_eq!(ask("allowed").await, "Paris [gpt-4o, endTurn]");
+    // Denied servers are not offered sampling at all
+    assert_eq!(ask("blocked").await, "NO_SAMPLING");
+    // Nobody to ask outside the interactive CLI
+    assert_eq!(
+        ask("asking").await,
+        "SAMPLING_ERROR: User rejected sampling request"
+    );
+
+    let usage = agent.metrics().session_usage("sampling:allowed").await;
+    assert_eq!(usage.llm_requests, 1);
+    assert_eq!(usage.prompt_tokens, 1000);
+    assert!((usage.cost - 0.0035).abs() < 1e-9, "cost {}", usage.cost);
+}
@@ -1,6 +1,14 @@
 #!/usr/bin/env python3
 import sys
 import json
+import time
+
+# Tools added at runtime by the "add_tool" tool
+extra_tools = []
+# Request ids the client reported as cancelled
+cancelled = []
+# Capabilities the client declared in initialize
+client_capabilities = {}
 
 def main():
     for line in sys.stdin:
@@ -10,12 +18,13 @@ def main():
             id = req.get("id")
 
             if method == "initialize":
+                client_capabilities.update(req.get("params", {}).get("capabilities", {}))
                 resp = {
                     "jsonrpc": "2.0",
                     "id": id,
                     "result": {
                         "protocolVersion": "2024-11-05",
-                        "capabilities": {},
+                        "capabilities": {"tools": {}, "resources": {}, "prompts": {}},
                         "serverInfo": {"name": "MockServer", "version": "1.0.0"}
                     }
                 }
@@ -36,6 +45,9 @@ def main():
                                     "required": ["text"]
                                 }
                             }
+                        ] + [
+                            {"name": n, "description": f"Test tool {n}", "inputSchema": {"type": "object"}}
+                            for n in ["crash", "slow", "cancelled", "add_tool", "ask_llm"] + extra_tools
                         ]
                     }
                 }
@@ -44,7 +56,70 @@ def main():
                 tool_name = params.get("name")
                 args = params.get("arguments", {})
                 
-                if tool_name == "echo":
+                if tool_name == "crash":
+                    sys.exit(1)
+                elif tool_name == "slow":
+                    # Report progress, then answer after the client gave up
+                    token = params.get("_meta", {}).get("progressToken")
+                    for i in (1, 2):
+                        note = {
+                            "jsonrpc": "2.0",
+                            "method": "notifications/progress",
+                            "params": {"progressToken": token, "progress": i, "total": 2, "message": f"step {i}"}
+                        }
+                        print(json.dumps(note), flush=True)
+                    time.sleep(float(args.get("seconds", 0)))
+                    resp = {
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {"content": [{"type": "text", "text": "slow done"}]}
+                    }
+                elif tool_name == "cancelled":
+                    resp = {
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {"content": [{"type": "text", "text": json.dumps(cancelled)}]}
+                    }
+                elif tool_name == "add_tool":
+                    extra_tools.append(args.get("name", "extra"))
+                    note = {"jsonrpc": "2.0", "method": "notifications/tools/list_changed"}
+                    print(json.dumps(note), flush=True)
+                    resp = {
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {"content": [{"type": "text", "text": "added"}]}
+                    }
+                elif tool_name == "ask_llm":
+                    # Ask the client's model via sampling, waiting for its answer
+                    if "sampling" not in client_capabilities:
+                        text = "NO_SAMPLING"
+                    else:
+                        sample = {
+                            "jsonrpc": "2.0",
+                            "id": "sample-1",
+                            "method": "sampling/createMessage",
+                            "params": {
+                                "messages": [{"role": "user", "content": {"type": "text", "text": args.get("prompt", "")}}],
+                                "systemPrompt": "You are helpful",
+                                "maxTokens": 5000
+                            }
+                        }
+                        print(json.dumps(sample), flush=True)
+                        while True:
+                            reply = json.loads(sys.stdin.readline())
+                            if reply.get("id") == "sample-1":
+                                break
+                        if "error" in reply:
+                            text = "SAMPLING_ERROR: " + reply["error"]["message"]
+                        else:
+                            result = reply["result"]
+                            text = f"{result['content']['text']} [{result['model']}, {result['stopReason']}]"
+                    resp = {
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {"content": [{"type": "text", "text": text}]}
+                    }
+                elif tool_name == "echo":
                     text = args.get("text", "nothing")
                     resp = {
                         "jsonrpc": "2.0",
@@ -60,6 +135,62 @@ def main():
                         "id": id,
                         "error": {"code": -32601, "message": "Method not found"}
                     }
+            elif method == "resources/list":
+                resp = {
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {
+                        "resources": [
+                            {"uri": "file:///notes.txt", "name": "notes", "mimeType": "text/plain"}
+                        ]
+                    }
+                }
+            elif method == "resources/read":
+                uri = req.get("params", {}).get("uri")
+                if uri == "file:///notes.txt":
+                    resp = {
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "contents": [{"uri": uri, "mimeType": "text/plain", "text": "buy milk"}]
+                        }
+                    }
+                else:
+                    resp = {
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": -32002, "message": "Resource not found"}
+                    }
+            elif method == "prompts/list":
+                resp = {
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {
+                        "prompts": [
+                            {
+                                "name": "greet",
+                                "description": "Greet someone",
+                                "arguments": [
+                                    {"name": "name", "required": True},
+                                    {"name": "style", "required": False}
+                                ]
+                            }
+                        ]
+                    }
+                }
+            elif method == "prompts/get":
+                args = req.get("params", {}).get("arguments", {})
+                text = f"Greet {args.get('name')} in a {args.get('style', 'plain')} style."
+                resp = {
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {
+                        "messages": [{"role": "user", "content": {"type": "text", "text": text}}]
+                    }
+                }
+            elif method == "notifications/cancelled":
+                cancelled.append(req.get("params", {}).get("requestId"))
+                continue
             elif method == "notifications/initialized":
                 continue # No response needed for notifications
             else:
@@ -0,0 +1,173 @@
+#!/usr/bin/env python3
+"""Stand-in MCP server for the HTTP transports.
+
+Streamable HTTP: POST /mcp (tools/call answers as an SSE stream), GET /mcp -> 405.
+Legacy SSE:      GET /sse (endpoint + message events), POST /messages?session_id=...
+
+Every request must carry `Authorization: Bearer test-token`.
+Prints the listening port on the first stdout line.
+"""
+import json
+import queue
+import sys
+import threading
+import uuid
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+from urllib.parse import parse_qs, urlparse
+
+TOKEN = "Bearer test-token"
+SESSION = "sess-1"
+sse_queues = {}
+sse_lock = threading.Lock()
+
+
+def handle(req):
+    """Return the JSON-RPC response for a request, or None for notifications."""
+    method = req.get("method")
+    id = req.get("id")
+    if id is None:
+        return None
+    if method == "initialize":
+        result = {
+            "protocolVersion": req.get("params", {}).get("protocolVersion", "2024-11-05"),
+            "capabilities": {"tools": {}},
+            "serverInfo": {"name": "MockHttpServer", "version": "1.0.0"},
+        }
+    elif method == "tools/list":
+        result = {
+            "tools": [
+                {
+                    "name": "echo",
+                    "description": "Echo back the input",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {"text": {"type": "string"}},
+                        "required": ["text"],
+                    },
+                }
+            ]
+        }
+    elif method == "tools/call":
+        text = req.get("params", {}).get("arguments", {}).get("text", "nothing")
+        result = {"content": [{"type": "text", "text": f"HTTP_ECHO: {text}"}], "isError": False}
+    else:
+        return {"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": "Method not found"}}
+    return {"jsonrpc": "2.0", "id": id, "result": result}
+
+
+class Handler(BaseHTTPRequestHandler):
+    def log_message(self, *args):
+        pass
+
+    def authorized(self):
+        if self.headers.get("Authorization") != TOKEN:
+            self.send_response(401)
+            self.send_header("Content-Length", "0")
+            self.end_headers()
+            return False
+        return True
+
+    def body(self):
+        length = int(self.headers.get("Content-Length", 0))
+        return json.loads(self.rfile.read(length))
+
+    def send_json(self, obj, extra_headers=None):
+        data = json.dumps(obj).encode()
+        self.send_response(200)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(data)))
+        for k, v in (extra_headers or {}).items():
+            self.send_header(k, v)
+        self.end_headers()
+        self.wfile.write(data)
+
+    def accepted(self):
+        self.send_response(202)
+        self.send_header("Content-Length", "0")
+        self.end_headers()
+
+    def do_GET(self):
+        if not self.authorized():
+            return
+        path = urlparse(self.path).path
+        if path == "/mcp":
+            self.send_response(405)
+            self.send_header("Content-Length", "0")
+            self.end_headers()
+        elif path == "/sse":
+            session = uuid.uuid4().hex
+            q = queue.Queue()
+            with sse_lock:
+                sse_queues[session] = q
+            self.send_response(200)
+            self.send_header("Content-Type", "text/event-stream")
+            self.end_headers()
+            try:
+                self.wfile.write(f"event: endpoint\ndata: /messages?session_id={session}\n\n".encode())
+                self.wfile.flush()
+                while True:
+                    try:
+                        msg = q.get(timeout=15)
+                    except queue.Empty:
+                        self.wfile.write(b": ping\n\n")
+                    else:
+                        self.wfile.write(f"event: message\ndata: {json.dumps(msg)}\n\n".encode())
+                    self.wfile.flush()
+            except (BrokenPipeError, ConnectionResetError):
+                pass
+            finally:
+                with sse_lock:
+                    sse_queues.pop(session, None)
+        else:
+            self.send_error(404)
+
+    def do_POST(self):
+        if not self.authorized():
+            return
+        url = urlparse(self.path)
+        if url.path == "/mcp":
+            req = self.body()
+            if req.get("method") != "initialize" and self.headers.get("Mcp-Session-Id") != SESSION:
+                self.send_response(400)
+                self.send_header("Content-Length", "0")
+                self.end_headers()
+                return
+            resp = handle(req)
+            if resp is None:
+                self.accepted()
+            elif req.get("method") == "tools/call":
+                # Answer as an SSE stream, preceded by a progress notification
+                progress = {"jsonrpc": "2.0", "method": "notifications/progress",
+                            "params": {"progressToken": 1, "progress": 1}}
+                data = f"data: {json.dumps(progress)}\n\nevent: message\ndata: {json.dumps(resp)}\n\n".encode()
+                self.send_response(200)
+                self.send_header("Content-Type", "text/event-stream")
+                self.send_header("Content-Length", str(len(data)))
+                self.end_headers()
+                self.wfile.write(data)
+            else:
+                self.send_json(resp, {"Mcp-Session-Id": SESSION})
+        elif url.path == "/messages":
+            session = parse_qs(url.query).get("session_id", [""])[0]
+            with sse_lock:
+                q = sse_queues.get(session)
+            if q is None:
+                self.send_error(404)
+                return
+            resp = handle(self.body())
+            if resp is not None:
+                q.put(resp)
+            self.accepted()
+        else:
+            self.send_error(404)
+
+
+def main():
+    server = ThreadingHTTPServer(("127.0.0.1", 0), Handler)
+    server.daemon_threads = True
+    print(server.server_address[1], flush=True)
+    server.serve_forever()
+
+
+if __name__ == "__main__":
+    sys.exit(main())